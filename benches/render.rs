@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use qr_generator::{generate, ModuleStyle, QrOptions};
+
+/// A payload of typical length, encoding to a version 3 code.
+const URL: &str = "https://example.com/products/2024/qr-generator?ref=benchmark";
+
+/// Generates one code per iteration at several sizes, with plain and rounded
+/// modules, reported in codes per second.
+fn bench_generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate");
+    group.throughput(Throughput::Elements(1));
+    for style in [ModuleStyle::Square, ModuleStyle::Rounded] {
+        for size in [200, 400, 800, 1600] {
+            let opts = QrOptions { size, ..QrOptions::default() }.with_module_style(style);
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", style).to_lowercase(), size), &opts, |b, opts| {
+                b.iter(|| generate(URL, opts).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Rasterizes the largest QR version at print size, where filling modules
+/// dominates the run time.
+fn bench_large_raster(c: &mut Criterion) {
+    let opts = QrOptions { version: Some(40), size: 4000, ..QrOptions::default() };
+    c.bench_function("generate version 40 at 4000px", |b| {
+        b.iter(|| generate("https://example.com", &opts).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_generate, bench_large_raster);
+criterion_main!(benches);
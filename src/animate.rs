@@ -0,0 +1,63 @@
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, RgbaImage};
+
+use crate::sheet::labelled_codes;
+use crate::{QrGenError, QrOptions, SheetEntry};
+
+/// Generates a code for each of `entries` with `opts`, as [`generate`](crate::generate)
+/// does, and encodes them as the frames of a looping GIF, each shown for
+/// `frame_delay_ms` milliseconds.
+///
+/// Each entry's label becomes its caption. Every frame is as large as the
+/// largest code, with smaller codes centered at its top on `opts.background`,
+/// so the animation does not jump between frames. GIF stores delays in
+/// hundredths of a second, so `frame_delay_ms` is rounded to one.
+pub fn animated_gif(entries: &[SheetEntry], frame_delay_ms: u32, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    let codes = labelled_codes(entries, opts, &|_, _| {})?;
+    let width = codes.iter().map(RgbaImage::width).max().unwrap_or(0);
+    let height = codes.iter().map(RgbaImage::height).max().unwrap_or(0);
+    let background = opts.background_pixel();
+    let delay = Delay::from_numer_denom_ms(frame_delay_ms.div_ceil(10).max(1) * 10, 1);
+
+    let frames = codes.iter().map(|code| {
+        let mut canvas = RgbaImage::from_pixel(width, height, background);
+        image::imageops::overlay(&mut canvas, code, ((width - code.width()) / 2) as i64, 0);
+        image::Frame::from_parts(canvas, 0, 0, delay)
+    });
+
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut bytes, 10);
+        encoder.set_repeat(Repeat::Infinite).map_err(QrGenError::ImageEncode)?;
+        encoder.encode_frames(frames).map_err(QrGenError::ImageEncode)?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_sheet_entries;
+    use crate::verify::decode_payload;
+    use image::codecs::gif::GifDecoder;
+    use image::{AnimationDecoder, DynamicImage};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_animated_gif_frames() {
+        let entries = parse_sheet_entries("https://example.com/a\nhttps://example.com/b\tPromo\nhttps://example.com/c\n");
+        let opts = QrOptions { size: 200, ..QrOptions::default() };
+        let gif = animated_gif(&entries, 1500, &opts).unwrap();
+
+        let frames = GifDecoder::new(Cursor::new(gif)).unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        // The labelled frame sets the size for all of them
+        let size = frames[1].buffer().dimensions();
+        assert!(size.1 > 200);
+        assert!(frames.iter().all(|frame| frame.buffer().dimensions() == size));
+        assert!(frames.iter().all(|frame| frame.delay().numer_denom_ms() == (1500, 1)));
+
+        let code = DynamicImage::ImageRgba8(frames[2].buffer().clone()).crop_imm(0, 0, 200, 200);
+        assert_eq!(decode_payload(&code).as_deref(), Some("https://example.com/c"));
+    }
+}
@@ -0,0 +1,103 @@
+use qrcode::types::QrError;
+
+use crate::{generate, EcLevel, QrGenError, QrOptions};
+
+/// Smallest icon scale `auto_tune` will shrink the icon to, in percent.
+pub const MIN_AUTO_ICON_SCALE: f64 = 6.0;
+
+/// Step by which `auto_tune` shrinks the icon between attempts, in percent.
+const AUTO_ICON_SCALE_STEP: f64 = 2.0;
+
+/// Error correction levels `auto_tune` tries, most robust first.
+const AUTO_EC_LEVELS: [EcLevel; 4] = [EcLevel::H, EcLevel::Q, EcLevel::M, EcLevel::L];
+
+/// Finds an error correction level and icon scale for which the QR code for
+/// `url` decodes, starting from `opts`.
+///
+/// Each level from H down to L is tried with the icon at `opts.icon_scale`,
+/// then shrunk by two percentage points at a time down to
+/// [`MIN_AUTO_ICON_SCALE`]; without `opts.icon` only the levels are tried. The
+/// first combination whose output verifies is returned as a copy of `opts`
+/// with `ec_level` and `icon_scale` filled in. Combinations that do not fit
+/// the payload in the canvas or `opts.version`, or whose icon hides too much
+/// under `opts.strict`, are skipped; any other error is returned as is.
+pub fn auto_tune(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<QrOptions, QrGenError> {
+    if opts.micro {
+        return Err(QrGenError::MicroUnsupported("--auto, since the decoder only reads standard codes"));
+    }
+    let url = url.as_ref();
+    let scales = match opts.icon {
+        Some(_) => icon_scales(opts.icon_scale),
+        None => vec![opts.icon_scale],
+    };
+
+    for ec_level in AUTO_EC_LEVELS {
+        for &icon_scale in &scales {
+            let candidate = QrOptions { ec_level: Some(ec_level), icon_scale, verify: true, ..opts.clone() };
+            match generate(url, &candidate) {
+                Ok(_) => return Ok(QrOptions { verify: opts.verify, ..candidate }),
+                Err(QrGenError::VerificationFailed(_))
+                | Err(QrGenError::SizeTooSmall { .. })
+                | Err(QrGenError::IconCoverageTooHigh { .. })
+                | Err(QrGenError::PayloadTooLong { .. })
+                | Err(QrGenError::QrEncode(QrError::DataTooLong)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Err(QrGenError::NoScannableCombination)
+}
+
+/// Icon scales to try, from `start` down to [`MIN_AUTO_ICON_SCALE`].
+fn icon_scales(start: f64) -> Vec<f64> {
+    let mut scales = vec![start];
+    let mut scale = start - AUTO_ICON_SCALE_STEP;
+    while scale >= MIN_AUTO_ICON_SCALE {
+        scales.push(scale);
+        scale -= AUTO_ICON_SCALE_STEP;
+    }
+    scales
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_icon_scales() {
+        assert_eq!(icon_scales(12.0), [12.0, 10.0, 8.0, 6.0]);
+        assert_eq!(icon_scales(5.0), [5.0]);
+    }
+
+    #[test]
+    fn test_auto_tune_shrinks_oversized_icon() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("test_icon.png");
+        DynamicImage::new_rgb8(100, 100).save(&icon_path).unwrap();
+
+        let opts = QrOptions::default().with_icon(&icon_path).with_icon_scale(40.0);
+        let tuned = auto_tune("https://example.com", &opts).unwrap();
+
+        assert_eq!(tuned.ec_level, Some(EcLevel::H));
+        assert!(tuned.icon_scale < 40.0);
+        assert!(!tuned.verify);
+        assert!(generate("https://example.com", &tuned.with_verify(true)).is_ok());
+    }
+
+    #[test]
+    fn test_auto_tune_lowers_ec_for_long_payloads() {
+        // Too long for level H, but fits at a lower level
+        let url = format!("https://example.com/?q={}", "a".repeat(1500));
+        let tuned = auto_tune(&url, &QrOptions::default().with_size(1000)).unwrap();
+        assert_ne!(tuned.ec_level, Some(EcLevel::H));
+    }
+
+    #[test]
+    fn test_auto_tune_gives_up() {
+        let url = "a".repeat(8000);
+        let result = auto_tune(&url, &QrOptions::default());
+        assert!(matches!(result, Err(QrGenError::NoScannableCombination)));
+    }
+}
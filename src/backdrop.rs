@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{ImageReader, Rgb, RgbaImage};
+use crate::code::Code;
+use crate::color::blend_over;
+use crate::render::{module_color, Layout};
+use crate::{contrast_ratio, QrGenError, QrOptions, MIN_CONTRAST_RATIO};
+
+/// Fraction of light modules that may sit on low-contrast parts of a
+/// background image before we warn about it.
+const MAX_LOW_CONTRAST_FRACTION: f64 = 0.05;
+
+/// Loads the image at `path`, scaled and cropped to cover a `size` x `size`
+/// canvas, with `opts.background` laid over it at `opts.scrim_opacity`.
+///
+/// Transparent parts of the image show the background, see
+/// [`QrOptions::background_pixel`].
+pub(crate) fn load_backdrop(path: &Path, size: u32, opts: &QrOptions) -> Result<RgbaImage, QrGenError> {
+    if !path.exists() {
+        return Err(QrGenError::BackgroundImageNotFound(path.to_path_buf()));
+    }
+    let image = ImageReader::open(path)?.decode().map_err(QrGenError::ImageDecode)?;
+    let image = image.resize_to_fill(size, size, FilterType::Lanczos3).to_rgba8();
+
+    let background = opts.background_pixel();
+    let scrim = opts.scrim_opacity.clamp(0.0, 1.0);
+    let mut backdrop = RgbaImage::from_pixel(size, size, background);
+    for (pixel, photo) in backdrop.pixels_mut().zip(image.pixels()) {
+        let [r, g, b, a] = photo.0;
+        *pixel = blend_over(*pixel, Rgb([r, g, b]), a as f64 / 255.0);
+        if scrim > 0.0 {
+            *pixel = blend_over(*pixel, opts.background, scrim);
+        }
+    }
+    Ok(backdrop)
+}
+
+/// Returns a warning if more than a few light modules of `code` sit on parts
+/// of `backdrop` whose contrast with the dark modules is below
+/// [`MIN_CONTRAST_RATIO`].
+///
+/// Each light module is judged by the average color of the backdrop across
+/// its cell, against the color a dark module in that cell would have.
+pub(crate) fn contrast_warning(backdrop: &RgbaImage, code: &Code, layout: &Layout, opts: &QrOptions) -> Option<String> {
+    let module_size = layout.module_size;
+    let (mut light, mut low) = (0u32, 0u32);
+    for y in 0..code.width() {
+        for x in 0..code.width() {
+            if code[(x, y)] == qrcode::Color::Dark {
+                continue;
+            }
+            let start_x = layout.offset + x as u32 * module_size;
+            let start_y = layout.offset + y as u32 * module_size;
+            light += 1;
+            if contrast_ratio(average_color(backdrop, start_x, start_y, module_size), module_color(opts, layout, start_x, start_y))
+                < MIN_CONTRAST_RATIO
+            {
+                low += 1;
+            }
+        }
+    }
+
+    let fraction = low as f64 / light.max(1) as f64;
+    if fraction <= MAX_LOW_CONTRAST_FRACTION {
+        return None;
+    }
+    Some(format!(
+        "{:.0}% of the light modules sit on parts of the background image with a contrast ratio below {:.1} against the \
+         modules, which may not scan; consider a higher --scrim-opacity or a lighter image",
+        fraction * 100.0,
+        MIN_CONTRAST_RATIO
+    ))
+}
+
+/// Average color of the `size` x `size` cell of `image` at `(x, y)`.
+fn average_color(image: &RgbaImage, x: u32, y: u32, size: u32) -> Rgb<u8> {
+    let mut sum = [0u64; 3];
+    let mut count = 0u64;
+    for py in y..(y + size).min(image.height()) {
+        for px in x..(x + size).min(image.width()) {
+            let pixel = image.get_pixel(px, py);
+            for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                *total += channel as u64;
+            }
+            count += 1;
+        }
+    }
+    Rgb(sum.map(|total| (total / count.max(1)) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use qrcode::QrCode;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_backdrop_covers_canvas_with_scrim() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("photo.png");
+        // A wide photo, dark on the left and mid-gray on the right
+        RgbaImage::from_fn(300, 100, |x, _| if x < 150 { Rgba([0, 0, 0, 255]) } else { Rgba([128, 128, 128, 255]) })
+            .save(&path)
+            .unwrap();
+
+        let opts = QrOptions::default();
+        let backdrop = load_backdrop(&path, 100, &opts).unwrap();
+        assert_eq!(backdrop.dimensions(), (100, 100));
+        assert_eq!(*backdrop.get_pixel(10, 50), Rgba([0, 0, 0, 255]));
+        assert_eq!(*backdrop.get_pixel(90, 50), Rgba([128, 128, 128, 255]));
+
+        let opts = QrOptions { scrim_opacity: 0.5, ..QrOptions::default() };
+        let backdrop = load_backdrop(&path, 100, &opts).unwrap();
+        assert_eq!(*backdrop.get_pixel(10, 50), Rgba([128, 128, 128, 255]));
+
+        let missing = dir.path().join("missing.png");
+        assert!(matches!(load_backdrop(&missing, 100, &opts), Err(QrGenError::BackgroundImageNotFound(_))));
+    }
+
+    #[test]
+    fn test_contrast_warning() {
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let opts = QrOptions::default();
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let size = layout.canvas_size;
+
+        let light = RgbaImage::from_pixel(size, size, Rgba([230, 230, 230, 255]));
+        assert_eq!(contrast_warning(&light, &code, &layout, &opts), None);
+
+        let dark = RgbaImage::from_pixel(size, size, Rgba([60, 60, 60, 255]));
+        assert!(contrast_warning(&dark, &code, &layout, &opts).unwrap().starts_with("100% of the light modules"));
+    }
+}
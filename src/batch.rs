@@ -0,0 +1,361 @@
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use csv::{ReaderBuilder, StringRecord, Trim};
+use image::ImageFormat;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use crate::{generate_bytes, generate_qr_with_icon, OutputFormat, QrGenError, QrOptions};
+
+/// Longest slug `{slug}` expands to, in characters.
+const MAX_SLUG_LEN: usize = 60;
+
+/// One QR code to generate, read from a row of a batch file.
+struct BatchRow {
+    line: u64,
+    url: String,
+    /// Icon to overlay, or `None` to fall back to the batch options.
+    icon: Option<String>,
+    output: String,
+}
+
+/// How a batch is run, as opposed to how each code looks.
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Worker threads, or 0 for one per CPU.
+    pub jobs: usize,
+    /// Replace output files that already exist.
+    pub overwrite: bool,
+    /// Generate, and verify if asked, every code but write nothing.
+    pub dry_run: bool,
+    /// Output path for rows that give none, with placeholders filled in by
+    /// [`template_path`].
+    pub output_template: Option<String>,
+    /// Format whose extension `{ext}` stands for, or PNG if `None`.
+    pub format: Option<OutputFormat>,
+}
+
+impl BatchOptions {
+    /// Sets [`jobs`](Self::jobs).
+    pub fn with_jobs(self, jobs: usize) -> Self {
+        BatchOptions { jobs, ..self }
+    }
+
+    /// Sets [`overwrite`](Self::overwrite).
+    pub fn with_overwrite(self, overwrite: bool) -> Self {
+        BatchOptions { overwrite, ..self }
+    }
+
+    /// Sets [`dry_run`](Self::dry_run).
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        BatchOptions { dry_run, ..self }
+    }
+
+    /// Sets [`output_template`](Self::output_template).
+    pub fn with_output_template(self, output_template: impl Into<String>) -> Self {
+        BatchOptions { output_template: Some(output_template.into()), ..self }
+    }
+
+    /// Sets [`format`](Self::format).
+    pub fn with_format(self, format: OutputFormat) -> Self {
+        BatchOptions { format: Some(format), ..self }
+    }
+}
+
+/// Result of one row of a batch run.
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// Line of the batch file the row was read from.
+    pub line: u64,
+    /// Output path of the row, empty if the row could not be read.
+    pub output: String,
+    pub result: Result<(), QrGenError>,
+}
+
+/// Generates one QR code per row of the CSV in `reader`, with `opts` applied
+/// to every row.
+///
+/// Rows are generated in parallel on `batch.jobs` threads; outcomes keep the
+/// order of the file. The CSV needs a header with a `url` column and an
+/// `output` column, which may be left out or empty when
+/// `batch.output_template` names the files instead. It may also have an
+/// `icon` column; an empty icon falls back to `opts.icon`, producing a plain
+/// QR code by default. A row that fails is recorded in its outcome without
+/// stopping the others. Only an unreadable header is an error for the whole
+/// batch.
+///
+/// A row whose output file already exists fails unless `batch.overwrite` is
+/// set. With `batch.dry_run` set, each code is generated, and verified if
+/// `opts.verify` is set, but nothing is written.
+pub fn run_batch<R: Read>(reader: R, opts: &QrOptions, batch: &BatchOptions) -> Result<Vec<BatchOutcome>, QrGenError> {
+    run_batch_with_progress(reader, opts, batch, |_, _| {})
+}
+
+/// Runs a batch as [`run_batch`] does, calling `progress` with the number of
+/// rows done so far and the number of rows after each one finishes.
+///
+/// `progress` is called from the worker threads, in whatever order the rows
+/// finish, but the counts it is given are each handed out once.
+pub fn run_batch_with_progress<R: Read>(
+    reader: R,
+    opts: &QrOptions,
+    batch: &BatchOptions,
+    progress: impl Fn(usize, usize) + Sync,
+) -> Result<Vec<BatchOutcome>, QrGenError> {
+    let mut csv = ReaderBuilder::new().trim(Trim::All).flexible(true).from_reader(reader);
+    let headers = csv.headers()?.clone();
+    let column = |name: &'static str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let url_column = column("url").ok_or(QrGenError::MissingColumn("url"))?;
+    let output_column = column("output");
+    if output_column.is_none() && batch.output_template.is_none() {
+        return Err(QrGenError::MissingColumn("output"));
+    }
+    let icon_column = column("icon");
+
+    // Read everything up front so rows can be handed out to worker threads
+    let mut rows: Vec<Result<BatchRow, BatchOutcome>> = csv
+        .records()
+        .map(|record| match record {
+            Ok(record) => Ok(BatchRow {
+                line: record.position().map_or(0, |p| p.line()),
+                url: field(&record, url_column).unwrap_or_default(),
+                icon: icon_column.and_then(|i| field(&record, i)),
+                output: output_column.and_then(|i| field(&record, i)).unwrap_or_default(),
+            }),
+            Err(e) => Err(BatchOutcome {
+                line: e.position().map_or(0, |p| p.line()),
+                output: String::new(),
+                result: Err(e.into()),
+            }),
+        })
+        .collect();
+    if let Some(template) = &batch.output_template {
+        fill_templated_outputs(&mut rows, template, batch.format.unwrap_or(OutputFormat::Raster(ImageFormat::Png)));
+    }
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(batch.jobs)
+        .build()
+        .map_err(|e| QrGenError::Io(io::Error::other(e)))?;
+    let (total, done) = (rows.len(), AtomicUsize::new(0));
+    let outcomes = pool.install(|| {
+        rows.into_par_iter()
+            .map(|row| {
+                let outcome = match row {
+                    Ok(row) => {
+                        let result = generate_row(&row, opts, batch);
+                        BatchOutcome { line: row.line, output: row.output, result }
+                    }
+                    Err(outcome) => outcome,
+                };
+                progress(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+                outcome
+            })
+            .collect()
+    });
+    Ok(outcomes)
+}
+
+/// Names the output of each row that has none after `template`, counting
+/// rows from 1, and makes every name unique by appending the row's index to
+/// any that an earlier row already took.
+fn fill_templated_outputs(rows: &mut [Result<BatchRow, BatchOutcome>], template: &str, format: OutputFormat) {
+    let mut taken = HashSet::new();
+    for (i, row) in rows.iter_mut().enumerate() {
+        let Ok(row) = row else { continue };
+        let index = i + 1;
+        if row.output.is_empty() {
+            row.output = template_path(template, index, &row.url, format);
+            while taken.contains(&row.output) {
+                row.output = with_suffix(&row.output, index);
+            }
+        }
+        taken.insert(row.output.clone());
+    }
+}
+
+/// Fills in `template`: `{index}` becomes `index`, `{slug}` the
+/// [`slug`] of `payload` and `{ext}` the usual extension of `format`.
+pub fn template_path(template: &str, index: usize, payload: &str, format: OutputFormat) -> String {
+    template
+        .replace("{index}", &index.to_string())
+        .replace("{slug}", &slug(payload))
+        .replace("{ext}", format.extension())
+}
+
+/// A filesystem-safe name for `payload`: a URL without its scheme or a
+/// leading `www.`, lowercased, with each run of other characters than ASCII
+/// letters and digits turned into one `-`, and at most [`MAX_SLUG_LEN`]
+/// long. A payload with nothing left is `qr`.
+pub fn slug(payload: &str) -> String {
+    let rest = payload.split_once("://").map_or(payload, |(_, rest)| rest);
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+    let mut slug = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() == MAX_SLUG_LEN {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() { "qr".to_string() } else { slug.to_string() }
+}
+
+/// `path` with `_index` added to its file stem, before any extension.
+fn with_suffix(path: &str, index: usize) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, index),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+fn generate_row(row: &BatchRow, opts: &QrOptions, batch: &BatchOptions) -> Result<(), QrGenError> {
+    if row.output.is_empty() {
+        return Err(QrGenError::MissingColumn("output"));
+    }
+    if batch.dry_run {
+        let format = OutputFormat::from_path(Path::new(&row.output))?;
+        let opts = match &row.icon {
+            Some(icon) => opts.clone().with_icon(icon),
+            None => opts.clone(),
+        };
+        return generate_bytes(&row.url, format, &opts).map(drop);
+    }
+    if !batch.overwrite && Path::new(&row.output).exists() {
+        return Err(QrGenError::OutputExists(row.output.clone().into()));
+    }
+    generate_qr_with_icon(&row.url, row.icon.as_deref(), &row.output, opts)
+}
+
+/// The non-empty value of column `index` in `record`.
+fn field(record: &StringRecord, index: usize) -> Option<String> {
+    record.get(index).filter(|value| !value.is_empty()).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_batch_continues_past_bad_rows() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path().display();
+        let icon_path = temp_dir.path().join("icon.png");
+        DynamicImage::new_rgb8(10, 10).save(&icon_path).unwrap();
+
+        let csv = format!(
+            "url,icon,output\n\
+             https://example.com/a,{icon},{dir}/a.png\n\
+             https://example.com/b,,{dir}/b.svg\n\
+             https://example.com/c,{dir}/missing.png,{dir}/c.png\n\
+             https://example.com/d,,{dir}/d.unknown\n\
+             https://example.com/e,,{dir}/e.png\n",
+            icon = icon_path.display(),
+        );
+        let outcomes = run_batch(csv.as_bytes(), &QrOptions::default(), &BatchOptions::default().with_jobs(2)).unwrap();
+
+        assert_eq!(outcomes.len(), 5);
+        assert_eq!(outcomes.iter().map(|o| o.line).collect::<Vec<_>>(), [2, 3, 4, 5, 6]);
+        assert!(outcomes[0].result.is_ok());
+        assert!(outcomes[1].result.is_ok());
+        assert!(matches!(outcomes[2].result, Err(QrGenError::IconNotFound(_))));
+        assert!(matches!(outcomes[3].result, Err(QrGenError::UnsupportedOutputFormat(_))));
+        assert!(outcomes[4].result.is_ok());
+        assert!(temp_dir.path().join("e.png").exists());
+    }
+
+    #[test]
+    fn test_parallel_batch_keeps_row_order() {
+        let temp_dir = tempdir().unwrap();
+        let mut csv = String::from("url,output\n");
+        for i in 0..40 {
+            csv.push_str(&format!("https://example.com/{0},{1}/{0}.png\n", i, temp_dir.path().display()));
+        }
+        let outcomes = run_batch(csv.as_bytes(), &QrOptions { size: 100, ..QrOptions::default() }, &BatchOptions::default().with_jobs(4)).unwrap();
+
+        assert!(outcomes.iter().all(|o| o.result.is_ok()));
+        for (i, outcome) in outcomes.iter().enumerate() {
+            assert_eq!(outcome.line, i as u64 + 2);
+            assert!(outcome.output.ends_with(&format!("/{}.png", i)));
+        }
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path().display();
+        let csv = format!(
+            "url,icon,output\n\
+             https://example.com/a,,{dir}/a.png\n\
+             https://example.com/b,{dir}/missing.png,{dir}/b.png\n\
+             https://example.com/c,,{dir}/c.svg\n"
+        );
+        let outcomes = run_batch(csv.as_bytes(), &QrOptions::default().with_verify(true), &BatchOptions::default().with_dry_run(true)).unwrap();
+
+        assert!(outcomes[0].result.is_ok());
+        assert!(matches!(outcomes[1].result, Err(QrGenError::IconNotFound(_))));
+        assert!(outcomes[2].result.is_ok());
+        assert_eq!(temp_dir.path().read_dir().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_batch_refuses_to_overwrite() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("a.png");
+        fs::write(&output_path, "keep me").unwrap();
+        let csv = format!("url,output\nhttps://example.com/a,{}\n", output_path.display());
+
+        let outcomes = run_batch(csv.as_bytes(), &QrOptions::default(), &BatchOptions::default()).unwrap();
+        assert!(matches!(outcomes[0].result, Err(QrGenError::OutputExists(_))));
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "keep me");
+
+        let outcomes = run_batch(csv.as_bytes(), &QrOptions::default(), &BatchOptions::default().with_overwrite(true)).unwrap();
+        assert!(outcomes[0].result.is_ok());
+        assert!(image::open(&output_path).is_ok());
+    }
+
+    #[test]
+    fn test_slug_sanitizes_urls() {
+        assert_eq!(slug("https://www.Example.com/menu?table=4&lang=en#top"), "example-com-menu-table-4-lang-en-top");
+        assert_eq!(slug("https://example.com/a%20b/"), "example-com-a-20b");
+        assert_eq!(slug("../../etc/passwd"), "etc-passwd");
+        assert_eq!(slug("¿?"), "qr");
+        assert_eq!(slug(&format!("https://example.com/{}", "x".repeat(100))).len(), MAX_SLUG_LEN);
+    }
+
+    #[test]
+    fn test_output_template() {
+        let temp_dir = tempdir().unwrap();
+        let template = format!("{}/qr_{{slug}}.{{ext}}", temp_dir.path().display());
+        let csv = "url,output\nhttps://example.com/a?x=1,\nhttps://example.com/b,\nhttps://example.com/a?x=1,\n";
+        let batch = BatchOptions::default().with_output_template(template).with_format(OutputFormat::Svg);
+        let outcomes = run_batch(csv.as_bytes(), &QrOptions::default(), &batch).unwrap();
+
+        let names: Vec<_> = outcomes.iter().map(|o| Path::new(&o.output).file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["qr_example-com-a-x-1.svg", "qr_example-com-b.svg", "qr_example-com-a-x-1_3.svg"]);
+        assert!(outcomes.iter().all(|o| o.result.is_ok() && Path::new(&o.output).exists()));
+
+        // Neither an output column nor a template leaves nowhere to write
+        let result = run_batch("url\nhttps://example.com\n".as_bytes(), &QrOptions::default(), &BatchOptions::default());
+        assert!(matches!(result, Err(QrGenError::MissingColumn("output"))));
+    }
+
+    #[test]
+    fn test_batch_requires_columns() {
+        let result = run_batch("url,icon\nhttps://example.com,\n".as_bytes(), &QrOptions::default(), &BatchOptions::default());
+        assert!(matches!(result, Err(QrGenError::MissingColumn("output"))));
+    }
+}
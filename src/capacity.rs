@@ -0,0 +1,202 @@
+use std::fmt;
+
+use qrcode::bits::Bits;
+use qrcode::types::{Mode, QrResult, Version};
+
+use crate::options::MAX_VERSION;
+use crate::render::encode_micro;
+use crate::{EcLevel, PayloadEncoding, QrGenError, QrOptions};
+
+/// ECI designator for UTF-8.
+const UTF8_ECI: u32 = 26;
+
+/// Bits taken by the mode indicator and designator of an ECI header.
+const ECI_HEADER_BITS: usize = 12;
+
+/// Character set a QR code stores its payload in. Narrower sets pack more
+/// characters into the same space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataMode {
+    /// Digits `0` to `9`.
+    Numeric,
+    /// Digits, uppercase `A` to `Z`, space and `$%*+-./:`.
+    Alphanumeric,
+    /// Arbitrary bytes, such as the UTF-8 encoding of text.
+    Byte,
+}
+
+impl DataMode {
+    /// The narrowest mode that can hold all of `payload`.
+    pub fn for_payload(payload: impl AsRef<[u8]>) -> DataMode {
+        let payload = payload.as_ref();
+        if payload.iter().all(|b| b.is_ascii_digit()) {
+            DataMode::Numeric
+        } else if payload.iter().all(|b| b.is_ascii_digit() || b.is_ascii_uppercase() || b" $%*+-./:".contains(b)) {
+            DataMode::Alphanumeric
+        } else {
+            DataMode::Byte
+        }
+    }
+
+    /// Length of `payload` in this mode's units: bytes in byte mode,
+    /// characters otherwise, which are one byte each.
+    pub fn length(self, payload: impl AsRef<[u8]>) -> usize {
+        payload.as_ref().len()
+    }
+
+    /// Name of the units [`DataMode::length`] counts in.
+    pub(crate) fn units(self) -> &'static str {
+        match self {
+            DataMode::Numeric => "digits",
+            DataMode::Alphanumeric => "characters",
+            DataMode::Byte => "bytes",
+        }
+    }
+}
+
+impl From<DataMode> for Mode {
+    fn from(mode: DataMode) -> Mode {
+        match mode {
+            DataMode::Numeric => Mode::Numeric,
+            DataMode::Alphanumeric => Mode::Alphanumeric,
+            DataMode::Byte => Mode::Byte,
+        }
+    }
+}
+
+impl fmt::Display for DataMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DataMode::Numeric => "numeric",
+            DataMode::Alphanumeric => "alphanumeric",
+            DataMode::Byte => "byte",
+        })
+    }
+}
+
+/// The most `mode` data that fits in a QR code of `version` at `ec_level`,
+/// in [`DataMode::length`] units.
+pub fn max_capacity(version: u8, ec_level: EcLevel, mode: DataMode) -> Result<usize, QrGenError> {
+    capacity_after(version, ec_level, mode, 0)
+}
+
+/// Like [`max_capacity`], with `overhead` bits already taken by headers.
+fn capacity_after(version: u8, ec_level: EcLevel, mode: DataMode, overhead: usize) -> Result<usize, QrGenError> {
+    if !(1..=MAX_VERSION).contains(&version) {
+        return Err(QrGenError::InvalidVersion(version));
+    }
+    let version = Version::Normal(version as i16);
+    let mode = Mode::from(mode);
+    let max_bits = Bits::new(version).max_len(ec_level.into())?;
+    let length_bits = mode.length_bits_count(version);
+    // The mode indicator and length field come first
+    let available = max_bits.saturating_sub(overhead + 4 + length_bits);
+
+    let fits = |n: usize| mode.data_bits_count(n) <= available;
+    let (mut low, mut high) = (0usize, (1 << length_bits) - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if fits(mid) { low = mid } else { high = mid - 1 }
+    }
+    Ok(low)
+}
+
+/// Encodes `payload` as the data bits of a QR code of `version`, without the
+/// terminator and padding.
+pub(crate) fn payload_bits(payload: &[u8], version: u8, encoding: PayloadEncoding) -> QrResult<Bits> {
+    let mut bits = Bits::new(Version::Normal(version as i16));
+    match encoding {
+        PayloadEncoding::Auto => bits.push_optimal_data(payload)?,
+        PayloadEncoding::Utf8 => {
+            bits.push_eci_designator(UTF8_ECI)?;
+            bits.push_byte_data(payload)?;
+        }
+    }
+    Ok(bits)
+}
+
+/// Checks that `payload` fits in a QR code at `opts.version`, or the largest
+/// version when that is unset, at the error correction level `opts` picks.
+///
+/// The check uses the same `opts.encoding` as the generator, so a payload
+/// passes exactly when it can be generated. With `opts.micro` it checks the
+/// Micro QR versions instead.
+pub fn fits(payload: impl AsRef<[u8]>, opts: &QrOptions) -> Result<(), QrGenError> {
+    let ec_level = opts.effective_ec_level(opts.icon.is_some());
+    if opts.micro {
+        return encode_micro(payload.as_ref(), ec_level, opts).map(drop);
+    }
+    check_fits(payload.as_ref(), opts.version.unwrap_or(MAX_VERSION), ec_level, opts.encoding)
+}
+
+/// Checks that `payload` fits in `version` at `ec_level`.
+pub(crate) fn check_fits(payload: &[u8], version: u8, ec_level: EcLevel, encoding: PayloadEncoding) -> Result<(), QrGenError> {
+    if !(1..=MAX_VERSION).contains(&version) {
+        return Err(QrGenError::InvalidVersion(version));
+    }
+    let max_bits = Bits::new(Version::Normal(version as i16)).max_len(ec_level.into())?;
+    if payload_bits(payload, version, encoding).is_ok_and(|bits| bits.len() <= max_bits) {
+        return Ok(());
+    }
+
+    let (mode, overhead) = match encoding {
+        PayloadEncoding::Auto => (DataMode::for_payload(payload), 0),
+        PayloadEncoding::Utf8 => (DataMode::Byte, ECI_HEADER_BITS),
+    };
+    Err(QrGenError::PayloadTooLong {
+        length: mode.length(payload),
+        max: capacity_after(version, ec_level, mode, overhead)?,
+        mode,
+        version,
+        ec_level,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_capacity_matches_standard() {
+        // From the capacity tables in ISO/IEC 18004
+        assert_eq!(max_capacity(1, EcLevel::L, DataMode::Numeric).unwrap(), 41);
+        assert_eq!(max_capacity(1, EcLevel::L, DataMode::Alphanumeric).unwrap(), 25);
+        assert_eq!(max_capacity(1, EcLevel::L, DataMode::Byte).unwrap(), 17);
+        assert_eq!(max_capacity(1, EcLevel::H, DataMode::Byte).unwrap(), 7);
+        assert_eq!(max_capacity(10, EcLevel::M, DataMode::Alphanumeric).unwrap(), 311);
+        assert_eq!(max_capacity(40, EcLevel::L, DataMode::Numeric).unwrap(), 7089);
+        assert_eq!(max_capacity(40, EcLevel::L, DataMode::Byte).unwrap(), 2953);
+        assert_eq!(max_capacity(40, EcLevel::H, DataMode::Byte).unwrap(), 1273);
+        assert!(matches!(max_capacity(41, EcLevel::L, DataMode::Byte), Err(QrGenError::InvalidVersion(41))));
+    }
+
+    #[test]
+    fn test_data_mode_for_payload() {
+        assert_eq!(DataMode::for_payload("0123456789"), DataMode::Numeric);
+        assert_eq!(DataMode::for_payload("HTTPS://EXAMPLE.COM/A-1"), DataMode::Alphanumeric);
+        assert_eq!(DataMode::for_payload("https://example.com"), DataMode::Byte);
+        assert_eq!(DataMode::Byte.length("héllo"), 6);
+    }
+
+    #[test]
+    fn test_fits() {
+        let opts = QrOptions::default().with_ec_level(EcLevel::L).with_version(1);
+        assert!(fits("a".repeat(17), &opts).is_ok());
+        let err = fits("a".repeat(20), &opts).unwrap_err();
+        assert!(matches!(
+            err,
+            QrGenError::PayloadTooLong { length: 20, max: 17, mode: DataMode::Byte, version: 1, ec_level: EcLevel::L }
+        ));
+        assert!(err.to_string().contains("20 bytes long, 3 over the 17 bytes"), "{}", err);
+
+        // Without a fixed version the largest one is allowed
+        let opts = QrOptions::default().with_ec_level(EcLevel::H);
+        assert!(fits("a".repeat(1273), &opts).is_ok());
+        assert!(fits("a".repeat(1274), &opts).is_err());
+
+        // The UTF-8 header takes 12 of the bits
+        let opts = opts.with_encoding(PayloadEncoding::Utf8);
+        assert!(fits("a".repeat(1272), &opts).is_ok());
+        assert!(matches!(fits("a".repeat(1273), &opts), Err(QrGenError::PayloadTooLong { max: 1272, .. })));
+    }
+}
@@ -0,0 +1,277 @@
+use image::imageops;
+use image::{GrayImage, Rgb, RgbaImage};
+use resvg::tiny_skia::{self, FillRule, Paint, PathBuilder, Pixmap, Transform};
+use skrifa::instance::{LocationRef, Size};
+use skrifa::outline::OutlinePen;
+use skrifa::{FontRef, GlyphId, MetadataProvider};
+
+use crate::color::blend_over;
+use crate::QrOptions;
+
+/// DejaVu Sans, which captions and frame text are set in. See
+/// `assets/caption-font-LICENSE.txt` for the font's license.
+const FONT: &[u8] = include_bytes!("../assets/caption-font.ttf");
+/// Height of the font's ascenders above the baseline and its descenders
+/// below it, as fractions of the em.
+const ASCENT: f64 = 0.928;
+const DESCENT: f64 = 0.236;
+/// Height of the capital letters above the baseline, as a fraction of the em.
+pub(crate) const CAP_HEIGHT: f64 = 0.729;
+
+/// The caption to draw, or `None` when `opts.caption` is unset or empty.
+pub(crate) fn caption_text(opts: &QrOptions) -> Option<&str> {
+    opts.caption.as_deref().filter(|caption| !caption.is_empty())
+}
+
+fn font() -> FontRef<'static> {
+    FontRef::new(FONT).expect("bundled caption font is a valid TrueType font")
+}
+
+/// Glyph for `c`, or the font's missing glyph box if it has none.
+fn glyph_id(font: &FontRef, c: char) -> GlyphId {
+    font.charmap().map(c).unwrap_or(GlyphId::NOTDEF)
+}
+
+/// Horizontal advance of each glyph of `text`, as a fraction of the em.
+fn advances<'a>(font: &'a FontRef, text: &'a str) -> impl Iterator<Item = (GlyphId, f64)> + 'a {
+    let em = font.metrics(Size::unscaled(), LocationRef::default()).units_per_em as f64;
+    let metrics = font.glyph_metrics(Size::unscaled(), LocationRef::default());
+    text.chars().map(move |c| {
+        let glyph = glyph_id(font, c);
+        (glyph, metrics.advance_width(glyph).unwrap_or(0.0) as f64 / em)
+    })
+}
+
+/// Width of `text` set at `size` pixels per em, measured by the glyph
+/// advances.
+pub(crate) fn text_width(text: &str, size: f64) -> f64 {
+    advances(&font(), text).map(|(_, advance)| advance).sum::<f64>() * size
+}
+
+/// The largest size up to `size` at which `text` is at most `max_width`
+/// wide.
+pub(crate) fn fitted_size(text: &str, size: f64, max_width: f64) -> f64 {
+    let width = text_width(text, size);
+    if width > max_width { size * max_width / width } else { size }
+}
+
+/// Height of the band a caption at `size` pixels per em adds below the
+/// canvas: one line of text and a quarter em of padding beneath it.
+pub fn caption_band_height(size: f64) -> u32 {
+    (size * (ASCENT + DESCENT + 0.25)).ceil() as u32
+}
+
+/// Where a caption is set within its band.
+pub(crate) struct CaptionLayout {
+    /// Font size, shrunk from `opts.caption_size` if the text would
+    /// otherwise be wider than the canvas.
+    pub size: f64,
+    /// Horizontal position of the first glyph's origin.
+    pub x: f64,
+    /// Vertical position of the baseline, from the top of the band.
+    pub baseline: f64,
+}
+
+impl CaptionLayout {
+    /// Centers `text` in a band below a canvas `width` pixels wide.
+    pub fn new(text: &str, width: u32, opts: &QrOptions) -> CaptionLayout {
+        let size = fitted_size(text, opts.caption_size, width as f64);
+
+        // Keep a shrunk line vertically centered where the full-size one would be
+        let line_offset = (opts.caption_size - size) * (ASCENT + DESCENT) / 2.0;
+        CaptionLayout {
+            size,
+            x: (width as f64 - text_width(text, size)) / 2.0,
+            baseline: line_offset + ASCENT * size,
+        }
+    }
+}
+
+/// Extends `image` downward by [`caption_band_height`] and draws `text`
+/// centered in the new band, in `opts.caption_color` or the foreground.
+pub(crate) fn add_caption(image: &RgbaImage, text: &str, opts: &QrOptions) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let background = opts.background_pixel();
+    let mut canvas = RgbaImage::from_pixel(width, height + caption_band_height(opts.caption_size), background);
+    imageops::replace(&mut canvas, image, 0, 0);
+
+    let layout = CaptionLayout::new(text, width, opts);
+    let color = opts.caption_color.unwrap_or(opts.foreground);
+    draw_text(&mut canvas, text, layout.x, height as f64 + layout.baseline, layout.size, color);
+    canvas
+}
+
+/// A line of text rasterized from the font and the box it covers once set.
+pub(crate) struct TextRun {
+    /// Coverage of the glyphs.
+    pub mask: GrayImage,
+    /// Top left corner of the mask.
+    pub x: f64,
+    pub y: f64,
+    /// Size the mask covers, in pixels.
+    pub width: f64,
+    pub height: f64,
+}
+
+impl TextRun {
+    /// Sets `text` at `size` pixels per em, with the first glyph's origin at
+    /// `x` and the baseline at `baseline`, rasterized at `resolution` mask
+    /// pixels per pixel.
+    ///
+    /// The mask is aligned to whole mask pixels, so at a resolution of 1 it
+    /// can be laid straight onto the canvas.
+    pub fn new(text: &str, x: f64, baseline: f64, size: f64, resolution: f64) -> TextRun {
+        let (origin_x, origin_y) = (x * resolution, baseline * resolution);
+        let Some(path) = text_path(text, size * resolution) else {
+            // Nothing to ink, such as a line of spaces
+            return TextRun { mask: GrayImage::new(1, 1), x, y: baseline, width: 1.0 / resolution, height: 1.0 / resolution };
+        };
+
+        let bounds = path.bounds();
+        let left = (origin_x + bounds.left() as f64).floor();
+        let top = (origin_y + bounds.top() as f64).floor();
+        let width = ((origin_x + bounds.right() as f64).ceil() - left).max(1.0) as u32;
+        let height = ((origin_y + bounds.bottom() as f64).ceil() - top).max(1.0) as u32;
+
+        let mut pixmap = Pixmap::new(width, height).expect("text mask has a nonzero size");
+        let mut paint = Paint::default();
+        paint.set_color(tiny_skia::Color::BLACK);
+        paint.anti_alias = true;
+        let transform = Transform::from_translate((origin_x - left) as f32, (origin_y - top) as f32);
+        pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+
+        let coverage = pixmap.pixels().iter().map(|pixel| pixel.alpha()).collect();
+        TextRun {
+            mask: GrayImage::from_raw(width, height, coverage).expect("one coverage value per pixel"),
+            x: left / resolution,
+            y: top / resolution,
+            width: width as f64 / resolution,
+            height: height as f64 / resolution,
+        }
+    }
+}
+
+/// Outlines of `text` set at `size` pixels per em, with the first glyph's
+/// origin at `(0, 0)` and y growing downward, or `None` if no glyph has any.
+fn text_path(text: &str, size: f64) -> Option<tiny_skia::Path> {
+    let font = font();
+    let outlines = font.outline_glyphs();
+    let settings = (Size::new(size as f32), LocationRef::default());
+    let (mut pen, mut x) = (PathPen { path: PathBuilder::new(), x: 0.0 }, 0.0);
+    for (glyph, advance) in advances(&font, text) {
+        if let Some(outline) = outlines.get(glyph) {
+            pen.x = x as f32;
+            outline.draw(settings, &mut pen).expect("bundled caption font outlines draw");
+        }
+        x += advance * size;
+    }
+    pen.path.finish()
+}
+
+/// Collects glyph outlines into a path, each shifted along by `x` and
+/// flipped so y grows downward like the canvas's.
+struct PathPen {
+    path: PathBuilder,
+    x: f32,
+}
+
+impl OutlinePen for PathPen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.path.move_to(self.x + x, -y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.path.line_to(self.x + x, -y);
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        self.path.quad_to(self.x + cx0, -cy0, self.x + x, -y);
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        self.path.cubic_to(self.x + cx0, -cy0, self.x + cx1, -cy1, self.x + x, -y);
+    }
+
+    fn close(&mut self) {
+        self.path.close();
+    }
+}
+
+/// Draws `text` at `size` pixels per em in `color`, with the first glyph's
+/// origin at `x` and the baseline at `baseline`, clipped to the canvas.
+pub(crate) fn draw_text(canvas: &mut RgbaImage, text: &str, x: f64, baseline: f64, size: f64, color: Rgb<u8>) {
+    let run = TextRun::new(text, x, baseline, size, 1.0);
+    let (width, height) = canvas.dimensions();
+    let (left, top) = (run.x as i64, run.y as i64);
+    for (mx, my, coverage) in run.mask.enumerate_pixels() {
+        let (px, py) = (left + mx as i64, top + my as i64);
+        if coverage[0] == 0 || px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+            continue;
+        }
+        let (px, py) = (px as u32, py as u32);
+        let blended = blend_over(*canvas.get_pixel(px, py), color, coverage[0] as f64 / 255.0);
+        canvas.put_pixel(px, py, blended);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_constants_match_font() {
+        let metrics = font().metrics(Size::unscaled(), LocationRef::default());
+        let em = metrics.units_per_em as f64;
+        assert!((metrics.ascent as f64 / em - ASCENT).abs() < 1e-3);
+        assert!((-metrics.descent as f64 / em - DESCENT).abs() < 1e-3);
+        let capital = font().glyph_metrics(Size::unscaled(), LocationRef::default()).bounds(glyph_id(&font(), 'H')).unwrap();
+        assert!((capital.y_max as f64 / em - CAP_HEIGHT).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_caption_is_centered_in_band() {
+        let opts = QrOptions { caption_size: 24.0, ..QrOptions::default() };
+        let image = RgbaImage::from_pixel(200, 200, Rgba([255, 255, 255, 255]));
+        let captioned = add_caption(&image, "Scan to order", &opts);
+        assert_eq!(captioned.dimensions(), (200, 200 + caption_band_height(24.0)));
+
+        let inked: Vec<(u32, u32)> =
+            captioned.enumerate_pixels().filter(|(_, _, p)| p[0] < 128).map(|(x, y, _)| (x, y)).collect();
+        assert!(inked.iter().all(|&(_, y)| y >= 200));
+        let (min_x, max_x) = (inked.iter().map(|p| p.0).min().unwrap(), inked.iter().map(|p| p.0).max().unwrap());
+        assert!((min_x as i64 - (199 - max_x) as i64).abs() <= 3, "ink spans {}..={}", min_x, max_x);
+        assert_eq!(*captioned.get_pixel(0, 199), Rgba([255, 255, 255, 255]));
+
+        let red = Rgb([200, 0, 0]);
+        let opts = QrOptions { caption_color: Some(red), ..opts };
+        let captioned = add_caption(&image, "I", &opts);
+        assert!(captioned.pixels().any(|p| p[0] > 150 && p[1] < 50 && p[2] < 50));
+    }
+
+    #[test]
+    fn test_long_caption_shrinks_to_fit() {
+        let opts = QrOptions { caption_size: 40.0, ..QrOptions::default() };
+        let text = "A caption far too long for a narrow code";
+        let layout = CaptionLayout::new(text, 100, &opts);
+        assert!(layout.size < 40.0);
+        assert!((text_width(text, layout.size) - 100.0).abs() < 1e-3);
+        assert!(layout.x.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_non_ascii_caption() {
+        // Accents, arrows and the like come from the font rather than a stand-in
+        assert_ne!(text_width("\u{e9}", 48.0), text_width("?", 48.0));
+        assert!("Men\u{fc} \u{2192} Caf\u{e9}".chars().all(|c| font().charmap().map(c).is_some()));
+
+        // An accent inks above the x-height, where the bare letter leaves nothing
+        let opts = QrOptions { caption_size: 48.0, ..QrOptions::default() };
+        let image = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+        let top_ink = |text: &str| {
+            let captioned = add_caption(&image, text, &opts);
+            captioned.enumerate_pixels().filter(|&(_, y, p)| y >= 100 && p[0] < 128).map(|(_, y, _)| y).min().unwrap()
+        };
+        assert!(top_ink("\u{e9}") + 5 < top_ink("e"));
+    }
+}
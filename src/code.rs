@@ -0,0 +1,193 @@
+use std::ops::Index;
+
+use qrcode::canvas::{Canvas, MaskPattern};
+use qrcode::types::{QrResult, Version};
+use qrcode::{Color, QrCode};
+
+use crate::EcLevel;
+
+/// The mask patterns, indexed by their number in the standard.
+const MASK_PATTERNS: [MaskPattern; 8] = [
+    MaskPattern::Checkerboard,
+    MaskPattern::HorizontalLines,
+    MaskPattern::VerticalLines,
+    MaskPattern::DiagonalLines,
+    MaskPattern::LargeCheckerboard,
+    MaskPattern::Fields,
+    MaskPattern::Diamonds,
+    MaskPattern::Meadow,
+];
+
+/// Columns and rows of the copy of the format information beside the
+/// top-left finder pattern, most significant bit first.
+const FORMAT_INFO_MODULES: [(usize, usize); 15] = [
+    (0, 8),
+    (1, 8),
+    (2, 8),
+    (3, 8),
+    (4, 8),
+    (5, 8),
+    (7, 8),
+    (8, 8),
+    (8, 7),
+    (8, 5),
+    (8, 4),
+    (8, 3),
+    (8, 2),
+    (8, 1),
+    (8, 0),
+];
+
+/// Pattern the format information is XORed with, so it is never all light.
+const FORMAT_INFO_MASK: u16 = 0b101_0100_0001_0010;
+
+/// Columns and rows of the format information of a Micro QR code, most
+/// significant bit first.
+const MICRO_FORMAT_INFO_MODULES: [(usize, usize); 15] = [
+    (1, 8),
+    (2, 8),
+    (3, 8),
+    (4, 8),
+    (5, 8),
+    (6, 8),
+    (7, 8),
+    (8, 8),
+    (8, 7),
+    (8, 6),
+    (8, 5),
+    (8, 4),
+    (8, 3),
+    (8, 2),
+    (8, 1),
+];
+
+/// Pattern the format information of a Micro QR code is XORed with.
+const MICRO_FORMAT_INFO_MASK: u16 = 0b100_0100_0100_0101;
+
+/// Widest Micro QR code, M4. Every standard code is wider.
+pub(crate) const MAX_MICRO_WIDTH: usize = 17;
+
+/// The modules of an encoded QR code, whether `qrcode` laid out the data or
+/// we assembled the data codewords ourselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Code {
+    width: usize,
+    version: u8,
+    micro: bool,
+    /// Row-major module colors.
+    modules: Vec<Color>,
+}
+
+impl Code {
+    /// Lays out `data`, the complete data codewords with terminator and
+    /// padding, in a code of `version` at `ec_level`, masked with pattern
+    /// `mask` or the lowest-penalty pattern when that is `None`.
+    ///
+    /// `mask` must be at most [`MAX_MASK`](crate::options::MAX_MASK).
+    pub fn from_data(data: &[u8], version: u8, ec_level: EcLevel, mask: Option<u8>) -> QrResult<Code> {
+        let qr_version = Version::Normal(version as i16);
+        let (data, ec) = qrcode::ec::construct_codewords(data, qr_version, ec_level.into())?;
+        let mut canvas = Canvas::new(qr_version, ec_level.into());
+        canvas.draw_all_functional_patterns();
+        canvas.draw_data(&data, &ec);
+        let canvas = match mask {
+            Some(mask) => {
+                canvas.apply_mask(MASK_PATTERNS[mask as usize]);
+                canvas
+            }
+            None => canvas.apply_best_mask(),
+        };
+        let modules = canvas.into_colors();
+        Ok(Code { width: qr_version.width() as usize, version, micro: false, modules })
+    }
+
+    /// Wraps the row-major `modules` of a code laid out elsewhere, `width`
+    /// modules across.
+    ///
+    /// `width` must be that of a version from 1 to 40, or of a Micro QR
+    /// code from M1 to M4.
+    pub fn from_modules(width: usize, modules: Vec<Color>) -> Code {
+        if width <= MAX_MICRO_WIDTH {
+            Code { width, version: ((width - 9) / 2) as u8, micro: true, modules }
+        } else {
+            Code { width, version: ((width - 17) / 4) as u8, micro: false, modules }
+        }
+    }
+
+    /// Number of modules along one side.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The version number, from 1 to 40, or from 1 to 4 for a Micro QR code.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Whether this is a Micro QR code, with a single finder pattern.
+    pub fn is_micro(&self) -> bool {
+        self.micro
+    }
+
+    /// Number of the mask pattern applied to the data, from 0 to 7, or from
+    /// 0 to 3 for a Micro QR code, as recorded in the format information.
+    pub fn mask(&self) -> u8 {
+        let read = |modules: &[(usize, usize)]| {
+            modules.iter().fold(0, |format, &position| (format << 1) | (self[position] == Color::Dark) as u16)
+        };
+        if self.micro {
+            // Three bits of symbol number, two of mask, then ten of BCH code
+            (((read(&MICRO_FORMAT_INFO_MODULES) ^ MICRO_FORMAT_INFO_MASK) >> 10) & 0b11) as u8
+        } else {
+            // Two bits of error correction level, three of mask, then ten of BCH code
+            (((read(&FORMAT_INFO_MODULES) ^ FORMAT_INFO_MASK) >> 10) & 0b111) as u8
+        }
+    }
+
+    /// The module colors, row by row.
+    pub fn to_colors(&self) -> Vec<Color> {
+        self.modules.clone()
+    }
+}
+
+impl From<QrCode> for Code {
+    fn from(code: QrCode) -> Code {
+        let (version, micro) = match code.version() {
+            Version::Normal(version) => (version as u8, false),
+            Version::Micro(version) => (version as u8, true),
+        };
+        Code { width: code.width(), version, micro, modules: code.into_colors() }
+    }
+}
+
+impl Index<(usize, usize)> for Code {
+    type Output = Color;
+
+    /// The module at column `x`, row `y`.
+    fn index(&self, (x, y): (usize, usize)) -> &Color {
+        &self.modules[y * self.width + x]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_from_format_information() {
+        for mask in 0..8 {
+            let data = [0x40, 0x11, 0x00, 0xec, 0x11, 0xec, 0x11, 0xec, 0x11, 0xec, 0x11, 0xec, 0x11, 0xec, 0x11, 0xec];
+            let code = Code::from_data(&data, 1, EcLevel::M, Some(mask)).unwrap();
+            assert_eq!(code.mask(), mask);
+        }
+    }
+
+    #[test]
+    fn test_micro_code() {
+        let code = Code::from(QrCode::with_version("12345", Version::Micro(2), qrcode::EcLevel::L).unwrap());
+        assert!(code.is_micro());
+        assert_eq!((code.version(), code.width()), (2, 13));
+        assert!(code.mask() <= 3);
+        assert_eq!(Code::from_modules(code.width(), code.to_colors()), code);
+    }
+}
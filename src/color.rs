@@ -0,0 +1,232 @@
+use image::{Rgb, Rgba};
+
+use crate::QrGenError;
+
+/// Minimum WCAG contrast ratio between foreground and background that we
+/// consider reliably scannable.
+pub const MIN_CONTRAST_RATIO: f64 = 3.0;
+
+/// The CSS named colors, sorted by name for binary search.
+const NAMED_COLORS: [(&str, Rgb<u8>); 148] = [
+    ("aliceblue", Rgb([0xf0, 0xf8, 0xff])), ("antiquewhite", Rgb([0xfa, 0xeb, 0xd7])),
+    ("aqua", Rgb([0x00, 0xff, 0xff])), ("aquamarine", Rgb([0x7f, 0xff, 0xd4])), ("azure", Rgb([0xf0, 0xff, 0xff])),
+    ("beige", Rgb([0xf5, 0xf5, 0xdc])), ("bisque", Rgb([0xff, 0xe4, 0xc4])), ("black", Rgb([0x00, 0x00, 0x00])),
+    ("blanchedalmond", Rgb([0xff, 0xeb, 0xcd])), ("blue", Rgb([0x00, 0x00, 0xff])),
+    ("blueviolet", Rgb([0x8a, 0x2b, 0xe2])), ("brown", Rgb([0xa5, 0x2a, 0x2a])), ("burlywood", Rgb([0xde, 0xb8, 0x87])),
+    ("cadetblue", Rgb([0x5f, 0x9e, 0xa0])), ("chartreuse", Rgb([0x7f, 0xff, 0x00])),
+    ("chocolate", Rgb([0xd2, 0x69, 0x1e])), ("coral", Rgb([0xff, 0x7f, 0x50])),
+    ("cornflowerblue", Rgb([0x64, 0x95, 0xed])), ("cornsilk", Rgb([0xff, 0xf8, 0xdc])),
+    ("crimson", Rgb([0xdc, 0x14, 0x3c])), ("cyan", Rgb([0x00, 0xff, 0xff])), ("darkblue", Rgb([0x00, 0x00, 0x8b])),
+    ("darkcyan", Rgb([0x00, 0x8b, 0x8b])), ("darkgoldenrod", Rgb([0xb8, 0x86, 0x0b])),
+    ("darkgray", Rgb([0xa9, 0xa9, 0xa9])), ("darkgreen", Rgb([0x00, 0x64, 0x00])),
+    ("darkgrey", Rgb([0xa9, 0xa9, 0xa9])), ("darkkhaki", Rgb([0xbd, 0xb7, 0x6b])),
+    ("darkmagenta", Rgb([0x8b, 0x00, 0x8b])), ("darkolivegreen", Rgb([0x55, 0x6b, 0x2f])),
+    ("darkorange", Rgb([0xff, 0x8c, 0x00])), ("darkorchid", Rgb([0x99, 0x32, 0xcc])),
+    ("darkred", Rgb([0x8b, 0x00, 0x00])), ("darksalmon", Rgb([0xe9, 0x96, 0x7a])),
+    ("darkseagreen", Rgb([0x8f, 0xbc, 0x8f])), ("darkslateblue", Rgb([0x48, 0x3d, 0x8b])),
+    ("darkslategray", Rgb([0x2f, 0x4f, 0x4f])), ("darkslategrey", Rgb([0x2f, 0x4f, 0x4f])),
+    ("darkturquoise", Rgb([0x00, 0xce, 0xd1])), ("darkviolet", Rgb([0x94, 0x00, 0xd3])),
+    ("deeppink", Rgb([0xff, 0x14, 0x93])), ("deepskyblue", Rgb([0x00, 0xbf, 0xff])),
+    ("dimgray", Rgb([0x69, 0x69, 0x69])), ("dimgrey", Rgb([0x69, 0x69, 0x69])), ("dodgerblue", Rgb([0x1e, 0x90, 0xff])),
+    ("firebrick", Rgb([0xb2, 0x22, 0x22])), ("floralwhite", Rgb([0xff, 0xfa, 0xf0])),
+    ("forestgreen", Rgb([0x22, 0x8b, 0x22])), ("fuchsia", Rgb([0xff, 0x00, 0xff])),
+    ("gainsboro", Rgb([0xdc, 0xdc, 0xdc])), ("ghostwhite", Rgb([0xf8, 0xf8, 0xff])), ("gold", Rgb([0xff, 0xd7, 0x00])),
+    ("goldenrod", Rgb([0xda, 0xa5, 0x20])), ("gray", Rgb([0x80, 0x80, 0x80])), ("green", Rgb([0x00, 0x80, 0x00])),
+    ("greenyellow", Rgb([0xad, 0xff, 0x2f])), ("grey", Rgb([0x80, 0x80, 0x80])), ("honeydew", Rgb([0xf0, 0xff, 0xf0])),
+    ("hotpink", Rgb([0xff, 0x69, 0xb4])), ("indianred", Rgb([0xcd, 0x5c, 0x5c])), ("indigo", Rgb([0x4b, 0x00, 0x82])),
+    ("ivory", Rgb([0xff, 0xff, 0xf0])), ("khaki", Rgb([0xf0, 0xe6, 0x8c])), ("lavender", Rgb([0xe6, 0xe6, 0xfa])),
+    ("lavenderblush", Rgb([0xff, 0xf0, 0xf5])), ("lawngreen", Rgb([0x7c, 0xfc, 0x00])),
+    ("lemonchiffon", Rgb([0xff, 0xfa, 0xcd])), ("lightblue", Rgb([0xad, 0xd8, 0xe6])),
+    ("lightcoral", Rgb([0xf0, 0x80, 0x80])), ("lightcyan", Rgb([0xe0, 0xff, 0xff])),
+    ("lightgoldenrodyellow", Rgb([0xfa, 0xfa, 0xd2])), ("lightgray", Rgb([0xd3, 0xd3, 0xd3])),
+    ("lightgreen", Rgb([0x90, 0xee, 0x90])), ("lightgrey", Rgb([0xd3, 0xd3, 0xd3])),
+    ("lightpink", Rgb([0xff, 0xb6, 0xc1])), ("lightsalmon", Rgb([0xff, 0xa0, 0x7a])),
+    ("lightseagreen", Rgb([0x20, 0xb2, 0xaa])), ("lightskyblue", Rgb([0x87, 0xce, 0xfa])),
+    ("lightslategray", Rgb([0x77, 0x88, 0x99])), ("lightslategrey", Rgb([0x77, 0x88, 0x99])),
+    ("lightsteelblue", Rgb([0xb0, 0xc4, 0xde])), ("lightyellow", Rgb([0xff, 0xff, 0xe0])),
+    ("lime", Rgb([0x00, 0xff, 0x00])), ("limegreen", Rgb([0x32, 0xcd, 0x32])), ("linen", Rgb([0xfa, 0xf0, 0xe6])),
+    ("magenta", Rgb([0xff, 0x00, 0xff])), ("maroon", Rgb([0x80, 0x00, 0x00])),
+    ("mediumaquamarine", Rgb([0x66, 0xcd, 0xaa])), ("mediumblue", Rgb([0x00, 0x00, 0xcd])),
+    ("mediumorchid", Rgb([0xba, 0x55, 0xd3])), ("mediumpurple", Rgb([0x93, 0x70, 0xdb])),
+    ("mediumseagreen", Rgb([0x3c, 0xb3, 0x71])), ("mediumslateblue", Rgb([0x7b, 0x68, 0xee])),
+    ("mediumspringgreen", Rgb([0x00, 0xfa, 0x9a])), ("mediumturquoise", Rgb([0x48, 0xd1, 0xcc])),
+    ("mediumvioletred", Rgb([0xc7, 0x15, 0x85])), ("midnightblue", Rgb([0x19, 0x19, 0x70])),
+    ("mintcream", Rgb([0xf5, 0xff, 0xfa])), ("mistyrose", Rgb([0xff, 0xe4, 0xe1])),
+    ("moccasin", Rgb([0xff, 0xe4, 0xb5])), ("navajowhite", Rgb([0xff, 0xde, 0xad])), ("navy", Rgb([0x00, 0x00, 0x80])),
+    ("oldlace", Rgb([0xfd, 0xf5, 0xe6])), ("olive", Rgb([0x80, 0x80, 0x00])), ("olivedrab", Rgb([0x6b, 0x8e, 0x23])),
+    ("orange", Rgb([0xff, 0xa5, 0x00])), ("orangered", Rgb([0xff, 0x45, 0x00])), ("orchid", Rgb([0xda, 0x70, 0xd6])),
+    ("palegoldenrod", Rgb([0xee, 0xe8, 0xaa])), ("palegreen", Rgb([0x98, 0xfb, 0x98])),
+    ("paleturquoise", Rgb([0xaf, 0xee, 0xee])), ("palevioletred", Rgb([0xdb, 0x70, 0x93])),
+    ("papayawhip", Rgb([0xff, 0xef, 0xd5])), ("peachpuff", Rgb([0xff, 0xda, 0xb9])), ("peru", Rgb([0xcd, 0x85, 0x3f])),
+    ("pink", Rgb([0xff, 0xc0, 0xcb])), ("plum", Rgb([0xdd, 0xa0, 0xdd])), ("powderblue", Rgb([0xb0, 0xe0, 0xe6])),
+    ("purple", Rgb([0x80, 0x00, 0x80])), ("rebeccapurple", Rgb([0x66, 0x33, 0x99])), ("red", Rgb([0xff, 0x00, 0x00])),
+    ("rosybrown", Rgb([0xbc, 0x8f, 0x8f])), ("royalblue", Rgb([0x41, 0x69, 0xe1])),
+    ("saddlebrown", Rgb([0x8b, 0x45, 0x13])), ("salmon", Rgb([0xfa, 0x80, 0x72])),
+    ("sandybrown", Rgb([0xf4, 0xa4, 0x60])), ("seagreen", Rgb([0x2e, 0x8b, 0x57])),
+    ("seashell", Rgb([0xff, 0xf5, 0xee])), ("sienna", Rgb([0xa0, 0x52, 0x2d])), ("silver", Rgb([0xc0, 0xc0, 0xc0])),
+    ("skyblue", Rgb([0x87, 0xce, 0xeb])), ("slateblue", Rgb([0x6a, 0x5a, 0xcd])),
+    ("slategray", Rgb([0x70, 0x80, 0x90])), ("slategrey", Rgb([0x70, 0x80, 0x90])), ("snow", Rgb([0xff, 0xfa, 0xfa])),
+    ("springgreen", Rgb([0x00, 0xff, 0x7f])), ("steelblue", Rgb([0x46, 0x82, 0xb4])), ("tan", Rgb([0xd2, 0xb4, 0x8c])),
+    ("teal", Rgb([0x00, 0x80, 0x80])), ("thistle", Rgb([0xd8, 0xbf, 0xd8])), ("tomato", Rgb([0xff, 0x63, 0x47])),
+    ("turquoise", Rgb([0x40, 0xe0, 0xd0])), ("violet", Rgb([0xee, 0x82, 0xee])), ("wheat", Rgb([0xf5, 0xde, 0xb3])),
+    ("white", Rgb([0xff, 0xff, 0xff])), ("whitesmoke", Rgb([0xf5, 0xf5, 0xf5])), ("yellow", Rgb([0xff, 0xff, 0x00])),
+    ("yellowgreen", Rgb([0x9a, 0xcd, 0x32])),
+];
+
+/// Parses an opaque color given as a CSS color name such as `navy`, in any
+/// case, or as a hex value, see [`parse_rgba_color`]. A hex value with an
+/// alpha channel must have it at `ff`.
+pub fn parse_color(s: &str) -> Result<Rgb<u8>, QrGenError> {
+    let Rgba([r, g, b, alpha]) = parse_rgba_color(s)?;
+    if alpha != 255 {
+        return Err(QrGenError::TranslucentColor(s.to_string()));
+    }
+    Ok(Rgb([r, g, b]))
+}
+
+/// Parses a color given as a CSS color name such as `navy`, in any case,
+/// which is opaque, or as a hex value `#rrggbb` or `#rrggbbaa` (the leading
+/// `#` is optional), which is opaque unless it gives an alpha.
+pub fn parse_rgba_color(s: &str) -> Result<Rgba<u8>, QrGenError> {
+    let name = s.to_ascii_lowercase();
+    if let Ok(i) = NAMED_COLORS.binary_search_by_key(&name.as_str(), |&(name, _)| name) {
+        let [r, g, b] = NAMED_COLORS[i].1.0;
+        return Ok(Rgba([r, g, b, 255]));
+    }
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        // Anything that could not be hex is taken for a misspelt name
+        return match s.starts_with('#') {
+            true => Err(QrGenError::InvalidColor(s.to_string())),
+            false => Err(QrGenError::UnknownColorName(s.to_string())),
+        };
+    }
+    if hex.len() != 6 && hex.len() != 8 {
+        return Err(QrGenError::InvalidColor(s.to_string()));
+    }
+
+    let channel = |i: usize| hex.get(i..i + 2).map_or(255, |digits| u8::from_str_radix(digits, 16).unwrap());
+    Ok(Rgba([channel(0), channel(2), channel(4), channel(6)]))
+}
+
+/// Parses a hex color such as `#1a2b3c` (the leading `#` is optional).
+pub fn parse_hex_color(s: &str) -> Result<Rgb<u8>, QrGenError> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(QrGenError::InvalidColor(s.to_string()));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok(Rgb([channel(0), channel(2), channel(4)]))
+}
+
+/// Formats `color` as a lowercase `#rrggbb` string.
+pub fn to_hex(color: Rgb<u8>) -> String {
+    let [r, g, b] = color.0;
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Composites `color` at opacity `alpha` (`0.0..=1.0`) over `dst` using
+/// source-over blending.
+pub(crate) fn blend_over(dst: Rgba<u8>, color: Rgb<u8>, alpha: f64) -> Rgba<u8> {
+    let src_a = alpha.clamp(0.0, 1.0);
+    let dst_a = dst[3] as f64 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let mix = |s: u8, d: u8| ((s as f64 * src_a + d as f64 * dst_a * (1.0 - src_a)) / out_a).round() as u8;
+    Rgba([
+        mix(color[0], dst[0]),
+        mix(color[1], dst[1]),
+        mix(color[2], dst[2]),
+        (out_a * 255.0).round() as u8,
+    ])
+}
+
+/// Relative luminance of `color` as defined by WCAG 2, in `0.0..=1.0`.
+pub fn relative_luminance(color: Rgb<u8>) -> f64 {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    let [r, g, b] = color.0;
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// WCAG contrast ratio between two colors, from 1.0 (identical) to 21.0
+/// (black on white).
+pub fn contrast_ratio(a: Rgb<u8>, b: Rgb<u8>) -> f64 {
+    luminance_contrast(relative_luminance(a), relative_luminance(b))
+}
+
+/// WCAG contrast ratio between two relative luminances.
+pub(crate) fn luminance_contrast(a: f64, b: f64) -> f64 {
+    let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#1a2b3c").unwrap(), Rgb([0x1a, 0x2b, 0x3c]));
+        assert_eq!(parse_hex_color("FFFFFF").unwrap(), Rgb([255, 255, 255]));
+        assert!(matches!(parse_hex_color("#12345"), Err(QrGenError::InvalidColor(_))));
+        assert!(matches!(parse_hex_color("#gggggg"), Err(QrGenError::InvalidColor(_))));
+    }
+
+    #[test]
+    fn test_parse_color_names() {
+        assert_eq!(to_hex(parse_color("navy").unwrap()), "#000080");
+        assert_eq!(parse_color("Crimson").unwrap(), Rgb([0xdc, 0x14, 0x3c]));
+        assert_eq!(parse_color("#1a2b3c").unwrap(), Rgb([0x1a, 0x2b, 0x3c]));
+        // Six hex digits are hex even without the #, never a name
+        assert_eq!(parse_color("beaded").unwrap(), Rgb([0xbe, 0xad, 0xed]));
+        assert!(matches!(parse_color("navvy"), Err(QrGenError::UnknownColorName(_))));
+        assert!(matches!(parse_color("#12345"), Err(QrGenError::InvalidColor(_))));
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_parse_rgba_color() {
+        assert_eq!(parse_rgba_color("#ffffff80").unwrap(), Rgba([255, 255, 255, 128]));
+        assert_eq!(parse_rgba_color("1a2b3c").unwrap(), Rgba([0x1a, 0x2b, 0x3c, 255]));
+        assert_eq!(parse_rgba_color("navy").unwrap(), Rgba([0, 0, 0x80, 255]));
+        assert!(matches!(parse_rgba_color("#ffffff8"), Err(QrGenError::InvalidColor(_))));
+        assert!(matches!(parse_rgba_color("#ffffffzz"), Err(QrGenError::InvalidColor(_))));
+
+        // Opaque colors may spell out their alpha, others are refused
+        assert_eq!(parse_color("#1a2b3cff").unwrap(), Rgb([0x1a, 0x2b, 0x3c]));
+        assert!(matches!(parse_color("#ffffff80"), Err(QrGenError::TranslucentColor(_))));
+    }
+
+    #[test]
+    fn test_to_hex_round_trip() {
+        let color = Rgb([0x1a, 0x2b, 0x3c]);
+        assert_eq!(to_hex(color), "#1a2b3c");
+        assert_eq!(parse_hex_color(&to_hex(color)).unwrap(), color);
+    }
+
+    #[test]
+    fn test_blend_over() {
+        let white = Rgb([255, 255, 255]);
+        assert_eq!(blend_over(Rgba([0, 0, 0, 255]), white, 1.0), Rgba([255, 255, 255, 255]));
+        assert_eq!(blend_over(Rgba([0, 0, 0, 255]), white, 0.5), Rgba([128, 128, 128, 255]));
+        // Over a fully transparent pixel the color is kept and only alpha changes
+        assert_eq!(blend_over(Rgba([0, 0, 0, 0]), white, 0.5), Rgba([255, 255, 255, 128]));
+        assert_eq!(blend_over(Rgba([0, 0, 0, 0]), white, 0.0), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        let black = Rgb([0, 0, 0]);
+        let white = Rgb([255, 255, 255]);
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(white, black) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(white, white) - 1.0).abs() < 0.01);
+    }
+}
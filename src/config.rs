@@ -0,0 +1,212 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use image::Rgb;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+use crate::{
+    parse_color, EcLevel, EyeStyle, GradientDirection, GradientType, IconAnchor, IconFilter, IconFit, IconPadding, IconShape, ModuleStyle, QrGenError, QrOptions,
+    PayloadEncoding, Rotation,
+};
+
+/// Name of the config file looked for in the current directory when none is
+/// given explicitly.
+pub const DEFAULT_CONFIG_FILE: &str = "qr-generator.toml";
+
+/// Reads `QrOptions` from the TOML file at `path`, see [`parse_config`].
+pub fn load_config<P: AsRef<Path>>(path: P) -> Result<QrOptions, QrGenError> {
+    parse_config(&fs::read_to_string(path)?)
+}
+
+/// Parses `QrOptions` from TOML.
+///
+/// Keys are the `QrOptions` field names, and any key left out keeps its
+/// default. Colors are hex strings or CSS color names, enums are the names the command line
+/// accepts, and `icon_padding` is a number of pixels or a string like
+/// `"10%"`:
+///
+/// ```toml
+/// size = 800
+/// foreground = "#1a2b3c"
+/// icon_padding = "10%"
+/// gradient = { start = "#ff0000", end = "#0000ff", direction = "vertical" }
+/// ```
+pub fn parse_config(text: &str) -> Result<QrOptions, QrGenError> {
+    Ok(toml::from_str(text)?)
+}
+
+/// Environment variables [`apply_env`] reads, each standing for the
+/// `QrOptions` field of the same name without the prefix.
+pub const ENV_VARS: [&str; 6] = ["QRGEN_SIZE", "QRGEN_MARGIN", "QRGEN_EC_LEVEL", "QRGEN_FOREGROUND", "QRGEN_BACKGROUND", "QRGEN_ICON_SCALE"];
+
+/// Overrides `opts` with the [`ENV_VARS`] that `var` finds set, looking each
+/// one up by name; pass `|name| std::env::var(name).ok()` to read the
+/// process environment.
+///
+/// Values are written as on the command line, and one that is empty counts
+/// as unset. The command line applies them between the config file and
+/// its flags, so the flags override the environment, which overrides the
+/// config file.
+pub fn apply_env(mut opts: QrOptions, var: impl Fn(&str) -> Option<String>) -> Result<QrOptions, QrGenError> {
+    let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+    if let Some(size) = env_value(var, "QRGEN_SIZE", str::parse)? {
+        opts.size = size;
+    }
+    if let Some(margin) = env_value(var, "QRGEN_MARGIN", str::parse)? {
+        opts.margin = margin;
+    }
+    opts.ec_level = env_value(var, "QRGEN_EC_LEVEL", str::parse)?.or(opts.ec_level);
+    if let Some(foreground) = env_value(var, "QRGEN_FOREGROUND", parse_color)? {
+        opts.foreground = foreground;
+    }
+    if let Some(background) = env_value(var, "QRGEN_BACKGROUND", parse_color)? {
+        opts.background = background;
+    }
+    if let Some(icon_scale) = env_value(var, "QRGEN_ICON_SCALE", str::parse)? {
+        opts.icon_scale = icon_scale;
+    }
+    Ok(opts)
+}
+
+/// Parses variable `name`, if `var` finds it set, with `parse`.
+fn env_value<T, E: fmt::Display>(
+    var: impl Fn(&'static str) -> Option<String>,
+    name: &'static str,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<Option<T>, QrGenError> {
+    var(name)
+        .map(|value| parse(value.trim()).map_err(|error| QrGenError::InvalidEnvVar { name, value, error: error.to_string() }))
+        .transpose()
+}
+
+/// Deserializes each type from a string with its `FromStr` implementation.
+macro_rules! deserialize_from_str {
+    ($($ty:ty),*) => {$(
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+            }
+        }
+    )*};
+}
+
+deserialize_from_str!(
+    EcLevel,
+    IconShape,
+    IconFit,
+    IconAnchor,
+    IconFilter,
+    ModuleStyle,
+    EyeStyle,
+    GradientDirection,
+    GradientType,
+    PayloadEncoding,
+    Rotation
+);
+
+impl<'de> Deserialize<'de> for IconPadding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Pixels(u32),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Pixels(px) => Ok(IconPadding::Pixels(px)),
+            Raw::Text(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Deserializes a color string such as `"#1a2b3c"` or `"navy"`.
+pub(crate) fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rgb<u8>, D::Error> {
+    parse_color(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+/// Deserializes an optional color, for fields where leaving the key out
+/// means `None`.
+pub(crate) fn optional_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Rgb<u8>>, D::Error> {
+    color(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frame, Gradient};
+
+    #[test]
+    fn test_parse_config() {
+        let opts = parse_config(
+            r##"
+            size = 800
+            margin = 2
+            foreground = "#1a2b3c"
+            ec_level = "q"
+            icon_scale = 25
+            icon_padding = "10%"
+            module_style = "rounded"
+            gradient = { start = "#ff0000", end = "#0000ff" }
+            eye_color = "#cc0000"
+            eye_style = "circle"
+            frame = { text = "ORDER HERE", color = "#0055aa" }
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(opts.size, 800);
+        assert_eq!(opts.margin, 2);
+        assert_eq!(opts.foreground, Rgb([0x1a, 0x2b, 0x3c]));
+        assert_eq!(opts.ec_level, Some(EcLevel::Q));
+        assert_eq!(opts.icon_scale, 25.0);
+        assert_eq!(opts.icon_padding, IconPadding::Percent(10.0));
+        assert_eq!(opts.module_style, ModuleStyle::Rounded);
+        let gradient = Gradient {
+            start: Rgb([255, 0, 0]),
+            end: Rgb([0, 0, 255]),
+            direction: GradientDirection::Diagonal,
+            kind: GradientType::Linear,
+        };
+        assert_eq!(opts.gradient, Some(gradient));
+        assert_eq!(opts.eye_color, Some(Rgb([0xcc, 0, 0])));
+        assert_eq!(opts.eye_style, Some(EyeStyle::Circle));
+        let frame = Frame { text: "ORDER HERE".to_string(), color: Rgb([0, 0x55, 0xaa]), ..Frame::default() };
+        assert_eq!(opts.frame, Some(frame));
+        // Keys left out keep their defaults
+        assert_eq!(opts.background, Rgb([255, 255, 255]));
+        assert_eq!(opts.quality, QrOptions::default().quality);
+
+        assert_eq!(parse_config("icon_padding = 8").unwrap().icon_padding, IconPadding::Pixels(8));
+        assert_eq!(parse_config("foreground = \"navy\"").unwrap().foreground, Rgb([0, 0, 0x80]));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(matches!(parse_config("sise = 800"), Err(QrGenError::Config(_))));
+        assert!(matches!(parse_config("foreground = \"navvy\""), Err(QrGenError::Config(_))));
+        assert!(matches!(parse_config("ec_level = \"X\""), Err(QrGenError::Config(_))));
+        assert!(matches!(load_config("does/not/exist.toml"), Err(QrGenError::Io(_))));
+    }
+
+    #[test]
+    fn test_apply_env() {
+        fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+            move |name| vars.iter().find(|&&(var, _)| var == name).map(|&(_, value)| value.to_string())
+        }
+        let base = parse_config("size = 800\nmargin = 2").unwrap();
+        let vars = [("QRGEN_SIZE", "600"), ("QRGEN_EC_LEVEL", "q"), ("QRGEN_FOREGROUND", "navy"), ("QRGEN_ICON_SCALE", " 25 ")];
+        let opts = apply_env(base.clone(), env(&vars)).unwrap();
+        assert_eq!((opts.size, opts.margin, opts.ec_level), (600, 2, Some(EcLevel::Q)));
+        assert_eq!((opts.foreground, opts.icon_scale), (Rgb([0, 0, 0x80]), 25.0));
+
+        // Empty variables are unset, and bad ones name themselves
+        assert_eq!(apply_env(base.clone(), env(&[("QRGEN_SIZE", "")])).unwrap().size, 800);
+        assert!(matches!(
+            apply_env(base, env(&[("QRGEN_BACKGROUND", "navvy")])),
+            Err(QrGenError::InvalidEnvVar { name: "QRGEN_BACKGROUND", .. })
+        ));
+    }
+}
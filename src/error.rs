@@ -0,0 +1,341 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use image::ImageError;
+use qrcode::types::QrError;
+
+use crate::{DataMode, EcLevel};
+
+/// Errors produced while generating a QR code.
+#[derive(Debug)]
+pub enum QrGenError {
+    /// The icon file does not exist.
+    IconNotFound(PathBuf),
+    /// The icon is a URL, and downloading it was not allowed.
+    NetworkDisabled(String),
+    /// Downloading the icon at the URL failed.
+    Download { url: String, error: Box<ureq::Error> },
+    /// The icon at the URL is larger than `limit` bytes.
+    DownloadTooLarge { url: String, limit: u64 },
+    /// The payload could not be encoded as a QR code.
+    QrEncode(QrError),
+    /// The requested QR version is outside 1 to 40.
+    InvalidVersion(u8),
+    /// The requested mask pattern is outside 0 to 7.
+    InvalidMask(u8),
+    /// The payload does not fit in the requested QR version, or in any
+    /// version when none was requested. `length` and `max` count in `mode`'s
+    /// units, see [`DataMode::length`].
+    PayloadTooLong { length: usize, max: usize, mode: DataMode, version: u8, ec_level: EcLevel },
+    /// The payload does not fit in `parts` Structured Append codes of
+    /// `version`. `length` counts bytes.
+    TooLongToSplit { length: usize, parts: usize, version: u8, ec_level: EcLevel },
+    /// The payload does not fit in a Micro QR code, M4 included, at
+    /// `ec_level`. `length` counts bytes.
+    TooLongForMicro { length: usize, ec_level: EcLevel },
+    /// Micro QR codes were requested together with something they cannot
+    /// carry, such as an icon.
+    MicroUnsupported(&'static str),
+    /// The background image file does not exist.
+    BackgroundImageNotFound(PathBuf),
+    /// The icon or background image could not be decoded.
+    ImageDecode(ImageError),
+    /// The requested icon frame is past the last of the icon's `frames`.
+    IconFrameOutOfRange { frame: usize, frames: usize },
+    /// An SVG icon could not be parsed.
+    SvgDecode(resvg::usvg::Error),
+    /// The final image could not be encoded.
+    ImageEncode(ImageError),
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// The output path has an extension we cannot write.
+    UnsupportedOutputFormat(String),
+    /// The output file already exists and was not to be overwritten.
+    OutputExists(PathBuf),
+    /// The requested image size cannot fit one pixel per module.
+    SizeTooSmall { size: u32, min: u32 },
+    /// The image size is over `QrOptions::max_dimension`.
+    SizeTooLarge { size: u32, max: u32 },
+    /// A color string could not be parsed.
+    InvalidColor(String),
+    /// A `QRGEN_*` environment variable holds a value that does not parse.
+    InvalidEnvVar { name: &'static str, value: String, error: String },
+    /// A color name is not one of the CSS named colors.
+    UnknownColorName(String),
+    /// A color with an alpha channel was given where only opaque colors go.
+    TranslucentColor(String),
+    /// The foreground and background colors are too similar to scan.
+    LowContrast { ratio: f64, min: f64 },
+    /// The icon scale percentage is outside the accepted range.
+    InvalidIconScale(f64),
+    /// The caption font size is not a positive number of pixels.
+    InvalidCaptionSize(f64),
+    /// The icon and its backing hide a larger fraction of the modules than
+    /// `ec_level` can recover, and `strict` was set.
+    IconCoverageTooHigh { hidden: f64, ec_level: EcLevel },
+    /// The icon or its backing reaches the quiet zone or a finder pattern,
+    /// with `QrOptions::forbid_icon_overlap` set. Holds which.
+    IconOverlap(&'static str),
+    /// The module gap is outside the accepted range.
+    InvalidModuleGap(f64),
+    /// A batch file could not be read as CSV.
+    Csv(csv::Error),
+    /// The generated image did not decode back to the payload. Holds what
+    /// was decoded instead, if anything.
+    VerificationFailed(Option<String>),
+    /// A scanned image holds no QR code that could be read.
+    NoCodeFound,
+    /// No error correction level and icon scale produced a code that decodes.
+    NoScannableCombination,
+    /// A batch file has no column with this header, or a row leaves it empty.
+    MissingColumn(&'static str),
+    /// A config file is not valid TOML or does not describe `QrOptions`.
+    Config(toml::de::Error),
+    /// A matrix file is not valid JSON or lacks the `width` and `modules`.
+    MatrixJson(serde_json::Error),
+    /// A matrix is `width` modules across, which no QR version is.
+    InvalidMatrixWidth(u32),
+    /// A matrix's rows do not form a `width` square: there are `rows` of
+    /// them, or one of `columns` modules.
+    MatrixShape { width: u32, rows: usize, columns: usize },
+    /// A matrix states a `version` other than the one its `width` implies.
+    MatrixVersionMismatch { version: u8, width: u32 },
+}
+
+impl fmt::Display for QrGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QrGenError::IconNotFound(path) => write!(f, "Icon file not found: {}", path.display()),
+            QrGenError::NetworkDisabled(url) => {
+                write!(f, "Icon {} is a URL, and downloads are off; pass --allow-network to fetch it", url)
+            }
+            QrGenError::Download { url, error } => write!(f, "Failed to download icon {}: {}", url, error),
+            QrGenError::DownloadTooLarge { url, limit } => {
+                write!(f, "Icon {} is larger than the {} MiB download limit", url, limit / (1024 * 1024))
+            }
+            QrGenError::QrEncode(e) => write!(f, "Failed to encode QR code: {}", e),
+            QrGenError::InvalidVersion(version) => {
+                write!(f, "QR version {} is out of range; expected 1 to {}", version, crate::options::MAX_VERSION)
+            }
+            QrGenError::InvalidMask(mask) => {
+                write!(f, "Mask pattern {} is out of range; expected 0 to {}", mask, crate::options::MAX_MASK)
+            }
+            QrGenError::PayloadTooLong { length, max, mode, version, ec_level } => {
+                // Mixing modes can fit slightly more than `max`, so anything
+                // rejected is at least one over
+                let units = mode.units();
+                let excess = length.saturating_sub(*max).max(1);
+                write!(
+                    f,
+                    "Payload is {} {} long, {} over the {} {} that fit in {} mode at QR version {} and error correction {}; ",
+                    length, units, excess, max, units, mode, version, ec_level
+                )?;
+                if *version < crate::options::MAX_VERSION {
+                    write!(f, "use a higher --qr-version, a lower --ec-level or a shorter payload")
+                } else {
+                    write!(f, "use a lower --ec-level or a shorter payload")
+                }
+            }
+            QrGenError::TooLongToSplit { length, parts, version, ec_level } => {
+                write!(
+                    f,
+                    "Payload is {} bytes long, too long to split over {} Structured Append codes at QR version {} and error correction {}; ",
+                    length, parts, version, ec_level
+                )?;
+                if *version < crate::options::MAX_VERSION {
+                    write!(f, "use a higher --qr-version, a lower --ec-level or a shorter payload")
+                } else {
+                    write!(f, "use a lower --ec-level or a shorter payload")
+                }
+            }
+            QrGenError::BackgroundImageNotFound(path) => write!(f, "Background image not found: {}", path.display()),
+            QrGenError::ImageDecode(e) => write!(f, "Failed to decode image: {}", e),
+            QrGenError::IconFrameOutOfRange { frame, frames } => write!(
+                f,
+                "Icon frame {} does not exist; the icon has {} frame{}, so use an --icon-frame below {}",
+                frame,
+                frames,
+                if *frames == 1 { "" } else { "s" },
+                frames
+            ),
+            QrGenError::SvgDecode(e) => write!(f, "Failed to decode SVG icon: {}", e),
+            QrGenError::ImageEncode(e) => write!(f, "Failed to encode image: {}", e),
+            QrGenError::Io(e) => write!(f, "I/O error: {}", e),
+            QrGenError::UnsupportedOutputFormat(ext) => write!(f, "Unsupported output format: {}", ext),
+            QrGenError::SizeTooSmall { size, min } => write!(
+                f,
+                "Image size {}px is too small for this QR code; use a --size of at least {}px",
+                size, min
+            ),
+            QrGenError::SizeTooLarge { size, max } => write!(
+                f,
+                "Image size {}px is over the {}px limit; use a smaller --size, raise --max-dimension, \
+                 or pass --clamp to render at the limit",
+                size, max
+            ),
+            QrGenError::InvalidColor(s) => write!(f, "Invalid color '{}', expected a hex value like #1a2b3c", s),
+            QrGenError::InvalidEnvVar { name, value, error } => {
+                write!(f, "Environment variable {}='{}' is invalid ({}); correct or unset it", name, value, error)
+            }
+            QrGenError::TranslucentColor(s) => write!(
+                f,
+                "Color '{}' is not fully opaque, which only the background allows (--bg, or background_alpha in a config file); \
+                 drop the alpha digits or end them in ff",
+                s
+            ),
+            QrGenError::UnknownColorName(s) => {
+                write!(f, "Unknown color name '{}', expected a CSS color name like navy or a hex value like #1a2b3c", s)
+            }
+            QrGenError::LowContrast { ratio, min } => write!(
+                f,
+                "Foreground/background contrast ratio {:.2} is below {:.1} and may not scan",
+                ratio, min
+            ),
+            QrGenError::InvalidIconScale(scale) => write!(
+                f,
+                "Icon scale {}% is out of range; expected more than 0% and at most {}%",
+                scale,
+                crate::options::MAX_ICON_SCALE
+            ),
+            QrGenError::InvalidCaptionSize(size) => {
+                write!(f, "Caption size {} is invalid; expected a positive number of pixels", size)
+            }
+            QrGenError::InvalidModuleGap(gap) => write!(
+                f,
+                "Module gap {} is out of range; expected 0 to {} of the module size",
+                gap,
+                crate::options::MAX_MODULE_GAP
+            ),
+            QrGenError::IconOverlap(region) => write!(
+                f,
+                "The icon or its backing covers {}, which scanners need clear to find the code; use a smaller \
+                 --icon-scale or less --icon-padding, or move it with --icon-anchor",
+                region
+            ),
+            QrGenError::IconCoverageTooHigh { hidden, ec_level } => write!(
+                f,
+                "The icon hides {:.1}% of the modules, more than the ~{:.0}% error correction level {} can recover; \
+                 use a smaller --icon-scale, less --icon-padding or a higher --ec-level",
+                hidden * 100.0,
+                ec_level.recoverable_fraction() * 100.0,
+                ec_level
+            ),
+            QrGenError::VerificationFailed(decoded) => {
+                match decoded {
+                    Some(decoded) => write!(f, "Generated QR code decodes to '{}' instead of the payload", decoded)?,
+                    None => write!(f, "Generated QR code could not be decoded")?,
+                }
+                write!(f, "; try a higher --ec-level or a smaller --icon-scale")
+            }
+            QrGenError::TooLongForMicro { length, ec_level } => write!(
+                f,
+                "Payload is {} bytes long, too long for a Micro QR code at error correction {}; use a lower --ec-level or leave out --micro",
+                length, ec_level
+            ),
+            QrGenError::MicroUnsupported(feature) => {
+                write!(f, "Micro QR codes cannot be used with {}; leave out --micro to make a standard code", feature)
+            }
+            QrGenError::NoCodeFound => write!(f, "No QR code could be read from the image; try a sharper or larger picture of it"),
+            QrGenError::NoScannableCombination => write!(
+                f,
+                "No error correction level and icon scale produced a scannable QR code; try a larger --size or a shorter payload"
+            ),
+            QrGenError::Csv(e) => write!(f, "Failed to read batch file: {}", e),
+            QrGenError::OutputExists(path) => {
+                write!(f, "Output file already exists: {}; pass --force to overwrite it", path.display())
+            }
+            QrGenError::MissingColumn(name) => write!(f, "Batch file is missing a value for the '{}' column", name),
+            QrGenError::Config(e) => write!(f, "Invalid config file: {}", e),
+            QrGenError::MatrixJson(e) => write!(f, "Invalid matrix file: {}", e),
+            QrGenError::InvalidMatrixWidth(width) => write!(
+                f,
+                "Matrix width {} is not a QR code size; expected 21 to {} modules, in steps of 4",
+                width,
+                17 + 4 * crate::options::MAX_VERSION as u32
+            ),
+            QrGenError::MatrixShape { width, rows, columns } => match *rows as u32 == *width {
+                true => write!(f, "Matrix is not square: a row has {} modules instead of its width of {}", columns, width),
+                false => write!(f, "Matrix is not square: it has {} rows instead of its width of {}", rows, width),
+            },
+            QrGenError::MatrixVersionMismatch { version, width } => write!(
+                f,
+                "Matrix states version {} but is {} modules across, which is version {}",
+                version,
+                width,
+                (width - 17) / 4
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QrGenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QrGenError::QrEncode(e) => Some(e),
+            QrGenError::ImageDecode(e) | QrGenError::ImageEncode(e) => Some(e),
+            QrGenError::SvgDecode(e) => Some(e),
+            QrGenError::Io(e) => Some(e),
+            QrGenError::Download { error, .. } => Some(error.as_ref()),
+            QrGenError::Csv(e) => Some(e),
+            QrGenError::Config(e) => Some(e),
+            QrGenError::MatrixJson(e) => Some(e),
+            QrGenError::IconNotFound(_)
+            | QrGenError::NetworkDisabled(_)
+            | QrGenError::DownloadTooLarge { .. }
+            | QrGenError::BackgroundImageNotFound(_)
+            | QrGenError::InvalidVersion(_)
+            | QrGenError::InvalidMask(_)
+            | QrGenError::IconFrameOutOfRange { .. }
+            | QrGenError::PayloadTooLong { .. }
+            | QrGenError::TooLongToSplit { .. }
+            | QrGenError::TooLongForMicro { .. }
+            | QrGenError::MicroUnsupported(_)
+            | QrGenError::UnsupportedOutputFormat(_)
+            | QrGenError::OutputExists(_)
+            | QrGenError::SizeTooSmall { .. }
+            | QrGenError::SizeTooLarge { .. }
+            | QrGenError::InvalidColor(_)
+            | QrGenError::InvalidEnvVar { .. }
+            | QrGenError::UnknownColorName(_)
+            | QrGenError::TranslucentColor(_)
+            | QrGenError::LowContrast { .. }
+            | QrGenError::InvalidIconScale(_)
+            | QrGenError::InvalidCaptionSize(_)
+            | QrGenError::InvalidModuleGap(_)
+            | QrGenError::IconCoverageTooHigh { .. }
+            | QrGenError::IconOverlap(_)
+            | QrGenError::VerificationFailed(_)
+            | QrGenError::NoCodeFound
+            | QrGenError::NoScannableCombination
+            | QrGenError::MissingColumn(_)
+            | QrGenError::InvalidMatrixWidth(_)
+            | QrGenError::MatrixShape { .. }
+            | QrGenError::MatrixVersionMismatch { .. } => None,
+        }
+    }
+}
+
+impl From<QrError> for QrGenError {
+    fn from(e: QrError) -> Self {
+        QrGenError::QrEncode(e)
+    }
+}
+
+impl From<csv::Error> for QrGenError {
+    fn from(e: csv::Error) -> Self {
+        QrGenError::Csv(e)
+    }
+}
+
+impl From<toml::de::Error> for QrGenError {
+    fn from(e: toml::de::Error) -> Self {
+        QrGenError::Config(e)
+    }
+}
+
+impl From<io::Error> for QrGenError {
+    fn from(e: io::Error) -> Self {
+        QrGenError::Io(e)
+    }
+}
@@ -0,0 +1,130 @@
+use image::Rgb;
+
+use crate::code::MAX_MICRO_WIDTH;
+use crate::render::{module_color, Layout};
+use crate::{EyeStyle, QrOptions};
+
+/// Side length of a finder pattern, in modules, not counting the light
+/// separator around it.
+pub(crate) const FINDER_SIZE: u32 = 7;
+
+/// Top-left module of each finder pattern in a matrix `qr_width` modules
+/// wide, in the top-left, top-right and bottom-left corners, or only the
+/// top-left one for a Micro QR code.
+pub(crate) fn finder_origins(qr_width: u32) -> Vec<(u32, u32)> {
+    if is_micro(qr_width) {
+        return vec![(0, 0)];
+    }
+    let far = qr_width - FINDER_SIZE;
+    vec![(0, 0), (far, 0), (0, far)]
+}
+
+/// Whether a matrix `qr_width` modules wide is a Micro QR code.
+fn is_micro(qr_width: u32) -> bool {
+    qr_width <= MAX_MICRO_WIDTH as u32
+}
+
+/// Whether the module at column `x`, row `y` belongs to a finder pattern.
+/// The separator around each pattern does not.
+pub(crate) fn is_finder_module(x: u32, y: u32, qr_width: u32) -> bool {
+    finder_origins(qr_width)
+        .iter()
+        .any(|&(ox, oy)| (ox..ox + FINDER_SIZE).contains(&x) && (oy..oy + FINDER_SIZE).contains(&y))
+}
+
+/// Row and column of the two timing patterns.
+const TIMING_LINE: u32 = 6;
+
+/// Whether the module at column `x`, row `y` belongs to a timing pattern:
+/// row or column 6, between the separators of the finder patterns, so it
+/// never overlaps an eye. A Micro QR code has its timing patterns along
+/// the top and left edges instead, from the separator to the far side.
+pub(crate) fn is_timing_module(x: u32, y: u32, qr_width: u32) -> bool {
+    if is_micro(qr_width) {
+        let beyond = FINDER_SIZE + 1..qr_width;
+        return (y == 0 && beyond.contains(&x)) || (x == 0 && beyond.contains(&y));
+    }
+    let between = FINDER_SIZE + 1..qr_width - FINDER_SIZE - 1;
+    (y == TIMING_LINE && between.contains(&x)) || (x == TIMING_LINE && between.contains(&y))
+}
+
+/// Whether the eyes are drawn apart from the data modules, in their own
+/// color or shape.
+pub(crate) fn styled(opts: &QrOptions) -> bool {
+    opts.eye_color.is_some() || opts.eye_style.is_some()
+}
+
+/// Color of the eye whose top-left corner is at `(x, y)`, taken from the
+/// module coloring at its center when `opts.eye_color` is unset.
+pub(crate) fn eye_color(opts: &QrOptions, layout: &Layout, x: u32, y: u32) -> Rgb<u8> {
+    let center = (FINDER_SIZE / 2) * layout.module_size;
+    opts.eye_color.unwrap_or_else(|| module_color(opts, layout, x + center, y + center))
+}
+
+/// A square with rounded corners making up part of an eye, in pixels from
+/// the eye's top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct EyeRect {
+    /// Distance from the eye's top and left edges.
+    pub inset: f64,
+    pub size: f64,
+    pub radius: f64,
+}
+
+/// The outer square of an eye, the hole cut out of it to leave a one-module
+/// ring, and the 3x3 center, for modules `module_size` pixels wide.
+pub(crate) fn eye_rects(style: EyeStyle, module_size: u32, corner_radius: f64) -> [EyeRect; 3] {
+    let module = module_size as f64;
+    let radius = |size: f64| match style {
+        EyeStyle::Square => 0.0,
+        EyeStyle::Rounded => corner_radius.clamp(0.0, 0.5) * size,
+        EyeStyle::Circle => size / 2.0,
+    };
+
+    let outer = EyeRect { inset: 0.0, size: 7.0 * module, radius: radius(7.0 * module) };
+    // Follow the outer corners so the ring keeps its width all the way round
+    let hole = EyeRect { inset: module, size: 5.0 * module, radius: (outer.radius - module).max(0.0) };
+    let center = EyeRect { inset: 2.0 * module, size: 3.0 * module, radius: radius(3.0 * module) };
+    [outer, hole, center]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finder_modules() {
+        // Version 1 is 21 modules wide
+        assert_eq!(finder_origins(21), [(0, 0), (14, 0), (0, 14)]);
+        for (x, y) in [(0, 0), (6, 6), (14, 0), (20, 6), (0, 20), (6, 14), (3, 3)] {
+            assert!(is_finder_module(x, y, 21), "({}, {})", x, y);
+        }
+        // Separators, timing patterns and the rest of the matrix
+        for (x, y) in [(7, 0), (0, 7), (13, 0), (14, 7), (7, 14), (6, 13), (8, 6), (20, 20), (10, 10)] {
+            assert!(!is_finder_module(x, y, 21), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_micro_finder_and_timing() {
+        // M2 is 13 modules wide, with one eye and timing along the edges
+        assert_eq!(finder_origins(13), [(0, 0)]);
+        assert!(is_finder_module(6, 6, 13) && !is_finder_module(12, 0, 13) && !is_finder_module(0, 12, 13));
+        assert!(is_timing_module(8, 0, 13) && is_timing_module(12, 0, 13) && is_timing_module(0, 12, 13));
+        assert!(!is_timing_module(7, 0, 13) && !is_timing_module(6, 8, 13));
+    }
+
+    #[test]
+    fn test_eye_rects() {
+        let [outer, hole, center] = eye_rects(EyeStyle::Square, 10, 0.25);
+        assert_eq!((outer.inset, outer.size, outer.radius), (0.0, 70.0, 0.0));
+        assert_eq!((hole.inset, hole.size, hole.radius), (10.0, 50.0, 0.0));
+        assert_eq!((center.inset, center.size, center.radius), (20.0, 30.0, 0.0));
+
+        let [outer, hole, center] = eye_rects(EyeStyle::Circle, 10, 0.25);
+        assert_eq!((outer.radius, hole.radius, center.radius), (35.0, 25.0, 15.0));
+
+        let [outer, hole, center] = eye_rects(EyeStyle::Rounded, 10, 0.25);
+        assert_eq!((outer.radius, hole.radius, center.radius), (17.5, 7.5, 7.5));
+    }
+}
@@ -0,0 +1,104 @@
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::FilterType;
+use image::{DynamicImage, ExtendedColorType};
+
+use crate::{generate, symbol_info, QrGenError, QrOptions};
+
+/// Sides in pixels of the images packed into an ICO favicon.
+pub const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+
+/// Renders `url` with `opts` at each of [`FAVICON_SIZES`], in place of
+/// `opts.size`, and packs them into one ICO file.
+///
+/// A size with fewer pixels than the code has modules is rendered at one
+/// pixel per module and scaled down, which keeps its look but not its
+/// modules, see [`favicon_warning`]. `opts.verify` is left to the caller,
+/// since none of the sizes is large enough to decode reliably.
+pub(crate) fn encode_favicon(url: &[u8], opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    pack_favicon(module_span(url, opts)?, opts, |opts| generate(url, opts))
+}
+
+/// Renders a code `min_size` modules across, quiet zone included, with
+/// `render` at each of [`FAVICON_SIZES`] as [`encode_favicon`] does, and
+/// packs the images into one ICO file.
+///
+/// `render` is given `opts` with the size to render at and `verify` unset.
+pub(crate) fn pack_favicon(
+    min_size: u32,
+    opts: &QrOptions,
+    render: impl Fn(&QrOptions) -> Result<DynamicImage, QrGenError>,
+) -> Result<Vec<u8>, QrGenError> {
+    let opts = QrOptions { verify: false, ..opts.clone() };
+
+    let frames = FAVICON_SIZES
+        .iter()
+        .map(|&size| {
+            let image = render(&QrOptions { size: size.max(min_size), ..opts.clone() })?;
+            // Captions and padding make the image larger than the code itself
+            let image = match image.width() > size || image.height() > size {
+                true => image.resize(size, size, FilterType::Triangle),
+                false => image,
+            };
+            let rgba = image.to_rgba8();
+            IcoFrame::as_png(rgba.as_raw(), rgba.width(), rgba.height(), ExtendedColorType::Rgba8).map_err(QrGenError::ImageEncode)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut bytes = Vec::new();
+    IcoEncoder::new(&mut bytes).encode_images(&frames).map_err(QrGenError::ImageEncode)?;
+    Ok(bytes)
+}
+
+/// Warns about the [`FAVICON_SIZES`] too small to give each module of the
+/// code for `url` a pixel, which will not scan and are decoration only.
+pub fn favicon_warning(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<Option<String>, QrGenError> {
+    Ok(small_sizes_warning(module_span(url.as_ref(), opts)?))
+}
+
+/// The warning [`favicon_warning`] gives for a code `min_size` modules
+/// across, quiet zone included.
+pub(crate) fn small_sizes_warning(min_size: u32) -> Option<String> {
+    let small: Vec<_> = FAVICON_SIZES.iter().filter(|&&size| size < min_size).map(|size| format!("{}px", size)).collect();
+    let (noun, pronoun) = match small.len() {
+        0 => return None,
+        1 => ("image is", "it"),
+        _ => ("images are", "them"),
+    };
+    Some(format!(
+        "the {} favicon {} narrower than the code's {} modules and will not scan; treat {} as decorative only",
+        small.join(" and "),
+        noun,
+        min_size,
+        pronoun
+    ))
+}
+
+/// Number of modules across the code for `url`, quiet zone included.
+fn module_span(url: &[u8], opts: &QrOptions) -> Result<u32, QrGenError> {
+    Ok(symbol_info(url, opts)?.width + 2 * opts.margin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_favicon_sizes() {
+        let ico = encode_favicon(b"https://example.com", &QrOptions::default()).unwrap();
+        // The ICONDIR header holds the image count, then one 16 byte entry
+        // per image starting with its width and height
+        assert_eq!(u16::from_le_bytes([ico[4], ico[5]]), 3);
+        let sizes: Vec<_> = (0..3).map(|i| (ico[6 + 16 * i], ico[7 + 16 * i])).collect();
+        assert_eq!(sizes, [(16, 16), (32, 32), (48, 48)]);
+    }
+
+    #[test]
+    fn test_favicon_warning() {
+        // 29 modules with the quiet zone: only 16px is too small
+        let warning = favicon_warning("https://a.io", &QrOptions::default()).unwrap().unwrap();
+        assert!(warning.starts_with("the 16px favicon image is narrower than the code's 29 modules"), "{}", warning);
+
+        let warning = favicon_warning("https://example.com", &QrOptions::default()).unwrap().unwrap();
+        assert!(warning.contains("16px and 32px favicon images are"), "{}", warning);
+    }
+}
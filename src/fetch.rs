@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::QrGenError;
+
+/// Longest an icon download may take, from connecting to the last byte.
+pub const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest icon that is downloaded, in bytes.
+pub const MAX_DOWNLOAD_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Icons downloaded so far, by URL, so one drawn at several sizes or on
+/// many batch rows is fetched once.
+static DOWNLOADS: LazyLock<Mutex<HashMap<String, Arc<[u8]>>>> = LazyLock::new(Mutex::default);
+
+/// The URL `path` holds, if it is an `http://` or `https://` one.
+pub(crate) fn as_url(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Downloads the icon at `url`, or reuses an earlier download of it.
+pub(crate) fn fetch(url: &str) -> Result<Arc<[u8]>, QrGenError> {
+    if let Some(body) = DOWNLOADS.lock().unwrap().get(url) {
+        return Ok(body.clone());
+    }
+    let body: Arc<[u8]> = download(url, MAX_DOWNLOAD_SIZE)?.into();
+    DOWNLOADS.lock().unwrap().insert(url.to_string(), body.clone());
+    Ok(body)
+}
+
+/// Downloads the body at `url`, failing past [`DOWNLOAD_TIMEOUT`] or
+/// `limit` bytes.
+fn download(url: &str, limit: u64) -> Result<Vec<u8>, QrGenError> {
+    let config = ureq::Agent::config_builder().timeout_global(Some(DOWNLOAD_TIMEOUT)).build();
+    let failed = |error| QrGenError::Download { url: url.to_string(), error: Box::new(error) };
+
+    log::debug!("Downloading {}", url);
+    let mut response = ureq::Agent::new_with_config(config).get(url).call().map_err(failed)?;
+    response.body_mut().with_config().limit(limit).read_to_vec().map_err(|error| match error {
+        ureq::Error::BodyExceedsLimit(_) => QrGenError::DownloadTooLarge { url: url.to_string(), limit },
+        error => failed(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::decode_payload;
+    use crate::{generate, QrOptions};
+    use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+    use std::io::{BufRead, BufReader, Cursor, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves `body` for every request on a local port, answering `requests`
+    /// of them, and returns the URL of `path` on it.
+    fn serve(body: Vec<u8>, requests: usize, path: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                stream.write_all(header.as_bytes()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        url
+    }
+
+    fn red_png() -> Vec<u8> {
+        let mut png = Vec::new();
+        let icon = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 40, Rgb([220, 0, 0])));
+        icon.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_icon_from_url() {
+        // Only the first load downloads; the icon is cached after that
+        let url = serve(red_png(), 1, "/logo.png");
+        let opts = QrOptions::default().with_icon(&url).with_allow_network(true);
+        let image = generate("https://example.com", &opts).unwrap();
+        assert_eq!(decode_payload(&image).as_deref(), Some("https://example.com"));
+        let center = image.to_rgb8()[(image.width() / 2, image.height() / 2)];
+        assert_eq!(center, Rgb([220, 0, 0]));
+        assert_eq!(generate("https://example.com", &opts).unwrap(), image);
+    }
+
+    #[test]
+    fn test_icon_url_needs_allow_network() {
+        let opts = QrOptions::default().with_icon("http://127.0.0.1:1/logo.png");
+        assert!(matches!(generate("https://example.com", &opts), Err(QrGenError::NetworkDisabled(_))));
+    }
+
+    #[test]
+    fn test_download_limit() {
+        let url = serve(red_png(), 1, "/big.png");
+        assert!(matches!(download(&url, 16), Err(QrGenError::DownloadTooLarge { limit: 16, .. })));
+    }
+}
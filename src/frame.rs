@@ -0,0 +1,174 @@
+use std::f64::consts::SQRT_2;
+
+use image::{Rgba, RgbaImage};
+
+use crate::caption::{draw_text, fitted_size, text_width, CAP_HEIGHT};
+use crate::color::blend_over;
+use crate::render::{coverage, rounded_rect_distance, Layout};
+use crate::{Frame, QrOptions};
+
+/// Border width, as a fraction of the canvas side.
+const BORDER_FRACTION: f64 = 0.04;
+
+/// Banner height, as a fraction of the canvas side.
+const BANNER_FRACTION: f64 = 0.2;
+
+/// Banner text size, as a fraction of the banner height.
+const TEXT_FRACTION: f64 = 0.5;
+
+/// Where the parts of a [`Frame`] sit around the canvas, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct FrameLayout {
+    /// Width of the border on the top, left and right, which is also how far
+    /// the canvas is inset.
+    pub border: u32,
+    /// Height of the banner below the canvas, on top of the border.
+    pub banner: u32,
+    /// Size of the framed image.
+    pub width: u32,
+    pub height: u32,
+    /// Radius of the frame's outer corners.
+    pub radius: f64,
+    /// Radius of the corners of the window the canvas shows through.
+    pub inner_radius: f64,
+    /// Font size of the banner text.
+    pub text_size: f64,
+    /// Horizontal position of the first glyph's origin.
+    pub text_x: f64,
+    /// Vertical position of the banner text's baseline.
+    pub baseline: f64,
+}
+
+impl FrameLayout {
+    /// Lays `frame` out around the canvas described by `layout`.
+    pub fn new(frame: &Frame, layout: &Layout) -> FrameLayout {
+        let size = layout.canvas_size;
+        let border = ((size as f64 * BORDER_FRACTION).round() as u32).max(1);
+        let banner = ((size as f64 * BANNER_FRACTION).round() as u32).max(1);
+        let width = size + 2 * border;
+        let height = size + border + banner;
+
+        // Round the window no further than the quiet zone, so the corners
+        // never cut into the finder patterns
+        let radius = frame.corner_radius.clamp(0.0, 0.5) * width as f64;
+        let max_inner_radius = layout.offset as f64 * SQRT_2 / (SQRT_2 - 1.0);
+        let inner_radius = (radius - border as f64).clamp(0.0, max_inner_radius);
+
+        let text_size = fitted_size(&frame.text, banner as f64 * TEXT_FRACTION, size as f64);
+        FrameLayout {
+            border,
+            banner,
+            width,
+            height,
+            radius,
+            inner_radius,
+            text_size,
+            text_x: (width as f64 - text_width(&frame.text, text_size)) / 2.0,
+            // Center the capitals vertically in the banner
+            baseline: (size + border) as f64 + banner as f64 / 2.0 + CAP_HEIGHT * text_size / 2.0,
+        }
+    }
+}
+
+/// Draws `frame` around `image`, the canvas described by `layout`, and
+/// returns the framed image.
+pub(crate) fn apply_frame(image: &RgbaImage, frame: &Frame, layout: &Layout, opts: &QrOptions) -> RgbaImage {
+    let placement = FrameLayout::new(frame, layout);
+    let background = opts.background_pixel();
+    let mut canvas = RgbaImage::from_pixel(placement.width, placement.height, background);
+
+    let (width, height) = (placement.width as f64, placement.height as f64);
+    let (inset, size) = (placement.border as f64, image.width() as f64);
+    for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+        // The frame is decorative, so its edges are always smoothed
+        let (cx, cy) = (x as f64 + 0.5, y as f64 + 0.5);
+        let outer = coverage(rounded_rect_distance(cx, cy, 0.0, 0.0, width, height, placement.radius), true);
+        if outer > 0.0 {
+            *pixel = blend_over(*pixel, frame.color, outer);
+        }
+
+        let window = coverage(rounded_rect_distance(cx, cy, inset, inset, size, size, placement.inner_radius), true);
+        let inside = x.checked_sub(placement.border).zip(y.checked_sub(placement.border));
+        if let Some((qx, qy)) = inside.filter(|&(qx, qy)| window > 0.0 && qx < image.width() && qy < image.height()) {
+            *pixel = mix(*pixel, *image.get_pixel(qx, qy), window);
+        }
+    }
+
+    draw_text(&mut canvas, &frame.text, placement.text_x, placement.baseline, placement.text_size, frame.text_color);
+    canvas
+}
+
+/// Interpolates every channel, alpha included, from `a` to `b` by `t`.
+fn mix(a: Rgba<u8>, b: Rgba<u8>, t: f64) -> Rgba<u8> {
+    Rgba(std::array::from_fn(|i| (a[i] as f64 + (b[i] as f64 - a[i] as f64) * t).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn framed(frame: &Frame, opts: &QrOptions) -> (RgbaImage, FrameLayout) {
+        let layout = Layout::new(25, opts).unwrap();
+        let image = RgbaImage::from_pixel(layout.canvas_size, layout.canvas_size, Rgba([255, 255, 255, 255]));
+        (apply_frame(&image, frame, &layout, opts), FrameLayout::new(frame, &layout))
+    }
+
+    #[test]
+    fn test_frame_layout() {
+        let opts = QrOptions { size: 400, ..QrOptions::default() };
+        let (img, placement) = framed(&Frame::default(), &opts);
+        assert_eq!((placement.border, placement.banner), (16, 80));
+        assert_eq!(img.dimensions(), (432, 496));
+
+        let blue = Rgba([0, 0, 200, 255]);
+        let frame = Frame { color: Rgb([0, 0, 200]), ..Frame::default() };
+        let (img, _) = framed(&frame, &opts);
+        // Border on three sides, banner below, canvas inset within
+        assert_eq!(*img.get_pixel(216, 5), blue);
+        assert_eq!(*img.get_pixel(5, 216), blue);
+        assert_eq!(*img.get_pixel(426, 216), blue);
+        assert_eq!(*img.get_pixel(5, 470), blue);
+        assert_eq!(*img.get_pixel(16, 216), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(216, 415), Rgba([255, 255, 255, 255]));
+        // Rounded outer corners show the background
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(431, 495), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_banner_text_is_centered() {
+        let opts = QrOptions::default();
+        let (img, placement) = framed(&Frame::default(), &opts);
+        let banner_top = img.height() - placement.banner;
+
+        // White text on the black banner
+        let inked: Vec<(u32, u32)> = img
+            .enumerate_pixels()
+            .filter(|&(_, y, p)| y >= banner_top && p[0] > 128)
+            // Skip the background showing through the rounded corners
+            .filter(|&(x, y, _)| x > 20 && x < img.width() - 20 && y < img.height() - 10)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!inked.is_empty());
+        let (min_x, max_x) = (inked.iter().map(|p| p.0).min().unwrap(), inked.iter().map(|p| p.0).max().unwrap());
+        let (min_y, max_y) = (inked.iter().map(|p| p.1).min().unwrap(), inked.iter().map(|p| p.1).max().unwrap());
+        assert!((min_x as i64 - (img.width() - 1 - max_x) as i64).abs() <= 3, "ink spans x {}..={}", min_x, max_x);
+        let center = banner_top as f64 + placement.banner as f64 / 2.0;
+        assert!(((min_y + max_y) as f64 / 2.0 - center).abs() <= 2.0, "ink spans y {}..={}", min_y, max_y);
+    }
+
+    #[test]
+    fn test_window_rounding_stays_in_quiet_zone() {
+        let opts = QrOptions { margin: 1, ..QrOptions::default() };
+        let layout = Layout::new(25, &opts).unwrap();
+        let frame = Frame { corner_radius: 0.5, ..Frame::default() };
+        let placement = FrameLayout::new(&frame, &layout);
+        assert!(placement.inner_radius < placement.radius - placement.border as f64);
+
+        // The corner of the matrix is still inside the window
+        let corner = (placement.border + layout.offset) as f64 + 0.5;
+        let (inset, size) = (placement.border as f64, layout.canvas_size as f64);
+        assert!(rounded_rect_distance(corner, corner, inset, inset, size, size, placement.inner_radius) < 0.0);
+    }
+}
@@ -0,0 +1,996 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{imageops::FilterType, AnimationDecoder, DynamicImage, Frames, ImageFormat, ImageReader, Rgb, Rgba, RgbaImage};
+use resvg::{tiny_skia, usvg};
+
+use crate::color::{blend_over, luminance_contrast, relative_luminance};
+use crate::eye::FINDER_SIZE;
+use crate::fetch;
+use crate::render::{coverage, fill_rect, rounded_rect_distance};
+use crate::render::Layout;
+use crate::shadow::{draw_shadow, Mask};
+use crate::{HaloBorder, IconAnchor, IconFilter, IconFit, IconShape, QrGenError, QrOptions};
+
+/// The part of the canvas an icon is placed within.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IconArea {
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    /// Edges of the area, in canvas pixels; `right` and `bottom` are
+    /// exclusive.
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    /// Side of the square the icon keeps clear of in the top-left, top-right
+    /// and bottom-left corners of the area, 0 for none.
+    pub reserved: u32,
+}
+
+impl IconArea {
+    /// The whole of a `width` x `height` canvas, with no corners to avoid.
+    pub fn canvas(width: u32, height: u32) -> IconArea {
+        IconArea { canvas_width: width, canvas_height: height, left: 0, top: 0, right: width, bottom: height, reserved: 0 }
+    }
+
+    /// The matrix laid out by `layout`, less each finder pattern with its
+    /// separator and the format information beside it.
+    pub fn matrix(layout: &Layout) -> IconArea {
+        let end = layout.offset + layout.matrix_size();
+        IconArea {
+            canvas_width: layout.canvas_size,
+            canvas_height: layout.canvas_size,
+            left: layout.offset,
+            top: layout.offset,
+            right: end,
+            bottom: end,
+            reserved: (FINDER_SIZE + 2) * layout.module_size,
+        }
+    }
+}
+
+/// Whether `opts` leaves the icon in the center of the canvas, where it is
+/// placed without regard to the finder patterns.
+pub(crate) fn icon_centered(opts: &QrOptions) -> bool {
+    opts.icon_anchor == IconAnchor::Center && opts.icon_offset == (0, 0)
+}
+
+/// Position of an icon and its backing rectangle on the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IconPlacement {
+    pub x: u32,
+    pub y: u32,
+    pub halo_x: u32,
+    pub halo_y: u32,
+    pub halo_width: u32,
+    pub halo_height: u32,
+}
+
+impl IconPlacement {
+    /// Centers an `icon_width` x `icon_height` icon on the canvas, with
+    /// `padding` pixels of backing on each side.
+    pub fn centered(canvas_width: u32, canvas_height: u32, icon_width: u32, icon_height: u32, padding: u32) -> IconPlacement {
+        let x = (canvas_width - icon_width) / 2;
+        let y = (canvas_height - icon_height) / 2;
+
+        IconPlacement {
+            x,
+            y,
+            halo_x: x.saturating_sub(padding),
+            halo_y: y.saturating_sub(padding),
+            halo_width: icon_width + 2 * padding,
+            halo_height: icon_height + 2 * padding,
+        }
+    }
+
+    /// Places an `icon_width` x `icon_height` icon with `padding` pixels of
+    /// backing at `opts.icon_anchor` in `area`, moved by `opts.icon_offset`.
+    ///
+    /// Unless it is left in the center, the backing is kept within the area,
+    /// or centered on it along a side it does not fit, and stepped
+    /// diagonally out of any reserved corner it would cover. Returns the
+    /// placement and whether it had to be stepped out of a corner.
+    pub fn anchored(area: &IconArea, icon_width: u32, icon_height: u32, padding: u32, opts: &QrOptions) -> (IconPlacement, bool) {
+        let centered = IconPlacement::centered(area.canvas_width, area.canvas_height, icon_width, icon_height, padding);
+        if icon_centered(opts) {
+            return (centered, false);
+        }
+
+        let (width, height) = (centered.halo_width as i64, centered.halo_height as i64);
+        let (left, top, right, bottom) = (area.left as i64, area.top as i64, area.right as i64, area.bottom as i64);
+        let (x, y) = match opts.icon_anchor {
+            IconAnchor::Center => (centered.x as i64 - padding as i64, centered.y as i64 - padding as i64),
+            IconAnchor::TopLeft => (left, top),
+            IconAnchor::TopRight => (right - width, top),
+            IconAnchor::BottomLeft => (left, bottom - height),
+            IconAnchor::BottomRight => (right - width, bottom - height),
+        };
+        let mut x = clamp_span(x + opts.icon_offset.0 as i64, width, left, right);
+        let mut y = clamp_span(y + opts.icon_offset.1 as i64, height, top, bottom);
+
+        // Each reserved corner, and where the backing goes to clear it
+        let reserved = area.reserved as i64;
+        let corners = [
+            (left, top, left + reserved, top + reserved),
+            (right - reserved, top, right - reserved - width, top + reserved),
+            (left, bottom - reserved, left + reserved, bottom - reserved - height),
+        ];
+        let covered = corners.into_iter().find(|&(corner_x, corner_y, _, _)| {
+            reserved > 0 && x < corner_x + reserved && corner_x < x + width && y < corner_y + reserved && corner_y < y + height
+        });
+        if let Some((_, _, clear_x, clear_y)) = covered {
+            (x, y) = (clear_x, clear_y);
+        }
+
+        let placement = IconPlacement {
+            x: (x + padding as i64).max(0) as u32,
+            y: (y + padding as i64).max(0) as u32,
+            halo_x: x.max(0) as u32,
+            halo_y: y.max(0) as u32,
+            ..centered
+        };
+        (placement, covered.is_some())
+    }
+
+    /// Places the icon in `area` according to `opts`, with percentage
+    /// padding taken from its longer side. Returns the placement and whether
+    /// a backing should be drawn at all.
+    pub fn for_options(area: &IconArea, icon: &DynamicImage, opts: &QrOptions) -> (IconPlacement, bool) {
+        let padding = opts.icon_padding.to_pixels(icon.width().max(icon.height()));
+        let (placement, _) = IconPlacement::anchored(area, icon.width(), icon.height(), padding, opts);
+        (placement, padding > 0)
+    }
+
+    /// Radius of the round backing: the circle inscribed in the backing
+    /// rectangle, matching the circle `mask_icon_circle` keeps.
+    pub fn halo_radius(&self) -> f64 {
+        self.halo_width.min(self.halo_height) as f64 / 2.0
+    }
+
+    /// Signed distance from the canvas point `(x, y)` to the edge of the
+    /// backing; negative inside.
+    pub fn halo_edge_distance(&self, x: f64, y: f64, shape: IconShape) -> f64 {
+        let (left, top) = (self.halo_x as f64, self.halo_y as f64);
+        let (width, height) = (self.halo_width as f64, self.halo_height as f64);
+        match shape {
+            IconShape::Square => rounded_rect_distance(x, y, left, top, width, height, 0.0),
+            IconShape::Circle => (x - left - width / 2.0).hypot(y - top - height / 2.0) - self.halo_radius(),
+        }
+    }
+
+    /// Whether the canvas pixel at `(px, py)` lies on the icon's backing.
+    pub fn in_halo(&self, px: u32, py: u32, shape: IconShape) -> bool {
+        let inside_rect = px >= self.halo_x
+            && py >= self.halo_y
+            && px < self.halo_x + self.halo_width
+            && py < self.halo_y + self.halo_height;
+
+        match shape {
+            IconShape::Square => inside_rect,
+            IconShape::Circle => {
+                let radius = self.halo_radius();
+                let dx = px as f64 + 0.5 - (self.halo_x as f64 + self.halo_width as f64 / 2.0);
+                let dy = py as f64 + 0.5 - (self.halo_y as f64 + self.halo_height as f64 / 2.0);
+                inside_rect && dx * dx + dy * dy <= radius * radius
+            }
+        }
+    }
+}
+
+/// Moves a span `length` long starting at `start` within `min..max`, or
+/// centers it there when it is longer.
+fn clamp_span(start: i64, length: i64, min: i64, max: i64) -> i64 {
+    if length > max - min { min + (max - min - length) / 2 } else { start.clamp(min, max - length) }
+}
+
+/// Loads the icon at `icon_path` and resizes it to fit within a `size` x `size`
+/// square.
+pub fn load_and_resize_icon<P: AsRef<Path>>(icon_path: P, size: u32) -> Result<DynamicImage, QrGenError> {
+    load_and_fit_icon(icon_path, size, IconFit::Contain, IconFilter::Lanczos3, 0)
+}
+
+/// Loads the icon at `icon_path` and fits it into a `size` x `size` square as
+/// described by `fit`, resampling with `filter`.
+///
+/// Of an animated GIF or WebP only `frame` is used, counting from 0; still
+/// images have just the one frame.
+///
+/// SVG icons are rasterized straight at the fitted size instead, so they
+/// stay sharp however large the canvas is, and `filter` does not apply.
+pub fn load_and_fit_icon<P: AsRef<Path>>(
+    icon_path: P,
+    size: u32,
+    fit: IconFit,
+    filter: IconFilter,
+    frame: usize,
+) -> Result<DynamicImage, QrGenError> {
+    Ok(fit_icon_data(read_icon(icon_path.as_ref(), false)?, size, fit, filter, frame)?.0)
+}
+
+/// Like [`load_and_fit_icon`], but downloads `icon_path` when it is a URL
+/// and `allow_network` is set, and also returns the number of frames in the
+/// image, as [`icon_frame_count`] does.
+pub(crate) fn load_and_fit_icon_from(
+    icon_path: &Path,
+    allow_network: bool,
+    size: u32,
+    fit: IconFit,
+    filter: IconFilter,
+    frame: usize,
+) -> Result<(DynamicImage, usize), QrGenError> {
+    fit_icon_data(read_icon(icon_path, allow_network)?, size, fit, filter, frame)
+}
+
+/// Decodes `frame` of `data` and fits it as [`load_and_fit_icon`] does,
+/// along with the number of frames in `data`.
+fn fit_icon_data(data: IconData, size: u32, fit: IconFit, filter: IconFilter, frame: usize) -> Result<(DynamicImage, usize), QrGenError> {
+    if data.svg {
+        if frame > 0 {
+            return Err(QrGenError::IconFrameOutOfRange { frame, frames: 1 });
+        }
+        return Ok((rasterize_svg(&data, size, fit)?, 1));
+    }
+
+    let (icon, frames) = decode_frame(data, frame)?;
+    Ok((fit_icon(&icon, size, fit, filter), frames))
+}
+
+/// The encoded contents of an icon, read from a file or downloaded.
+struct IconData {
+    bytes: Arc<[u8]>,
+    /// Format the file extension names, for images whose contents do not
+    /// identify it.
+    format: Option<ImageFormat>,
+    svg: bool,
+    /// Directory that relative references in an SVG icon resolve against;
+    /// none for a download, so it cannot reach local files.
+    resources_dir: Option<PathBuf>,
+}
+
+/// Reads the icon at `icon_path`, downloading it if it is a URL and
+/// `allow_network` is set.
+fn read_icon(icon_path: &Path, allow_network: bool) -> Result<IconData, QrGenError> {
+    if let Some(url) = fetch::as_url(icon_path) {
+        if !allow_network {
+            return Err(QrGenError::NetworkDisabled(url.to_string()));
+        }
+        let bytes = fetch::fetch(url)?;
+        return Ok(IconData { svg: looks_like_svg(&bytes), bytes, format: None, resources_dir: None });
+    }
+
+    if !icon_path.exists() {
+        return Err(QrGenError::IconNotFound(icon_path.to_path_buf()));
+    }
+    Ok(IconData {
+        bytes: std::fs::read(icon_path)?.into(),
+        format: ImageFormat::from_path(icon_path).ok(),
+        svg: is_svg(icon_path),
+        resources_dir: icon_path.parent().map(Path::to_path_buf),
+    })
+}
+
+/// Fits a decoded `icon` into a `size` x `size` square as described by
+/// `fit`, resampling with `filter`.
+pub(crate) fn fit_icon(icon: &DynamicImage, size: u32, fit: IconFit, filter: IconFilter) -> DynamicImage {
+    let filter = FilterType::from(filter);
+    match fit {
+        IconFit::Contain => icon.resize(size, size, filter),
+        IconFit::Cover => icon.resize_to_fill(size, size, filter),
+        IconFit::Stretch => icon.resize_exact(size, size, filter),
+    }
+}
+
+/// Number of frames in the image at `icon_path`: more than one for an
+/// animated GIF or WebP, and one for any other image.
+pub fn icon_frame_count<P: AsRef<Path>>(icon_path: P) -> Result<usize, QrGenError> {
+    icon_frame_count_from(icon_path.as_ref(), false)
+}
+
+/// Like [`icon_frame_count`], but downloads `icon_path` when it is a URL and
+/// `allow_network` is set.
+pub(crate) fn icon_frame_count_from(icon_path: &Path, allow_network: bool) -> Result<usize, QrGenError> {
+    let data = read_icon(icon_path, allow_network)?;
+    if data.svg {
+        return Ok(1);
+    }
+    match animation_frames(&data)? {
+        Some(frames) => {
+            let mut count = 0;
+            for frame in frames {
+                frame.map_err(QrGenError::ImageDecode)?;
+                count += 1;
+            }
+            Ok(count)
+        }
+        None => Ok(1),
+    }
+}
+
+/// Decodes `frame` of the image in `data`, counting from 0, with the frames
+/// of an animation composed onto its full canvas, along with the number of
+/// frames in it.
+fn decode_frame(data: IconData, frame: usize) -> Result<(DynamicImage, usize), QrGenError> {
+    let Some(frames) = animation_frames(&data)? else {
+        if frame > 0 {
+            return Err(QrGenError::IconFrameOutOfRange { frame, frames: 1 });
+        }
+        return Ok((image_reader(&data)?.decode().map_err(QrGenError::ImageDecode)?, 1));
+    };
+
+    let mut count = 0;
+    let mut found = None;
+    for decoded in frames {
+        let decoded = decoded.map_err(QrGenError::ImageDecode)?;
+        if count == frame {
+            found = Some(DynamicImage::ImageRgba8(decoded.into_buffer()));
+        }
+        count += 1;
+    }
+    match found {
+        Some(icon) => Ok((icon, count)),
+        None => Err(QrGenError::IconFrameOutOfRange { frame, frames: count }),
+    }
+}
+
+/// A reader of the image in `data`, in the format its contents show or
+/// else the one its extension names.
+fn image_reader(data: &IconData) -> Result<ImageReader<Cursor<Arc<[u8]>>>, QrGenError> {
+    let mut reader = ImageReader::new(Cursor::new(data.bytes.clone()));
+    if let Some(format) = data.format {
+        reader.set_format(format);
+    }
+    Ok(reader.with_guessed_format()?)
+}
+
+/// The frames of the GIF or animated WebP in `data`, detected from its
+/// contents, or `None` for any other image.
+fn animation_frames(data: &IconData) -> Result<Option<Frames<'static>>, QrGenError> {
+    let reader = image_reader(data)?;
+    let frames = match reader.format() {
+        Some(ImageFormat::Gif) => GifDecoder::new(reader.into_inner()).map_err(QrGenError::ImageDecode)?.into_frames(),
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader.into_inner()).map_err(QrGenError::ImageDecode)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frames))
+}
+
+/// Whether `path` names an SVG file, compressed or not.
+fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg") || ext.eq_ignore_ascii_case("svgz"))
+}
+
+/// Whether downloaded `bytes` hold an SVG document, compressed or not,
+/// as there is no file extension to tell.
+fn looks_like_svg(bytes: &[u8]) -> bool {
+    let text = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    text.starts_with(&[0x1f, 0x8b]) || text.trim_ascii_start().starts_with(b"<")
+}
+
+/// Renders the SVG in `data` into a `size` x `size` square as described by
+/// `fit`, keeping its transparency.
+fn rasterize_svg(data: &IconData, size: u32, fit: IconFit) -> Result<DynamicImage, QrGenError> {
+    let mut options = usvg::Options { resources_dir: data.resources_dir.clone(), ..usvg::Options::default() };
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_data(&data.bytes, &options).map_err(QrGenError::SvgDecode)?;
+
+    let (svg_width, svg_height) = (tree.size().width(), tree.size().height());
+    let size = size.max(1) as f32;
+    let (scale_x, scale_y) = match fit {
+        IconFit::Contain => {
+            let scale = size / svg_width.max(svg_height);
+            (scale, scale)
+        },
+        IconFit::Cover => {
+            let scale = size / svg_width.min(svg_height);
+            (scale, scale)
+        },
+        IconFit::Stretch => (size / svg_width, size / svg_height),
+    };
+    // Cover crops the overflow evenly from both sides
+    let (width, height) = match fit {
+        IconFit::Contain => ((svg_width * scale_x).round().max(1.0), (svg_height * scale_y).round().max(1.0)),
+        IconFit::Cover | IconFit::Stretch => (size, size),
+    };
+    let offset_x = (width - svg_width * scale_x) / 2.0;
+    let offset_y = (height - svg_height * scale_y) / 2.0;
+
+    let mut pixmap = tiny_skia::Pixmap::new(width as u32, height as u32).expect("icon size is positive");
+    let transform = tiny_skia::Transform::from_row(scale_x, 0.0, 0.0, scale_y, offset_x, offset_y);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia stores premultiplied alpha
+    let pixels = pixmap.pixels().iter().flat_map(|p| {
+        let p = p.demultiply();
+        [p.red(), p.green(), p.blue(), p.alpha()]
+    });
+    let icon = RgbaImage::from_raw(width as u32, height as u32, pixels.collect()).expect("pixmap matches icon size");
+    Ok(DynamicImage::ImageRgba8(icon))
+}
+
+/// Recolors `icon` in shades of `tint`, keeping its alpha channel.
+///
+/// The darkest visible color becomes `tint` itself and lighter ones are
+/// mixed toward white by how much lighter they are, so a one-color logo
+/// takes on exactly `tint` while light detail inside it survives.
+pub fn tint_icon(icon: &DynamicImage, tint: Rgb<u8>) -> DynamicImage {
+    let mut rgba = icon.to_rgba8();
+    let luminance = |p: &Rgba<u8>| relative_luminance(Rgb([p[0], p[1], p[2]]));
+    let darkest = rgba.pixels().filter(|p| p[3] > 0).map(luminance).fold(1.0, f64::min);
+    let range = 1.0 - darkest;
+
+    for pixel in rgba.pixels_mut() {
+        let lightness = if range > 0.0 { (luminance(pixel) - darkest) / range } else { 0.0 };
+        let [r, g, b] = tint.0.map(|c| (c as f64 + (255.0 - c as f64) * lightness).round() as u8);
+        *pixel = Rgba([r, g, b, pixel[3]]);
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Converts `icon` to shades of gray, keeping its alpha channel.
+pub fn grayscale_icon(icon: &DynamicImage) -> DynamicImage {
+    DynamicImage::ImageRgba8(icon.grayscale().to_rgba8())
+}
+
+/// Dark neutral that [`suggest_halo_color`] offers for light icons.
+pub const DARK_HALO: Rgb<u8> = Rgb([0x40, 0x40, 0x40]);
+
+/// Picks the backing color that stands out most from `icon`: `background`,
+/// white or [`DARK_HALO`], whichever contrasts most with the mean luminance
+/// of the icon's visible pixels. A fully transparent icon keeps
+/// `background`.
+pub fn suggest_halo_color(icon: &DynamicImage, background: Rgb<u8>) -> Rgb<u8> {
+    let (sum, count) = icon
+        .to_rgba8()
+        .pixels()
+        .filter(|p| p[3] > 0)
+        .fold((0.0, 0), |(sum, count), p| (sum + relative_luminance(Rgb([p[0], p[1], p[2]])), count + 1));
+    if count == 0 {
+        return background;
+    }
+
+    let mean = sum / count as f64;
+    let contrast = |color: Rgb<u8>| luminance_contrast(mean, relative_luminance(color));
+    // Earlier candidates win ties, so the background is kept when it does as well
+    [background, Rgb([255, 255, 255]), DARK_HALO]
+        .into_iter()
+        .reduce(|best, color| if contrast(color) > contrast(best) { color } else { best })
+        .unwrap_or(background)
+}
+
+/// Color of the backing behind `icon`: `opts.background`, or the one
+/// [`suggest_halo_color`] picks with `opts.auto_halo`.
+pub(crate) fn halo_color(icon: &DynamicImage, opts: &QrOptions) -> Rgb<u8> {
+    if opts.auto_halo { suggest_halo_color(icon, opts.background) } else { opts.background }
+}
+
+/// Makes every pixel of `icon` outside its inscribed circle transparent,
+/// anti-aliasing the edge.
+pub fn mask_icon_circle(icon: &DynamicImage) -> DynamicImage {
+    let mut rgba = icon.to_rgba8();
+    let cx = rgba.width() as f64 / 2.0;
+    let cy = rgba.height() as f64 / 2.0;
+    let radius = cx.min(cy);
+
+    for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+        let dx = x as f64 + 0.5 - cx;
+        let dy = y as f64 + 0.5 - cy;
+        let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+        pixel[3] = (pixel[3] as f64 * coverage).round() as u8;
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Draws `icon` over `qr_image` at `opts.icon_anchor` on a backing in the
+/// background color (or the one `opts.auto_halo` picks), shaped according to `opts.halo_shape` (or
+/// `opts.icon_shape`) and padded by `opts.icon_padding`, at
+/// `opts.halo_opacity`, with the ring of `opts.halo_border` stroked around
+/// its edge.
+///
+/// The icon is composited source-over by its alpha channel, so transparent
+/// regions of a logo show the backing, or the modules where there is none.
+/// With `opts.icon_shadow` set, a blurred shadow of the backing (or of the
+/// icon, without one) is drawn first.
+pub fn overlay_icon_on_qr(qr_image: RgbaImage, icon: DynamicImage, opts: &QrOptions) -> Result<DynamicImage, QrGenError> {
+    let area = IconArea::canvas(qr_image.width(), qr_image.height());
+    overlay_icon(qr_image, icon, &area, opts)
+}
+
+/// Draws `icon` over `qr_image` as [`overlay_icon_on_qr`] does, placing it
+/// within `area`.
+pub(crate) fn overlay_icon(
+    mut qr_image: RgbaImage,
+    icon: DynamicImage,
+    area: &IconArea,
+    opts: &QrOptions,
+) -> Result<DynamicImage, QrGenError> {
+    let qr_width = qr_image.width();
+    let qr_height = qr_image.height();
+    let (placement, draw_halo) = IconPlacement::for_options(area, &icon, opts);
+    let halo = halo_color(&icon, opts);
+    let icon = icon.to_rgba8();
+    let halo_shape = opts.effective_halo_shape();
+
+    if let Some(shadow) = opts.icon_shadow {
+        if draw_halo {
+            let mask = Mask::from_fn(placement.halo_width, placement.halo_height, |x, y| {
+                let inside = placement.in_halo(placement.halo_x + x, placement.halo_y + y, halo_shape);
+                if inside { opts.halo_opacity as f32 } else { 0.0 }
+            });
+            draw_shadow(&mut qr_image, &mask, placement.halo_x, placement.halo_y, shadow);
+        } else {
+            let mask = Mask::from_fn(icon.width(), icon.height(), |x, y| icon.get_pixel(x, y)[3] as f32 / 255.0);
+            draw_shadow(&mut qr_image, &mask, placement.x, placement.y, shadow);
+        }
+    }
+
+    // Draw the backing directly on the image to ensure the icon is readable
+    if draw_halo && halo_shape == IconShape::Square && opts.halo_opacity >= 1.0 {
+        let [r, g, b] = halo.0;
+        let (width, height) = (placement.halo_width, placement.halo_height);
+        fill_rect(&mut qr_image, placement.halo_x, placement.halo_y, width, height, Rgba([r, g, b, 255]));
+    } else if draw_halo {
+        for y in 0..placement.halo_height {
+            for x in 0..placement.halo_width {
+                let px = placement.halo_x + x;
+                let py = placement.halo_y + y;
+                if px < qr_width && py < qr_height && placement.in_halo(px, py, halo_shape) {
+                    let backed = blend_over(*qr_image.get_pixel(px, py), halo, opts.halo_opacity);
+                    qr_image.put_pixel(px, py, backed);
+                }
+            }
+        }
+    }
+
+    if let Some(border) = opts.halo_border.filter(|border| draw_halo && border.width > 0) {
+        draw_halo_border(&mut qr_image, &placement, halo_shape, border);
+    }
+
+    composite_over(&mut qr_image, &icon, placement.x, placement.y);
+
+    Ok(DynamicImage::ImageRgba8(qr_image))
+}
+
+/// Strokes `border` just inside the edge of the backing at `placement`,
+/// anti-aliasing both sides of the ring.
+fn draw_halo_border(image: &mut RgbaImage, placement: &IconPlacement, shape: IconShape, border: HaloBorder) {
+    let right = (placement.halo_x + placement.halo_width).min(image.width());
+    let bottom = (placement.halo_y + placement.halo_height).min(image.height());
+    for py in placement.halo_y..bottom {
+        for px in placement.halo_x..right {
+            let distance = placement.halo_edge_distance(px as f64 + 0.5, py as f64 + 0.5, shape);
+            // Inside the outer edge but not the inner one
+            let ring = coverage(distance, true) - coverage(distance + border.width as f64, true);
+            if ring > 0.0 {
+                let blended = blend_over(*image.get_pixel(px, py), border.color, ring);
+                image.put_pixel(px, py, blended);
+            }
+        }
+    }
+}
+
+/// Whether `icon`, placed on the matrix laid out by `layout` as `opts`
+/// asks, had to be moved clear of a finder pattern.
+pub(crate) fn icon_moved_off_finders(layout: &Layout, icon: &DynamicImage, opts: &QrOptions) -> bool {
+    let padding = opts.icon_padding.to_pixels(icon.width().max(icon.height()));
+    IconPlacement::anchored(&IconArea::matrix(layout), icon.width(), icon.height(), padding, opts).1
+}
+
+/// The part of the code that `icon`'s backing, or the icon itself without
+/// one, covers once placed on the matrix laid out by `layout` as `opts`
+/// asks, if it reaches the quiet zone or a finder pattern with its
+/// separator and format information, which scanners need clear.
+pub(crate) fn icon_overlap(layout: &Layout, icon: &DynamicImage, opts: &QrOptions) -> Option<&'static str> {
+    let area = IconArea::matrix(layout);
+    let (placement, draw_halo) = IconPlacement::for_options(&area, icon, opts);
+    let (left, top, width, height) = match draw_halo {
+        true => (placement.halo_x, placement.halo_y, placement.halo_width, placement.halo_height),
+        false => (placement.x, placement.y, icon.width(), icon.height()),
+    };
+    let (right, bottom) = (left + width, top + height);
+    if left < area.left || top < area.top || right > area.right || bottom > area.bottom {
+        return Some("the quiet zone");
+    }
+    let reserved = area.reserved;
+    let corners = [(area.left, area.top), (area.right - reserved, area.top), (area.left, area.bottom - reserved)];
+    let covered = corners.into_iter().any(|(x, y)| left < x + reserved && x < right && top < y + reserved && y < bottom);
+    covered.then_some("a finder pattern")
+}
+
+/// Fraction of the matrix laid out by `layout` that `icon` hides once
+/// overlaid as by [`overlay_icon`]: the modules whose center falls on
+/// a mostly opaque backing or on a mostly opaque pixel of the icon.
+pub(crate) fn hidden_module_fraction(layout: &Layout, icon: &DynamicImage, opts: &QrOptions) -> f64 {
+    let (placement, draw_halo) = IconPlacement::for_options(&IconArea::matrix(layout), icon, opts);
+    let halo_shape = opts.effective_halo_shape();
+    let icon = icon.to_rgba8();
+
+    let hides = |px: u32, py: u32| {
+        let on_halo = draw_halo && opts.halo_opacity >= 0.5 && placement.in_halo(px, py, halo_shape);
+        let on_icon = (placement.x..placement.x + icon.width()).contains(&px)
+            && (placement.y..placement.y + icon.height()).contains(&py)
+            && icon.get_pixel(px - placement.x, py - placement.y)[3] >= 128;
+        on_halo || on_icon
+    };
+    let center = |module: u32| layout.offset + module * layout.module_size + layout.module_size / 2;
+    let hidden = (0..layout.qr_width)
+        .flat_map(|my| (0..layout.qr_width).map(move |mx| (mx, my)))
+        .filter(|&(mx, my)| hides(center(mx), center(my)))
+        .count();
+    hidden as f64 / (layout.qr_width * layout.qr_width) as f64
+}
+
+/// Composites `src` onto `dst` with its top-left corner at `(x, y)`, using
+/// source-over blending and clipping to `dst`.
+fn composite_over(dst: &mut RgbaImage, src: &RgbaImage, x: u32, y: u32) {
+    for (sx, sy, pixel) in src.enumerate_pixels() {
+        let (px, py) = (x + sx, y + sy);
+        if px >= dst.width() || py >= dst.height() || pixel[3] == 0 {
+            continue;
+        }
+
+        let [r, g, b, a] = pixel.0;
+        let blended = if a == 255 {
+            *pixel
+        } else {
+            blend_over(*dst.get_pixel(px, py), Rgb([r, g, b]), a as f64 / 255.0)
+        };
+        dst.put_pixel(px, py, blended);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IconPadding, IconShadow};
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_icon_loading_and_resizing() {
+        let temp_dir = tempdir().unwrap();
+
+        // Create a test icon
+        let test_icon = DynamicImage::new_rgb8(100, 100);
+        let icon_path = temp_dir.path().join("test_icon.png");
+        test_icon.save(&icon_path).unwrap();
+
+        // Test loading and resizing
+        let result = load_and_resize_icon(icon_path.to_str().unwrap(), 50);
+        assert!(result.is_ok());
+
+        let resized = result.unwrap();
+        assert_eq!(resized.width(), 50);
+        assert_eq!(resized.height(), 50);
+    }
+
+    #[test]
+    fn test_svg_icon_rasterized_at_size() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("logo.svg");
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10"><rect width="10" height="10" fill="red"/></svg>"#;
+        std::fs::write(&icon_path, svg).unwrap();
+
+        // Scaled up past its own size without blurring, and the uncovered
+        // half stays transparent
+        let icon = load_and_resize_icon(&icon_path, 200).unwrap().to_rgba8();
+        assert_eq!(icon.dimensions(), (200, 100));
+        assert_eq!(icon.get_pixel(50, 50).0, [255, 0, 0, 255]);
+        assert_eq!(icon.get_pixel(98, 50).0, [255, 0, 0, 255]);
+        assert_eq!(icon.get_pixel(150, 50)[3], 0);
+
+        let icon = load_and_fit_icon(&icon_path, 60, IconFit::Cover, IconFilter::Lanczos3, 0).unwrap();
+        assert_eq!((icon.width(), icon.height()), (60, 60));
+    }
+
+    #[test]
+    fn test_animated_icon_frames() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("logo.gif");
+        let frames = [[255, 0, 0, 255], [0, 0, 255, 255]].map(|color| image::Frame::new(RgbaImage::from_pixel(8, 8, Rgba(color))));
+        image::codecs::gif::GifEncoder::new(File::create(&icon_path).unwrap()).encode_frames(frames).unwrap();
+        assert_eq!(icon_frame_count(&icon_path).unwrap(), 2);
+
+        let fit = |frame| load_and_fit_icon(&icon_path, 8, IconFit::Contain, IconFilter::Nearest, frame);
+        assert_eq!(fit(0).unwrap().to_rgba8().get_pixel(4, 4).0, [255, 0, 0, 255]);
+        assert_eq!(fit(1).unwrap().to_rgba8().get_pixel(4, 4).0, [0, 0, 255, 255]);
+        assert!(matches!(fit(2), Err(QrGenError::IconFrameOutOfRange { frame: 2, frames: 2 })));
+        assert_eq!(load_and_fit_icon_from(&icon_path, false, 8, IconFit::Contain, IconFilter::Nearest, 0).unwrap().1, 2);
+
+        // A still image is a single frame
+        let still_path = temp_dir.path().join("logo.png");
+        DynamicImage::new_rgb8(8, 8).save(&still_path).unwrap();
+        assert_eq!(icon_frame_count(&still_path).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_missing_icon_error() {
+        let result = load_and_resize_icon("does/not/exist.png", 50);
+        assert!(matches!(result, Err(QrGenError::IconNotFound(_))));
+    }
+
+    #[test]
+    fn test_mask_icon_circle() {
+        let icon = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(40, 40, image::Rgba([255, 0, 0, 255])));
+        let masked = mask_icon_circle(&icon).to_rgba8();
+
+        assert_eq!(masked.get_pixel(0, 0)[3], 0);
+        assert_eq!(masked.get_pixel(39, 39)[3], 0);
+        assert_eq!(masked.get_pixel(20, 20)[3], 255);
+        assert_eq!(masked.get_pixel(20, 1)[3], 255);
+    }
+
+    #[test]
+    fn test_tint_icon() {
+        // A red logo with a white highlight, on transparency
+        let mut logo = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 0]));
+        fill_rect(&mut logo, 2, 2, 6, 6, Rgba([220, 0, 0, 255]));
+        logo.put_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        logo.put_pixel(5, 5, Rgba([220, 0, 0, 128]));
+
+        let tint = Rgb([0, 80, 160]);
+        let tinted = tint_icon(&DynamicImage::ImageRgba8(logo), tint).to_rgba8();
+        assert_eq!(*tinted.get_pixel(2, 2), Rgba([0, 80, 160, 255]));
+        assert_eq!(*tinted.get_pixel(5, 5), Rgba([0, 80, 160, 128]));
+        assert_eq!(*tinted.get_pixel(4, 4), Rgba([255, 255, 255, 255]));
+        assert_eq!(tinted.get_pixel(0, 0)[3], 0);
+
+        let gray = grayscale_icon(&DynamicImage::ImageRgba8(tinted)).to_rgba8();
+        let [r, g, b, a] = gray.get_pixel(2, 2).0;
+        assert!(r == g && g == b && a == 255);
+    }
+
+    #[test]
+    fn test_circle_overlay_shows_backing_in_corners() {
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 0, 255]));
+        let icon = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(40, 40, image::Rgba([255, 0, 0, 255])));
+        let opts = QrOptions { icon_shape: IconShape::Circle, ..QrOptions::default() };
+
+        let result = overlay_icon_on_qr(qr, mask_icon_circle(&icon), &opts).unwrap().to_rgb8();
+
+        // Icon center is opaque red
+        assert_eq!(*result.get_pixel(100, 100), Rgb([255, 0, 0]));
+        // The icon's transparent corner shows the white backing circle
+        assert_eq!(*result.get_pixel(82, 82), Rgb([255, 255, 255]));
+        // The corners of the backing square stay untouched
+        assert_eq!(*result.get_pixel(75, 75), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_halo_opacity_on_transparent_background() {
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([255, 255, 255, 0]));
+        let icon = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(40, 40, image::Rgba([0, 0, 0, 0])));
+        let opts = QrOptions { transparent: true, halo_opacity: 0.5, ..QrOptions::default() };
+
+        let result = overlay_icon_on_qr(qr, icon, &opts).unwrap().to_rgba8();
+
+        assert_eq!(result.get_pixel(0, 0)[3], 0);
+        assert_eq!(result.get_pixel(100, 100)[3], 128);
+    }
+
+    #[test]
+    fn test_halo_follows_padding_and_background() {
+        let cream = Rgb([0xff, 0xf8, 0xe7]);
+        let icon = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 40, image::Rgba([255, 0, 0, 255])));
+
+        let opts = QrOptions { background: cream, icon_padding: IconPadding::Pixels(10), ..QrOptions::default() };
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 0, 255]));
+        let result = overlay_icon_on_qr(qr, icon.clone(), &opts).unwrap().to_rgb8();
+        assert_eq!(*result.get_pixel(71, 100), cream);
+        assert_eq!(*result.get_pixel(69, 100), Rgb([0, 0, 0]));
+
+        let opts = QrOptions { icon_padding: IconPadding::Pixels(0), ..QrOptions::default() };
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 0, 255]));
+        let result = overlay_icon_on_qr(qr, icon, &opts).unwrap().to_rgb8();
+        assert_eq!(*result.get_pixel(79, 100), Rgb([0, 0, 0]));
+        assert_eq!(*result.get_pixel(80, 100), Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn test_suggest_halo_color() {
+        let white = Rgb([255, 255, 255]);
+        // A mostly white logo with a little dark detail, on a transparent field
+        let mut logo = RgbaImage::from_pixel(40, 40, image::Rgba([0, 0, 0, 0]));
+        for (x, y, pixel) in logo.enumerate_pixels_mut().filter(|&(x, y, _)| (5..35).contains(&x) && (5..35).contains(&y)) {
+            *pixel = if x < 8 && y < 8 { image::Rgba([0x10, 0x10, 0x10, 255]) } else { image::Rgba([0xfa, 0xfa, 0xfa, 255]) };
+        }
+        let logo = DynamicImage::ImageRgba8(logo);
+        assert_eq!(suggest_halo_color(&logo, white), DARK_HALO);
+
+        let dark = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 40, image::Rgba([0, 0x30, 0x80, 255])));
+        assert_eq!(suggest_halo_color(&dark, white), white);
+        // The background is kept when nothing beats it
+        assert_eq!(suggest_halo_color(&logo, Rgb([0, 0, 0])), Rgb([0, 0, 0]));
+        let clear = DynamicImage::ImageRgba8(RgbaImage::new(10, 10));
+        assert_eq!(suggest_halo_color(&clear, white), white);
+
+        let opts = QrOptions { icon_padding: IconPadding::Pixels(10), ..QrOptions::default() }.with_auto_halo(true);
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([255, 255, 255, 255]));
+        let result = overlay_icon_on_qr(qr, logo, &opts).unwrap().to_rgb8();
+        assert_eq!(*result.get_pixel(71, 100), DARK_HALO);
+    }
+
+    #[test]
+    fn test_halo_border_ring() {
+        let blue = Rgb([0, 0x50, 0xa0]);
+        let icon = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 40, image::Rgba([255, 0, 0, 255])));
+        let opts = QrOptions { icon_padding: IconPadding::Pixels(10), ..QrOptions::default() }
+            .with_halo_shape(IconShape::Circle)
+            .with_halo_border(HaloBorder { color: blue, width: 3 });
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 0, 255]));
+        let result = overlay_icon_on_qr(qr, icon, &opts).unwrap().to_rgb8();
+
+        // The round backing spans 70..130; the ring takes its outer 3 pixels
+        assert_eq!(*result.get_pixel(71, 100), blue);
+        assert_eq!(*result.get_pixel(100, 128), blue);
+        assert_eq!(*result.get_pixel(75, 100), Rgb([255, 255, 255]));
+        assert_eq!(*result.get_pixel(69, 100), Rgb([0, 0, 0]));
+        // The icon keeps its square shape, and the corners lie off the ring
+        assert_eq!(*result.get_pixel(80, 80), Rgb([255, 0, 0]));
+        assert_eq!(*result.get_pixel(72, 72), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_icon_shadow_falls_below_and_right_of_halo() {
+        let icon = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 40, image::Rgba([255, 0, 0, 255])));
+        let shadow = IconShadow { offset: 6, blur: 2.0, opacity: 0.8 };
+        let opts = QrOptions { background: Rgb([200, 200, 255]), ..QrOptions::default() }.with_icon_shadow(shadow);
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([255, 255, 255, 255]));
+
+        let result = overlay_icon_on_qr(qr, icon, &opts).unwrap().to_rgba8();
+
+        // The backing spans 75..125 and stays on top of its own shadow
+        assert_eq!(*result.get_pixel(124, 124), image::Rgba([200, 200, 255, 255]));
+        assert_eq!(*result.get_pixel(100, 100), image::Rgba([255, 0, 0, 255]));
+        // Just past the bottom-right edge the shadow darkens the canvas
+        let shaded = result.get_pixel(127, 127);
+        assert!(shaded[0] < 150 && shaded[0] == shaded[2]);
+        assert!(result.get_pixel(100, 127)[0] < 150);
+        // The top-left side is not shaded, and the shadow fades out
+        assert_eq!(*result.get_pixel(73, 100), image::Rgba([255, 255, 255, 255]));
+        assert_eq!(*result.get_pixel(140, 140), image::Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_centered_placement() {
+        let placement = IconPlacement::centered(400, 400, 80, 80, 5);
+        assert_eq!((placement.x, placement.y), (160, 160));
+        assert_eq!((placement.halo_x, placement.halo_y), (155, 155));
+        assert_eq!((placement.halo_width, placement.halo_height), (90, 90));
+    }
+
+    #[test]
+    fn test_anchored_placement_clears_finders() {
+        // 41 modules of 10px, 4 quiet ones on each side of the matrix
+        let layout = Layout { qr_width: 33, module_size: 10, offset: 40, canvas_size: 410, drawn_size: 410 };
+        let area = IconArea::matrix(&layout);
+        let opts = QrOptions::default().with_icon_anchor(IconAnchor::TopLeft);
+        let (placement, moved) = IconPlacement::anchored(&area, 60, 60, 5, &opts);
+        // The backing steps diagonally past the finder, separator and format
+        // information, 9 modules in
+        assert!(moved);
+        assert_eq!((placement.halo_x, placement.halo_y), (130, 130));
+        assert_eq!((placement.x, placement.y), (135, 135));
+
+        // The bottom-right corner has no finder, and offsets stay in the matrix
+        let opts = opts.with_icon_anchor(IconAnchor::BottomRight).with_icon_offset(-10, 50);
+        let (placement, moved) = IconPlacement::anchored(&area, 60, 60, 5, &opts);
+        assert!(!moved);
+        assert_eq!((placement.halo_x, placement.halo_y), (290, 300));
+
+        // An offset from the center that clears the finders keeps its place
+        let opts = opts.with_icon_anchor(IconAnchor::Center).with_icon_offset(0, -60);
+        let (placement, moved) = IconPlacement::anchored(&area, 60, 60, 5, &opts);
+        assert!(!moved);
+        assert_eq!((placement.x, placement.y), (175, 115));
+    }
+
+    #[test]
+    fn test_hidden_fraction_follows_anchor() {
+        let layout = Layout { qr_width: 33, module_size: 10, offset: 40, canvas_size: 410, drawn_size: 410 };
+        let icon = DynamicImage::ImageRgba8(RgbaImage::from_pixel(60, 60, image::Rgba([255, 0, 0, 255])));
+        let opts = QrOptions::default().with_icon_anchor(IconAnchor::BottomRight);
+        let centered = hidden_module_fraction(&layout, &icon, &QrOptions::default());
+        assert_eq!(hidden_module_fraction(&layout, &icon, &opts), centered);
+
+        // It hides as many modules in the corner as in the center, and is
+        // drawn there
+        let mut qr = RgbaImage::from_pixel(410, 410, image::Rgba([0, 0, 0, 255]));
+        qr = overlay_icon(qr, icon, &IconArea::matrix(&layout), &opts).unwrap().to_rgba8();
+        assert_eq!(*qr.get_pixel(205, 205), image::Rgba([0, 0, 0, 255]));
+        assert_eq!(*qr.get_pixel(335, 335), image::Rgba([255, 0, 0, 255]));
+        assert_eq!(*qr.get_pixel(367, 367), image::Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_icon_fit_modes() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("wide.png");
+        DynamicImage::new_rgb8(200, 100).save(&icon_path).unwrap();
+
+        let contain = load_and_fit_icon(&icon_path, 50, IconFit::Contain, IconFilter::Lanczos3, 0).unwrap();
+        assert_eq!((contain.width(), contain.height()), (50, 25));
+        let cover = load_and_fit_icon(&icon_path, 50, IconFit::Cover, IconFilter::Lanczos3, 0).unwrap();
+        assert_eq!((cover.width(), cover.height()), (50, 50));
+        let stretch = load_and_fit_icon(&icon_path, 50, IconFit::Stretch, IconFilter::Lanczos3, 0).unwrap();
+        assert_eq!((stretch.width(), stretch.height()), (50, 50));
+    }
+
+    #[test]
+    fn test_nearest_filter_keeps_crisp_blocks() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("checker.png");
+        let black = image::Rgb([0, 0, 0]);
+        let white = image::Rgb([255, 255, 255]);
+        image::RgbImage::from_fn(2, 2, |x, y| if (x + y) % 2 == 0 { black } else { white })
+            .save(&icon_path)
+            .unwrap();
+
+        let resized = load_and_fit_icon(&icon_path, 4, IconFit::Contain, IconFilter::Nearest, 0).unwrap().to_rgb8();
+        assert_eq!(resized.dimensions(), (4, 4));
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            let expected = if (x / 2 + y / 2) % 2 == 0 { black } else { white };
+            assert_eq!(*pixel, expected, "pixel ({}, {})", x, y);
+        }
+
+        // A smoothing filter blends the blocks where they meet
+        let smooth = load_and_fit_icon(&icon_path, 4, IconFit::Contain, IconFilter::Triangle, 0).unwrap().to_rgb8();
+        assert!(smooth.pixels().any(|p| *p != black && *p != white));
+    }
+
+    #[test]
+    fn test_wide_icon_is_centered_with_matching_halo() {
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 0, 255]));
+        let icon = DynamicImage::ImageRgba8(RgbaImage::from_pixel(60, 20, image::Rgba([255, 0, 0, 255])));
+        let opts = QrOptions { icon_padding: IconPadding::Pixels(4), ..QrOptions::default() };
+
+        let result = overlay_icon_on_qr(qr, icon, &opts).unwrap().to_rgb8();
+
+        // The icon spans x 70..130 and y 90..110, with 4px of backing around it
+        assert_eq!(*result.get_pixel(70, 90), Rgb([255, 0, 0]));
+        assert_eq!(*result.get_pixel(129, 109), Rgb([255, 0, 0]));
+        assert_eq!(*result.get_pixel(100, 87), Rgb([255, 255, 255]));
+        assert_eq!(*result.get_pixel(67, 100), Rgb([255, 255, 255]));
+        assert_eq!(*result.get_pixel(100, 85), Rgb([0, 0, 0]));
+        assert_eq!(*result.get_pixel(65, 100), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_transparent_logo_border_composites_over_backing() {
+        // A red logo with an 8px fully transparent border and a half-transparent center pixel
+        let mut logo = RgbaImage::from_pixel(40, 40, image::Rgba([0, 0, 255, 0]));
+        for y in 8..32 {
+            for x in 8..32 {
+                logo.put_pixel(x, y, image::Rgba([255, 0, 0, 255]));
+            }
+        }
+        logo.put_pixel(20, 20, image::Rgba([255, 0, 0, 128]));
+        let icon = DynamicImage::ImageRgba8(logo);
+
+        // The border shows the white backing, not the logo's hidden blue
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 0, 255]));
+        let result = overlay_icon_on_qr(qr, icon.clone(), &QrOptions::default()).unwrap().to_rgba8();
+        assert_eq!(*result.get_pixel(82, 82), image::Rgba([255, 255, 255, 255]));
+        assert_eq!(*result.get_pixel(90, 90), image::Rgba([255, 0, 0, 255]));
+        assert_eq!(*result.get_pixel(100, 100), image::Rgba([255, 127, 127, 255]));
+
+        // Without a backing the border shows the modules underneath
+        let opts = QrOptions { icon_padding: IconPadding::Pixels(0), ..QrOptions::default() };
+        let qr = RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 0, 255]));
+        let result = overlay_icon_on_qr(qr, icon, &opts).unwrap().to_rgba8();
+        assert_eq!(*result.get_pixel(82, 82), image::Rgba([0, 0, 0, 255]));
+        assert_eq!(*result.get_pixel(100, 100), image::Rgba([128, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,1681 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use image::{DynamicImage, ImageFormat, Rgb, Rgba, RgbaImage};
+
+mod animate;
+mod auto;
+mod backdrop;
+mod batch;
+mod capacity;
+mod caption;
+mod code;
+mod color;
+mod config;
+mod error;
+mod eye;
+mod favicon;
+mod fetch;
+mod frame;
+mod icon;
+mod matrix;
+mod options;
+mod output;
+mod payload;
+mod pdf;
+mod render;
+mod shadow;
+mod sheet;
+mod split;
+mod svg;
+mod terminal;
+mod verify;
+
+pub use animate::animated_gif;
+pub use auto::{auto_tune, MIN_AUTO_ICON_SCALE};
+pub use batch::{run_batch, run_batch_with_progress, slug, template_path, BatchOptions, BatchOutcome};
+pub use capacity::{fits, max_capacity, DataMode};
+pub use caption::caption_band_height;
+pub use color::{contrast_ratio, parse_color, parse_hex_color, parse_rgba_color, relative_luminance, to_hex, MIN_CONTRAST_RATIO};
+pub use config::{apply_env, load_config, parse_config, DEFAULT_CONFIG_FILE, ENV_VARS};
+pub use error::QrGenError;
+pub use favicon::{favicon_warning, FAVICON_SIZES};
+pub use fetch::{DOWNLOAD_TIMEOUT, MAX_DOWNLOAD_SIZE};
+pub use icon::{
+    grayscale_icon, icon_frame_count, load_and_fit_icon, load_and_resize_icon, mask_icon_circle, overlay_icon_on_qr, suggest_halo_color, tint_icon,
+    DARK_HALO,
+};
+pub use matrix::{module_matrix, QrMatrix};
+pub use options::{
+    EcLevel, EyeStyle, Frame, Gradient, GradientDirection, GradientType, HaloBorder, IconAnchor, IconFilter, IconFit, IconPadding, IconShadow, IconShape,
+    ModuleStyle, PayloadEncoding, QrOptions, Rotation, DEFAULT_CAPTION_SIZE, DEFAULT_CARD_PADDING, DEFAULT_ICON_SCALE, DEFAULT_MARGIN, DEFAULT_MAX_DIMENSION,
+    DEFAULT_PDF_SIZE_MM, DEFAULT_SIZE, MAX_ICON_SCALE, MAX_MASK, MAX_MODULE_GAP, MAX_VERSION,
+};
+pub use output::{encode_image, encode_png, save_image, write_png_image, OutputFormat, MIN_SAFE_JPEG_QUALITY, PAYLOAD_KEYWORD};
+pub use payload::{
+    encode_url, geo_payload, mailto_payload, sms_payload, tel_payload, vcard_payload, wifi_payload, VCard, WifiAuth, WifiConfig,
+};
+pub use pdf::PDF_ICON_DPI;
+pub use sheet::{contact_sheet, contact_sheet_with_progress, ec_comparison_sheet, parse_sheet_entries, SheetEntry};
+pub use split::MAX_SPLIT_PARTS;
+pub use svg::SVG_METADATA_NAMESPACE;
+pub use terminal::{print_to_terminal, render_terminal};
+pub use verify::{scan_image, verify_image};
+
+use code::Code;
+use color::blend_over;
+use icon::IconArea;
+use render::Layout;
+
+/// Generates a QR code for `url` and writes it to `output_path`, with the
+/// icon at `icon_path` overlaid in the center, or `opts.icon` if there is no
+/// `icon_path`.
+///
+/// The format is chosen from the extension of `output_path`: `.svg` and `.pdf`
+/// produce vector output, anything else is rasterized.
+pub fn generate_qr_with_icon(url: impl AsRef<[u8]>, icon_path: Option<&str>, output_path: &str, opts: &QrOptions) -> Result<(), QrGenError> {
+    let output_path = Path::new(output_path);
+    let format = OutputFormat::from_path(output_path)?;
+    let opts = &match icon_path {
+        Some(icon_path) => opts.clone().with_icon(icon_path),
+        None => opts.clone(),
+    };
+    if format == OutputFormat::Raster(ImageFormat::Png) {
+        // Generate before creating the file, so a failure leaves nothing behind
+        let url = url.as_ref();
+        return output::write_png_file(output_path, &generate(url, opts)?, str::from_utf8(url).ok(), opts);
+    }
+    fs::write(output_path, generate_bytes(url, format, opts)?)?;
+    Ok(())
+}
+
+/// Generates a QR code for `url` encoded as `format`. PNG output records
+/// `url` in its metadata when it is text, see [`encode_png`].
+///
+/// The same `url`, `opts` and input files always give the same bytes, with
+/// no timestamps written, so the output can be committed and compared. Text
+/// in an SVG icon is the exception, as it is set in the system's fonts.
+pub fn generate_bytes(url: impl AsRef<[u8]>, format: OutputFormat, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    let url = url.as_ref();
+    // Vector output has no decoder and favicons are too small for it, so
+    // check the equivalent raster instead
+    let decodable = matches!(format, OutputFormat::Raster(format) if format != ImageFormat::Ico);
+    if opts.verify && !decodable {
+        generate(url, opts)?;
+    }
+
+    match format {
+        OutputFormat::Svg => Ok(generate_svg(url, opts)?.into_bytes()),
+        OutputFormat::Pdf => generate_pdf(url, opts),
+        OutputFormat::Raster(ImageFormat::Ico) => favicon::encode_favicon(url, opts),
+        OutputFormat::Raster(ImageFormat::Png) => encode_png(&generate(url, opts)?, str::from_utf8(url).ok(), opts),
+        OutputFormat::Raster(format) => encode_image(&generate(url, opts)?, format, opts),
+    }
+}
+
+/// Generates a QR code for `url` once and encodes it as each of `formats`,
+/// returning the bytes in the same order, as [`generate_bytes`] would one
+/// format at a time.
+///
+/// The payload is encoded, the icon and background image loaded and the
+/// raster image rendered only once, however many formats there are, and the
+/// symbol's size and the warnings about it come from that same preparation.
+pub fn generate_bytes_all(url: impl AsRef<[u8]>, formats: &[OutputFormat], opts: &QrOptions) -> Result<Generated, QrGenError> {
+    let url = url.as_ref();
+    let symbol = Symbol::prepare(url, opts)?;
+    let module_span = symbol.code.width() as u32 + 2 * opts.margin;
+    let favicon = formats.contains(&OutputFormat::Raster(ImageFormat::Ico));
+    let warnings = [
+        symbol.contrast_warning(opts),
+        symbol.anchor_warning(opts),
+        favicon.then(|| favicon::small_sizes_warning(module_span)).flatten(),
+    ];
+    let info = SymbolInfo::new(&symbol.code, opts.effective_ec_level(symbol.icon.is_some()));
+    let icon_coverage = symbol.icon_coverage(opts);
+    let icon_note = symbol.animated_note(opts);
+
+    let rasters = formats.iter().any(|format| matches!(format, OutputFormat::Raster(format) if *format != ImageFormat::Ico));
+    let image = match rasters || opts.verify {
+        true => Some(symbol.clone().render_image(opts)?),
+        false => None,
+    };
+    if let Some(image) = image.as_ref().filter(|_| opts.verify) {
+        verify_image(image, url)?;
+    }
+
+    let outputs = formats
+        .iter()
+        .map(|&format| match format {
+            OutputFormat::Svg => Ok(symbol.render_svg(opts, Some(url))?.into_bytes()),
+            OutputFormat::Pdf => symbol.render_pdf(opts),
+            OutputFormat::Raster(ImageFormat::Ico) => {
+                favicon::pack_favicon(module_span, opts, |opts| Symbol::new(symbol.code.clone(), opts)?.render_image(opts))
+            }
+            OutputFormat::Raster(format) => {
+                let image = image.as_ref().expect("rendered for every raster format");
+                match format {
+                    ImageFormat::Png => encode_png(image, str::from_utf8(url).ok(), opts),
+                    format => encode_image(image, format, opts),
+                }
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Generated { outputs, info, icon_coverage, warnings: warnings.into_iter().flatten().collect(), icon_note })
+}
+
+/// A QR code generated by [`generate_bytes_all`], and what it found out
+/// about the code on the way.
+#[derive(Debug, Clone)]
+pub struct Generated {
+    /// The encoded output in each of the formats asked for, in order.
+    pub outputs: Vec<Vec<u8>>,
+    /// The symbol encoded, as [`symbol_info`] reports it.
+    pub info: SymbolInfo,
+    /// The fraction of the modules the icon hides, as [`icon_coverage`]
+    /// reports it.
+    pub icon_coverage: Option<f64>,
+    /// The warnings [`background_contrast_warning`], [`icon_anchor_warning`]
+    /// and, when ICO was asked for, [`favicon_warning`] give.
+    pub warnings: Vec<String>,
+    /// The note [`animated_icon_note`] gives.
+    pub icon_note: Option<String>,
+}
+
+impl Generated {
+    /// The warning [`icon_coverage_warning`] gives for this code.
+    pub fn icon_coverage_warning(&self) -> Option<String> {
+        self.icon_coverage.and_then(|hidden| coverage_warning(hidden, self.info.ec_level))
+    }
+}
+
+/// Generates a QR code for `url` as [`generate_bytes`] does and writes it
+/// to `writer` as PNG, without holding the encoded file in memory.
+pub fn write_png<W: Write>(url: impl AsRef<[u8]>, opts: &QrOptions, writer: &mut W) -> Result<(), QrGenError> {
+    let url = url.as_ref();
+    write_png_image(&generate(url, opts)?, str::from_utf8(url).ok(), opts, writer)
+}
+
+/// Generates a QR code for `url` as PNG bytes, as [`generate_bytes`] does,
+/// with `icon`, the encoded bytes of any image format `image` reads, in the
+/// center instead of `opts.icon`. Nothing touches the filesystem, unless
+/// `icon` is `None` and `opts` names files.
+pub fn generate_png_bytes(url: impl AsRef<[u8]>, icon: Option<&[u8]>, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    let url = url.as_ref();
+    let Some(icon) = icon else {
+        return generate_bytes(url, OutputFormat::Raster(ImageFormat::Png), opts);
+    };
+
+    let icon = image::load_from_memory(icon).map_err(QrGenError::ImageDecode)?;
+    let code = render::encode(url, opts.effective_ec_level(true), opts)?;
+    let symbol = Symbol::with_icon(code, opts, |size| {
+        Ok(Some(finish_icon(icon::fit_icon(&icon, size, opts.icon_fit, opts.icon_filter), opts)))
+    })?;
+    let final_image = symbol.render_image(opts)?;
+    if opts.verify {
+        verify_image(&final_image, url)?;
+    }
+    encode_png(&final_image, str::from_utf8(url).ok(), opts)
+}
+
+/// Generates a QR code for `url` encoded as `format` and wrapped in a base64
+/// `data:` URI, ready to embed in HTML or JSON.
+pub fn generate_data_uri(url: impl AsRef<[u8]>, format: OutputFormat, opts: &QrOptions) -> Result<String, QrGenError> {
+    let bytes = generate_bytes(url, format, opts)?;
+    Ok(format!("data:{};base64,{}", format.mime_type(), STANDARD.encode(bytes)))
+}
+
+/// Generates a QR code for `url`, text or any other bytes, as an in-memory image, drawn over
+/// `opts.background_image` (if any), overlaying `opts.icon` (if any) in the
+/// center, drawing `opts.frame` (if any) around it and setting
+/// `opts.caption` (if any) below it, then rotating the result by
+/// `opts.rotate` (if set), placing it on an `opts.card_color` card (if set),
+/// padding it by `opts.outer_padding` and rounding its corners, or the
+/// card's, by `opts.card_radius`.
+///
+/// With `opts.verify` set, the finished image is decoded and must read back
+/// as `url`.
+pub fn generate(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<DynamicImage, QrGenError> {
+    let url = url.as_ref();
+    let final_image = Symbol::prepare(url, opts)?.render_image(opts)?;
+    if opts.verify {
+        verify_image(&final_image, url)?;
+    }
+    Ok(final_image)
+}
+
+/// Generates a QR code for `url` as [`generate`] does, along with the
+/// version, size, error correction level and mask it was encoded with.
+pub fn generate_with_info(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<(DynamicImage, SymbolInfo), QrGenError> {
+    let url = url.as_ref();
+    let symbol = Symbol::prepare(url, opts)?;
+    let info = SymbolInfo::new(&symbol.code, opts.effective_ec_level(symbol.icon.is_some()));
+    let final_image = symbol.render_image(opts)?;
+    if opts.verify {
+        verify_image(&final_image, url)?;
+    }
+    Ok((final_image, info))
+}
+
+/// Generates a QR code for `url` as an SVG document, embedding `opts.icon`
+/// (if any) in the center and `opts.background_image` (if any) behind it.
+pub fn generate_svg(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<String, QrGenError> {
+    let url = url.as_ref();
+    Symbol::prepare(url, opts)?.render_svg(opts, Some(url))
+}
+
+/// Checks whether the light modules of the code for `url` stand out from the
+/// dark ones over `opts.background_image`, returning a warning if too many
+/// of them sit on parts of the image that are too dark.
+///
+/// Returns `Ok(None)` when no background image is set.
+pub fn background_contrast_warning(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<Option<String>, QrGenError> {
+    if opts.background_image.is_none() {
+        return Ok(None);
+    }
+    Ok(Symbol::prepare(url.as_ref(), opts)?.contrast_warning(opts))
+}
+
+/// Fraction of the matrix modules of the code for `url` that `opts.icon`
+/// and its backing hide, or `None` when there is no icon.
+pub fn icon_coverage(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<Option<f64>, QrGenError> {
+    Ok(Symbol::prepare(url.as_ref(), &QrOptions { strict: false, ..opts.clone() })?.icon_coverage(opts))
+}
+
+/// Returns a warning when `opts.icon` and its backing hide more of the
+/// modules of the code for `url` than its error correction level can
+/// recover, see [`icon_coverage`].
+///
+/// Unlike [`QrOptions::icon_scale_warning`] this measures the overlay as
+/// drawn, backing and transparent parts of the icon included.
+pub fn icon_coverage_warning(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<Option<String>, QrGenError> {
+    let ec_level = opts.effective_ec_level(opts.icon.is_some());
+    Ok(icon_coverage(url, opts)?.and_then(|hidden| coverage_warning(hidden, ec_level)))
+}
+
+/// The warning about an icon hiding `hidden` of the modules, if that is
+/// more than `ec_level` can recover.
+fn coverage_warning(hidden: f64, ec_level: EcLevel) -> Option<String> {
+    (hidden > ec_level.recoverable_fraction()).then(|| {
+        format!(
+            "the icon hides {:.1}% of the modules, more than the ~{:.0}% error correction level {} can recover, \
+             so the code will likely not scan; use a smaller --icon-scale, less --icon-padding or a higher --ec-level",
+            hidden * 100.0,
+            ec_level.recoverable_fraction() * 100.0,
+            ec_level
+        )
+    })
+}
+
+/// Returns a warning when `opts.icon_anchor` and `opts.icon_offset` would
+/// put `opts.icon` or its backing over a finder pattern of the code for
+/// `url`, which the icon is moved clear of instead.
+pub fn icon_anchor_warning(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<Option<String>, QrGenError> {
+    if icon::icon_centered(opts) {
+        return Ok(None);
+    }
+    Ok(Symbol::prepare(url.as_ref(), &QrOptions { strict: false, ..opts.clone() })?.anchor_warning(opts))
+}
+
+/// Says which frame of an animated `opts.icon` is overlaid, as only
+/// `opts.icon_frame` of it can be.
+///
+/// Returns `Ok(None)` when there is no icon, it is a still image, or it
+/// does not exist or may not be downloaded, which generating the code
+/// reports instead.
+pub fn animated_icon_note(opts: &QrOptions) -> Result<Option<String>, QrGenError> {
+    let readable = |path: &&Path| path.exists() || (opts.allow_network && fetch::as_url(path).is_some());
+    let Some(icon_path) = opts.icon.as_deref().filter(readable) else {
+        return Ok(None);
+    };
+    Ok(frames_note(icon_path, icon::icon_frame_count_from(icon_path, opts.allow_network)?, opts))
+}
+
+/// The note about using `opts.icon_frame` of the icon at `icon_path`, if it
+/// has more than one of `frames`.
+fn frames_note(icon_path: &Path, frames: usize, opts: &QrOptions) -> Option<String> {
+    (frames > 1).then(|| {
+        format!(
+            "{} is animated; using frame {} of its {} frames (pick another with --icon-frame)",
+            icon_path.display(),
+            opts.icon_frame,
+            frames
+        )
+    })
+}
+
+/// Generates a QR code for `url` as a PDF document whose canvas is printed
+/// `opts.pdf_size_mm` square, embedding `opts.icon` (if any) in the center at
+/// [`PDF_ICON_DPI`].
+pub fn generate_pdf(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    Symbol::prepare(url.as_ref(), opts)?.render_pdf(opts)
+}
+
+/// Renders `matrix`, a code encoded elsewhere such as one read with
+/// [`QrMatrix::from_json`], as [`generate`] renders a payload. The matrix's
+/// quiet zone and error correction level take the place of `opts.margin` and
+/// `opts.ec_level`.
+///
+/// With `opts.verify` set, the finished image must decode, though to
+/// whatever the matrix holds, as there is no payload to compare it with.
+pub fn generate_from_matrix(matrix: &QrMatrix, opts: &QrOptions) -> Result<DynamicImage, QrGenError> {
+    let opts = &matrix_options(matrix, opts);
+    render::check_contrast(opts)?;
+    let final_image = Symbol::new(matrix.to_code(), opts)?.render_image(opts)?;
+    if opts.verify && verify::decode_bytes(&final_image).is_none() {
+        return Err(QrGenError::VerificationFailed(None));
+    }
+    Ok(final_image)
+}
+
+/// Renders `matrix` as [`generate_from_matrix`] does, encoded as `format`.
+/// ICO output packs the favicon sizes, as [`generate_bytes`] does.
+pub fn generate_from_matrix_bytes(matrix: &QrMatrix, format: OutputFormat, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    // Vector output has no decoder and favicons are too small for it, so
+    // check the equivalent raster instead
+    let decodable = matches!(format, OutputFormat::Raster(format) if format != ImageFormat::Ico);
+    if opts.verify && !decodable {
+        generate_from_matrix(matrix, opts)?;
+    }
+
+    let opts = &matrix_options(matrix, opts);
+    render::check_contrast(opts)?;
+    match format {
+        OutputFormat::Svg => Ok(Symbol::new(matrix.to_code(), opts)?.render_svg(opts, None)?.into_bytes()),
+        OutputFormat::Pdf => Symbol::new(matrix.to_code(), opts)?.render_pdf(opts),
+        OutputFormat::Raster(ImageFormat::Ico) => {
+            favicon::pack_favicon(matrix.width() + 2 * opts.margin, opts, |opts| generate_from_matrix(matrix, opts))
+        }
+        OutputFormat::Raster(ImageFormat::Png) => encode_png(&generate_from_matrix(matrix, opts)?, None, opts),
+        OutputFormat::Raster(format) => encode_image(&generate_from_matrix(matrix, opts)?, format, opts),
+    }
+}
+
+/// `opts` with the quiet zone and error correction level of `matrix`.
+fn matrix_options(matrix: &QrMatrix, opts: &QrOptions) -> QrOptions {
+    QrOptions { margin: matrix.quiet_zone(), ec_level: Some(matrix.ec_level()), ..opts.clone() }
+}
+
+/// Generates the codes for `url` encoded as `format`: just the one when `url`
+/// fits in a single code, and otherwise a Structured Append sequence of up to
+/// [`MAX_SPLIT_PARTS`] that scanners reassemble, in order.
+///
+/// Each part is decorated as [`generate_bytes`] would, and PNG and SVG parts
+/// record the whole payload in their metadata. `opts.verify` only
+/// applies to a single code, since the decoder cannot read the parts. Micro
+/// QR codes have no Structured Append mode, so with `opts.micro` a payload
+/// that does not fit one is an error.
+pub fn generate_split_bytes(url: impl AsRef<[u8]>, format: OutputFormat, opts: &QrOptions) -> Result<Vec<Vec<u8>>, QrGenError> {
+    let url = url.as_ref();
+    if opts.micro || fits(url, opts).is_ok() {
+        return Ok(vec![generate_bytes(url, format, opts)?]);
+    }
+
+    render::check_contrast(opts)?;
+    let ec_level = opts.effective_ec_level(opts.icon.is_some());
+    split::encode_parts(url, ec_level, opts)?
+        .into_iter()
+        .map(|code| {
+            if format == OutputFormat::Raster(ImageFormat::Ico) {
+                let min_size = code.width() as u32 + 2 * opts.margin;
+                return favicon::pack_favicon(min_size, opts, |opts| Symbol::new(code.clone(), opts)?.render_image(opts));
+            }
+            let symbol = Symbol::new(code, opts)?;
+            match format {
+                OutputFormat::Svg => Ok(symbol.render_svg(opts, Some(url))?.into_bytes()),
+                OutputFormat::Pdf => symbol.render_pdf(opts),
+                OutputFormat::Raster(ImageFormat::Png) => encode_png(&symbol.render_image(opts)?, str::from_utf8(url).ok(), opts),
+                OutputFormat::Raster(format) => encode_image(&symbol.render_image(opts)?, format, opts),
+            }
+        })
+        .collect()
+}
+
+/// Size of the QR symbol a payload encodes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolInfo {
+    /// QR version, from 1 to 40, or from 1 to 4 for a Micro QR code.
+    pub version: u8,
+    /// Whether the code is a Micro QR code.
+    pub micro: bool,
+    /// Number of modules along one side of the matrix.
+    pub width: u32,
+    pub ec_level: EcLevel,
+    /// Mask pattern applied to the data, from 0 to [`MAX_MASK`], or to 3 for
+    /// a Micro QR code.
+    pub mask: u8,
+}
+
+impl SymbolInfo {
+    fn new(code: &Code, ec_level: EcLevel) -> SymbolInfo {
+        SymbolInfo { version: code.version(), micro: code.is_micro(), width: code.width() as u32, ec_level, mask: code.mask() }
+    }
+
+    /// The version as it is usually written: `M2` for a Micro QR code, or
+    /// just the number otherwise.
+    pub fn version_name(&self) -> String {
+        version_name(self.version, self.micro)
+    }
+}
+
+/// How version `version` is written: prefixed with `M` if it is a Micro QR
+/// version.
+fn version_name(version: u8, micro: bool) -> String {
+    if micro { format!("M{}", version) } else { version.to_string() }
+}
+
+/// Reports the version, module count, error correction level and mask `url`
+/// is encoded with under `opts`.
+pub fn symbol_info(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<SymbolInfo, QrGenError> {
+    let ec_level = opts.effective_ec_level(opts.icon.is_some());
+    let code = render::encode_matrix(url.as_ref(), ec_level, opts)?;
+    Ok(SymbolInfo::new(&code, ec_level))
+}
+
+/// An encoded QR code together with everything needed to render it in any
+/// output format.
+#[derive(Clone)]
+struct Symbol {
+    code: Code,
+    layout: Layout,
+    icon: Option<DynamicImage>,
+    /// Number of frames in the file `icon` was taken from.
+    icon_frames: usize,
+    /// `opts.background_image`, scaled to the canvas with the scrim applied.
+    backdrop: Option<RgbaImage>,
+}
+
+impl Symbol {
+    /// Encodes `url` and prepares it for rendering.
+    fn prepare(url: &[u8], opts: &QrOptions) -> Result<Symbol, QrGenError> {
+        let ec_level = opts.effective_ec_level(opts.icon.is_some());
+        Symbol::new(render::encode(url, ec_level, opts)?, opts)
+    }
+
+    /// Lays out `code` and loads the images drawn with it.
+    fn new(code: Code, opts: &QrOptions) -> Result<Symbol, QrGenError> {
+        let mut icon_frames = 1;
+        let symbol = Symbol::with_icon(code, opts, |size| {
+            let Some(icon_path) = opts.icon.as_deref() else {
+                return Ok(None);
+            };
+            let (icon, frames) = load_icon(icon_path, size, opts)?;
+            icon_frames = frames;
+            Ok(Some(icon))
+        })?;
+        Ok(Symbol { icon_frames, ..symbol })
+    }
+
+    /// Lays out `code` and loads the images drawn with it, taking the icon
+    /// from `load_icon`, which is given the size to fit it to.
+    fn with_icon(
+        code: Code,
+        opts: &QrOptions,
+        load_icon: impl FnOnce(u32) -> Result<Option<DynamicImage>, QrGenError>,
+    ) -> Result<Symbol, QrGenError> {
+        let layout = Layout::new(code.width() as u32, opts)?;
+
+        // Load and process the icon (make it proportional to QR code size)
+        let icon_size = ((layout.matrix_size() as f64 * opts.icon_scale / 100.0).round() as u32).max(1);
+        let icon = match load_icon(icon_size)? {
+            Some(_) if !(opts.icon_scale > 0.0 && opts.icon_scale <= MAX_ICON_SCALE) => {
+                return Err(QrGenError::InvalidIconScale(opts.icon_scale));
+            }
+            Some(icon) => Some(match opts.rotate {
+                Some(rotation) if opts.icon_upright => rotate(icon, rotation.inverse()),
+                _ => icon,
+            }),
+            None => None,
+        };
+
+        if caption::caption_text(opts).is_some() && !(opts.caption_size.is_finite() && opts.caption_size > 0.0) {
+            return Err(QrGenError::InvalidCaptionSize(opts.caption_size));
+        }
+        if !(0.0..=MAX_MODULE_GAP).contains(&opts.module_gap) {
+            return Err(QrGenError::InvalidModuleGap(opts.module_gap));
+        }
+
+        let ec_level = opts.effective_ec_level(icon.is_some());
+        log::debug!(
+            "Encoded version {} ({}x{} modules) at error correction {} with mask {}; module size {}px on a {}px canvas",
+            version_name(code.version(), code.is_micro()),
+            layout.qr_width,
+            layout.qr_width,
+            ec_level,
+            code.mask(),
+            layout.module_size,
+            layout.canvas_size
+        );
+        if let Some(icon) = icon.as_ref().filter(|_| opts.strict || log::log_enabled!(log::Level::Debug)) {
+            let hidden = icon::hidden_module_fraction(&layout, icon, opts);
+            log::debug!("Icon is {}x{}px and hides {:.1}% of the modules", icon.width(), icon.height(), hidden * 100.0);
+            if opts.strict && hidden > ec_level.recoverable_fraction() {
+                return Err(QrGenError::IconCoverageTooHigh { hidden, ec_level });
+            }
+        }
+        if let Some(region) = icon.as_ref().filter(|_| opts.forbid_icon_overlap).and_then(|icon| icon::icon_overlap(&layout, icon, opts)) {
+            return Err(QrGenError::IconOverlap(region));
+        }
+
+        let backdrop = match &opts.background_image {
+            Some(path) => Some(backdrop::load_backdrop(path, layout.canvas_size, opts)?),
+            None => None,
+        };
+
+        Ok(Symbol { code, layout, icon, icon_frames: 1, backdrop })
+    }
+
+    /// The warning [`background_contrast_warning`] gives.
+    fn contrast_warning(&self, opts: &QrOptions) -> Option<String> {
+        self.backdrop.as_ref().and_then(|backdrop| backdrop::contrast_warning(backdrop, &self.code, &self.layout, opts))
+    }
+
+    /// The fraction [`icon_coverage`] reports.
+    fn icon_coverage(&self, opts: &QrOptions) -> Option<f64> {
+        self.icon.as_ref().map(|icon| icon::hidden_module_fraction(&self.layout, icon, opts))
+    }
+
+    /// The warning [`icon_anchor_warning`] gives.
+    fn anchor_warning(&self, opts: &QrOptions) -> Option<String> {
+        let moved = self.icon.as_ref().is_some_and(|icon| !icon::icon_centered(opts) && icon::icon_moved_off_finders(&self.layout, icon, opts));
+        moved.then(|| "the icon's --icon-anchor and --icon-offset would put it over a finder pattern, so it is moved clear of it".to_string())
+    }
+
+    /// The note [`animated_icon_note`] gives.
+    fn animated_note(&self, opts: &QrOptions) -> Option<String> {
+        opts.icon.as_deref().filter(|_| self.icon.is_some()).and_then(|icon_path| frames_note(icon_path, self.icon_frames, opts))
+    }
+
+    /// Renders the finished raster image: the code over the backdrop with the
+    /// icon, frame and caption, rotated and padded.
+    fn render_image(self, opts: &QrOptions) -> Result<DynamicImage, QrGenError> {
+        let qr_image = match self.backdrop {
+            Some(backdrop) => render::render_raster_onto(backdrop, &self.code, &self.layout, opts),
+            None => render::render_raster(&self.code, &self.layout, opts),
+        };
+
+        let final_image = match self.icon {
+            // Create the final image with the icon at its anchor
+            Some(icon) => icon::overlay_icon(qr_image, icon, &IconArea::matrix(&self.layout), opts)?,
+            None => DynamicImage::ImageRgba8(qr_image),
+        };
+        let final_image = match &opts.frame {
+            Some(frame) => DynamicImage::ImageRgba8(frame::apply_frame(&final_image.to_rgba8(), frame, &self.layout, opts)),
+            None => final_image,
+        };
+        let final_image = match caption::caption_text(opts) {
+            Some(caption) => DynamicImage::ImageRgba8(caption::add_caption(&final_image.to_rgba8(), caption, opts)),
+            None => final_image,
+        };
+        let final_image = match opts.rotate {
+            Some(rotation) => rotate(final_image, rotation),
+            None => final_image,
+        };
+        let final_image = match (opts.card_color, opts.outer_padding) {
+            (Some(color), _) => DynamicImage::ImageRgba8(place_on_card(&final_image.to_rgba8(), color, opts)),
+            (None, 0) => final_image,
+            (None, padding) => DynamicImage::ImageRgba8(pad(&final_image.to_rgba8(), padding, opts)),
+        };
+        let final_image = match opts.card_radius {
+            // A card has its corners rounded as it is drawn
+            _ if opts.card_color.is_some() => final_image,
+            0 => final_image,
+            radius => DynamicImage::ImageRgba8(round_corners(final_image.to_rgba8(), radius)),
+        };
+
+        // Only keep the alpha channel when it carries information
+        let final_image = if opts.background_pixel()[3] < 255 || opts.card_radius > 0 {
+            final_image
+        } else {
+            DynamicImage::ImageRgb8(final_image.to_rgb8())
+        };
+        Ok(final_image)
+    }
+
+    /// Renders an SVG document, recording `payload` in its metadata when it
+    /// is text, and described by `opts.alt` or else by that text.
+    fn render_svg(&self, opts: &QrOptions, payload: Option<&[u8]>) -> Result<String, QrGenError> {
+        let text = payload.and_then(|payload| str::from_utf8(payload).ok()).filter(|_| opts.metadata);
+        let alt = opts.alt.as_deref().or(text).filter(|alt| !alt.is_empty());
+        svg::render_svg(&self.code, &self.layout, opts, alt, text, self.icon.as_ref(), self.backdrop.as_ref())
+    }
+
+    /// Renders a PDF document, reloading the icon and background image so
+    /// they stay sharp at the printed size.
+    fn render_pdf(&self, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+        let print_icon = match (&opts.icon, &self.icon) {
+            (Some(icon_path), Some(placed)) => {
+                let size = pdf::print_pixels(placed.width().max(placed.height()), &self.layout, opts);
+                let (icon, _) = load_icon(icon_path, size, opts)?;
+                Some(match opts.rotate {
+                    Some(rotation) if opts.icon_upright => rotate(icon, rotation.inverse()),
+                    _ => icon,
+                })
+            }
+            _ => None,
+        };
+        let print_backdrop = match &opts.background_image {
+            Some(path) => Some(backdrop::load_backdrop(path, pdf::print_pixels(self.layout.canvas_size, &self.layout, opts), opts)?),
+            None => None,
+        };
+        Ok(pdf::render_pdf(&self.code, &self.layout, opts, print_icon.as_ref(), print_backdrop.as_ref()))
+    }
+}
+
+/// Loads the icon at `icon_path`, downloading it if it is a URL and
+/// `opts.allow_network` is set, into a `size` x `size` box, fitted according
+/// to `opts.icon_fit` with `opts.icon_filter`, recolored by
+/// `opts.icon_grayscale` and `opts.icon_tint` and shaped according to
+/// `opts.icon_shape`.
+fn load_icon(icon_path: &Path, size: u32, opts: &QrOptions) -> Result<(DynamicImage, usize), QrGenError> {
+    let (icon, frames) = icon::load_and_fit_icon_from(icon_path, opts.allow_network, size, opts.icon_fit, opts.icon_filter, opts.icon_frame)?;
+    Ok((finish_icon(icon, opts), frames))
+}
+
+/// Recolors a fitted `icon` by `opts.icon_grayscale` and `opts.icon_tint`
+/// and shapes it according to `opts.icon_shape`.
+fn finish_icon(icon: DynamicImage, opts: &QrOptions) -> DynamicImage {
+    let icon = if opts.icon_grayscale { grayscale_icon(&icon) } else { icon };
+    let icon = match opts.icon_tint {
+        Some(tint) => tint_icon(&icon, tint),
+        None => icon,
+    };
+    match opts.icon_shape {
+        IconShape::Square => icon,
+        IconShape::Circle => mask_icon_circle(&icon),
+    }
+}
+
+/// Surrounds `image` with `padding` pixels of the background, see
+/// [`QrOptions::background_pixel`].
+fn pad(image: &RgbaImage, padding: u32, opts: &QrOptions) -> RgbaImage {
+    let background = opts.background_pixel();
+    let mut canvas = RgbaImage::from_pixel(image.width() + 2 * padding, image.height() + 2 * padding, background);
+    image::imageops::replace(&mut canvas, image, padding as i64, padding as i64);
+    canvas
+}
+
+/// Centers `image` on a `color` card `opts.card_padding` wider on each side,
+/// with corners rounded by `opts.card_radius`, inside `opts.outer_padding` of
+/// background. Past the card's corners shows the outer padding, or nothing
+/// without any.
+fn place_on_card(image: &RgbaImage, color: Rgb<u8>, opts: &QrOptions) -> RgbaImage {
+    let (padding, inset) = (opts.card_padding, opts.outer_padding);
+    let (card_width, card_height) = (image.width() + 2 * padding, image.height() + 2 * padding);
+    let surround = if inset > 0 { opts.background_pixel() } else { Rgba([0, 0, 0, 0]) };
+    let mut canvas = RgbaImage::from_pixel(card_width + 2 * inset, card_height + 2 * inset, surround);
+
+    let (left, width, height) = (inset as f64, card_width as f64, card_height as f64);
+    let radius = (opts.card_radius as f64).min(width.min(height) / 2.0);
+    for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+        let distance = render::rounded_rect_distance(x as f64 + 0.5, y as f64 + 0.5, left, left, width, height, radius);
+        *pixel = blend_over(*pixel, color, render::coverage(distance, true));
+    }
+    image::imageops::replace(&mut canvas, image, (inset + padding) as i64, (inset + padding) as i64);
+    canvas
+}
+
+/// Makes the corners of `image` transparent beyond a rounding of `radius`
+/// pixels, or half its shorter side if that is smaller, anti-aliasing the
+/// edge.
+fn round_corners(mut image: RgbaImage, radius: u32) -> RgbaImage {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let radius = (radius as f64).min(width.min(height) / 2.0);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let distance = render::rounded_rect_distance(x as f64 + 0.5, y as f64 + 0.5, 0.0, 0.0, width, height, radius);
+        pixel[3] = (pixel[3] as f64 * render::coverage(distance, true)).round() as u8;
+    }
+    image
+}
+
+/// Rotates `image` clockwise by `rotation`.
+fn rotate(image: DynamicImage, rotation: Rotation) -> DynamicImage {
+    match rotation {
+        Rotation::Deg90 => image.rotate90(),
+        Rotation::Deg180 => image.rotate180(),
+        Rotation::Deg270 => image.rotate270(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb};
+    use qrcode::QrCode;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_qr_generation() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("test_qr.png");
+
+        // Create a simple test icon (1x1 white pixel)
+        let test_icon = DynamicImage::new_rgb8(1, 1);
+        let icon_path = temp_dir.path().join("test_icon.png");
+        test_icon.save(&icon_path).unwrap();
+
+        // Test QR generation
+        let result = generate_qr_with_icon(
+            "https://example.com",
+            Some(icon_path.to_str().unwrap()),
+            output_path.to_str().unwrap(),
+            &QrOptions::default(),
+        );
+
+        assert!(result.is_ok());
+        assert!(output_path.exists());
+    }
+
+    /// Decodes the single QR code in `image`.
+    fn decode(image: &DynamicImage) -> String {
+        verify::decode_payload(image).expect("QR code did not decode")
+    }
+
+    #[test]
+    fn test_plain_qr_without_icon() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("plain_qr.png");
+
+        generate_qr_with_icon("https://example.com", None, output_path.to_str().unwrap(), &QrOptions::default()).unwrap();
+
+        let image = image::open(&output_path).unwrap();
+        assert_eq!(decode(&image), "https://example.com");
+    }
+
+    #[test]
+    fn test_generate_in_memory() {
+        let temp_dir = tempdir().unwrap();
+
+        let test_icon = DynamicImage::new_rgb8(1, 1);
+        let icon_path = temp_dir.path().join("test_icon.png");
+        test_icon.save(&icon_path).unwrap();
+
+        let opts = QrOptions { ec_level: Some(EcLevel::H), ..QrOptions::default() };
+        let plain = generate("https://example.com", &opts).unwrap();
+        let with_icon = generate("https://example.com", &opts.with_icon(&icon_path)).unwrap();
+
+        assert!(plain.width() > 0);
+        assert_eq!(plain.width(), plain.height());
+        assert_eq!(plain.dimensions(), with_icon.dimensions());
+    }
+
+    #[test]
+    fn test_icon_tint() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("red.png");
+        RgbaImage::from_pixel(40, 40, image::Rgba([230, 20, 20, 255])).save(&icon_path).unwrap();
+
+        let opts = QrOptions::default().with_icon(&icon_path).with_icon_tint(Rgb([0, 80, 160]));
+        let img = generate("https://example.com", &opts).unwrap().to_rgb8();
+        let center = img.width() / 2;
+        assert_eq!(*img.get_pixel(center, center), Rgb([0, 80, 160]));
+
+        let opts = QrOptions { icon_grayscale: true, ..QrOptions::default().with_icon(&icon_path) };
+        let [r, g, b] = generate("https://example.com", &opts).unwrap().to_rgb8().get_pixel(center, center).0;
+        assert!(r == g && g == b);
+    }
+
+    #[test]
+    fn test_custom_size() {
+        let opts = QrOptions { size: 1000, ..QrOptions::default() };
+        let image = generate("https://example.com", &opts).unwrap();
+        assert_eq!(image.dimensions(), (1000, 1000));
+    }
+
+    #[test]
+    fn test_qr_is_centered_in_canvas() {
+        // Leave a 10px band that doesn't divide evenly into modules
+        let qr_width = QrCode::new("https://example.com").unwrap().width() as u32;
+        let opts = QrOptions { size: qr_width * 16 + 10, margin: 0, ..QrOptions::default() };
+        let image = generate("https://example.com", &opts).unwrap().to_rgb8();
+
+        let dark: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, p)| **p == Rgb([0, 0, 0]))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let min_x = dark.iter().map(|&(x, _)| x).min().unwrap();
+        let max_x = dark.iter().map(|&(x, _)| x).max().unwrap();
+        let min_y = dark.iter().map(|&(_, y)| y).min().unwrap();
+        let max_y = dark.iter().map(|&(_, y)| y).max().unwrap();
+
+        let right = image.width() - 1 - max_x;
+        let bottom = image.height() - 1 - max_y;
+        assert_eq!(min_x, 5);
+        assert!(min_x.abs_diff(right) <= 1);
+        assert!(min_y.abs_diff(bottom) <= 1);
+    }
+
+    #[test]
+    fn test_margin_is_background() {
+        let qr_width = QrCode::new("https://example.com").unwrap().width() as u32;
+        let margin = 4;
+        let module_size = 10;
+        let opts = QrOptions { size: (qr_width + 2 * margin) * module_size, margin, ..QrOptions::default() };
+        let image = generate("https://example.com", &opts).unwrap().to_rgb8();
+
+        let band = margin * module_size;
+        let size = image.width();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if x < band || y < band || x >= size - band || y >= size - band {
+                assert_eq!(*pixel, Rgb([255, 255, 255]), "margin pixel ({}, {}) is not background", x, y);
+            }
+        }
+
+        // The finder patterns sit right inside the quiet zone on both sides
+        assert_eq!(*image.get_pixel(band, band), Rgb([0, 0, 0]));
+        assert_eq!(*image.get_pixel(size - band - 1, band), Rgb([0, 0, 0]));
+        assert_eq!(*image.get_pixel(band, size - band - 1), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_png_bytes_with_icon_from_memory() {
+        let mut icon = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(40, 40, Rgb([255, 0, 0])))
+            .write_to(&mut std::io::Cursor::new(&mut icon), ImageFormat::Png)
+            .unwrap();
+        let opts = QrOptions::default().with_verify(true);
+
+        let bytes = generate_png_bytes("https://example.com", Some(&icon), &opts).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+        let image = image::load_from_memory(&bytes).unwrap();
+        let center = DEFAULT_SIZE / 2;
+        assert_eq!(image.to_rgb8().get_pixel(center, center).0, [255, 0, 0]);
+        assert_eq!(decode(&image), "https://example.com");
+
+        let plain = generate_png_bytes("https://example.com", None, &opts).unwrap();
+        assert!(plain.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert!(matches!(generate_png_bytes("https://example.com", Some(b"not an image"), &opts), Err(QrGenError::ImageDecode(_))));
+    }
+
+    #[test]
+    fn test_transparent_png_keeps_alpha() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("transparent.png");
+        let opts = QrOptions { transparent: true, ..QrOptions::default() };
+
+        generate_qr_with_icon("https://example.com", None, output_path.to_str().unwrap(), &opts).unwrap();
+
+        let image = image::open(&output_path).unwrap();
+        assert!(image.color().has_alpha());
+        let rgba = image.to_rgba8();
+        assert_eq!(rgba.get_pixel(0, 0)[3], 0);
+        assert!(rgba.pixels().any(|p| p[3] == 255));
+    }
+
+    #[test]
+    fn test_translucent_background() {
+        let opts = QrOptions::default().with_background_alpha(128).with_outer_padding(10);
+        let image = generate("https://example.com", &opts.clone().with_verify(true)).unwrap();
+        assert!(image.color().has_alpha());
+        let rgba = image.to_rgba8();
+        // The padding and quiet zone are half opaque, the modules fully
+        assert_eq!(*rgba.get_pixel(0, 0), image::Rgba([255, 255, 255, 128]));
+        assert_eq!(*rgba.get_pixel(20, 20), image::Rgba([255, 255, 255, 128]));
+        assert!(rgba.pixels().any(|p| *p == image::Rgba([0, 0, 0, 255])));
+
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert!(svg.contains("fill=\"#ffffff\" fill-opacity=\"0.502\""));
+        let pdf = generate_pdf("https://example.com", &opts).unwrap();
+        let graphics_state = b"/Background << /Type /ExtGState /ca 0.50";
+        assert!(pdf.windows(graphics_state.len()).any(|w| w == graphics_state));
+    }
+
+    #[test]
+    fn test_icon_coverage() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("icon.png");
+        RgbaImage::from_pixel(40, 40, image::Rgba([255, 0, 0, 255])).save(&icon_path).unwrap();
+        let opts = QrOptions::default().with_icon(&icon_path).with_icon_scale(30.0).with_icon_padding(IconPadding::Pixels(0));
+        assert_eq!(icon_coverage("https://example.com", &QrOptions::default()).unwrap(), None);
+
+        // A third of the side hides about a ninth of the modules
+        let hidden = icon_coverage("https://example.com", &opts).unwrap().unwrap();
+        assert!((0.07..0.11).contains(&hidden), "{}", hidden);
+        let padded = icon_coverage("https://example.com", &opts.clone().with_icon_padding(IconPadding::Percent(20.0))).unwrap();
+        assert!(padded.unwrap() > hidden);
+
+        // Too much for L, which only recovers about 7%
+        let opts = opts.with_ec_level(EcLevel::L);
+        assert!(icon_coverage_warning("https://example.com", &opts).unwrap().unwrap().contains("error correction level L"));
+        assert!(generate("https://example.com", &opts).is_ok());
+        let result = generate("https://example.com", &opts.clone().with_strict(true));
+        assert!(matches!(result, Err(QrGenError::IconCoverageTooHigh { ec_level: EcLevel::L, .. })));
+        let opts = opts.with_ec_level(EcLevel::H).with_strict(true);
+        assert!(icon_coverage_warning("https://example.com", &opts).unwrap().is_none());
+        assert!(generate("https://example.com", &opts).is_ok());
+    }
+
+    #[test]
+    fn test_icon_anchor_warning() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("red.png");
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(40, 40, Rgb([220, 0, 0]))).save(&icon_path).unwrap();
+
+        // A top-left icon steps past the finder pattern, which stays whole
+        let opts = QrOptions::default().with_icon(&icon_path).with_icon_anchor(IconAnchor::TopLeft);
+        assert!(icon_anchor_warning("https://example.com", &opts).unwrap().unwrap().contains("finder pattern"));
+        let image = generate("https://example.com", &opts).unwrap();
+        assert_eq!(decode(&image), "https://example.com");
+        let symbol = Symbol::prepare(b"https://example.com", &opts).unwrap();
+        let (offset, module) = (symbol.layout.offset, symbol.layout.module_size);
+        assert_eq!(image.to_rgb8()[(offset + module / 2, offset + module / 2)], Rgb([0, 0, 0]));
+        assert_eq!(image.to_rgb8()[(offset + 3 * module, offset + 3 * module)], Rgb([0, 0, 0]));
+
+        let opts = opts.with_icon_anchor(IconAnchor::BottomRight);
+        assert!(icon_anchor_warning("https://example.com", &opts).unwrap().is_none());
+        assert_eq!(decode(&generate("https://example.com", &opts).unwrap()), "https://example.com");
+        assert!(icon_anchor_warning("https://example.com", &QrOptions::default().with_icon(&icon_path)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_forbid_icon_overlap() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("red.png");
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(40, 40, Rgb([220, 0, 0]))).save(&icon_path).unwrap();
+        let opts = QrOptions::default().with_icon(&icon_path).with_forbid_icon_overlap(true);
+        assert!(generate("https://example.com", &opts).is_ok());
+
+        // A 120 pixel icon with 80 pixels of backing a side reaches the finder patterns of a 300 pixel matrix,
+        // and with 100 a side spills into the quiet zone
+        let opts = opts.with_icon_scale(MAX_ICON_SCALE);
+        let result = generate("https://example.com", &opts.clone().with_icon_padding(IconPadding::Pixels(80)));
+        assert!(matches!(result, Err(QrGenError::IconOverlap("a finder pattern"))));
+        let error = generate("https://example.com", &opts.clone().with_icon_padding(IconPadding::Pixels(100))).unwrap_err();
+        assert!(matches!(error, QrGenError::IconOverlap("the quiet zone")));
+        assert!(error.to_string().contains("--icon-padding"), "{}", error);
+        assert!(generate_svg("https://example.com", &opts.clone().with_icon_padding(IconPadding::Pixels(100))).is_err());
+
+        // Only asked for, since a code can often survive it
+        let opts = opts.with_icon_padding(IconPadding::Pixels(80)).with_forbid_icon_overlap(false);
+        assert!(generate("https://example.com", &opts).is_ok());
+    }
+
+    #[test]
+    fn test_icon_scale() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("test_icon.png");
+        DynamicImage::new_rgb8(100, 100).save(&icon_path).unwrap();
+
+        let opts = QrOptions::default().with_icon(&icon_path).with_icon_scale(25.0);
+        let symbol = Symbol::prepare(b"https://example.com", &opts).unwrap();
+        let expected = (symbol.layout.matrix_size() as f64 * 0.25).round() as u32;
+        assert_eq!(symbol.icon.unwrap().width(), expected);
+
+        let result = generate("https://example.com", &opts.with_icon_scale(45.0));
+        assert!(matches!(result, Err(QrGenError::InvalidIconScale(_))));
+    }
+
+    #[test]
+    fn test_custom_colors() {
+        let navy = Rgb([0x1a, 0x2b, 0x3c]);
+        let cream = Rgb([0xff, 0xf8, 0xe7]);
+        let opts = QrOptions { foreground: navy, background: cream, ..QrOptions::default() };
+        let image = generate("https://example.com", &opts).unwrap().to_rgb8();
+
+        assert!(image.pixels().all(|p| *p == navy || *p == cream));
+        assert!(image.pixels().any(|p| *p == navy));
+        assert!(image.pixels().any(|p| *p == cream));
+    }
+
+    #[test]
+    fn test_low_contrast_rejected() {
+        let opts = QrOptions {
+            foreground: Rgb([200, 200, 200]),
+            background: Rgb([255, 255, 255]),
+            ..QrOptions::default()
+        };
+        let result = generate("https://example.com", &opts);
+        assert!(matches!(result, Err(QrGenError::LowContrast { .. })));
+    }
+
+    #[test]
+    fn test_size_too_small() {
+        let opts = QrOptions { size: 10, ..QrOptions::default() };
+        let result = generate("https://example.com", &opts);
+        assert!(matches!(result, Err(QrGenError::SizeTooSmall { size: 10, .. })));
+    }
+
+    #[test]
+    fn test_long_url_renders_non_degenerate_image() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("test_icon.png");
+        DynamicImage::new_rgb8(10, 10).save(&icon_path).unwrap();
+
+        let url = format!("https://example.com/?q={}", "a".repeat(1200));
+        let image = generate(&url, &QrOptions::default().with_icon(&icon_path)).unwrap().to_rgb8();
+
+        assert_eq!(image.dimensions(), (DEFAULT_SIZE, DEFAULT_SIZE));
+        assert!(image.pixels().any(|p| *p == Rgb([0, 0, 0])));
+        assert!(image.pixels().any(|p| *p == Rgb([255, 255, 255])));
+    }
+
+    #[test]
+    fn test_minimum_size_gives_one_pixel_modules() {
+        let code = QrCode::new("https://example.com").unwrap();
+        let min = code.width() as u32 + 2 * DEFAULT_MARGIN;
+
+        let opts = QrOptions { size: min, ..QrOptions::default() };
+        let image = generate("https://example.com", &opts).unwrap();
+        assert_eq!(image.dimensions(), (min, min));
+
+        let opts = QrOptions { size: min - 1, ..QrOptions::default() };
+        let err = generate("https://example.com", &opts).unwrap_err();
+        assert!(err.to_string().contains(&format!("at least {}px", min)));
+    }
+
+    #[test]
+    fn test_unsupported_output_format() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("test_qr.unknown");
+
+        let image = generate("https://example.com", &QrOptions::default()).unwrap();
+        let result = save_image(&image, &output_path, &QrOptions::default());
+        assert!(matches!(result, Err(QrGenError::UnsupportedOutputFormat(ext)) if ext == "unknown"));
+
+        // A favicon is packed from the code itself, which a finished image no longer is
+        let result = save_image(&image, &temp_dir.path().join("sheet.ico"), &QrOptions::default());
+        assert!(matches!(result, Err(QrGenError::UnsupportedOutputFormat(ext)) if ext == "ico"));
+    }
+
+    #[test]
+    fn test_svg_output() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("test_qr.svg");
+
+        let test_icon = DynamicImage::new_rgb8(10, 10);
+        let icon_path = temp_dir.path().join("test_icon.png");
+        test_icon.save(&icon_path).unwrap();
+
+        let opts = QrOptions {
+            foreground: Rgb([0x1a, 0x2b, 0x3c]),
+            background: Rgb([0xff, 0xf8, 0xe7]),
+            ..QrOptions::default()
+        };
+        generate_qr_with_icon(
+            "https://example.com",
+            Some(icon_path.to_str().unwrap()),
+            output_path.to_str().unwrap(),
+            &opts,
+        )
+        .unwrap();
+
+        let svg = fs::read_to_string(&output_path).unwrap();
+        assert!(svg.contains("<svg"));
+        assert!(svg.contains("viewBox=\"0 0 400 400\""));
+        assert!(svg.contains("fill=\"#1a2b3c\""));
+        assert!(svg.contains("fill=\"#fff8e7\""));
+        assert!(svg.contains("href=\"data:image/png;base64,"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_svg_alt_text() {
+        let svg = generate_svg("https://example.com/?a=1&b=\"2\"", &QrOptions::default()).unwrap();
+        assert!(svg.contains(" role=\"img\" aria-label=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">\n"));
+        assert!(svg.contains("<title>https://example.com/?a=1&amp;b=&quot;2&quot;</title>"));
+
+        let svg = generate_svg("https://example.com", &QrOptions::default().with_alt("Menu for table 4")).unwrap();
+        assert!(svg.contains("<title>Menu for table 4</title>"));
+        assert!(svg.contains("aria-label=\"Menu for table 4\""));
+        // The title is the root's first child
+        assert!(svg.lines().nth(2).unwrap().starts_with("<title>"));
+
+        let svg = generate_svg("https://example.com", &QrOptions::default().with_alt("")).unwrap();
+        assert!(!svg.contains("<title>") && !svg.contains("aria-label"));
+    }
+
+    #[test]
+    fn test_write_png_streams_to_writer() {
+        let opts = QrOptions { dpi: Some(300), ..QrOptions::default() };
+        let mut png = Vec::new();
+        write_png("https://example.com", &opts, &mut png).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(png, generate_bytes("https://example.com", OutputFormat::Raster(ImageFormat::Png), &opts).unwrap());
+    }
+
+    /// Reference outputs for `test_golden_output`; run it with
+    /// `UPDATE_GOLDEN=1` set to rewrite them after an intended change.
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+    #[test]
+    fn test_golden_output() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("icon.png");
+        image::RgbaImage::from_fn(64, 64, |x, y| image::Rgba([x as u8 * 4, 40, y as u8 * 4, 255])).save(&icon_path).unwrap();
+        // Metadata names the crate version, which would change the bytes with every release
+        let opts = QrOptions {
+            antialias: true,
+            ..QrOptions::default()
+                .with_size(300)
+                .with_module_style(ModuleStyle::Rounded)
+                .with_eye_color(Rgb([0xc0, 0x20, 0x20]))
+                .with_icon(&icon_path)
+                .with_icon_shape(IconShape::Circle)
+                .with_metadata(false)
+        };
+
+        let formats = [OutputFormat::Raster(ImageFormat::Png), OutputFormat::Svg];
+        let outputs = generate_bytes_all("https://example.com/golden", &formats, &opts).unwrap().outputs;
+        for (name, bytes) in ["styled.png", "styled.svg"].into_iter().zip(outputs) {
+            let path = Path::new(GOLDEN_DIR).join(name);
+            if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                fs::write(&path, &bytes).unwrap();
+            }
+            let reference = fs::read(&path).unwrap();
+            assert!(reference == bytes, "{} no longer matches its reference; set UPDATE_GOLDEN=1 if that is intended", name);
+        }
+
+        // With metadata too, the same inputs give the same bytes every time
+        let png = OutputFormat::Raster(ImageFormat::Png);
+        let opts = opts.with_metadata(true);
+        assert_eq!(generate_bytes("https://example.com/golden", png, &opts).unwrap(), generate_bytes("https://example.com/golden", png, &opts).unwrap());
+    }
+
+    #[test]
+    fn test_generate_bytes_all_matches_each_format() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("icon.png");
+        DynamicImage::new_rgb8(40, 40).save(&icon_path).unwrap();
+        let opts = QrOptions::default().with_icon(&icon_path).with_verify(true);
+
+        let formats = [
+            OutputFormat::Raster(ImageFormat::Png),
+            OutputFormat::Svg,
+            OutputFormat::Raster(ImageFormat::Jpeg),
+            OutputFormat::Pdf,
+            OutputFormat::Raster(ImageFormat::Ico),
+        ];
+        let all = generate_bytes_all("https://example.com", &formats, &opts).unwrap().outputs;
+        assert_eq!(all.len(), formats.len());
+        for (bytes, format) in all.iter().zip(formats) {
+            assert_eq!(*bytes, generate_bytes("https://example.com", format, &opts).unwrap());
+        }
+        assert!(generate_bytes_all("https://example.com", &[], &opts).unwrap().outputs.is_empty());
+    }
+
+    #[test]
+    fn test_generate_bytes_all_reports_as_each_check() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("logo.gif");
+        let frames = [[220, 0, 0, 255], [0, 0, 220, 255]].map(|color| image::Frame::new(RgbaImage::from_pixel(40, 40, Rgba(color))));
+        image::codecs::gif::GifEncoder::new(fs::File::create(&icon_path).unwrap()).encode_frames(frames).unwrap();
+        let opts = QrOptions::default().with_icon(&icon_path).with_icon_anchor(IconAnchor::TopLeft).with_ec_level(EcLevel::L);
+
+        let url = "https://example.com";
+        let generated = generate_bytes_all(url, &[OutputFormat::Raster(ImageFormat::Ico)], &opts).unwrap();
+        assert_eq!(generated.info, symbol_info(url, &opts).unwrap());
+        assert_eq!(generated.icon_coverage, icon_coverage(url, &opts).unwrap());
+        assert_eq!(generated.icon_coverage_warning(), icon_coverage_warning(url, &opts).unwrap());
+        assert_eq!(generated.icon_note, animated_icon_note(&opts).unwrap());
+        assert!(generated.icon_note.is_some());
+        let expected: Vec<_> = [icon_anchor_warning(url, &opts).unwrap(), favicon_warning(url, &opts).unwrap()].into_iter().flatten().collect();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(generated.warnings, expected);
+
+        // The favicon warning only comes with a favicon
+        let generated = generate_bytes_all(url, &[OutputFormat::Svg], &QrOptions::default()).unwrap();
+        assert!(generated.warnings.is_empty() && generated.icon_note.is_none() && generated.icon_coverage.is_none());
+    }
+
+    #[test]
+    fn test_svg_metadata() {
+        let svg = generate_svg("https://example.com/a&b", &QrOptions::default()).unwrap();
+        assert!(svg.contains(&format!(
+            "<metadata xmlns:qr=\"urn:qr-generator\"><qr:generator>qr-generator {}</qr:generator>\
+             <qr:payload>https://example.com/a&amp;b</qr:payload></metadata>",
+            env!("CARGO_PKG_VERSION")
+        )));
+
+        // Without metadata the payload is in neither the block nor the title
+        let opts = QrOptions::default().with_metadata(false);
+        let svg = generate_svg("https://example.com/a&b", &opts).unwrap();
+        assert!(!svg.contains("<metadata") && !svg.contains("example.com"));
+        let svg = generate_svg("https://example.com/a&b", &opts.clone().with_alt("Menu")).unwrap();
+        assert!(svg.contains("<title>Menu</title>"));
+
+        let png = generate_bytes("https://example.com/a&b", OutputFormat::Raster(ImageFormat::Png), &opts).unwrap();
+        let reader = png::Decoder::new(std::io::Cursor::new(png)).read_info().unwrap();
+        assert!(reader.info().utf8_text.is_empty() && reader.info().uncompressed_latin1_text.is_empty());
+    }
+
+    #[test]
+    fn test_caption_adds_band_below_code() {
+        let opts = QrOptions::default().with_caption("Scan to order");
+        let band = caption_band_height(DEFAULT_CAPTION_SIZE);
+        assert_eq!(band, 34);
+
+        let img = generate("https://example.com", &opts.clone().with_verify(true)).unwrap();
+        assert_eq!(img.dimensions(), (DEFAULT_SIZE, DEFAULT_SIZE + band));
+        // The quiet zone below the matrix is untouched
+        let plain = generate("https://example.com", &QrOptions::default()).unwrap();
+        assert_eq!(img.crop_imm(0, 0, DEFAULT_SIZE, DEFAULT_SIZE).to_rgb8(), plain.to_rgb8());
+
+        let svg = generate_svg("https://example.com", &opts.with_caption("Fish & <Chips>")).unwrap();
+        assert!(svg.contains(&format!("viewBox=\"0 0 400 {}\"", 400 + band)));
+        assert!(svg.contains(">Fish &amp; &lt;Chips&gt;</text>"));
+
+        // An empty caption adds nothing
+        let img = generate("https://example.com", &QrOptions::default().with_caption("")).unwrap();
+        assert_eq!(img.height(), DEFAULT_SIZE);
+
+        let opts = QrOptions { caption_size: 0.0, ..QrOptions::default() }.with_caption("Hi");
+        assert!(matches!(generate("https://example.com", &opts), Err(QrGenError::InvalidCaptionSize(_))));
+    }
+
+    #[test]
+    fn test_frame_surrounds_code() {
+        let opts = QrOptions::default().with_frame(Frame::default());
+        let img = generate("https://example.com", &opts.clone().with_verify(true)).unwrap();
+        assert_eq!(img.dimensions(), (432, 496));
+
+        // The code sits inset by the border, unchanged behind a square window
+        let plain = generate("https://example.com", &QrOptions::default()).unwrap();
+        let square = QrOptions::default().with_frame(Frame { corner_radius: 0.0, ..Frame::default() });
+        let img = generate("https://example.com", &square).unwrap();
+        assert_eq!(img.crop_imm(16, 16, DEFAULT_SIZE, DEFAULT_SIZE).to_rgb8(), plain.to_rgb8());
+
+        // A caption goes below the whole frame
+        let img = generate("https://example.com", &opts.clone().with_caption("Menu")).unwrap();
+        assert_eq!(img.height(), 496 + caption_band_height(DEFAULT_CAPTION_SIZE));
+
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert!(svg.contains("viewBox=\"0 0 432 496\""));
+        assert!(svg.contains("<g transform=\"translate(16,16)\">"));
+        assert!(svg.contains(">SCAN ME</text>"));
+    }
+
+    #[test]
+    fn test_styled_eyes() {
+        let opts = QrOptions::default().with_eye_color(Rgb([0xcc, 0, 0])).with_eye_style(EyeStyle::Circle);
+
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert_eq!(svg.matches("<path fill=\"#cc0000\" fill-rule=\"evenodd\"").count(), 3);
+        assert!(svg.contains("<path fill=\"#000000\" d=\"M"));
+
+        // Recolored and gently rounded eyes still decode
+        let opts = QrOptions { corner_radius: 0.15, ..opts }.with_eye_style(EyeStyle::Rounded).with_verify(true);
+        assert!(generate("https://example.com", &opts).is_ok());
+    }
+
+    #[test]
+    fn test_svg_timing_color() {
+        let opts = QrOptions::default().with_timing_color(Rgb([0, 0, 0xa0]));
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        // Version 2 has 5 dark modules on each timing line, one run apiece
+        let timing = svg.lines().find(|line| line.starts_with("<path fill=\"#0000a0\"")).unwrap();
+        assert_eq!(timing.matches('M').count(), 10);
+        assert!(svg.contains("<path fill=\"#000000\" d=\"M"));
+
+        assert!(generate("https://example.com", &opts.clone().with_verify(true)).is_ok());
+        assert!(generate_pdf("https://example.com", &opts).is_ok());
+        let low_contrast = opts.with_timing_color(Rgb([0xf0, 0xf0, 0xf0]));
+        assert!(matches!(generate("https://example.com", &low_contrast), Err(QrGenError::LowContrast { .. })));
+    }
+
+    #[test]
+    fn test_saved_png_records_payload() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("out.png");
+        generate_qr_with_icon("https://example.com/menu", None, output_path.to_str().unwrap(), &QrOptions::default()).unwrap();
+
+        let reader = png::Decoder::new(std::io::BufReader::new(fs::File::open(&output_path).unwrap())).read_info().unwrap();
+        let payload = reader.info().utf8_text.iter().find(|chunk| chunk.keyword == PAYLOAD_KEYWORD).unwrap();
+        assert_eq!(payload.get_text().unwrap(), "https://example.com/menu");
+    }
+
+    #[test]
+    fn test_matrix_round_trip_renders_identically() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("icon.png");
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 8, Rgb([200, 30, 30]))).save(&icon_path).unwrap();
+
+        let opts = QrOptions { margin: 2, foreground: Rgb([20, 40, 120]), ..QrOptions::default() }
+            .with_module_style(ModuleStyle::Rounded)
+            .with_icon(&icon_path);
+        let json = module_matrix("https://example.com/menu", &opts).unwrap().to_json();
+        let matrix = QrMatrix::from_json(&json).unwrap();
+        assert_eq!(matrix.ec_level(), EcLevel::H);
+
+        // The matrix carries the quiet zone, so the margin given here is overridden
+        let imported = generate_from_matrix(&matrix, &QrOptions { margin: 7, ..opts.clone() }.with_verify(true)).unwrap();
+        assert_eq!(imported.to_rgba8(), generate("https://example.com/menu", &opts).unwrap().to_rgba8());
+        assert_eq!(decode(&imported), "https://example.com/menu");
+
+        // The matrix does not carry the payload to describe or tag the SVG with
+        let opts = opts.with_alt("Menu").with_metadata(false);
+        let svg = generate_from_matrix_bytes(&matrix, OutputFormat::Svg, &opts).unwrap();
+        assert_eq!(svg, generate_bytes("https://example.com/menu", OutputFormat::Svg, &opts).unwrap());
+    }
+
+    #[test]
+    fn test_matrix_to_favicon() {
+        let opts = QrOptions::default();
+        let matrix = module_matrix("https://example.com", &opts).unwrap();
+        let ico = generate_from_matrix_bytes(&matrix, OutputFormat::Raster(ImageFormat::Ico), &opts.clone().with_verify(true)).unwrap();
+        assert_eq!(ico, generate_bytes("https://example.com", OutputFormat::Raster(ImageFormat::Ico), &opts).unwrap());
+        let sizes: Vec<_> = (0..3).map(|i| (ico[6 + 16 * i], ico[7 + 16 * i])).collect();
+        assert_eq!(sizes, [(16, 16), (32, 32), (48, 48)]);
+    }
+
+    #[test]
+    fn test_binary_payload_round_trips() {
+        let payload = [0x00, 0xff, 0xfe, 0x80, 0x7f, 0xc3, 0x28, 0x0a];
+        let image = generate(payload, &QrOptions::default().with_verify(true)).unwrap();
+        assert_eq!(verify::decode_bytes(&image).as_deref(), Some(&payload[..]));
+
+        // Bytes that are not text are left out of the PNG metadata
+        let png = generate_bytes(payload, OutputFormat::Raster(ImageFormat::Png), &QrOptions::default()).unwrap();
+        let reader = png::Decoder::new(std::io::Cursor::new(png)).read_info().unwrap();
+        assert!(reader.info().utf8_text.iter().all(|chunk| chunk.keyword != PAYLOAD_KEYWORD));
+
+        assert!(matches!(
+            generate(vec![0xff; 3000], &QrOptions::default()),
+            Err(QrGenError::PayloadTooLong { length: 3000, max: 2331, mode: DataMode::Byte, version: 40, ec_level: EcLevel::M })
+        ));
+    }
+
+    #[test]
+    fn test_generate_split_bytes() {
+        let opts = QrOptions::default().with_ec_level(EcLevel::H);
+        let png = OutputFormat::Raster(ImageFormat::Png);
+        let single = generate_split_bytes("https://example.com", png, &opts).unwrap();
+        assert_eq!(single, vec![generate_bytes("https://example.com", png, &opts).unwrap()]);
+
+        let payload = "t".repeat(2000);
+        assert!(generate_bytes(&payload, png, &opts).is_err());
+        let parts = generate_split_bytes(&payload, png, &opts).unwrap();
+        assert_eq!(parts.len(), 2);
+        for part in &parts {
+            let image = image::load_from_memory(part).unwrap();
+            assert_eq!(image.dimensions(), (DEFAULT_SIZE, DEFAULT_SIZE));
+            // Every part records the whole payload, not just its share
+            let reader = png::Decoder::new(std::io::Cursor::new(part)).read_info().unwrap();
+            let text = reader.info().utf8_text.iter().find(|chunk| chunk.keyword == PAYLOAD_KEYWORD).unwrap();
+            assert_eq!(text.get_text().unwrap(), payload);
+        }
+        let svg = generate_split_bytes(&payload, OutputFormat::Svg, &opts).unwrap();
+        assert!(svg.iter().all(|part| part.starts_with(b"<?xml")));
+        let metadata = format!("<qr:payload>{}</qr:payload>", payload);
+        assert!(svg.iter().all(|part| part.windows(metadata.len()).any(|w| w == metadata.as_bytes())));
+
+        // Each part is a whole favicon set
+        let ico = generate_split_bytes(&payload, OutputFormat::Raster(ImageFormat::Ico), &opts).unwrap();
+        assert_eq!(ico.len(), 2);
+        assert!(ico.iter().all(|part| u16::from_le_bytes([part[4], part[5]]) == 3 && (part[6 + 32], part[7 + 32]) == (48, 48)));
+    }
+
+    #[test]
+    fn test_module_gap_in_vector_output() {
+        let opts = QrOptions { size: 330, ..QrOptions::default() }.with_module_gap(0.2);
+        // 10 pixel modules shrink to 8, except in the finder patterns
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert!(svg.contains("width=\"8\" height=\"8\""));
+        assert!(svg.contains("<rect x=\"40\" y=\"40\" width=\"10\" height=\"10\""));
+        assert!(generate_pdf("https://example.com", &opts).is_ok());
+
+        let opts = opts.with_module_gap(0.5);
+        assert!(matches!(generate_svg("https://example.com", &opts), Err(QrGenError::InvalidModuleGap(_))));
+    }
+
+    #[test]
+    fn test_outer_padding_and_trim() {
+        let opts = QrOptions::default().with_outer_padding(12);
+        let img = generate("https://example.com", &opts.clone().with_verify(true)).unwrap();
+        assert_eq!(img.dimensions(), (DEFAULT_SIZE + 24, DEFAULT_SIZE + 24));
+        let plain = generate("https://example.com", &QrOptions::default()).unwrap();
+        assert_eq!(img.crop_imm(12, 12, DEFAULT_SIZE, DEFAULT_SIZE).to_rgb8(), plain.to_rgb8());
+
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert!(svg.contains("viewBox=\"0 0 424 424\""));
+        assert!(svg.contains("<g transform=\"translate(12,12)\">"));
+
+        // 400 pixels hold 33 modules of 12 pixels, leaving 4 to trim
+        let opts = QrOptions::default().with_trim(true);
+        let img = generate("https://example.com", &opts.clone().with_verify(true)).unwrap();
+        assert_eq!(img.dimensions(), (396, 396));
+        let symbol = Symbol::prepare(b"https://example.com", &opts).unwrap();
+        assert_eq!(symbol.layout.offset, DEFAULT_MARGIN * symbol.layout.module_size);
+        assert!(generate_svg("https://example.com", &opts).unwrap().contains("viewBox=\"0 0 396 396\""));
+    }
+
+    #[test]
+    fn test_card_radius() {
+        let opts = QrOptions::default().with_outer_padding(10).with_card_radius(40);
+        let img = generate("https://example.com", &opts.clone().with_verify(true)).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (DEFAULT_SIZE + 20, DEFAULT_SIZE + 20));
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        assert_eq!(img.get_pixel(img.width() - 1, img.height() - 1)[3], 0);
+        assert_eq!(img.get_pixel(img.width() / 2, img.height() / 2)[3], 255);
+        // The straight edges between the corners stay opaque
+        assert_eq!(img.get_pixel(img.width() / 2, 0).0, [255, 255, 255, 255]);
+
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert!(svg.contains("<clipPath id=\"card\"><rect width=\"420\" height=\"420\" rx=\"40\"/></clipPath>"), "{}", svg);
+        assert!(svg.contains("<g clip-path=\"url(#card)\">"));
+    }
+
+    #[test]
+    fn test_card() {
+        let card = Rgb([0x2a, 0x6f, 0xdb]);
+        let opts = QrOptions::default().with_card(card, 30).with_card_radius(40);
+        let img = generate("https://example.com", &opts.clone().with_verify(true)).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (DEFAULT_SIZE + 60, DEFAULT_SIZE + 60));
+
+        // The card shows around the code, with its own white quiet zone, and is cut away past its corners
+        assert_eq!(img.get_pixel(20, 20).0, [0x2a, 0x6f, 0xdb, 255]);
+        assert_eq!(img.get_pixel(img.width() / 2, 5).0, [0x2a, 0x6f, 0xdb, 255]);
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        assert_eq!(img.get_pixel(img.width() - 1, img.height() - 1)[3], 0);
+        assert_eq!(img.get_pixel(32, 32).0, [255, 255, 255, 255]);
+        let plain = generate("https://example.com", &QrOptions::default()).unwrap();
+        assert_eq!(image::imageops::crop_imm(&img, 30, 30, DEFAULT_SIZE, DEFAULT_SIZE).to_image(), plain.to_rgba8());
+
+        // Within outer padding, the background shows past the corners instead
+        let img = generate("https://example.com", &opts.clone().with_outer_padding(10)).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (DEFAULT_SIZE + 80, DEFAULT_SIZE + 80));
+        assert_eq!(img.get_pixel(12, 12).0, [255, 255, 255, 255]);
+        assert_eq!(img.get_pixel(30, 30).0, [0x2a, 0x6f, 0xdb, 255]);
+
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"460\" height=\"460\" rx=\"40\" fill=\"#2a6fdb\"/>"), "{}", svg);
+        assert!(svg.contains("<rect x=\"30\" y=\"30\" width=\"400\" height=\"400\" fill=\"#ffffff\"/>"));
+        assert!(svg.contains("<g transform=\"translate(30,30)\">"));
+        assert!(!svg.contains("clip-path"));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let opts = QrOptions::default().with_caption("Menu");
+        let upright = generate("https://example.com", &opts).unwrap();
+        let (width, height) = upright.dimensions();
+        for (rotation, dimensions) in [
+            (Rotation::Deg90, (height, width)),
+            (Rotation::Deg180, (width, height)),
+            (Rotation::Deg270, (height, width)),
+        ] {
+            let img = generate("https://example.com", &opts.clone().with_rotate(rotation).with_verify(true)).unwrap();
+            assert_eq!(img.dimensions(), dimensions, "{:?}", rotation);
+        }
+
+        let svg = generate_svg("https://example.com", &opts.clone().with_rotate(Rotation::Deg90)).unwrap();
+        assert!(svg.contains(&format!("viewBox=\"0 0 {} {}\"", height, width)));
+        assert!(svg.contains(&format!("<g transform=\"translate({},0) rotate(90)\">", height)));
+    }
+
+    #[test]
+    fn test_icon_upright() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("icon.png");
+        // Red on top, blue below
+        RgbaImage::from_fn(40, 40, |_, y| if y < 20 { image::Rgba([255, 0, 0, 255]) } else { image::Rgba([0, 0, 255, 255]) })
+            .save(&icon_path)
+            .unwrap();
+
+        let opts = QrOptions::default().with_icon(&icon_path).with_rotate(Rotation::Deg90);
+        let center = DEFAULT_SIZE / 2;
+        let offset = 10;
+        let img = generate("https://example.com", &opts).unwrap().to_rgb8();
+        // Rotated clockwise, the red half ends up on the right
+        assert_eq!(img.get_pixel(center + offset, center), &Rgb([255, 0, 0]));
+
+        let img = generate("https://example.com", &QrOptions { icon_upright: true, ..opts }).unwrap().to_rgb8();
+        assert_eq!(img.get_pixel(center, center - offset), &Rgb([255, 0, 0]));
+        assert_eq!(img.get_pixel(center, center + offset), &Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn test_background_image() {
+        let temp_dir = tempdir().unwrap();
+        let photo_path = temp_dir.path().join("photo.png");
+        // A light photo with some texture
+        RgbaImage::from_fn(640, 480, |x, y| image::Rgba([200 + (x % 50) as u8, 220, 200 + (y % 40) as u8, 255]))
+            .save(&photo_path)
+            .unwrap();
+
+        let opts = QrOptions::default().with_background_image(&photo_path, 0.0);
+        let img = generate("https://example.com", &opts.clone().with_verify(true)).unwrap();
+        assert_eq!(img.dimensions(), (DEFAULT_SIZE, DEFAULT_SIZE));
+        assert_ne!(img.to_rgb8().get_pixel(5, 5), &Rgb([255, 255, 255]));
+        assert_eq!(background_contrast_warning("https://example.com", &opts).unwrap(), None);
+
+        let svg = generate_svg("https://example.com", &opts).unwrap();
+        assert!(svg.contains("<image x=\"0\" y=\"0\" width=\"400\" height=\"400\" href=\"data:image/png;base64,"));
+
+        // PDF output embeds the photo at print resolution
+        let pdf = generate_pdf("https://example.com", &opts).unwrap();
+        let symbol = Symbol::prepare(b"https://example.com", &opts).unwrap();
+        let needle = format!("/Width {0} /Height {0}", pdf::print_pixels(symbol.layout.canvas_size, &symbol.layout, &opts));
+        assert!(pdf.windows(needle.len()).any(|w| w == needle.as_bytes()));
+        assert!(pdf.windows(9).any(|w| w == b"/Backdrop"));
+
+        // A dark photo is flagged until a scrim lightens it
+        RgbaImage::from_pixel(64, 64, image::Rgba([40, 40, 40, 255])).save(&photo_path).unwrap();
+        assert!(background_contrast_warning("https://example.com", &opts).unwrap().is_some());
+        let opts = opts.with_background_image(&photo_path, 0.8);
+        assert_eq!(background_contrast_warning("https://example.com", &opts).unwrap(), None);
+
+        assert_eq!(background_contrast_warning("https://example.com", &QrOptions::default()).unwrap(), None);
+        let missing = QrOptions::default().with_background_image(temp_dir.path().join("missing.png"), 0.0);
+        assert!(matches!(generate("https://example.com", &missing), Err(QrGenError::BackgroundImageNotFound(_))));
+    }
+
+    #[test]
+    fn test_verify_rejects_oversized_icon() {
+        let temp_dir = tempdir().unwrap();
+        let icon_path = temp_dir.path().join("test_icon.png");
+        DynamicImage::new_rgb8(100, 100).save(&icon_path).unwrap();
+
+        let opts = QrOptions::default().with_icon(&icon_path).with_verify(true);
+        assert!(generate("https://example.com", &opts).is_ok());
+
+        // A solid icon covering 16% of the matrix is more than level L recovers
+        let opts = opts.with_ec_level(EcLevel::L).with_icon_scale(MAX_ICON_SCALE);
+        let result = generate("https://example.com", &opts);
+        assert!(matches!(result, Err(QrGenError::VerificationFailed(_))));
+
+        let output_path = temp_dir.path().join("test_qr.svg");
+        let result = generate_qr_with_icon(
+            "https://example.com",
+            Some(icon_path.to_str().unwrap()),
+            output_path.to_str().unwrap(),
+            &opts,
+        );
+        assert!(matches!(result, Err(QrGenError::VerificationFailed(_))));
+        assert!(!output_path.exists());
+    }
+
+    #[test]
+    fn test_data_uri() {
+        let format = OutputFormat::Raster(image::ImageFormat::Png);
+        let uri = generate_data_uri("https://example.com", format, &QrOptions::default()).unwrap();
+        let encoded = uri.strip_prefix("data:image/png;base64,").unwrap();
+
+        let image = image::load_from_memory(&STANDARD.decode(encoded).unwrap()).unwrap();
+        assert_eq!(decode(&image), "https://example.com");
+
+        let uri = generate_data_uri("https://example.com", OutputFormat::Svg, &QrOptions::default()).unwrap();
+        assert!(uri.starts_with("data:image/svg+xml;base64,"));
+    }
+
+    #[test]
+    fn test_generate_with_info() {
+        let (image, info) = generate_with_info("hi", &QrOptions::default()).unwrap();
+        assert_eq!((info.version, info.width, info.ec_level), (1, 21, EcLevel::M));
+        assert_eq!(image, generate("hi", &QrOptions::default()).unwrap());
+        assert_eq!(info, symbol_info("hi", &QrOptions::default()).unwrap());
+
+        let long = format!("https://example.com/{}", "a".repeat(200));
+        let (_, info) = generate_with_info(&long, &QrOptions::default()).unwrap();
+        assert!(info.version > 1);
+        assert_eq!(info.width, 17 + 4 * info.version as u32);
+
+        let (_, info) = generate_with_info("hi", &QrOptions::default().with_mask(5)).unwrap();
+        assert_eq!(info.mask, 5);
+    }
+
+    #[test]
+    fn test_micro_code() {
+        let eye_color = Rgb([0xcc, 0, 0]);
+        let opts = QrOptions::default().with_micro(true).with_eye_color(eye_color);
+        let (image, info) = generate_with_info("12345", &opts).unwrap();
+        assert!(info.micro && info.mask <= 3);
+        assert_eq!((info.version_name().as_str(), info.width, info.ec_level), ("M2", 13, EcLevel::M));
+        assert!(fits("12345", &opts).is_ok());
+
+        // The one eye sits top left, with no trace of the other two corners
+        let image = image.to_rgb8();
+        let limit = image.width() * 2 / 3;
+        assert!(image.pixels().any(|&pixel| pixel == eye_color));
+        assert!(image.enumerate_pixels().filter(|&(_, _, &pixel)| pixel == eye_color).all(|(x, y, _)| x < limit && y < limit));
+
+        assert!(matches!(
+            generate("x".repeat(40), &opts),
+            Err(QrGenError::TooLongForMicro { length: 40, ec_level: EcLevel::M })
+        ));
+        assert!(matches!(generate("12345", &opts.clone().with_icon("logo.png")), Err(QrGenError::MicroUnsupported(_))));
+        assert!(matches!(generate("12345", &opts.clone().with_ec_level(EcLevel::H)), Err(QrGenError::MicroUnsupported(_))));
+    }
+
+    #[test]
+    fn test_symbol_info() {
+        let info = symbol_info("https://example.com", &QrOptions::default()).unwrap();
+        assert_eq!(info.ec_level, EcLevel::M);
+        assert_eq!(info.width, 17 + 4 * info.version as u32);
+
+        let opts = QrOptions { version: Some(7), ..QrOptions::default() };
+        let info = symbol_info("https://example.com", &opts.clone().with_icon("logo.png")).unwrap();
+        assert_eq!((info.version, info.width, info.ec_level), (7, 45, EcLevel::H));
+        let image = generate("https://example.com", &opts.with_margin(0).with_size(450)).unwrap();
+        assert_eq!(decode(&image), "https://example.com");
+    }
+
+    #[test]
+    fn test_pdf_output() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("test_qr.pdf");
+        let icon_path = temp_dir.path().join("test_icon.png");
+        DynamicImage::new_rgb8(10, 10).save(&icon_path).unwrap();
+
+        let opts = QrOptions { pdf_size_mm: 80.0, ..QrOptions::default() };
+        generate_qr_with_icon(
+            "https://example.com",
+            Some(icon_path.to_str().unwrap()),
+            output_path.to_str().unwrap(),
+            &opts,
+        )
+        .unwrap();
+
+        let pdf = fs::read(&output_path).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+
+        // The icon is resampled for 300 DPI rather than the 400px canvas
+        let opts = opts.with_icon(&icon_path);
+        let symbol = Symbol::prepare(b"https://example.com", &opts).unwrap();
+        let expected = pdf::print_pixels(symbol.icon.unwrap().width(), &symbol.layout, &opts);
+        let needle = format!("/Width {0} /Height {0}", expected);
+        assert!(pdf.windows(needle.len()).any(|w| w == needle.as_bytes()));
+    }
+}
@@ -1,174 +1,2611 @@
+use std::borrow::Cow;
 use std::env;
-use std::path::Path;
-use qrcode::QrCode;
-use image::{DynamicImage, Rgb, RgbImage, imageops};
-use image::ImageReader;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{self, Path, PathBuf};
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use image::{DynamicImage, ImageFormat, Rgb, Rgba};
+use log::{error, info, warn, Level, LevelFilter};
+use notify::{EventKind, RecursiveMode, Watcher};
+use qr_generator::{
+    animated_gif, apply_env, auto_tune, contact_sheet_with_progress, ec_comparison_sheet, encode_url, fits, generate,
+    generate_bytes_all, generate_data_uri, generate_from_matrix_bytes, generate_split_bytes, geo_payload, load_config,
+    mailto_payload, module_matrix, parse_color, parse_rgba_color, parse_sheet_entries, print_to_terminal,
+    run_batch_with_progress, save_image, scan_image, sms_payload, tel_payload, BatchOptions, EcLevel, EyeStyle, Frame,
+    Gradient, GradientDirection, GradientType, HaloBorder, IconAnchor, IconFilter, IconFit, IconPadding, IconShadow,
+    IconShape, ModuleStyle, OutputFormat, PayloadEncoding, QrGenError, QrMatrix, QrOptions, Rotation, VCard, WifiAuth,
+    WifiConfig, DEFAULT_CONFIG_FILE, MAX_MASK, MAX_MODULE_GAP, MAX_VERSION, MIN_SAFE_JPEG_QUALITY,
+};
+
+mod progress;
+
+use progress::ProgressBar;
+
+enum Action {
+    /// Write the QR code to each of the files, or to stdout for a path of
+    /// `-`, rendering it once for all of them.
+    Generate { output_paths: Vec<String> },
+    /// Write the QR code to a file, then again whenever the icon, background
+    /// image, input file or `config` changes.
+    Watch { output_path: String, config: PathBuf },
+    /// Write the QR code to a file, or a Structured Append sequence to
+    /// numbered files next to it if the payload does not fit in one.
+    Split { output_path: String },
+    /// Print the QR code to the terminal without writing anything.
+    Preview,
+    /// Print the encoded image to stdout as a base64 `data:` URI.
+    DataUri,
+    /// Print the module matrix to stdout as JSON.
+    Json,
+    /// Put the image on the system clipboard.
+    Clipboard,
+    /// Generate one QR code per row of a CSV file, or of stdin if the path
+    /// is `-`, naming the files of rows without an output path after
+    /// `output_template`.
+    Batch { csv_path: String, jobs: usize, output_template: Option<String> },
+    /// Tile one QR code per line of a file, or of stdin if the path is `-`,
+    /// into a single image.
+    ContactSheet { list_path: String, output_path: String, columns: u32, spacing: u32 },
+    /// Tile the code at each error correction level, L to H, into a single
+    /// captioned image.
+    CompareEc { output_path: String },
+    /// Write a looping GIF showing the QR code for each line of a file, or
+    /// of stdin if the path is `-`, in turn.
+    Animated { list_path: String, output_path: String, frame_delay: u32 },
+    /// Render a module matrix read as JSON from a file, or from stdin if the
+    /// path is `-`.
+    RenderMatrix { matrix_path: String, output_path: String },
+    /// Decode the QR code in an image file, or in stdin if the path is `-`,
+    /// and print its payload.
+    Scan { image_path: String },
+}
+
+/// Codes per row of a contact sheet when `--columns` is not given.
+const DEFAULT_SHEET_COLUMNS: u32 = 4;
+
+/// Pixels between contact sheet cells when `--spacing` is not given.
+const DEFAULT_SHEET_SPACING: u32 = 20;
+
+/// Milliseconds each code of an animation is shown when `--frame-delay` is
+/// not given.
+const DEFAULT_FRAME_DELAY: u32 = 2000;
+
+/// How long `--watch` waits after a change for further events before
+/// regenerating, so the several events of one save trigger one rebuild.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How often `--watch` checks for Ctrl-C while waiting for changes.
+const WATCH_POLL: Duration = Duration::from_millis(100);
+
+/// Whether an output file that already exists may be replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overwrite {
+    /// Replace it, for `--force`.
+    Always,
+    /// Ask on the terminal, or refuse when stdin is not one.
+    Ask,
+    /// Refuse, for `--no-clobber`.
+    Never,
+}
+
+struct Cli {
+    /// Bytes to encode: the URL, or a payload built by a subcommand.
+    payload: Vec<u8>,
+    /// File whose raw contents replace `payload`, or - for stdin.
+    input_file: Option<String>,
+    /// Whether `input_file` must hold UTF-8 text, for `--text-file`.
+    text_input: bool,
+    /// Percent-encode the payload once read, if it is a URL.
+    encode_url: bool,
+    action: Action,
+    options: QrOptions,
+    /// Format to write, overriding the output path's extension.
+    format: Option<OutputFormat>,
+    /// Proceed despite settings that risk an unscannable code.
+    force: bool,
+    /// Whether to replace output files that already exist.
+    overwrite: Overwrite,
+    /// Pick the error correction level and icon scale by trial decoding.
+    auto: bool,
+    /// Most detailed messages to log.
+    log_level: LevelFilter,
+    /// Generate and verify everything, but write no files.
+    dry_run: bool,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<OsString> = env::args_os().collect();
+    let mut cli = parse_args(&args).unwrap_or_else(|e| e.exit());
+    init_logging(cli.log_level);
+    if let Err(e) = read_input(&mut cli) {
+        error!("{}", e);
+        std::process::exit(1);
+    }
 
-    if args.len() != 4 {
-        eprintln!("Usage: {} <url> <icon_path> <output_path>", args[0]);
-        eprintln!("Example: {} https://example.com logo.png output.png", args[0]);
+    let result = match cli.action {
+        Action::Watch { .. } => watch(cli, &args),
+        _ => run(&cli),
+    };
+    if let Err(e) = result {
+        error!("{}", e);
         std::process::exit(1);
     }
+}
 
-    let url = &args[1];
-    let icon_path = &args[2];
-    let output_path = &args[3];
+/// Logs to stderr at up to `level`, with warnings and errors prefixed as
+/// such and other messages as they are.
+///
+/// Other crates only get to log warnings and errors, which `--verbose` does
+/// not change.
+fn init_logging(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(level.min(LevelFilter::Warn))
+        .filter_module("qr_generator", level)
+        .format(|out, record| {
+            let prefix = match record.level() {
+                Level::Error => "Error: ",
+                Level::Warn => "Warning: ",
+                Level::Info => "",
+                Level::Debug | Level::Trace => "Debug: ",
+            };
+            writeln!(out, "{}{}", prefix, record.args())
+        })
+        .init();
+}
 
-    match generate_qr_with_icon(url, icon_path, output_path) {
-        Ok(_) => println!("QR code with icon generated successfully: {}", output_path),
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
+/// Replaces the payload with the contents of `--input-file`, or with stdin
+/// when it is `-`.
+fn read_input(cli: &mut Cli) -> Result<(), String> {
+    if let Some(input_file) = &cli.input_file {
+        let contents = if input_file == "-" { read_bytes(io::stdin().lock()) } else { fs::read(input_file) };
+        cli.payload = contents.map_err(|e| format!("failed to read the payload from {}: {}", input_file, e))?;
+        if cli.text_input && str::from_utf8(&cli.payload).is_err() {
+            return Err(format!("{} is not UTF-8 text; pass binary data with --input-file instead", input_file));
         }
+    } else if cli.payload == b"-" {
+        let payload = read_payload(io::stdin().lock()).map_err(|e| format!("failed to read the payload from stdin: {}", e))?;
+        cli.payload = payload.into_bytes();
     }
+    if cli.encode_url
+        && let Ok(payload) = str::from_utf8(&cli.payload)
+    {
+        cli.payload = encode_url(payload).into_bytes();
+    }
+    Ok(())
 }
 
-fn generate_qr_with_icon(url: &str, icon_path: &str, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // Generate QR code
-    let code = QrCode::new(url)?;
+/// Generates the QR code, then again each time one of the files it is
+/// made from changes, until interrupted with Ctrl-C.
+///
+/// The command line is parsed anew for each rebuild, so a changed config
+/// file takes effect. A payload read from stdin is kept from the start.
+fn watch(mut cli: Cli, args: &[OsString]) -> Result<(), Box<dyn Error>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))?;
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let (mut watched_files_before, mut watched_dirs) = (Vec::new(), Vec::new());
+    let mut written = false;
+    loop {
+        // Once written, the output is ours to replace on each rebuild
+        if written {
+            cli.overwrite = Overwrite::Always;
+        }
+        match run(&cli) {
+            Ok(()) => written = true,
+            Err(e) => error!("[{}] {}", clock_time(SystemTime::now()), e),
+        }
+
+        // Watch the directories rather than the files, since many editors
+        // save by replacing the file
+        let files = watched_files(&cli);
+        let mut dirs: Vec<PathBuf> = files.iter().filter_map(|file| file.parent().map(Path::to_path_buf)).collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in watched_dirs.iter().filter(|dir| !dirs.contains(dir)) {
+            watcher.unwatch(dir)?;
+        }
+        for dir in dirs.iter().filter(|dir| !watched_dirs.contains(dir)) {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        watched_dirs = dirs;
+        if files != watched_files_before {
+            let names: Vec<_> = files.iter().map(|file| file.display().to_string()).collect();
+            info!("Watching {} for changes; press Ctrl-C to stop", names.join(", "));
+        }
 
-    // Create QR code image that occupies the entire canvas
-    let qr_size = 400;
-    let qr_width = code.width();
-    let module_size = qr_size / qr_width as u32; // Calculate module size to fill entire image
-    let actual_qr_size = module_size * qr_width as u32; // Actual size might be slightly smaller
+        let mut changed = None;
+        let changed = loop {
+            if stop.load(Ordering::SeqCst) {
+                info!("Stopped watching");
+                return Ok(());
+            }
+            let timeout = if changed.is_some() { WATCH_DEBOUNCE } else { WATCH_POLL };
+            match events.recv_timeout(timeout) {
+                // Reads, including our own, are not changes
+                Ok(Ok(event)) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) => {
+                    if let Some(path) = event.paths.into_iter().find(|path| files.contains(path)) {
+                        changed = Some(path);
+                    }
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("{}", e),
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(path) = changed.take() {
+                        break path;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err("the file watcher stopped unexpectedly".into()),
+            }
+        };
 
-    let mut qr_image = RgbImage::new(actual_qr_size, actual_qr_size);
+        watched_files_before = files;
+        info!("[{}] {} changed, regenerating", clock_time(SystemTime::now()), changed.display());
+        match reparse(&cli, args) {
+            Ok(reparsed) => cli = reparsed,
+            Err(e) => warn!("[{}] Keeping the previous settings, as the new ones are invalid: {}", clock_time(SystemTime::now()), e),
+        }
+    }
+}
 
-    // Fill with white background
-    for pixel in qr_image.pixels_mut() {
-        *pixel = Rgb([255, 255, 255]);
+/// Parses `args` again for a rebuild, reading `--input-file` afresh unless
+/// the payload came from stdin, in which case `cli`'s is kept.
+fn reparse(cli: &Cli, args: &[OsString]) -> Result<Cli, String> {
+    let mut reparsed = resolve(CommandLine::try_parse_from(args).map_err(|e| e.to_string())?)?;
+    let from_stdin = match reparsed.input_file.as_deref() {
+        Some(input_file) => input_file == "-",
+        None => reparsed.payload == b"-",
+    };
+    if from_stdin {
+        reparsed.payload = cli.payload.clone();
+    } else {
+        read_input(&mut reparsed)?;
     }
+    Ok(reparsed)
+}
 
-    // Draw QR code modules to fill the entire image
-    for y in 0..qr_width {
-        for x in 0..qr_width {
-            if code[(x, y)] == qrcode::Color::Dark {
-                // Draw a dark module
-                let start_x = (x as u32) * module_size;
-                let start_y = (y as u32) * module_size;
+/// The absolute paths of the files the QR code is made from: the icon unless
+/// it is downloaded, the background image, the input file and the config
+/// file, whether or not it exists yet.
+fn watched_files(cli: &Cli) -> Vec<PathBuf> {
+    let config = match &cli.action {
+        Action::Watch { config, .. } => Some(config.clone()),
+        _ => None,
+    };
+    let input_file = cli.input_file.as_deref().filter(|path| *path != "-").map(PathBuf::from);
+    let is_url = |icon: &str| icon.starts_with("http://") || icon.starts_with("https://");
+    let icon = cli.options.icon.clone().filter(|icon| !icon.to_str().is_some_and(is_url));
+    [icon, cli.options.background_image.clone(), input_file, config]
+        .into_iter()
+        .flatten()
+        .filter_map(|file| path::absolute(file).ok())
+        .collect()
+}
 
-                for dy in 0..module_size {
-                    for dx in 0..module_size {
-                        let px = start_x + dx;
-                        let py = start_y + dy;
-                        if px < actual_qr_size && py < actual_qr_size {
-                            qr_image.put_pixel(px, py, Rgb([0, 0, 0]));
-                        }
-                    }
+/// Formats `time` as a UTC time of day, `HH:MM:SS`.
+fn clock_time(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()) % 86_400;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn run(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let done = if cli.dry_run { "would be generated" } else { "generated successfully" };
+    match &cli.action {
+        Action::Split { output_path } if fits(&cli.payload, &cli.options).is_err() => write_split(cli, output_path)?,
+        Action::Generate { output_paths } => write_outputs(cli, output_paths)?,
+        Action::Split { output_path } | Action::Watch { output_path, .. } => write_outputs(cli, slice::from_ref(output_path))?,
+        Action::Preview => print_to_terminal(&cli.payload, &cli.options)?,
+        Action::DataUri => {
+            let format = cli.format.unwrap_or(OutputFormat::Raster(ImageFormat::Png));
+            let options = tuned_options(cli)?;
+            let uri = generate_data_uri(&cli.payload, format, &options)?;
+            writeln!(io::stdout().lock(), "{}", uri)?;
+        }
+        Action::Clipboard => {
+            let options = tuned_options(cli)?;
+            copy_to_clipboard(&generate(&cli.payload, &options)?)?;
+        }
+        Action::Json => writeln!(io::stdout().lock(), "{}", module_matrix(&cli.payload, &cli.options)?.to_json())?,
+        Action::Batch { csv_path, jobs, output_template } => {
+            // Rows are generated in parallel, so there is no asking about each
+            let batch = BatchOptions {
+                jobs: *jobs,
+                overwrite: cli.overwrite == Overwrite::Always,
+                dry_run: cli.dry_run,
+                output_template: output_template.clone(),
+                format: cli.format,
+            };
+            let bar = ProgressBar::for_terminal(cli);
+            let progress = |done, total| bar.update(done, total);
+            let outcomes = if csv_path == "-" {
+                run_batch_with_progress(io::stdin().lock(), &cli.options, &batch, progress)?
+            } else {
+                run_batch_with_progress(fs::File::open(csv_path)?, &cli.options, &batch, progress)?
+            };
+            drop(bar);
+
+            // A dry run reports every row, so it reads as a checklist
+            for outcome in &outcomes {
+                match &outcome.result {
+                    Err(e) => error!("Row at line {} ({}): {}", outcome.line, outcome.output, e),
+                    Ok(()) if cli.dry_run => info!("Row at line {} ({}): OK", outcome.line, outcome.output),
+                    Ok(()) => {}
                 }
             }
+            let failed = outcomes.iter().filter(|o| o.result.is_err()).count();
+            match cli.dry_run {
+                true => info!("{} of {} QR codes would be generated", outcomes.len() - failed, outcomes.len()),
+                false => info!("Generated {} of {} QR codes", outcomes.len() - failed, outcomes.len()),
+            }
+            if failed > 0 {
+                return Err(format!("{} rows failed", failed).into());
+            }
+        }
+        Action::ContactSheet { list_path, output_path, columns, spacing } => {
+            let list = if list_path == "-" { io::read_to_string(io::stdin().lock())? } else { fs::read_to_string(list_path)? };
+            let entries = parse_sheet_entries(&list);
+            if entries.is_empty() {
+                return Err(format!("{} lists no payloads", list_path).into());
+            }
+            let output_path = Path::new(output_path);
+            if OutputFormat::from_path(output_path)? == OutputFormat::Raster(ImageFormat::Jpeg) {
+                check_jpeg_quality(cli.options.quality, cli.force)?;
+            }
+            check_overwrite(cli, output_path)?;
+
+            let bar = ProgressBar::for_terminal(cli);
+            let sheet = contact_sheet_with_progress(&entries, *columns, *spacing, &cli.options, |done, total| bar.update(done, total))?;
+            drop(bar);
+            if !cli.dry_run {
+                save_image(&sheet, output_path, &cli.options)?;
+            }
+            info!("Contact sheet of {} QR codes {}: {}", entries.len(), done, output_path.display());
+        }
+        Action::CompareEc { output_path } => {
+            let output_path = Path::new(output_path);
+            if OutputFormat::from_path(output_path)? == OutputFormat::Raster(ImageFormat::Jpeg) {
+                check_jpeg_quality(cli.options.quality, cli.force)?;
+            }
+            check_overwrite(cli, output_path)?;
+
+            let sheet = ec_comparison_sheet(&cli.payload, DEFAULT_SHEET_SPACING, &cli.options)?;
+            if !cli.dry_run {
+                save_image(&sheet, output_path, &cli.options)?;
+            }
+            info!("Error correction comparison {}: {}", done, output_path.display());
+        }
+        Action::Animated { list_path, output_path, frame_delay } => {
+            let list = if list_path == "-" { io::read_to_string(io::stdin().lock())? } else { fs::read_to_string(list_path)? };
+            let entries = parse_sheet_entries(&list);
+            if entries.is_empty() {
+                return Err(format!("{} lists no payloads", list_path).into());
+            }
+            if OutputFormat::from_path(Path::new(output_path))? != OutputFormat::Raster(ImageFormat::Gif) {
+                return Err(format!("animated output must be a .gif file, not {}", output_path).into());
+            }
+            check_overwrite(cli, Path::new(output_path))?;
+
+            let gif = animated_gif(&entries, *frame_delay, &cli.options)?;
+            if !cli.dry_run {
+                fs::write(output_path, gif)?;
+            }
+            info!("Animated QR code of {} frames {}: {}", entries.len(), done, output_path);
+        }
+        Action::RenderMatrix { matrix_path, output_path } => {
+            let json = if matrix_path == "-" { io::read_to_string(io::stdin().lock())? } else { fs::read_to_string(matrix_path)? };
+            let matrix = QrMatrix::from_json(&json)?;
+            let format = match cli.format {
+                Some(format) => format,
+                None => OutputFormat::from_path(Path::new(output_path))?,
+            };
+            if format == OutputFormat::Raster(ImageFormat::Jpeg) {
+                check_jpeg_quality(cli.options.quality, cli.force)?;
+            }
+
+            check_overwrite(cli, Path::new(output_path))?;
+
+            let bytes = generate_from_matrix_bytes(&matrix, format, &cli.options)?;
+            if !cli.dry_run {
+                fs::write(output_path, bytes)?;
+            }
+            info!(
+                "QR code {}: {} (error correction: {}, version {}, {}x{} modules)",
+                if cli.dry_run { "would be rendered" } else { "rendered successfully" },
+                output_path,
+                matrix.ec_level(),
+                matrix.version(),
+                matrix.width(),
+                matrix.width()
+            );
+        }
+        Action::Scan { image_path } => {
+            let bytes = if image_path == "-" { read_bytes(io::stdin().lock())? } else { fs::read(image_path)? };
+            let image = image::load_from_memory(&bytes).map_err(QrGenError::ImageDecode)?;
+            let payload = scan_image(&image)?;
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&payload)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates the QR code once and writes it to each of `output_paths`, in
+/// the format `--format` or the path's extension names, or to stdout for a
+/// path of `-`.
+fn write_outputs(cli: &Cli, output_paths: &[String]) -> Result<(), Box<dyn Error>> {
+    let done = if cli.dry_run { "would be generated" } else { "generated successfully" };
+    let options = tuned_options(cli)?;
+    let has_icon = options.icon.is_some();
+    if let Some(warning) = options.inverted_warning() {
+        warn!("{}", warning);
+    }
+    if let Some(warning) = options.module_gap_warning() {
+        warn!("{}", warning);
+    }
+    if let Some(warning) = options.margin_color_warning() {
+        warn!("{}", warning);
+    }
+    if let Some(warning) = options.clamp_warning() {
+        warn!("{}", warning);
+    }
+
+    let mut formats = Vec::with_capacity(output_paths.len());
+    for output_path in output_paths {
+        formats.push(match cli.format {
+            Some(format) => format,
+            None if output_path == "-" => return Err("writing to stdout requires --format".into()),
+            None => OutputFormat::from_path(Path::new(output_path))?,
+        });
+    }
+    if formats.contains(&OutputFormat::Raster(ImageFormat::Jpeg)) {
+        check_jpeg_quality(options.quality, cli.force)?;
+    }
+    if formats.contains(&OutputFormat::Raster(ImageFormat::Png))
+        && let Some(warning) = options.bit_depth_warning()
+    {
+        warn!("{}", warning);
+    }
+    let to_stdout = output_paths.iter().zip(&formats).find(|(output_path, _)| *output_path == "-");
+    if let Some((_, &format)) = to_stdout
+        && format != OutputFormat::Svg
+        && !cli.dry_run
+        && io::stdout().is_terminal()
+    {
+        return Err("refusing to write binary image data to a terminal; redirect stdout or give an output path".into());
+    }
+
+    for output_path in output_paths.iter().filter(|output_path| *output_path != "-") {
+        check_overwrite(cli, Path::new(output_path))?;
+    }
+    let generated = generate_bytes_all(&cli.payload, &formats, &options)?;
+    // --auto has already checked that the code decodes, and --strict
+    // refuses what the coverage warning would catch
+    let coverage_warning = generated.icon_coverage_warning().filter(|_| !cli.auto && !options.strict);
+    let ec_level = generated.info.ec_level;
+    let warning = coverage_warning.or_else(|| (has_icon && !cli.auto).then(|| options.icon_scale_warning(ec_level)).flatten());
+    for warning in warning.iter().chain(&generated.warnings) {
+        warn!("{}", warning);
+    }
+    if let Some(note) = &generated.icon_note {
+        info!("{}", note);
+    }
+
+    let info = generated.info;
+    let kind = if has_icon { "QR code with icon" } else { "QR code" };
+    for (output_path, bytes) in output_paths.iter().zip(generated.outputs) {
+        if output_path == "-" && !cli.dry_run {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&bytes)?;
+            stdout.flush()?;
+            continue;
+        }
+        if !cli.dry_run {
+            fs::write(output_path, bytes)?;
+        }
+        info!(
+            "{} {}: {} (error correction: {}, version {}, {}x{} modules)",
+            kind, done, output_path, info.ec_level, info.version_name(), info.width, info.width
+        );
+    }
+    Ok(())
+}
+
+/// Writes the Structured Append sequence for the payload, numbering each
+/// part's file after `output_path`.
+fn write_split(cli: &Cli, output_path: &str) -> Result<(), Box<dyn Error>> {
+    let format = match cli.format {
+        Some(format) => format,
+        None => OutputFormat::from_path(Path::new(output_path))?,
+    };
+    if format == OutputFormat::Raster(ImageFormat::Jpeg) {
+        check_jpeg_quality(cli.options.quality, cli.force)?;
+    }
+
+    let parts = generate_split_bytes(&cli.payload, format, &cli.options)?;
+    for number in 1..=parts.len() {
+        check_overwrite(cli, &part_path(Path::new(output_path), number))?;
+    }
+    for (index, bytes) in parts.iter().enumerate() {
+        let path = part_path(Path::new(output_path), index + 1);
+        if !cli.dry_run {
+            fs::write(&path, bytes)?;
         }
+        let done = if cli.dry_run { "would be generated" } else { "generated successfully" };
+        info!("Structured Append part {} of {} {}: {}", index + 1, parts.len(), done, path.display());
     }
+    Ok(())
+}
+
+/// Checks that `path` may be written: that it does not exist yet, or that
+/// `cli.overwrite` allows replacing it, asking if need be. A dry run
+/// writes nothing, so anything goes.
+fn check_overwrite(cli: &Cli, path: &Path) -> Result<(), Box<dyn Error>> {
+    if cli.dry_run || cli.overwrite == Overwrite::Always || !path.exists() {
+        return Ok(());
+    }
+    if cli.overwrite == Overwrite::Ask && io::stdin().is_terminal() && confirm(&format!("{} already exists. Overwrite it?", path.display()))? {
+        return Ok(());
+    }
+    Err(QrGenError::OutputExists(path.to_path_buf()).into())
+}
 
-    // Load and process the icon (make it proportional to QR code size)
-    let icon_size = actual_qr_size / 5; // Icon will be 1/5 of the QR code size
-    let icon = load_and_resize_icon(icon_path, icon_size)?;
+/// Asks `question` on the terminal and reads whether the answer is yes.
+fn confirm(question: &str) -> io::Result<bool> {
+    let mut stderr = io::stderr().lock();
+    write!(stderr, "{} [y/N] ", question)?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
 
-    // Create the final image with icon in center
-    let final_image = overlay_icon_on_qr(qr_image, icon)?;
+/// The path of the `number`th part of a split code: `out.png` becomes
+/// `out-1.png`, `out-2.png` and so on.
+fn part_path(output_path: &Path, number: usize) -> PathBuf {
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output_path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
+    };
+    output_path.with_file_name(name)
+}
 
-    // Save the result
-    final_image.save(output_path)?;
+/// Puts `image` on the system clipboard, where it pastes as a PNG.
+///
+/// X11 and Wayland clipboards are served by the program that set them, so
+/// there this keeps running until something else is copied.
+fn copy_to_clipboard(image: &DynamicImage) -> Result<(), Box<dyn Error>> {
+    let rgba = image.to_rgba8();
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+    let data = arboard::ImageData { width, height, bytes: Cow::Owned(rgba.into_raw()) };
+    let mut clipboard = arboard::Clipboard::new()
+        .map_err(|e| format!("no clipboard is available ({}); on a headless system, write the image to a file instead", e))?;
 
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+    {
+        use arboard::SetExtLinux;
+        info!("QR code copied to the clipboard; keeping it there until something else is copied (Ctrl-C to stop)");
+        clipboard.set().wait().image(data)?;
+    }
+    #[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")))))]
+    {
+        clipboard.set_image(data)?;
+        info!("QR code copied to the clipboard");
+    }
     Ok(())
 }
 
-fn load_and_resize_icon(icon_path: &str, size: u32) -> Result<DynamicImage, Box<dyn std::error::Error>> {
-    // Check if file exists
-    if !Path::new(icon_path).exists() {
-        return Err(format!("Icon file not found: {}", icon_path).into());
+/// The options to generate with, tuned for scannability first when `--auto`
+/// is given.
+fn tuned_options(cli: &Cli) -> Result<QrOptions, QrGenError> {
+    if !cli.auto {
+        return Ok(cli.options.clone());
+    }
+
+    let options = auto_tune(&cli.payload, &cli.options)?;
+    let ec_level = options.effective_ec_level(options.icon.is_some());
+    match options.icon {
+        Some(_) => info!("Auto-selected error correction {} and icon scale {}%", ec_level, options.icon_scale),
+        None => info!("Auto-selected error correction {}", ec_level),
+    }
+    Ok(options)
+}
+
+/// Reads the whole of `reader` as the payload, dropping a single trailing
+/// newline so `echo` output encodes as typed.
+fn read_payload<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut payload = String::new();
+    reader.read_to_string(&mut payload)?;
+    if let Some(stripped) = payload.strip_suffix('\n') {
+        let stripped = stripped.strip_suffix('\r').unwrap_or(stripped);
+        payload.truncate(stripped.len());
+    }
+    Ok(payload)
+}
+
+/// Reads the whole of `reader` as raw bytes, left exactly as they are.
+fn read_bytes<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Refuses JPEG qualities low enough to blur module edges unless `force` is
+/// set, and warns about artifacts below a comfortable level.
+fn check_jpeg_quality(quality: u8, force: bool) -> Result<(), String> {
+    if quality < MIN_SAFE_JPEG_QUALITY && !force {
+        return Err(format!(
+            "JPEG quality {} is below {} and may produce an unscannable code; pass --force to write it anyway",
+            quality, MIN_SAFE_JPEG_QUALITY
+        ));
     }
+    if quality < 80 {
+        warn!("JPEG compression artifacts at quality {} can reduce scannability", quality);
+    }
+    Ok(())
+}
+
+/// Generate QR codes, optionally with an icon in the center.
+///
+/// Without a subcommand the arguments are those of `url`, so
+/// `qr-generator https://example.com logo.png out.png` still works.
+#[derive(Parser)]
+#[command(
+    name = "qr-generator",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    disable_help_subcommand = true,
+    after_help = "Pass - as the payload to read it from stdin, or as the output path to write to stdout.\n\n\
+                  Options come from the built-in defaults, overridden by the config file (--config, or \
+                  qr-generator.toml in the current directory), then by the environment variables QRGEN_SIZE, \
+                  QRGEN_MARGIN, QRGEN_EC_LEVEL, QRGEN_FOREGROUND, QRGEN_BACKGROUND and QRGEN_ICON_SCALE, \
+                  and finally by flags on the command line. Config keys are the QrOptions field names, \
+                  e.g. size = 800 or foreground = \"#1a2b3c\"."
+)]
+struct CommandLine {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    url: UrlArgs,
+}
 
-    // Load the icon image
-    let icon = ImageReader::open(icon_path)?
-        .decode()?;
+#[derive(Subcommand)]
+enum Command {
+    /// Encode a URL or any other text (the default)
+    Url(UrlArgs),
+    /// Encode Wi-Fi network credentials
+    Wifi(WifiArgs),
+    /// Encode a contact card; always generated with --auto
+    Vcard(VcardArgs),
+    /// Encode a mailto: link
+    Mailto(MailtoArgs),
+    /// Encode an sms: link
+    Sms(SmsArgs),
+    /// Encode a tel: link
+    Tel(TelArgs),
+    /// Encode a geo: location
+    Geo(GeoArgs),
+    /// Generate one QR code per row of a CSV file with a url,icon,output
+    /// header; the icon may be left empty
+    Batch(BatchArgs),
+    /// Tile one QR code per line of a file into a single image to print and
+    /// cut apart; a tab after the payload starts a label to set below it
+    ContactSheet(ContactSheetArgs),
+    /// Generate a looping GIF that shows the QR code for each line of a file
+    /// in turn; a tab after the payload starts a label to set below it
+    Animated(AnimatedArgs),
+    /// Render a module matrix encoded elsewhere, in the JSON format --json prints,
+    /// without encoding a payload
+    RenderMatrix(RenderMatrixArgs),
+    /// Decode the QR code in an image, such as a photo of a printed one, and
+    /// print its payload
+    Scan(ScanArgs),
+}
 
-    // Resize the icon to the specified size while maintaining aspect ratio
-    let resized_icon = icon.resize(size, size, imageops::FilterType::Lanczos3);
+#[derive(Args)]
+struct UrlArgs {
+    /// Text to encode
+    #[arg(value_name = "URL", required_unless_present_any = ["input_file", "text_file"])]
+    payload: Option<String>,
+    /// Encode the raw bytes of this file (- for stdin) in place of the URL, in byte mode
+    #[arg(long, value_name = "PATH")]
+    input_file: Option<String>,
+    /// Encode the UTF-8 text of this file (- for stdin) in place of the URL, exactly as written,
+    /// newlines and all
+    #[arg(long, value_name = "PATH", conflicts_with = "input_file")]
+    text_file: Option<String>,
+    /// Percent-encode spaces and other characters a URL cannot hold as they are, leaving existing %XX
+    /// escapes alone; a payload that is not a URL is kept as it is
+    #[arg(long, conflicts_with = "input_file")]
+    encode_url: bool,
+    #[command(flatten)]
+    target: TargetArgs,
+    #[command(flatten)]
+    qr: QrArgs,
+}
 
-    Ok(resized_icon)
+#[derive(Args)]
+struct WifiArgs {
+    /// Network name
+    ssid: String,
+    /// Network password
+    #[arg(long)]
+    password: Option<String>,
+    /// Authentication type: nopass, WPA or WEP [default: WPA]
+    #[arg(long)]
+    auth: Option<WifiAuth>,
+    /// The network does not broadcast its SSID
+    #[arg(long)]
+    hidden: bool,
+    #[command(flatten)]
+    target: TargetArgs,
+    #[command(flatten)]
+    qr: QrArgs,
 }
 
-fn overlay_icon_on_qr(mut qr_image: RgbImage, icon: DynamicImage) -> Result<DynamicImage, Box<dyn std::error::Error>> {
-    // Calculate center position for the icon
-    let qr_width = qr_image.width();
-    let qr_height = qr_image.height();
-    let icon_width = icon.width();
-    let icon_height = icon.height();
+#[derive(Args)]
+struct VcardArgs {
+    /// Full name of the contact
+    #[arg(long)]
+    name: String,
+    /// Organization
+    #[arg(long)]
+    org: Option<String>,
+    /// Job title
+    #[arg(long)]
+    title: Option<String>,
+    /// Phone number
+    #[arg(long)]
+    phone: Option<String>,
+    /// Email address
+    #[arg(long)]
+    email: Option<String>,
+    /// Website
+    #[arg(long)]
+    url: Option<String>,
+    #[command(flatten)]
+    target: TargetArgs,
+    #[command(flatten)]
+    qr: QrArgs,
+}
 
-    let x_offset = (qr_width - icon_width) / 2;
-    let y_offset = (qr_height - icon_height) / 2;
+#[derive(Args)]
+struct MailtoArgs {
+    /// Recipient address
+    address: String,
+    /// Subject line
+    #[arg(long)]
+    subject: Option<String>,
+    /// Message body
+    #[arg(long)]
+    body: Option<String>,
+    #[command(flatten)]
+    target: TargetArgs,
+    #[command(flatten)]
+    qr: QrArgs,
+}
 
-    // Create a white background for the icon area to ensure it's readable
-    let background_size = icon_width + 10; // Add 5 pixels padding on each side
-    let bg_x = if x_offset >= 5 { x_offset - 5 } else { 0 };
-    let bg_y = if y_offset >= 5 { y_offset - 5 } else { 0 };
+#[derive(Args)]
+struct SmsArgs {
+    /// Phone number to text
+    number: String,
+    /// Message body
+    #[arg(long)]
+    body: Option<String>,
+    #[command(flatten)]
+    target: TargetArgs,
+    #[command(flatten)]
+    qr: QrArgs,
+}
 
-    // Draw white background directly on the RGB image
-    for y in 0..background_size {
-        for x in 0..background_size {
-            let px = bg_x + x;
-            let py = bg_y + y;
-            if px < qr_width && py < qr_height {
-                qr_image.put_pixel(px, py, Rgb([255, 255, 255]));
-            }
+#[derive(Args)]
+struct TelArgs {
+    /// Phone number to call
+    number: String,
+    #[command(flatten)]
+    target: TargetArgs,
+    #[command(flatten)]
+    qr: QrArgs,
+}
+
+#[derive(Args)]
+struct GeoArgs {
+    /// Coordinates in degrees
+    #[arg(value_name = "LAT,LONG", allow_hyphen_values = true, value_parser = parse_coordinates)]
+    coordinates: (f64, f64),
+    #[command(flatten)]
+    target: TargetArgs,
+    #[command(flatten)]
+    qr: QrArgs,
+}
+
+#[derive(Args)]
+struct BatchArgs {
+    /// CSV file to read, or - for stdin
+    #[arg(value_name = "ROWS.CSV")]
+    csv_path: String,
+    /// Number of QR codes to generate in parallel [default: one per CPU]
+    #[arg(long, value_name = "N", value_parser = parse_jobs)]
+    jobs: Option<usize>,
+    /// Output path for rows without one, where {index} is the row number, {slug} a filename-safe
+    /// form of the payload and {ext} the extension of --format (png by default), e.g. qr_{index}_{slug}.{ext}
+    #[arg(long, value_name = "TEMPLATE")]
+    output_template: Option<String>,
+    #[command(flatten)]
+    qr: QrArgs,
+}
+
+#[derive(Args)]
+struct ContactSheetArgs {
+    /// File of payloads, one per line, or - for stdin
+    #[arg(value_name = "LIST")]
+    list_path: String,
+    /// Image to write the sheet to
+    #[arg(value_name = "OUTPUT")]
+    output_path: String,
+    /// Number of codes per row [default: 4]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    columns: Option<u32>,
+    /// Pixels of background between the codes and around the sheet [default: 20]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_spacing)]
+    spacing: Option<u32>,
+    #[command(flatten)]
+    qr: QrArgs,
+}
+
+#[derive(Args)]
+struct AnimatedArgs {
+    /// File of payloads, one per line, or - for stdin
+    #[arg(value_name = "LIST")]
+    list_path: String,
+    /// GIF file to write the animation to
+    #[arg(value_name = "OUTPUT")]
+    output_path: String,
+    /// Milliseconds to show each code, rounded to hundredths of a second [default: 2000]
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u32).range(1..))]
+    frame_delay: Option<u32>,
+    #[command(flatten)]
+    qr: QrArgs,
+}
+
+#[derive(Args)]
+struct RenderMatrixArgs {
+    /// JSON file of the matrix, or - for stdin
+    #[arg(value_name = "MATRIX")]
+    matrix_path: String,
+    /// An optional icon to overlay, then the output path
+    #[arg(value_name = "PATHS", num_args = 1..=2, required = true)]
+    paths: Vec<String>,
+    #[command(flatten)]
+    qr: QrArgs,
+}
+
+#[derive(Args)]
+struct ScanArgs {
+    /// Image to read the code from, or - for stdin
+    #[arg(value_name = "IMAGE")]
+    image_path: String,
+}
+
+/// Where a single QR code goes.
+#[derive(Args)]
+struct TargetArgs {
+    /// An optional icon to overlay, a path or with --allow-network a URL, then the output path (- for stdout).
+    /// With --data-uri and --clipboard only the icon is given, with --preview and --json neither
+    #[arg(value_name = "PATHS", num_args = 0..=2)]
+    paths: Vec<String>,
+    /// Write the QR code to this path, in the format its extension names; repeat it to write several
+    /// formats from one rendering, in which case PATHS is only the icon
+    #[arg(short, long = "output", value_name = "PATH", conflicts_with_all = ["preview", "data_uri", "clipboard", "json"])]
+    outputs: Vec<String>,
+    /// Print the QR code to the terminal instead of writing a file
+    #[arg(long, conflicts_with_all = ["data_uri", "dry_run"])]
+    preview: bool,
+    /// Print a base64 data: URI (PNG unless --format is given) instead of writing a file
+    #[arg(long, conflicts_with = "dry_run")]
+    data_uri: bool,
+    /// Copy the image to the system clipboard instead of writing a file
+    #[arg(long, conflicts_with_all = ["preview", "data_uri", "dry_run"])]
+    clipboard: bool,
+    /// Print the module matrix as JSON, {"version", "width", "quiet_zone", "modules": [[bool, ...], ...]},
+    /// instead of writing a file, to render with your own styling
+    #[arg(long, conflicts_with_all = ["preview", "data_uri", "clipboard", "dry_run"])]
+    json: bool,
+    /// Split a payload too long for one code over up to 16 linked codes, written as
+    /// OUTPUT-1.png, OUTPUT-2.png, ...; --auto and --verify do not apply to the parts
+    #[arg(long, conflicts_with_all = ["preview", "data_uri", "clipboard", "json"])]
+    split: bool,
+    /// Keep running and regenerate the output whenever the icon, background image,
+    /// input file or config file changes, until Ctrl-C
+    #[arg(long, conflicts_with_all = ["preview", "data_uri", "clipboard", "json", "split", "dry_run"])]
+    watch: bool,
+    /// Write one image showing the code at each error correction level, L to H, side by side,
+    /// to judge how large an icon each can carry
+    #[arg(long, conflicts_with_all = ["preview", "data_uri", "clipboard", "json", "split", "watch", "auto", "ec_level"])]
+    compare_ec: bool,
+}
+
+/// Flags shared by every subcommand, mapping onto `QrOptions`.
+#[derive(Args)]
+struct QrArgs {
+    /// TOML file of default options, which QRGEN_* environment variables and then flags override
+    /// [default: qr-generator.toml in the current directory, if present]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Error correction level: L, M, Q or H [default: H with an icon, M without]
+    #[arg(long)]
+    ec_level: Option<EcLevel>,
+    /// Fix the QR version (module count) from 1 to 40 instead of picking the smallest
+    #[arg(long, value_name = "VERSION", value_parser = clap::value_parser!(u8).range(1..=MAX_VERSION as i64))]
+    qr_version: Option<u8>,
+    /// How to store the payload: auto, or utf8 to declare UTF-8 for non-ASCII text [default: auto]
+    #[arg(long)]
+    encoding: Option<PayloadEncoding>,
+    /// Force mask pattern 0 to 7, for output that stays the same across versions [default: lowest penalty]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=MAX_MASK as i64))]
+    mask: Option<u8>,
+    /// Encode a Micro QR code, M1 to M4 with a single finder pattern, for very short payloads
+    #[arg(long, conflicts_with_all = ["qr_version", "encoding", "mask"])]
+    micro: bool,
+    /// Side length of the output image in pixels, including the margin [default: 400]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_size)]
+    size: Option<u32>,
+    /// Quiet zone width in modules [default: 4]
+    #[arg(long, value_name = "MODULES", value_parser = parse_margin)]
+    margin: Option<u32>,
+    /// Color of the quiet zone, if it should differ from the background behind the modules
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    margin_color: Option<Rgb<u8>>,
+    /// Largest --size to render, in pixels, so a mistyped size cannot exhaust memory [default: 8192]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_size)]
+    max_dimension: Option<u32>,
+    /// Render a --size over --max-dimension at the limit instead of failing
+    #[arg(long)]
+    clamp: bool,
+    /// Crop the pixels left over past the quiet zone when the size does not divide into whole modules
+    #[arg(long)]
+    trim: bool,
+    /// Scale the modules up to fill the size exactly, rather than centering them in the pixels left over (raster output)
+    #[arg(long, conflicts_with = "trim")]
+    stretch: bool,
+    /// Pixels of background to add around the finished image [default: 0]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_outer_padding)]
+    outer_padding: Option<u32>,
+    /// Round the corners of the finished image, padding included, leaving them transparent, or of the --card-color card
+    /// [default: 0]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_card_radius)]
+    card_radius: Option<u32>,
+    /// Draw the finished image on a card of this color, inside any --outer-padding, for a sticker look
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    card_color: Option<Rgb<u8>>,
+    /// Width of the --card-color card around the finished image, in pixels [default: 24]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_card_padding)]
+    card_padding: Option<u32>,
+    /// Module color, as hex like #1a2b3c or a CSS color name like navy [default: #000000]
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    fg: Option<Rgb<u8>>,
+    /// Background color, optionally partly transparent as #rrggbbaa [default: #ffffff]
+    #[arg(long, value_name = "COLOR", value_parser = parse_rgba_color)]
+    bg: Option<Rgba<u8>>,
+    /// Shape of the icon and its backing: square or circle [default: square]
+    #[arg(long)]
+    icon_shape: Option<IconShape>,
+    /// Shape of the icon's backing, if it should differ from --icon-shape: square or circle
+    #[arg(long, value_name = "SHAPE")]
+    halo_shape: Option<IconShape>,
+    /// Stroke a ring of this color around the edge of the icon's backing
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    halo_border_color: Option<Rgb<u8>>,
+    /// Thickness of the backing's ring [default: 3]
+    #[arg(long, value_name = "PIXELS", requires = "halo_border_color")]
+    halo_border_width: Option<u32>,
+    /// Back the icon with the background color, white or a dark neutral, whichever stands out most from it
+    #[arg(long)]
+    auto_halo: bool,
+    /// Icon size relative to the QR code, at most 40 [default: 18]
+    #[arg(long, value_name = "PERCENT", value_parser = parse_icon_scale)]
+    icon_scale: Option<f64>,
+    /// How a non-square icon fills its box: contain, cover or stretch [default: contain]
+    #[arg(long)]
+    icon_fit: Option<IconFit>,
+    /// Where to place the icon: center, top-left, top-right, bottom-left or bottom-right [default: center]
+    #[arg(long, value_name = "ANCHOR")]
+    icon_anchor: Option<IconAnchor>,
+    /// Pixels to move the icon right and down from its anchor, such as 20,-10
+    #[arg(long, value_name = "X,Y", allow_hyphen_values = true, value_parser = parse_icon_offset)]
+    icon_offset: Option<(i32, i32)>,
+    /// Filter for resizing the icon: nearest, triangle, catmull-rom, gaussian or lanczos3 [default: lanczos3]
+    #[arg(long, value_name = "FILTER")]
+    icon_filter: Option<IconFilter>,
+    /// Frame of an animated GIF or WebP icon to use, counting from 0 [default: 0]
+    #[arg(long, value_name = "N")]
+    icon_frame: Option<usize>,
+    /// Backing around the icon in pixels or PERCENT%, 0 for none [default: 5]
+    #[arg(long, value_name = "PADDING")]
+    icon_padding: Option<IconPadding>,
+    /// Recolor the icon in shades of this color, keeping its shape
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    icon_tint: Option<Rgb<u8>>,
+    /// Convert the icon to grayscale
+    #[arg(long)]
+    icon_grayscale: bool,
+    /// Download the icon when it is an http:// or https:// URL, for up to 10 seconds and 10 MiB
+    #[arg(long)]
+    allow_network: bool,
+    /// Shape of the dark modules: square, circle or rounded [default: square]
+    #[arg(long)]
+    module_style: Option<ModuleStyle>,
+    /// Corner radius for rounded modules, 0 to 0.5 [default: 0.25]
+    #[arg(long, value_name = "FRACTION", value_parser = parse_corner_radius)]
+    corner_radius: Option<f64>,
+    /// Smooth the edges of non-square modules
+    #[arg(long)]
+    antialias: bool,
+    /// Gap around each dark module for a dotted look, 0 to 0.3 of the module size [default: 0]
+    #[arg(long, value_name = "FRACTION", value_parser = parse_module_gap)]
+    module_gap: Option<f64>,
+    /// Color the modules with a gradient between two colors
+    #[arg(long, num_args = 2, value_names = ["START", "END"], value_parser = parse_color)]
+    gradient: Option<Vec<Rgb<u8>>>,
+    /// Gradient type: linear, or radial from the start color at the center to the end color at the corners
+    /// [default: linear]
+    #[arg(long, value_name = "TYPE", requires = "gradient")]
+    gradient_type: Option<GradientType>,
+    /// Direction of a linear gradient: horizontal, vertical or diagonal [default: diagonal]
+    #[arg(long, value_name = "DIRECTION", requires = "gradient")]
+    gradient_direction: Option<GradientDirection>,
+    /// Color of the three corner finder patterns (eyes) [default: the module color]
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    eye_color: Option<Rgb<u8>>,
+    /// Draw each eye as one shape: square, rounded or circle [default: like the modules]
+    #[arg(long)]
+    eye_style: Option<EyeStyle>,
+    /// Color of the dotted timing lines along row and column 6 between the eyes [default: the module color]
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    timing_color: Option<Rgb<u8>>,
+    /// Draw the modules over an image, scaled and cropped to fill the code
+    #[arg(long, value_name = "PATH")]
+    background_image: Option<PathBuf>,
+    /// Opacity of a layer of the background color over the image, 0 to 1 [default: 0]
+    #[arg(long, value_name = "OPACITY", value_parser = parse_scrim_opacity, requires = "background_image")]
+    scrim_opacity: Option<f64>,
+    /// Swap the module and background colors, for light modules on a dark background
+    #[arg(long)]
+    invert: bool,
+    /// Leave the background transparent (PNG/SVG)
+    #[arg(long)]
+    transparent: bool,
+    /// Opacity of the icon backing, 0 to 1 [default: 1]
+    #[arg(long, value_name = "OPACITY", value_parser = parse_halo_opacity)]
+    halo_opacity: Option<f64>,
+    /// Draw a soft drop shadow beneath the icon's backing (raster output only)
+    #[arg(long)]
+    icon_shadow: bool,
+    /// Distance the shadow is shifted right and down [default: 4]
+    #[arg(long, value_name = "PIXELS", requires = "icon_shadow")]
+    shadow_offset: Option<u32>,
+    /// Blur radius of the shadow (Gaussian standard deviation) [default: 4]
+    #[arg(long, value_name = "PIXELS", requires = "icon_shadow", value_parser = parse_shadow_blur)]
+    shadow_blur: Option<f64>,
+    /// Opacity of the shadow, 0 to 1 [default: 0.5]
+    #[arg(long, value_name = "OPACITY", requires = "icon_shadow", value_parser = parse_shadow_opacity)]
+    shadow_opacity: Option<f64>,
+    /// Draw a rounded border with a text banner around the code
+    #[arg(long)]
+    frame: bool,
+    /// Color of the frame's border and banner [default: #000000]
+    #[arg(long, value_name = "COLOR", requires = "frame", value_parser = parse_color)]
+    frame_color: Option<Rgb<u8>>,
+    /// Text in the frame's banner [default: SCAN ME]
+    #[arg(long, value_name = "TEXT", requires = "frame")]
+    frame_text: Option<String>,
+    /// Color of the banner text [default: #ffffff]
+    #[arg(long, value_name = "COLOR", requires = "frame", value_parser = parse_color)]
+    frame_text_color: Option<Rgb<u8>>,
+    /// Radius of the frame's corners as a fraction of its width, 0 to 0.5 [default: 0.05]
+    #[arg(long, value_name = "FRACTION", requires = "frame", value_parser = parse_corner_radius)]
+    frame_radius: Option<f64>,
+    /// Text label centered below the code
+    #[arg(long, value_name = "TEXT")]
+    caption: Option<String>,
+    /// Caption font size [default: 24]
+    #[arg(long, value_name = "PIXELS", value_parser = parse_caption_size)]
+    caption_size: Option<f64>,
+    /// Caption color [default: the module color]
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    caption_color: Option<Rgb<u8>>,
+    /// Accessible description of SVG output, set as its <title> and aria-label [default: the payload]
+    #[arg(long, value_name = "TEXT")]
+    alt: Option<String>,
+    /// Output format (png, jpg, webp, svg, pdf, ico, ...), required for - output; ico packs 16, 32 and 48px favicons
+    #[arg(long)]
+    format: Option<OutputFormat>,
+    /// JPEG and lossy WebP quality from 1 to 100 [default: 90]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    /// Encode WebP output losslessly
+    #[arg(long)]
+    webp_lossless: bool,
+    /// Bits per pixel channel of PNG output: 1 for plain black and white codes, or 8 [default: 8]
+    #[arg(long, value_name = "BITS", value_parser = parse_bit_depth)]
+    bit_depth: Option<u8>,
+    /// Print resolution to record in PNG output, in dots per inch
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    dpi: Option<u32>,
+    /// Leave the payload and this tool's name out of PNG and SVG metadata, and the payload out of the SVG title
+    #[arg(long)]
+    no_metadata: bool,
+    /// Printed side length of the code in PDF output, not counting any frame, caption or padding [default: 50]
+    #[arg(long, value_name = "MM", value_parser = parse_pdf_size)]
+    pdf_size_mm: Option<f64>,
+    /// Pick the EC level and icon scale that decode, shrinking the icon as needed
+    #[arg(long)]
+    auto: bool,
+    /// Rotate the finished image clockwise: 90, 180 or 270
+    #[arg(long, value_name = "DEGREES")]
+    rotate: Option<Rotation>,
+    /// Keep the icon upright when rotating
+    #[arg(long)]
+    icon_upright: bool,
+    /// Decode the result and fail if it does not read back as the payload
+    #[arg(long)]
+    verify: bool,
+    /// Refuse to write a code whose icon hides more modules than its error correction can recover
+    #[arg(long)]
+    strict: bool,
+    /// Refuse to write a code whose icon or its backing reaches the quiet zone or a finder pattern
+    #[arg(long = "no-quiet-zone-icon-overlap")]
+    forbid_icon_overlap: bool,
+    /// Overwrite existing output files, and write output even at a JPEG quality below 60
+    #[arg(long)]
+    force: bool,
+    /// Never overwrite an existing output file [default: ask when stdin is a terminal, except in batch mode]
+    #[arg(long, conflicts_with = "force")]
+    no_clobber: bool,
+    /// Generate, and with --verify check, each code without writing any file, to confirm the inputs will encode
+    #[arg(long)]
+    dry_run: bool,
+    /// Also log how each code is laid out: version, error correction, module size, icon size and coverage
+    #[arg(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Log nothing but errors
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+impl QrArgs {
+    /// A command line that runs `action` with `options` and these flags,
+    /// for the subcommands that read their payloads from files.
+    fn file_cli(&self, action: Action, options: QrOptions, input_file: Option<String>) -> Cli {
+        Cli {
+            payload: Vec::new(),
+            input_file,
+            text_input: false,
+            encode_url: false,
+            action,
+            options,
+            format: self.format,
+            force: self.force,
+            overwrite: self.overwrite(),
+            auto: self.auto,
+            log_level: self.log_level(),
+            dry_run: self.dry_run,
+        }
+    }
+
+    /// Whether `--force` or `--no-clobber` settle what to do with existing
+    /// output files.
+    fn overwrite(&self) -> Overwrite {
+        match (self.force, self.no_clobber) {
+            (true, _) => Overwrite::Always,
+            (false, true) => Overwrite::Never,
+            (false, false) => Overwrite::Ask,
         }
     }
 
-    // Convert to DynamicImage for overlay operation
-    let mut base_image = DynamicImage::ImageRgb8(qr_image);
+    /// The most detailed messages `--verbose` and `--quiet` ask for.
+    fn log_level(&self) -> LevelFilter {
+        match (self.verbose, self.quiet) {
+            (true, _) => LevelFilter::Debug,
+            (false, true) => LevelFilter::Error,
+            (false, false) => LevelFilter::Info,
+        }
+    }
+
+    /// The options to start from: the `--config` file, else
+    /// `qr-generator.toml` in the current directory if there is one, else
+    /// the defaults, with the `QRGEN_*` variables `var` finds applied over
+    /// them.
+    fn base_options(&self, var: impl Fn(&str) -> Option<String>) -> Result<QrOptions, String> {
+        let path = match &self.config {
+            Some(path) => Some(path.as_path()),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Some(Path::new(DEFAULT_CONFIG_FILE)),
+            None => None,
+        };
+        let config = match path {
+            Some(path) => load_config(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+            None => QrOptions::default(),
+        };
+        apply_env(config, var).map_err(|e| e.to_string())
+    }
+
+    /// Applies the flags that were given on top of the base options, read
+    /// from the process environment.
+    fn options(&self) -> Result<QrOptions, String> {
+        self.options_with_env(|name| env::var(name).ok())
+    }
+
+    /// Applies the flags that were given on top of the base options, looking
+    /// up environment variables with `var`.
+    fn options_with_env(&self, var: impl Fn(&str) -> Option<String>) -> Result<QrOptions, String> {
+        let base = self.base_options(var)?;
+        let (direction, kind) = (self.gradient_direction.unwrap_or_default(), self.gradient_type.unwrap_or_default());
+        let gradient = self.gradient.as_deref().map(|colors| Gradient { start: colors[0], end: colors[1], direction, kind });
+        let icon_shadow = match (self.icon_shadow, base.icon_shadow) {
+            (false, base_shadow) => base_shadow,
+            (true, base_shadow) => {
+                let shadow = base_shadow.unwrap_or_default();
+                Some(IconShadow {
+                    offset: self.shadow_offset.unwrap_or(shadow.offset),
+                    blur: self.shadow_blur.unwrap_or(shadow.blur),
+                    opacity: self.shadow_opacity.unwrap_or(shadow.opacity),
+                })
+            }
+        };
+        let halo_border = match (self.halo_border_color, base.halo_border) {
+            (None, base_border) => base_border,
+            (Some(color), base_border) => {
+                let width = self.halo_border_width.unwrap_or(base_border.unwrap_or_default().width);
+                Some(HaloBorder { color, width })
+            }
+        };
+        let frame = match (self.frame, base.frame) {
+            (false, base_frame) => base_frame,
+            (true, base_frame) => {
+                let frame = base_frame.unwrap_or_default();
+                Some(Frame {
+                    color: self.frame_color.unwrap_or(frame.color),
+                    text: self.frame_text.clone().unwrap_or(frame.text),
+                    text_color: self.frame_text_color.unwrap_or(frame.text_color),
+                    corner_radius: self.frame_radius.unwrap_or(frame.corner_radius),
+                })
+            }
+        };
+        let options = QrOptions {
+            ec_level: self.ec_level.or(base.ec_level),
+            version: self.qr_version.or(base.version),
+            encoding: self.encoding.unwrap_or(base.encoding),
+            mask: self.mask.or(base.mask),
+            micro: self.micro || base.micro,
+            size: self.size.unwrap_or(base.size),
+            margin: self.margin.unwrap_or(base.margin),
+            margin_color: self.margin_color.or(base.margin_color),
+            trim: self.trim || base.trim,
+            stretch: self.stretch || base.stretch,
+            max_dimension: self.max_dimension.unwrap_or(base.max_dimension),
+            clamp: self.clamp || base.clamp,
+            foreground: self.fg.unwrap_or(base.foreground),
+            background: self.bg.map_or(base.background, |Rgba([r, g, b, _])| Rgb([r, g, b])),
+            background_alpha: self.bg.map_or(base.background_alpha, |bg| bg[3]),
+            icon_shape: self.icon_shape.unwrap_or(base.icon_shape),
+            halo_shape: self.halo_shape.or(base.halo_shape),
+            halo_border,
+            auto_halo: self.auto_halo || base.auto_halo,
+            icon_scale: self.icon_scale.unwrap_or(base.icon_scale),
+            icon_fit: self.icon_fit.unwrap_or(base.icon_fit),
+            icon_anchor: self.icon_anchor.unwrap_or(base.icon_anchor),
+            icon_offset: self.icon_offset.unwrap_or(base.icon_offset),
+            icon_filter: self.icon_filter.unwrap_or(base.icon_filter),
+            icon_frame: self.icon_frame.unwrap_or(base.icon_frame),
+            icon_padding: self.icon_padding.unwrap_or(base.icon_padding),
+            icon_tint: self.icon_tint.or(base.icon_tint),
+            icon_grayscale: self.icon_grayscale || base.icon_grayscale,
+            allow_network: self.allow_network || base.allow_network,
+            module_style: self.module_style.unwrap_or(base.module_style),
+            corner_radius: self.corner_radius.unwrap_or(base.corner_radius),
+            antialias: self.antialias || base.antialias,
+            module_gap: self.module_gap.unwrap_or(base.module_gap),
+            gradient: gradient.or(base.gradient),
+            eye_color: self.eye_color.or(base.eye_color),
+            timing_color: self.timing_color.or(base.timing_color),
+            eye_style: self.eye_style.or(base.eye_style),
+            background_image: self.background_image.clone().or(base.background_image),
+            scrim_opacity: self.scrim_opacity.unwrap_or(base.scrim_opacity),
+            transparent: self.transparent || base.transparent,
+            halo_opacity: self.halo_opacity.unwrap_or(base.halo_opacity),
+            icon_shadow,
+            frame,
+            caption: self.caption.clone().or(base.caption),
+            caption_size: self.caption_size.unwrap_or(base.caption_size),
+            caption_color: self.caption_color.or(base.caption_color),
+            alt: self.alt.clone().or(base.alt),
+            rotate: self.rotate.or(base.rotate),
+            icon_upright: self.icon_upright || base.icon_upright,
+            outer_padding: self.outer_padding.unwrap_or(base.outer_padding),
+            card_radius: self.card_radius.unwrap_or(base.card_radius),
+            card_color: self.card_color.or(base.card_color),
+            card_padding: self.card_padding.unwrap_or(base.card_padding),
+            quality: self.quality.unwrap_or(base.quality),
+            webp_lossless: self.webp_lossless || base.webp_lossless,
+            bit_depth: self.bit_depth.unwrap_or(base.bit_depth),
+            dpi: self.dpi.or(base.dpi),
+            metadata: !self.no_metadata && base.metadata,
+            pdf_size_mm: self.pdf_size_mm.unwrap_or(base.pdf_size_mm),
+            verify: self.verify || base.verify,
+            strict: self.strict || base.strict,
+            forbid_icon_overlap: self.forbid_icon_overlap || base.forbid_icon_overlap,
+            ..base
+        };
+        Ok(if self.invert { options.inverted() } else { options })
+    }
+}
+
+fn parse_size(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid size '{}', expected a number of pixels", value))
+}
+
+fn parse_margin(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid margin '{}', expected a number of modules", value))
+}
+
+fn parse_outer_padding(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid outer padding '{}', expected a number of pixels", value))
+}
+
+fn parse_card_padding(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid card padding '{}', expected a number of pixels", value))
+}
+
+fn parse_card_radius(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid card radius '{}', expected a number of pixels", value))
+}
+
+fn parse_spacing(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid spacing '{}', expected a number of pixels", value))
+}
+
+fn parse_bit_depth(value: &str) -> Result<u8, String> {
+    match value {
+        "1" => Ok(1),
+        "8" => Ok(8),
+        _ => Err(format!("invalid bit depth '{}', expected 1 or 8", value)),
+    }
+}
+
+fn parse_icon_scale(value: &str) -> Result<f64, String> {
+    value
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid icon scale '{}', expected a percentage", value))
+}
+
+fn parse_corner_radius(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|r| (0.0..=0.5).contains(r))
+        .ok_or_else(|| format!("invalid corner radius '{}', expected a fraction from 0 to 0.5", value))
+}
+
+fn parse_module_gap(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|g| (0.0..=MAX_MODULE_GAP).contains(g))
+        .ok_or_else(|| format!("invalid module gap '{}', expected a fraction from 0 to {}", value, MAX_MODULE_GAP))
+}
+
+fn parse_halo_opacity(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|o| (0.0..=1.0).contains(o))
+        .ok_or_else(|| format!("invalid halo opacity '{}', expected a value from 0 to 1", value))
+}
+
+fn parse_shadow_blur(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|b: &f64| b.is_finite() && *b >= 0.0)
+        .ok_or_else(|| format!("invalid shadow blur '{}', expected a non-negative number of pixels", value))
+}
+
+fn parse_shadow_opacity(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|o| (0.0..=1.0).contains(o))
+        .ok_or_else(|| format!("invalid shadow opacity '{}', expected a value from 0 to 1", value))
+}
+
+fn parse_scrim_opacity(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|o| (0.0..=1.0).contains(o))
+        .ok_or_else(|| format!("invalid scrim opacity '{}', expected a value from 0 to 1", value))
+}
+
+fn parse_caption_size(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|s: &f64| s.is_finite() && *s > 0.0)
+        .ok_or_else(|| format!("invalid caption size '{}', expected a positive number of pixels", value))
+}
+
+fn parse_pdf_size(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|mm: &f64| mm.is_finite() && *mm > 0.0)
+        .ok_or_else(|| format!("invalid PDF size '{}', expected a positive number of millimetres", value))
+}
+
+fn parse_jobs(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|&n: &usize| n > 0)
+        .ok_or_else(|| format!("invalid job count '{}', expected a positive number", value))
+}
+
+fn parse_icon_offset(value: &str) -> Result<(i32, i32), String> {
+    value
+        .split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| format!("invalid icon offset '{}', expected X,Y in pixels", value))
+}
+
+fn parse_coordinates(value: &str) -> Result<(f64, f64), String> {
+    value
+        .split_once(',')
+        .and_then(|(lat, lon)| Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?)))
+        .ok_or_else(|| format!("invalid coordinates '{}', expected LAT,LONG", value))
+}
+
+/// Parses the command line, including the program name.
+fn parse_args<I, T>(args: I) -> Result<Cli, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let command_line = CommandLine::try_parse_from(args)?;
+    resolve(command_line).map_err(|e| CommandLine::command().error(ErrorKind::ValueValidation, e))
+}
+
+/// Builds the payload and action a parsed command line asks for.
+fn resolve(command_line: CommandLine) -> Result<Cli, String> {
+    let (mut input_file, mut text_input, mut encode_url) = (None, false, false);
+    let (payload, target, qr, auto) = match command_line.command.unwrap_or(Command::Url(command_line.url)) {
+        Command::Url(mut args) => {
+            // With the payload read from a file the first path is parsed as the URL
+            text_input = args.text_file.is_some();
+            encode_url = args.encode_url;
+            if let Some(file) = args.input_file.take().or(args.text_file.take()) {
+                args.target.paths.splice(0..0, args.payload.take());
+                if args.target.paths.len() > 2 {
+                    let flag = if text_input { "--text-file" } else { "--input-file" };
+                    return Err(format!("{} replaces the URL, so give at most an icon and the output path", flag));
+                }
+                input_file = Some(file);
+            }
+            (args.payload.unwrap_or_default(), args.target, args.qr, false)
+        }
+        Command::Wifi(args) => {
+            let wifi = WifiConfig {
+                ssid: args.ssid,
+                password: args.password.unwrap_or_default(),
+                auth: args.auth.unwrap_or_default(),
+                hidden: args.hidden,
+            };
+            (wifi.payload(), args.target, args.qr, false)
+        }
+        Command::Vcard(args) => {
+            if args.name.trim().is_empty() {
+                return Err("the vcard subcommand requires a non-empty --name".to_string());
+            }
+            let card = VCard {
+                name: args.name,
+                org: args.org.unwrap_or_default(),
+                title: args.title.unwrap_or_default(),
+                phone: args.phone.unwrap_or_default(),
+                email: args.email.unwrap_or_default(),
+                url: args.url.unwrap_or_default(),
+            };
+            // Contact cards get long, so always let --auto find a level that fits
+            (card.payload(), args.target, args.qr, true)
+        }
+        Command::Mailto(args) => {
+            let payload = mailto_payload(&args.address, args.subject.as_deref().unwrap_or(""), args.body.as_deref().unwrap_or(""));
+            (payload, args.target, args.qr, false)
+        }
+        Command::Sms(args) => (sms_payload(&args.number, args.body.as_deref().unwrap_or("")), args.target, args.qr, false),
+        Command::Tel(args) => (tel_payload(&args.number), args.target, args.qr, false),
+        Command::Geo(args) => {
+            let (latitude, longitude) = args.coordinates;
+            (geo_payload(latitude, longitude)?, args.target, args.qr, false)
+        }
+        Command::Batch(args) => {
+            let action = Action::Batch { csv_path: args.csv_path, jobs: args.jobs.unwrap_or(0), output_template: args.output_template };
+            return Ok(args.qr.file_cli(action, args.qr.options()?, input_file));
+        }
+        Command::ContactSheet(args) => {
+            let action = Action::ContactSheet {
+                list_path: args.list_path,
+                output_path: args.output_path,
+                columns: args.columns.unwrap_or(DEFAULT_SHEET_COLUMNS),
+                spacing: args.spacing.unwrap_or(DEFAULT_SHEET_SPACING),
+            };
+            return Ok(args.qr.file_cli(action, args.qr.options()?, input_file));
+        }
+        Command::Animated(args) => {
+            let action = Action::Animated {
+                list_path: args.list_path,
+                output_path: args.output_path,
+                frame_delay: args.frame_delay.unwrap_or(DEFAULT_FRAME_DELAY),
+            };
+            return Ok(args.qr.file_cli(action, args.qr.options()?, input_file));
+        }
+        Command::RenderMatrix(mut args) => {
+            let mut options = args.qr.options()?;
+            let output_path = args.paths.pop().unwrap_or_default();
+            if let Some(icon_path) = args.paths.pop() {
+                options.icon = Some(PathBuf::from(icon_path));
+            }
+            let action = Action::RenderMatrix { matrix_path: args.matrix_path, output_path };
+            return Ok(args.qr.file_cli(action, options, input_file));
+        }
+        Command::Scan(args) => {
+            return Ok(Cli {
+                payload: Vec::new(),
+                input_file,
+                text_input: false,
+                encode_url: false,
+                action: Action::Scan { image_path: args.image_path },
+                options: QrOptions::default(),
+                format: None,
+                force: false,
+                overwrite: Overwrite::Never,
+                auto: false,
+                log_level: LevelFilter::Info,
+                dry_run: false,
+            });
+        }
+    };
 
-    // Overlay the icon
-    imageops::overlay(&mut base_image, &icon, x_offset as i64, y_offset as i64);
+    let mut options = qr.options()?;
+    let mut paths = target.paths.into_iter();
+    let action = if target.preview {
+        if paths.next().is_some() {
+            return Err("--preview takes no icon or output path".to_string());
+        }
+        Action::Preview
+    } else if target.json {
+        if paths.next().is_some() {
+            return Err("--json takes no icon or output path".to_string());
+        }
+        Action::Json
+    } else if target.clipboard {
+        if let Some(icon_path) = paths.next() {
+            options.icon = Some(PathBuf::from(icon_path));
+        }
+        if paths.next().is_some() {
+            return Err("--clipboard takes an icon path but no output path".to_string());
+        }
+        Action::Clipboard
+    } else if target.data_uri {
+        if let Some(icon_path) = paths.next() {
+            options.icon = Some(PathBuf::from(icon_path));
+        }
+        if paths.next().is_some() {
+            return Err("--data-uri takes an icon path but no output path".to_string());
+        }
+        Action::DataUri
+    } else {
+        let mut output_paths = target.outputs;
+        if output_paths.is_empty() {
+            output_paths.push(match (paths.next(), paths.next()) {
+                (Some(output_path), None) => output_path,
+                (Some(icon_path), Some(output_path)) => {
+                    options.icon = Some(PathBuf::from(icon_path));
+                    output_path
+                }
+                _ => return Err("missing the output path".to_string()),
+            });
+        } else {
+            if let Some(icon_path) = paths.next() {
+                options.icon = Some(PathBuf::from(icon_path));
+            }
+            if paths.next().is_some() {
+                return Err("with --output only the icon path is given, not the output path".to_string());
+            }
+        }
+        if output_paths.iter().filter(|output_path| *output_path == "-").count() > 1 {
+            return Err("only one output can go to stdout".to_string());
+        }
+        if !(target.watch || target.split || target.compare_ec) {
+            Action::Generate { output_paths }
+        } else if output_paths.len() > 1 {
+            return Err("--watch, --split and --compare-ec write a single output path".to_string());
+        } else if target.compare_ec {
+            let output_path = output_paths.remove(0);
+            if output_path == "-" {
+                return Err("--compare-ec cannot write to stdout".to_string());
+            }
+            Action::CompareEc { output_path }
+        } else if target.watch {
+            let output_path = output_paths.remove(0);
+            if output_path == "-" {
+                return Err("--watch rewrites the output file and cannot write to stdout".to_string());
+            }
+            Action::Watch { output_path, config: qr.config.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE)) }
+        } else if output_paths[0] == "-" {
+            return Err("--split writes numbered files and cannot write to stdout".to_string());
+        } else {
+            Action::Split { output_path: output_paths.remove(0) }
+        }
+    };
 
-    Ok(base_image)
+    let (format, force, overwrite, auto) = (qr.format, qr.force, qr.overwrite(), auto || qr.auto);
+    let (log_level, dry_run) = (qr.log_level(), qr.dry_run);
+    Ok(Cli { payload: payload.into_bytes(), input_file, text_input, encode_url, action, options, format, force, overwrite, auto, log_level, dry_run })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::tempdir;
+    use std::sync::{Mutex, Once};
+    use std::thread::{self, ThreadId};
+
+    /// The command line `qr-generator <list...>`.
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("qr-generator").chain(list.iter().copied()).map(str::to_string).collect()
+    }
 
     #[test]
-    fn test_qr_generation() {
-        let temp_dir = tempdir().unwrap();
-        let output_path = temp_dir.path().join("test_qr.png");
+    fn test_parse_positional() {
+        let cli = parse_args(args(&["https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"https://example.com");
+        assert!(matches!(cli.action, Action::Generate { ref output_paths } if output_paths == &["out.png"]));
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+        assert_eq!(cli.options.ec_level, None);
+    }
 
-        // Create a simple test icon (1x1 white pixel)
-        let test_icon = DynamicImage::new_rgb8(1, 1);
-        let icon_path = temp_dir.path().join("test_icon.png");
-        test_icon.save(&icon_path).unwrap();
+    #[test]
+    fn test_parse_split() {
+        let cli = parse_args(args(&["https://example.com", "out.png", "--split"])).unwrap();
+        assert!(matches!(cli.action, Action::Split { ref output_path } if output_path == "out.png"));
+        assert!(parse_args(args(&["https://example.com", "-", "--split"])).is_err());
+        assert!(parse_args(args(&["https://example.com", "--preview", "--split"])).is_err());
 
-        // Test QR generation
-        let result = generate_qr_with_icon(
-            "https://example.com",
-            icon_path.to_str().unwrap(),
-            output_path.to_str().unwrap()
-        );
+        assert_eq!(part_path(Path::new("codes/out.png"), 2), PathBuf::from("codes/out-2.png"));
+        assert_eq!(part_path(Path::new("out"), 1), PathBuf::from("out-1"));
+    }
+
+    #[test]
+    fn test_parse_compare_ec() {
+        let cli = parse_args(args(&["https://example.com", "logo.png", "compare.png", "--compare-ec"])).unwrap();
+        assert!(matches!(cli.action, Action::CompareEc { ref output_path } if output_path == "compare.png"));
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+        assert!(parse_args(args(&["https://example.com", "-", "--compare-ec"])).is_err());
+        assert!(parse_args(args(&["https://example.com", "out.png", "--compare-ec", "--ec-level", "H"])).is_err());
+        assert!(parse_args(args(&["https://example.com", "-o", "a.png", "-o", "b.png", "--compare-ec"])).is_err());
+    }
 
-        assert!(result.is_ok());
-        assert!(output_path.exists());
+    #[test]
+    fn test_writes_every_output() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let png_path = temp_dir.path().join("web.png");
+        let svg_path = temp_dir.path().join("print.svg");
+        let (png, svg) = (png_path.to_str().unwrap(), svg_path.to_str().unwrap());
+        let cli = parse_args(args(&["https://example.com", "-o", png, "--output", svg])).unwrap();
+        assert!(matches!(cli.action, Action::Generate { ref output_paths } if output_paths == &[png, svg]));
+        assert_eq!(cli.options.icon, None);
+        run(&cli).unwrap();
+        assert!(image::open(&png_path).is_ok());
+        assert!(fs::read_to_string(&svg_path).unwrap().contains("<svg"));
+
+        let cli = parse_args(args(&["https://example.com", "logo.png", "-o", "a.png", "-o", "b.pdf"])).unwrap();
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+        assert!(parse_args(args(&["https://example.com", "logo.png", "out.png", "-o", "b.svg"])).is_err());
+        assert!(parse_args(args(&["https://example.com", "-o", "-", "-o", "-"])).is_err());
+        assert!(parse_args(args(&["https://example.com", "-o", "a.png", "-o", "b.svg", "--split"])).is_err());
+        assert!(parse_args(args(&["https://example.com", "-o", "a.png", "--preview"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_without_icon() {
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert!(matches!(cli.action, Action::Generate { ref output_paths } if output_paths == &["out.png"]));
+        assert_eq!(cli.options.icon, None);
+
+        assert!(parse_args(args(&["https://example.com"])).is_err());
+        assert!(parse_args(args(&["a", "b", "c", "d"])).is_err());
+    }
+
+    #[test]
+    fn test_read_payload_from_stdin() {
+        assert_eq!(read_payload(&b"https://example.com\n"[..]).unwrap(), "https://example.com");
+        assert_eq!(read_payload(&b"line one\r\n"[..]).unwrap(), "line one");
+        assert_eq!(read_payload(&b"  two\nlines \n\n"[..]).unwrap(), "  two\nlines \n");
+        assert_eq!(read_payload(&b"no newline "[..]).unwrap(), "no newline ");
+        assert!(read_payload(&[0xff, 0xfe][..]).is_err());
+
+        let cli = parse_args(args(&["-", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"-");
     }
 
     #[test]
-    fn test_icon_loading_and_resizing() {
-        let temp_dir = tempdir().unwrap();
+    fn test_parse_input_file() {
+        let cli = parse_args(args(&["--input-file", "blob.bin", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.input_file.as_deref(), Some("blob.bin"));
+        assert!(matches!(cli.action, Action::Generate { ref output_paths } if output_paths == &["out.png"]));
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
 
-        // Create a test icon
-        let test_icon = DynamicImage::new_rgb8(100, 100);
-        let icon_path = temp_dir.path().join("test_icon.png");
-        test_icon.save(&icon_path).unwrap();
+        let cli = parse_args(args(&["url", "--input-file", "-", "--preview"])).unwrap();
+        assert!(matches!(cli.action, Action::Preview));
+        assert!(parse_args(args(&["--input-file", "blob.bin", "a", "b", "c"])).is_err());
+        assert_eq!(read_bytes(&[0xff, 0xfe, b'\n'][..]).unwrap(), [0xff, 0xfe, b'\n']);
+    }
 
-        // Test loading and resizing
-        let result = load_and_resize_icon(icon_path.to_str().unwrap(), 50);
-        assert!(result.is_ok());
+    #[test]
+    fn test_encode_url_flag() {
+        let mut cli = parse_args(args(&["--encode-url", "https://example.com/?q=red shoes", "out.png"])).unwrap();
+        read_input(&mut cli).unwrap();
+        assert_eq!(cli.payload, b"https://example.com/?q=red%20shoes");
 
-        let resized = result.unwrap();
-        assert_eq!(resized.width(), 50);
-        assert_eq!(resized.height(), 50);
+        let mut cli = parse_args(args(&["https://example.com/?q=red shoes", "out.png"])).unwrap();
+        read_input(&mut cli).unwrap();
+        assert_eq!(cli.payload, b"https://example.com/?q=red shoes");
+        assert!(parse_args(args(&["--encode-url", "--input-file", "blob.bin", "out.png"])).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_text_file_keeps_newlines() {
+        let dir = std::env::temp_dir().join(format!("qr-generator-text-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let note = dir.join("note.txt");
+        fs::write(&note, "Buy milk\r\nCall Sam at 5 — don't forget\n").unwrap();
+
+        let mut cli = parse_args(args(&["--text-file", note.to_str().unwrap(), "out.png"])).unwrap();
+        assert!(matches!(cli.action, Action::Generate { ref output_paths } if output_paths == &["out.png"]));
+        read_input(&mut cli).unwrap();
+        assert_eq!(cli.payload, "Buy milk\r\nCall Sam at 5 — don't forget\n".as_bytes());
+        let image = generate(&cli.payload, &cli.options).unwrap();
+        assert_eq!(scan_image(&image).unwrap(), cli.payload);
+
+        // Binary data belongs to --input-file
+        fs::write(&note, [0xff, 0xfe, b'\n']).unwrap();
+        let mut cli = parse_args(args(&["--text-file", note.to_str().unwrap(), "out.png"])).unwrap();
+        assert!(read_input(&mut cli).unwrap_err().contains("--input-file"));
+        assert!(parse_args(args(&["--text-file", "a.txt", "--input-file", "b.bin", "out.png"])).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let cli = parse_args(args(&["--format", "png", "https://example.com", "-"])).unwrap();
+        assert_eq!(cli.format, Some(OutputFormat::Raster(ImageFormat::Png)));
+        assert!(matches!(cli.action, Action::Generate { ref output_paths } if output_paths == &["-"]));
+
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.format, None);
+
+        assert!(parse_args(args(&["--format", "nope", "https://example.com", "-"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let cli = parse_args(args(&["batch", "--size", "200", "rows.csv"])).unwrap();
+        assert!(matches!(cli.action, Action::Batch { ref csv_path, jobs: 0, output_template: None } if csv_path == "rows.csv"));
+        assert_eq!(cli.options.size, 200);
+
+        let cli = parse_args(args(&["batch", "--jobs", "4", "rows.csv"])).unwrap();
+        assert!(matches!(cli.action, Action::Batch { jobs: 4, .. }));
+        let cli = parse_args(args(&["batch", "--output-template", "qr_{index}.{ext}", "rows.csv"])).unwrap();
+        assert!(matches!(cli.action, Action::Batch { output_template: Some(ref template), .. } if template == "qr_{index}.{ext}"));
+        assert!(parse_args(args(&["batch", "--jobs", "0", "rows.csv"])).is_err());
+        assert!(parse_args(args(&["--jobs", "4", "https://example.com", "out.png"])).is_err());
+
+        assert!(parse_args(args(&["batch"])).is_err());
+        assert!(parse_args(args(&["batch", "a.csv", "b.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_contact_sheet() {
+        let cli = parse_args(args(&["contact-sheet", "codes.txt", "sheet.png", "--columns", "3", "--size", "200"])).unwrap();
+        assert!(matches!(
+            cli.action,
+            Action::ContactSheet { ref list_path, ref output_path, columns: 3, spacing: DEFAULT_SHEET_SPACING }
+                if list_path == "codes.txt" && output_path == "sheet.png"
+        ));
+        assert_eq!(cli.options.size, 200);
+
+        let cli = parse_args(args(&["contact-sheet", "-", "sheet.png", "--spacing", "0"])).unwrap();
+        assert!(matches!(cli.action, Action::ContactSheet { columns: DEFAULT_SHEET_COLUMNS, spacing: 0, .. }));
+        assert!(parse_args(args(&["contact-sheet", "codes.txt", "sheet.png", "--columns", "0"])).is_err());
+        assert!(parse_args(args(&["contact-sheet", "codes.txt"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_animated() {
+        let cli = parse_args(args(&["animated", "promos.txt", "promo.gif", "--frame-delay", "500"])).unwrap();
+        assert!(matches!(
+            cli.action,
+            Action::Animated { ref list_path, ref output_path, frame_delay: 500 }
+                if list_path == "promos.txt" && output_path == "promo.gif"
+        ));
+
+        let cli = parse_args(args(&["animated", "-", "promo.gif"])).unwrap();
+        assert!(matches!(cli.action, Action::Animated { frame_delay: DEFAULT_FRAME_DELAY, .. }));
+        assert!(parse_args(args(&["animated", "promos.txt", "promo.gif", "--frame-delay", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_progress_bar_off_terminal() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let csv_path = temp_dir.path().join("rows.csv");
+        let output = temp_dir.path().join("a.png");
+        fs::write(&csv_path, format!("url,output\nhttps://example.com/a,{}\n", output.display())).unwrap();
+
+        // Tests run with their output captured, so the bar only shows when both would reach a terminal
+        let cli = parse_args(args(&["batch", csv_path.to_str().unwrap()])).unwrap();
+        let on_terminal = io::stdout().is_terminal() && io::stderr().is_terminal();
+        assert_eq!(progress::shown(&cli), on_terminal);
+        run(&cli).unwrap();
+        assert!(output.exists());
+
+        let cli = parse_args(args(&["batch", "--quiet", csv_path.to_str().unwrap()])).unwrap();
+        assert!(!progress::shown(&cli));
+    }
+
+    #[test]
+    fn test_scan_generated_code() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("out.png");
+        let output = output_path.to_str().unwrap();
+        run(&parse_args(args(&["https://example.com/scan", output])).unwrap()).unwrap();
+
+        let cli = parse_args(args(&["scan", output])).unwrap();
+        assert!(matches!(cli.action, Action::Scan { ref image_path } if image_path == output));
+        run(&cli).unwrap();
+
+        fs::write(&output_path, b"not an image").unwrap();
+        assert!(run(&cli).is_err());
+        assert!(parse_args(args(&["scan"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_render_matrix() {
+        let cli = parse_args(args(&["render-matrix", "matrix.json", "logo.png", "out.svg", "--module-style", "rounded"])).unwrap();
+        assert!(matches!(
+            cli.action,
+            Action::RenderMatrix { ref matrix_path, ref output_path } if matrix_path == "matrix.json" && output_path == "out.svg"
+        ));
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+        assert_eq!(cli.options.module_style, ModuleStyle::Rounded);
+
+        let cli = parse_args(args(&["render-matrix", "-", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon, None);
+        assert!(parse_args(args(&["render-matrix", "matrix.json"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_vcard() {
+        let cli = parse_args(args(&[
+            "vcard", "--name", "Ada Lovelace", "--email", "ada@example.com", "logo.png", "card.png",
+        ]))
+        .unwrap();
+        assert_eq!(cli.payload, qr_generator::vcard_payload("Ada Lovelace", "", "", "", "ada@example.com", "").into_bytes());
+        assert!(cli.auto);
+        assert!(matches!(cli.action, Action::Generate { ref output_paths } if output_paths == &["card.png"]));
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+
+        assert!(parse_args(args(&["vcard", "--org", "Acme", "card.png"])).is_err());
+        assert!(parse_args(args(&["--name", "Ada", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_uri_subcommands() {
+        let cli = parse_args(args(&["mailto", "--subject", "Hi there", "ada@example.com", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"mailto:ada@example.com?subject=Hi%20there");
+
+        let cli = parse_args(args(&["sms", "--body", "On my way", "+15550100", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"sms:+15550100?body=On%20my%20way");
+
+        let cli = parse_args(args(&["tel", "+1 555 0100", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"tel:+15550100");
+        assert!(cli.options.icon.is_some());
+
+        let cli = parse_args(args(&["geo", "-33.8568,151.2153", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"geo:-33.8568,151.2153");
+
+        assert!(parse_args(args(&["geo", "north", "out.png"])).is_err());
+        assert!(parse_args(args(&["geo", "95,0", "out.png"])).is_err());
+        assert!(parse_args(args(&["sms", "--subject", "x", "5550100", "out.png"])).is_err());
+        assert!(parse_args(args(&["--subject", "x", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_wifi() {
+        let cli = parse_args(args(&[
+            "wifi", "--password", "p;ss", "--hidden", "Home Net", "logo.png", "out.png",
+        ]))
+        .unwrap();
+        assert_eq!(cli.payload, br"WIFI:T:WPA;S:Home Net;P:p\;ss;H:true;;");
+        assert!(matches!(cli.action, Action::Generate { .. }));
+
+        let cli = parse_args(args(&["wifi", "--auth", "nopass", "--preview", "Cafe"])).unwrap();
+        assert_eq!(cli.payload, b"WIFI:T:nopass;S:Cafe;;");
+
+        assert!(parse_args(args(&["--password", "x", "https://example.com", "logo.png", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_anchor_flags() {
+        let cli = parse_args(args(&["--icon-anchor", "top-right", "--icon-offset", "-20,10", "https://example.com", "logo.png", "out.png"]))
+            .unwrap();
+        assert_eq!(cli.options.icon_anchor, IconAnchor::TopRight);
+        assert_eq!(cli.options.icon_offset, (-20, 10));
+
+        let cli = parse_args(args(&["https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!((cli.options.icon_anchor, cli.options.icon_offset), (IconAnchor::Center, (0, 0)));
+        assert!(parse_args(args(&["--icon-offset", "20", "https://example.com", "out.png"])).is_err());
+        assert!(parse_args(args(&["--icon-anchor", "middle", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_shape_flag() {
+        let cli = parse_args(args(&["--icon-shape", "circle", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_shape, IconShape::Circle);
+        assert_eq!(cli.options.icon_fit, IconFit::Contain);
+        assert_eq!(cli.options.effective_halo_shape(), IconShape::Circle);
+
+        let cli = parse_args(args(&[
+            "--halo-shape",
+            "circle",
+            "--halo-border-color",
+            "#0050a0",
+            "https://example.com",
+            "logo.png",
+            "out.png",
+        ]))
+        .unwrap();
+        assert_eq!((cli.options.icon_shape, cli.options.halo_shape), (IconShape::Square, Some(IconShape::Circle)));
+        assert_eq!(cli.options.halo_border, Some(HaloBorder { color: Rgb([0, 0x50, 0xa0]), width: 3 }));
+        let cli =
+            parse_args(args(&["--halo-border-color", "#0050a0", "--halo-border-width", "5", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.halo_border.map(|border| border.width), Some(5));
+        assert!(parse_args(args(&["--halo-border-width", "5", "https://example.com", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--icon-fit", "cover", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_fit, IconFit::Cover);
+        assert!(parse_args(args(&["--icon-fit", "fill", "https://example.com", "logo.png", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--icon-filter", "nearest", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_filter, IconFilter::Nearest);
+        assert!(parse_args(args(&["--icon-filter", "bicubic", "https://example.com", "logo.png", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--icon-frame", "2", "https://example.com", "logo.gif", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_frame, 2);
+        assert!(parse_args(args(&["--icon-frame", "-1", "https://example.com", "logo.gif", "out.png"])).is_err());
+
+        let cli =
+            parse_args(args(&["--icon-tint", "#0050a0", "--icon-grayscale", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_tint, Some(Rgb([0, 0x50, 0xa0])));
+        assert!(cli.options.icon_grayscale);
+        assert!(parse_args(args(&["--icon-tint", "bleu", "https://example.com", "logo.png", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_url() {
+        let cli = parse_args(args(&["https://example.com", "https://example.com/logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon, Some(PathBuf::from("https://example.com/logo.png")));
+        assert!(!cli.options.allow_network);
+        // A downloaded icon has no file to watch
+        assert!(watched_files(&cli).is_empty());
+
+        let cli = parse_args(args(&["--allow-network", "https://example.com", "https://example.com/logo.png", "out.png"])).unwrap();
+        assert!(cli.options.allow_network);
+    }
+
+    #[test]
+    fn test_parse_module_style_flags() {
+        let cli = parse_args(args(&[
+            "--module-style", "rounded", "--corner-radius", "0.4", "--antialias", "https://example.com", "out.png",
+        ]))
+        .unwrap();
+        assert_eq!(cli.options.module_style, ModuleStyle::Rounded);
+        assert_eq!(cli.options.corner_radius, 0.4);
+        assert!(cli.options.antialias);
+
+        assert!(parse_args(args(&["--corner-radius", "0.9", "https://example.com", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--module-gap", "0.2", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.module_gap, 0.2);
+        assert!(parse_args(args(&["--module-gap", "0.5", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_eye_flags() {
+        let cli = parse_args(args(&["--eye-color", "#cc0000", "--eye-style", "rounded", "https://example.com", "out.png"]))
+            .unwrap();
+        assert_eq!(cli.options.eye_color, Some(Rgb([0xcc, 0, 0])));
+        assert_eq!(cli.options.eye_style, Some(EyeStyle::Rounded));
+
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!((cli.options.eye_color, cli.options.eye_style), (None, None));
+        assert!(parse_args(args(&["--eye-style", "star", "https://example.com", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--timing-color", "teal", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.timing_color, Some(Rgb([0, 0x80, 0x80])));
+    }
+
+    #[test]
+    fn test_parse_gradient_flags() {
+        let cli = parse_args(args(&[
+            "--gradient-direction", "vertical", "--gradient", "#ff0000", "#0000ff", "https://example.com", "out.png",
+        ]))
+        .unwrap();
+        let gradient = cli.options.gradient.unwrap();
+        assert_eq!(gradient.start, Rgb([255, 0, 0]));
+        assert_eq!(gradient.end, Rgb([0, 0, 255]));
+        assert_eq!(gradient.direction, GradientDirection::Vertical);
+        assert_eq!(gradient.kind, GradientType::Linear);
+
+        let cli = parse_args(args(&["--gradient", "navy", "teal", "--gradient-type", "radial", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.gradient.unwrap().kind, GradientType::Radial);
+        assert!(parse_args(args(&["--gradient-type", "radial", "https://example.com", "out.png"])).is_err());
+
+        assert!(parse_args(args(&["--gradient-direction", "vertical", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_transparency_flags() {
+        let cli = parse_args(args(&["--transparent", "--halo-opacity", "0.6", "https://example.com", "out.png"])).unwrap();
+        assert!(cli.options.transparent);
+        assert_eq!(cli.options.halo_opacity, 0.6);
+
+        assert!(parse_args(args(&["--halo-opacity", "2", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_shadow_flags() {
+        let cli = parse_args(args(&["https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_shadow, None);
+
+        let cli = parse_args(args(&["--icon-shadow", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_shadow, Some(IconShadow::default()));
+
+        let cli = parse_args(args(&[
+            "--icon-shadow", "--shadow-offset", "8", "--shadow-opacity", "0.3", "https://example.com", "logo.png", "out.png",
+        ]))
+        .unwrap();
+        assert_eq!(cli.options.icon_shadow, Some(IconShadow { offset: 8, blur: 4.0, opacity: 0.3 }));
+
+        assert!(parse_args(args(&["--shadow-blur", "2", "https://example.com", "logo.png", "out.png"])).is_err());
+        assert!(parse_args(args(&["--icon-shadow", "--shadow-opacity", "2", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_frame_flags() {
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.frame, None);
+
+        let cli = parse_args(args(&["--frame", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.frame, Some(Frame::default()));
+
+        let cli = parse_args(args(&[
+            "--frame", "--frame-color", "#0055aa", "--frame-text", "ORDER HERE", "--frame-radius", "0.1", "https://example.com",
+            "out.png",
+        ]))
+        .unwrap();
+        let frame = cli.options.frame.unwrap();
+        assert_eq!(frame.color, Rgb([0, 0x55, 0xaa]));
+        assert_eq!(frame.text, "ORDER HERE");
+        assert_eq!(frame.text_color, Rgb([255, 255, 255]));
+        assert_eq!(frame.corner_radius, 0.1);
+
+        assert!(parse_args(args(&["--frame-text", "HI", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_rotate_flags() {
+        let cli = parse_args(args(&["--rotate", "270", "--icon-upright", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.rotate, Some(Rotation::Deg270));
+        assert!(cli.options.icon_upright);
+
+        assert!(parse_args(args(&["--rotate", "45", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_invert_flag() {
+        let cli = parse_args(args(&["--invert", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.foreground, Rgb([255, 255, 255]));
+        assert_eq!(cli.options.background, Rgb([0, 0, 0]));
+
+        // Applies after --fg and --bg
+        let cli = parse_args(args(&["--invert", "--fg", "#112233", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.background, Rgb([0x11, 0x22, 0x33]));
+    }
+
+    #[test]
+    fn test_parse_background_image_flags() {
+        let cli = parse_args(args(&[
+            "--background-image", "photo.jpg", "--scrim-opacity", "0.4", "https://example.com", "out.png",
+        ]))
+        .unwrap();
+        assert_eq!(cli.options.background_image, Some(PathBuf::from("photo.jpg")));
+        assert_eq!(cli.options.scrim_opacity, 0.4);
+
+        assert!(parse_args(args(&["--scrim-opacity", "0.4", "https://example.com", "out.png"])).is_err());
+        assert!(parse_args(args(&["--background-image", "a.jpg", "--scrim-opacity", "2", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_caption_flags() {
+        let cli = parse_args(args(&[
+            "--caption", "Scan to order", "--caption-size", "30", "--caption-color", "#cc0000", "https://example.com", "out.png",
+        ]))
+        .unwrap();
+        assert_eq!(cli.options.caption.as_deref(), Some("Scan to order"));
+        assert_eq!(cli.options.caption_size, 30.0);
+        assert_eq!(cli.options.caption_color, Some(Rgb([0xcc, 0, 0])));
+
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.caption, None);
+        assert!(parse_args(args(&["--caption", "Hi", "--caption-size", "0", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_no_metadata_flag() {
+        assert!(parse_args(args(&["https://example.com", "out.svg"])).unwrap().options.metadata);
+        assert!(!parse_args(args(&["--no-metadata", "https://example.com", "out.svg"])).unwrap().options.metadata);
+    }
+
+    #[test]
+    fn test_parse_alt_flag() {
+        let cli = parse_args(args(&["--alt", "Our menu", "https://example.com", "out.svg"])).unwrap();
+        assert_eq!(cli.options.alt.as_deref(), Some("Our menu"));
+        let cli = parse_args(args(&["https://example.com", "out.svg"])).unwrap();
+        assert_eq!(cli.options.alt, None);
+    }
+
+    #[test]
+    fn test_parse_icon_scale_flag() {
+        let cli = parse_args(args(&["--icon-scale", "12%", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_scale, 12.0);
+
+        assert!(parse_args(args(&["--icon-scale", "big", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_padding_flag() {
+        let cli = parse_args(args(&["--icon-padding", "15%", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_padding, IconPadding::Percent(15.0));
+
+        let cli = parse_args(args(&["--icon-padding", "0", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.icon_padding, IconPadding::Pixels(0));
+    }
+
+    #[test]
+    fn test_parse_qr_version() {
+        let cli = parse_args(args(&["--qr-version", "10", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.version, Some(10));
+
+        assert!(parse_args(args(&["--qr-version", "0", "https://example.com", "out.png"])).is_err());
+        assert!(parse_args(args(&["--qr-version", "41", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_encoding_flag() {
+        let cli = parse_args(args(&["--encoding", "utf8", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.encoding, PayloadEncoding::Utf8);
+
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.encoding, PayloadEncoding::Auto);
+        assert!(parse_args(args(&["--encoding", "sjis", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_env_defaults() {
+        let var = |name: &str| match name {
+            "QRGEN_SIZE" => Some("600".to_string()),
+            "QRGEN_EC_LEVEL" => Some("q".to_string()),
+            _ => None,
+        };
+        let options = |list: &[&str]| CommandLine::try_parse_from(args(list)).unwrap().url.qr.options_with_env(var).unwrap();
+
+        let opts = options(&["https://example.com", "out.png"]);
+        assert_eq!((opts.size, opts.ec_level), (600, Some(EcLevel::Q)));
+        // Flags override the environment, which overrides the config file
+        let opts = options(&["https://example.com", "out.png", "--size", "800"]);
+        assert_eq!((opts.size, opts.ec_level), (800, Some(EcLevel::Q)));
+
+        let dir = std::env::temp_dir().join(format!("qr-generator-env-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("qr-generator.toml");
+        fs::write(&config, "size = 500\nmargin = 2\n").unwrap();
+        let opts = options(&["https://example.com", "out.png", "--config", config.to_str().unwrap()]);
+        assert_eq!((opts.size, opts.margin), (600, 2));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_mask() {
+        let cli = parse_args(args(&["--mask", "0", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.mask, Some(0));
+
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.mask, None);
+        assert!(parse_args(args(&["--mask", "8", "https://example.com", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_micro() {
+        let cli = parse_args(args(&["--micro", "12345", "out.png"])).unwrap();
+        assert!(cli.options.micro);
+        assert!(parse_args(args(&["--micro", "--qr-version", "2", "12345", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_config_file_is_overridden_by_flags() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = temp_dir.path().join("style.toml");
+        fs::write(&config, "size = 800\nmargin = 2\nforeground = \"#1a2b3c\"\nicon = \"brand.png\"\n").unwrap();
+        let config = config.to_str().unwrap();
+
+        let cli = parse_args(args(&["--config", config, "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.size, 800);
+        assert_eq!(cli.options.margin, 2);
+        assert_eq!(cli.options.foreground, Rgb([0x1a, 0x2b, 0x3c]));
+        assert_eq!(cli.options.icon, Some(PathBuf::from("brand.png")));
+
+        let cli = parse_args(args(&["--config", config, "--size", "300", "https://example.com", "logo.png", "out.png"]))
+            .unwrap();
+        assert_eq!(cli.options.size, 300);
+        assert_eq!(cli.options.margin, 2);
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+
+        let bad = temp_dir.path().join("bad.toml");
+        fs::write(&bad, "size = \"big\"").unwrap();
+        assert!(parse_args(args(&["--config", bad.to_str().unwrap(), "https://example.com", "out.png"])).is_err());
+    }
+
+    /// Keeps every message logged, with the thread that logged it.
+    struct CaptureLogger(Mutex<Vec<(ThreadId, String)>>);
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            if record.level() <= log::max_level() {
+                self.0.lock().unwrap().push((thread::current().id(), record.args().to_string()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+    /// Runs `cli` logging at its level, and returns what this thread logged.
+    fn run_logged(cli: &Cli) -> Vec<String> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| log::set_logger(&LOGGER).unwrap());
+        log::set_max_level(cli.log_level);
+        run(cli).unwrap();
+
+        let mut logged = LOGGER.0.lock().unwrap();
+        let (ours, others) = logged.drain(..).partition(|(thread, _)| *thread == thread::current().id());
+        *logged = others;
+        ours.into_iter().map(|(_, message)| message).collect()
+    }
+
+    #[test]
+    fn test_verbose_logs_module_size() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("out.png");
+        let output_path = output_path.to_str().unwrap();
+
+        // 25 modules and the quiet zone in 290 pixels leave 8 for each
+        let cli = parse_args(args(&["-v", "--force", "--size", "290", "https://example.com", output_path])).unwrap();
+        assert_eq!(cli.log_level, LevelFilter::Debug);
+        let logged = run_logged(&cli);
+        assert!(logged.iter().any(|message| message.contains("module size 8px")), "{:?}", logged);
+        assert!(logged.iter().any(|message| message.contains("version 2 (25x25 modules) at error correction M with mask")), "{:?}", logged);
+
+        let cli = parse_args(args(&["--force", "--size", "290", "https://example.com", output_path])).unwrap();
+        let logged = run_logged(&cli);
+        assert!(logged.iter().any(|message| message.contains("generated successfully")));
+        assert!(!logged.iter().any(|message| message.contains("module size")));
+
+        let cli = parse_args(args(&["--quiet", "--force", "https://example.com", output_path])).unwrap();
+        assert!(run_logged(&cli).is_empty());
+        assert!(parse_args(args(&["-q", "-v", "https://example.com", output_path])).is_err());
+    }
+
+    #[test]
+    fn test_help_and_version_flags() {
+        let kind = |list: &[&str]| parse_args(args(list)).err().map(|e| e.kind());
+        assert_eq!(kind(&["--help"]), Some(ErrorKind::DisplayHelp));
+        assert_eq!(kind(&["wifi", "--help"]), Some(ErrorKind::DisplayHelp));
+        assert_eq!(kind(&["--version"]), Some(ErrorKind::DisplayVersion));
+        CommandLine::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_url_subcommand() {
+        let cli = parse_args(args(&["url", "--size", "800", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"https://example.com");
+        assert_eq!(cli.options.size, 800);
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+
+        // A URL that happens to be a subcommand name can still be encoded through `url`
+        let cli = parse_args(args(&["url", "batch", "out.png"])).unwrap();
+        assert_eq!(cli.payload, b"batch");
+    }
+
+    #[test]
+    fn test_parse_quality_flags() {
+        let cli = parse_args(args(&["--quality", "75", "--force", "https://example.com", "out.jpg"])).unwrap();
+        assert_eq!(cli.options.quality, 75);
+        assert!(cli.force);
+        assert!(!cli.options.webp_lossless);
+
+        let cli = parse_args(args(&["--bit-depth", "1", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.bit_depth, 1);
+        assert!(parse_args(args(&["--bit-depth", "4", "https://example.com", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--webp-lossless", "https://example.com", "out.webp"])).unwrap();
+        assert!(cli.options.webp_lossless);
+        assert!(!cli.options.verify);
+
+        let cli = parse_args(args(&["--verify", "https://example.com", "out.png"])).unwrap();
+        assert!(cli.options.verify);
+        assert!(!cli.auto);
+        assert!(!cli.options.strict);
+
+        let cli = parse_args(args(&["--strict", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert!(cli.options.strict);
+        assert!(!cli.options.forbid_icon_overlap);
+        let cli = parse_args(args(&["--no-quiet-zone-icon-overlap", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert!(cli.options.forbid_icon_overlap);
+
+        let cli = parse_args(args(&["--auto", "https://example.com", "out.png"])).unwrap();
+        assert!(cli.auto);
+
+        let cli = parse_args(args(&["--dpi", "300", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.dpi, Some(300));
+        assert!(parse_args(args(&["--dpi", "0", "https://example.com", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--pdf-size-mm", "80.5", "https://example.com", "out.pdf"])).unwrap();
+        assert_eq!(cli.options.pdf_size_mm, 80.5);
+        assert!(parse_args(args(&["--pdf-size-mm", "0", "https://example.com", "out.pdf"])).is_err());
+
+        assert!(parse_args(args(&["--quality", "0", "https://example.com", "out.jpg"])).is_err());
+        assert!(parse_args(args(&["--quality", "101", "https://example.com", "out.jpg"])).is_err());
+    }
+
+    #[test]
+    fn test_check_jpeg_quality() {
+        assert!(check_jpeg_quality(90, false).is_ok());
+        assert!(check_jpeg_quality(40, false).is_err());
+        assert!(check_jpeg_quality(40, true).is_ok());
+    }
+
+    #[test]
+    fn test_parse_data_uri() {
+        let cli = parse_args(args(&["--data-uri", "https://example.com"])).unwrap();
+        assert!(matches!(cli.action, Action::DataUri));
+        assert_eq!(cli.options.icon, None);
+
+        let cli = parse_args(args(&["--data-uri", "https://example.com", "logo.png"])).unwrap();
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+
+        assert!(parse_args(args(&["--data-uri", "https://example.com", "logo.png", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_watch() {
+        let cli = parse_args(args(&["https://example.com", "logo.png", "out.png", "--watch"])).unwrap();
+        assert!(matches!(
+            cli.action,
+            Action::Watch { ref output_path, ref config } if output_path == "out.png" && config == Path::new(DEFAULT_CONFIG_FILE)
+        ));
+        let files = watched_files(&cli);
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| file.is_absolute()));
+        assert!(files[0].ends_with("logo.png"));
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = temp_dir.path().join("brand.toml");
+        fs::write(&config, "icon = \"brand.png\"\n").unwrap();
+        let cli = parse_args(args(&["https://example.com", "out.png", "--watch", "--config", config.to_str().unwrap()])).unwrap();
+        assert!(matches!(cli.action, Action::Watch { config: ref watched, .. } if *watched == config));
+        assert_eq!(watched_files(&cli).len(), 2);
+        assert!(parse_args(args(&["https://example.com", "-", "--watch"])).is_err());
+        assert!(parse_args(args(&["https://example.com", "out.png", "--watch", "--split"])).is_err());
+
+        assert_eq!(clock_time(UNIX_EPOCH + Duration::from_secs(3 * 86_400 + 13 * 3600 + 5 * 60 + 9)), "13:05:09");
+    }
+
+    #[test]
+    fn test_parse_clipboard() {
+        let cli = parse_args(args(&["--clipboard", "https://example.com", "logo.png"])).unwrap();
+        assert!(matches!(cli.action, Action::Clipboard));
+        assert_eq!(cli.options.icon, Some(PathBuf::from("logo.png")));
+
+        assert!(parse_args(args(&["--clipboard", "https://example.com", "logo.png", "out.png"])).is_err());
+        assert!(parse_args(args(&["--clipboard", "--data-uri", "https://example.com"])).is_err());
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("out.png");
+        let cli = parse_args(args(&["--dry-run", "--verify", "https://example.com", output_path.to_str().unwrap()])).unwrap();
+        assert!(cli.dry_run);
+        run(&cli).unwrap();
+        assert!(!output_path.exists());
+
+        let csv_path = temp_dir.path().join("batch.csv");
+        let rows = format!("url,output\nhttps://example.com,{0}/out.png\nhttps://example.com,{0}/out.unknown\n", temp_dir.path().display());
+        fs::write(&csv_path, rows).unwrap();
+        let cli = parse_args(args(&["batch", "--dry-run", csv_path.to_str().unwrap()])).unwrap();
+        assert!(run(&cli).is_err());
+        assert!(!output_path.exists());
+
+        assert!(parse_args(args(&["--dry-run", "--watch", "https://example.com", "out.png"])).is_err());
+        assert!(parse_args(args(&["--dry-run", "--clipboard", "https://example.com"])).is_err());
+    }
+
+    #[test]
+    fn test_refuses_to_overwrite_without_force() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("out.png");
+        let output = output_path.to_str().unwrap();
+        fs::write(&output_path, "keep me").unwrap();
+
+        let cli = parse_args(args(&["--no-clobber", "https://example.com", output])).unwrap();
+        assert_eq!(cli.overwrite, Overwrite::Never);
+        let e = run(&cli).unwrap_err();
+        assert!(e.to_string().contains("pass --force"), "{}", e);
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "keep me");
+
+        let cli = parse_args(args(&["--force", "https://example.com", output])).unwrap();
+        run(&cli).unwrap();
+        assert!(image::open(&output_path).is_ok());
+
+        assert_eq!(parse_args(args(&["https://example.com", output])).unwrap().overwrite, Overwrite::Ask);
+        assert!(parse_args(args(&["--force", "--no-clobber", "https://example.com", output])).is_err());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let cli = parse_args(args(&["--json", "https://example.com"])).unwrap();
+        assert!(matches!(cli.action, Action::Json));
+        assert!(parse_args(args(&["--json", "https://example.com", "out.png"])).is_err());
+        assert!(parse_args(args(&["--json", "--preview", "https://example.com"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_preview() {
+        let cli = parse_args(args(&["--preview", "https://example.com"])).unwrap();
+        assert_eq!(cli.payload, b"https://example.com");
+        assert!(matches!(cli.action, Action::Preview));
+    }
+
+    #[test]
+    fn test_parse_named_colors() {
+        let cli = parse_args(args(&["--fg", "navy", "--bg", "White", "--eye-color", "crimson", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.foreground, Rgb([0, 0, 0x80]));
+        assert_eq!(cli.options.background, Rgb([255, 255, 255]));
+        assert_eq!(cli.options.eye_color, Some(Rgb([0xdc, 0x14, 0x3c])));
+
+        let e = parse_args(args(&["--fg", "navvy", "https://example.com", "out.png"])).err().unwrap();
+        assert!(e.to_string().contains("Unknown color name 'navvy'"), "{}", e);
+    }
+
+    #[test]
+    fn test_parse_translucent_background() {
+        let cli = parse_args(args(&["--bg", "#ffffff80", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.background, Rgb([255, 255, 255]));
+        assert_eq!(cli.options.background_alpha, 128);
+        assert_eq!(parse_args(args(&["--bg", "navy", "https://example.com", "out.png"])).unwrap().options.background_alpha, 255);
+
+        let e = parse_args(args(&["--fg", "#00000080", "https://example.com", "out.png"])).err().unwrap();
+        assert!(e.to_string().contains("not fully opaque"), "{}", e);
+    }
+
+    #[test]
+    fn test_parse_ec_level_flag() {
+        let cli = parse_args(args(&["--ec-level", "q", "https://example.com", "logo.png", "out.png"])).unwrap();
+        assert_eq!(cli.options.ec_level, Some(EcLevel::Q));
+
+        assert!(parse_args(args(&["--ec-level", "Z", "https://example.com", "logo.png", "out.png"])).is_err());
+        assert!(parse_args(args(&["https://example.com"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_size_flag() {
+        let cli = parse_args(args(&["https://example.com", "logo.png", "out.png", "--size", "1200"])).unwrap();
+        assert_eq!(cli.options.size, 1200);
+
+        assert!(parse_args(args(&["--size", "big", "https://example.com", "logo.png", "out.png"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_margin_flag() {
+        let cli = parse_args(args(&["--margin", "2", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.margin, 2);
+
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!((cli.options.margin, cli.options.margin_color), (4, None));
+
+        let cli = parse_args(args(&["--margin-color", "#fafafa", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.margin_color, Some(Rgb([0xfa, 0xfa, 0xfa])));
+    }
+
+    #[test]
+    fn test_parse_max_dimension() {
+        let cli = parse_args(args(&["--size", "20000", "https://example.com", "out.png"])).unwrap();
+        assert_eq!((cli.options.max_dimension, cli.options.clamp), (8192, false));
+        assert_eq!(cli.options.clamp_warning(), None);
+
+        let cli = parse_args(args(&["--size", "20000", "--max-dimension", "10000", "--clamp", "https://example.com", "out.png"]))
+            .unwrap();
+        assert_eq!((cli.options.max_dimension, cli.options.clamp), (10000, true));
+        assert!(cli.options.clamp_warning().unwrap().contains("rendered at 10000px"));
+    }
+
+    #[test]
+    fn test_parse_padding_flags() {
+        let cli = parse_args(args(&["--outer-padding", "30", "--trim", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.outer_padding, 30);
+        assert!(cli.options.trim);
+
+        let cli = parse_args(args(&["https://example.com", "out.png"])).unwrap();
+        assert_eq!((cli.options.outer_padding, cli.options.trim, cli.options.stretch), (0, false, false));
+        assert!(parse_args(args(&["--stretch", "https://example.com", "out.png"])).unwrap().options.stretch);
+        assert!(parse_args(args(&["--stretch", "--trim", "https://example.com", "out.png"])).is_err());
+        assert!(parse_args(args(&["--outer-padding", "-1", "https://example.com", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--card-radius", "24", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.card_radius, 24);
+        assert!(parse_args(args(&["--card-radius", "round", "https://example.com", "out.png"])).is_err());
+
+        let cli = parse_args(args(&["--card-color", "#2a6fdb", "--card-padding", "16", "https://example.com", "out.png"])).unwrap();
+        assert_eq!((cli.options.card_color, cli.options.card_padding), (Some(Rgb([0x2a, 0x6f, 0xdb])), 16));
+        let cli = parse_args(args(&["--card-color", "navy", "https://example.com", "out.png"])).unwrap();
+        assert_eq!(cli.options.card_padding, qr_generator::DEFAULT_CARD_PADDING);
+    }
+
+    #[test]
+    fn test_parse_color_flags() {
+        let cli = parse_args(args(&["--fg", "#1a2b3c", "--bg", "fff8e7", "https://example.com", "logo.png", "out.png"]))
+            .unwrap();
+        assert_eq!(cli.options.foreground, Rgb([0x1a, 0x2b, 0x3c]));
+        assert_eq!(cli.options.background, Rgb([0xff, 0xf8, 0xe7]));
+
+        assert!(parse_args(args(&["--fg", "navy-ish", "https://example.com", "logo.png", "out.png"])).is_err());
+    }
+}
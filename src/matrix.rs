@@ -0,0 +1,244 @@
+use qrcode::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::code::Code;
+use crate::options::{DEFAULT_MARGIN, MAX_VERSION};
+use crate::render;
+use crate::{EcLevel, QrGenError, QrOptions};
+
+/// Modules holding the format information beside the top-left finder
+/// pattern, as `(x, y)`, most significant bit first.
+const FORMAT_INFO_COORDS: [(usize, usize); 15] =
+    [(0, 8), (1, 8), (2, 8), (3, 8), (4, 8), (5, 8), (7, 8), (8, 8), (8, 7), (8, 5), (8, 4), (8, 3), (8, 2), (8, 1), (8, 0)];
+
+/// Generator polynomial of the BCH code protecting the format information.
+const FORMAT_INFO_GENERATOR: u16 = 0x537;
+
+/// Pattern the format information is XORed with, so it is never all light.
+const FORMAT_INFO_MASK: u16 = 0x5412;
+
+/// The modules of an encoded QR code, for rendering on a custom canvas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrMatrix {
+    width: u32,
+    /// Row-major, `true` for dark modules.
+    modules: Vec<bool>,
+    quiet_zone: u32,
+    version: u8,
+    ec_level: EcLevel,
+}
+
+impl QrMatrix {
+    /// Number of modules along one side, without the quiet zone.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Whether the module at column `x`, row `y` is dark. Coordinates outside
+    /// the matrix are light, like the quiet zone around it.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.width && self.modules[(y * self.width + x) as usize]
+    }
+
+    /// Width of the light margin to leave around the matrix, in modules.
+    pub fn quiet_zone(&self) -> u32 {
+        self.quiet_zone
+    }
+
+    /// QR version, from 1 to 40.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Error correction level the payload was encoded with.
+    pub fn ec_level(&self) -> EcLevel {
+        self.ec_level
+    }
+
+    /// The modules as rows of columns, `true` for dark.
+    pub fn to_rows(&self) -> Vec<Vec<bool>> {
+        self.modules.chunks(self.width as usize).map(<[bool]>::to_vec).collect()
+    }
+
+    /// The matrix as a JSON object of its `version`, `width`, `quiet_zone`
+    /// and `modules`, the rows from [`QrMatrix::to_rows`], for rendering
+    /// outside Rust, such as in a browser.
+    pub fn to_json(&self) -> String {
+        let json = MatrixJson {
+            version: Some(self.version),
+            width: self.width,
+            quiet_zone: Some(self.quiet_zone),
+            modules: self.to_rows(),
+        };
+        serde_json::to_string(&json).expect("a matrix always serializes")
+    }
+
+    /// Reads a matrix in the format [`QrMatrix::to_json`] writes, such as one
+    /// encoded by another tool, to render with [`crate::generate_from_matrix`].
+    ///
+    /// The rows must form a square `width` modules across, a width some QR
+    /// version has. `version` may be left out, as it follows from the width,
+    /// and `quiet_zone` defaults to [`DEFAULT_MARGIN`]. The error correction
+    /// level is read from the matrix's format information.
+    pub fn from_json(json: &str) -> Result<QrMatrix, QrGenError> {
+        let parsed: MatrixJson = serde_json::from_str(json).map_err(QrGenError::MatrixJson)?;
+        let width = parsed.width;
+        if !(21..=17 + 4 * MAX_VERSION as u32).contains(&width) || !(width - 17).is_multiple_of(4) {
+            return Err(QrGenError::InvalidMatrixWidth(width));
+        }
+        let version = ((width - 17) / 4) as u8;
+        if let Some(stated) = parsed.version.filter(|&stated| stated != version) {
+            return Err(QrGenError::MatrixVersionMismatch { version: stated, width });
+        }
+        let columns = parsed.modules.iter().map(Vec::len).find(|&len| len != width as usize).unwrap_or(width as usize);
+        if parsed.modules.len() != width as usize || columns != width as usize {
+            return Err(QrGenError::MatrixShape { width, rows: parsed.modules.len(), columns });
+        }
+
+        let modules = parsed.modules.concat();
+        let ec_level = format_ec_level(&modules, width as usize);
+        Ok(QrMatrix { width, modules, quiet_zone: parsed.quiet_zone.unwrap_or(DEFAULT_MARGIN), version, ec_level })
+    }
+
+    /// The modules as a code to render.
+    pub(crate) fn to_code(&self) -> Code {
+        let colors = self.modules.iter().map(|&dark| if dark { Color::Dark } else { Color::Light }).collect();
+        Code::from_modules(self.width as usize, colors)
+    }
+}
+
+/// The fields [`QrMatrix::to_json`] writes, in order.
+#[derive(Serialize, Deserialize)]
+struct MatrixJson {
+    version: Option<u8>,
+    width: u32,
+    quiet_zone: Option<u32>,
+    modules: Vec<Vec<bool>>,
+}
+
+/// The error correction level in the format information of `modules`, a
+/// matrix `width` across. Like a scanner, this takes the valid format
+/// information closest to what is drawn, so a few wrong modules are ignored.
+fn format_ec_level(modules: &[bool], width: usize) -> EcLevel {
+    let drawn = FORMAT_INFO_COORDS.iter().fold(0, |bits, &(x, y)| bits << 1 | modules[y * width + x] as u16);
+    let data = (0..32).min_by_key(|&data| (format_info(data) ^ drawn).count_ones()).unwrap_or(0);
+    match data >> 3 {
+        0b01 => EcLevel::L,
+        0b00 => EcLevel::M,
+        0b11 => EcLevel::Q,
+        _ => EcLevel::H,
+    }
+}
+
+/// The 15 bits of format information for `data`, the two error correction
+/// level bits and the three mask bits: `data`, then its BCH check bits, masked.
+fn format_info(data: u16) -> u16 {
+    let mut remainder = data << 10;
+    for bit in (10..15).rev() {
+        if remainder & (1 << bit) != 0 {
+            remainder ^= FORMAT_INFO_GENERATOR << (bit - 10);
+        }
+    }
+    (data << 10 | remainder) ^ FORMAT_INFO_MASK
+}
+
+/// Encodes `payload` into its module matrix without rendering an image.
+///
+/// The error correction level and version are chosen as for [`crate::generate`],
+/// including level `H` when `opts.icon` is set, and the quiet zone is
+/// `opts.margin`. Colors, size and styling options are ignored.
+pub fn module_matrix(payload: impl AsRef<[u8]>, opts: &QrOptions) -> Result<QrMatrix, QrGenError> {
+    let ec_level = opts.effective_ec_level(opts.icon.is_some());
+    let code = render::encode_matrix(payload.as_ref(), ec_level, opts)?;
+
+    Ok(QrMatrix {
+        width: code.width() as u32,
+        modules: code.to_colors().into_iter().map(|color| color == Color::Dark).collect(),
+        quiet_zone: opts.margin,
+        version: code.version(),
+        ec_level,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_matrix() {
+        let opts = QrOptions { margin: 2, ..QrOptions::default() };
+        let matrix = module_matrix("https://example.com", &opts).unwrap();
+
+        assert_eq!(matrix.width(), 17 + 4 * matrix.version() as u32);
+        assert_eq!(matrix.quiet_zone(), 2);
+        assert_eq!(matrix.ec_level(), EcLevel::M);
+
+        // Finder pattern: dark outer ring, light ring, dark center
+        let last = matrix.width() - 1;
+        assert!(matrix.get(0, 0) && matrix.get(last, 0) && matrix.get(0, last));
+        assert!(!matrix.get(1, 1));
+        assert!(matrix.get(3, 3));
+        assert!(!matrix.get(matrix.width(), 0));
+
+        let rows = matrix.to_rows();
+        assert_eq!(rows.len(), matrix.width() as usize);
+        assert!(rows.iter().all(|row| row.len() == matrix.width() as usize));
+        assert_eq!(rows[3][last as usize - 3], matrix.get(last - 3, 3));
+    }
+
+    #[test]
+    fn test_matrix_json() {
+        let matrix = module_matrix("https://example.com", &QrOptions::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&matrix.to_json()).unwrap();
+        assert_eq!(json["version"], matrix.version());
+        assert_eq!(json["width"], matrix.width());
+        assert_eq!(json["quiet_zone"], 4);
+
+        let modules = json["modules"].as_array().unwrap();
+        assert_eq!(modules.len(), matrix.width() as usize);
+        assert_eq!(modules[0].as_array().unwrap().len(), matrix.width() as usize);
+        assert_eq!(modules[3][3], true);
+        assert_eq!(modules[1][1], false);
+    }
+
+    #[test]
+    fn test_matrix_from_json() {
+        for ec_level in [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H] {
+            let matrix = module_matrix("https://example.com", &QrOptions::default().with_ec_level(ec_level)).unwrap();
+            assert_eq!(QrMatrix::from_json(&matrix.to_json()).unwrap(), matrix);
+        }
+        assert_eq!(format_info(0), 0x5412);
+        assert_eq!(format_info(0b11111), 0x2bed);
+
+        let row = |width: usize| format!("[{}]", vec!["false"; width].join(","));
+        let rows = |count: usize, width: usize| vec![row(width); count].join(",");
+        let json = QrMatrix::from_json(&format!("{{\"width\":21,\"modules\":[{}]}}", rows(21, 21))).unwrap();
+        assert_eq!((json.version(), json.quiet_zone()), (1, DEFAULT_MARGIN));
+
+        assert!(matches!(
+            QrMatrix::from_json(&format!("{{\"width\":22,\"modules\":[{}]}}", rows(22, 22))),
+            Err(QrGenError::InvalidMatrixWidth(22))
+        ));
+        assert!(matches!(
+            QrMatrix::from_json(&format!("{{\"width\":21,\"modules\":[{}]}}", rows(20, 21))),
+            Err(QrGenError::MatrixShape { width: 21, rows: 20, columns: 21 })
+        ));
+        assert!(matches!(
+            QrMatrix::from_json(&format!("{{\"width\":21,\"modules\":[{},{}]}}", rows(20, 21), row(25))),
+            Err(QrGenError::MatrixShape { width: 21, rows: 21, columns: 25 })
+        ));
+        assert!(matches!(
+            QrMatrix::from_json(&format!("{{\"version\":2,\"width\":21,\"modules\":[{}]}}", rows(21, 21))),
+            Err(QrGenError::MatrixVersionMismatch { version: 2, width: 21 })
+        ));
+        assert!(matches!(QrMatrix::from_json("{\"width\":21}"), Err(QrGenError::MatrixJson(_))));
+    }
+
+    #[test]
+    fn test_module_matrix_matches_symbol_info() {
+        let opts = QrOptions { version: Some(7), ..QrOptions::default() }.with_icon("logo.png");
+        let matrix = module_matrix("https://example.com", &opts).unwrap();
+        let info = crate::symbol_info("https://example.com", &opts).unwrap();
+        assert_eq!((matrix.version(), matrix.width(), matrix.ec_level()), (info.version, info.width, info.ec_level));
+    }
+}
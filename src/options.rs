@@ -0,0 +1,1387 @@
+use std::f64::consts::SQRT_2;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use image::imageops::FilterType;
+use image::{Rgb, Rgba};
+use serde::Deserialize;
+
+use crate::relative_luminance;
+
+/// QR error correction level, from lowest (`L`, ~7% recoverable) to highest
+/// (`H`, ~30% recoverable).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcLevel {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl EcLevel {
+    /// Approximate fraction of codewords this level can recover.
+    pub fn recoverable_fraction(self) -> f64 {
+        match self {
+            EcLevel::L => 0.07,
+            EcLevel::M => 0.15,
+            EcLevel::Q => 0.25,
+            EcLevel::H => 0.30,
+        }
+    }
+}
+
+impl From<EcLevel> for qrcode::EcLevel {
+    fn from(level: EcLevel) -> Self {
+        match level {
+            EcLevel::L => qrcode::EcLevel::L,
+            EcLevel::M => qrcode::EcLevel::M,
+            EcLevel::Q => qrcode::EcLevel::Q,
+            EcLevel::H => qrcode::EcLevel::H,
+        }
+    }
+}
+
+impl FromStr for EcLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "L" => Ok(EcLevel::L),
+            "M" => Ok(EcLevel::M),
+            "Q" => Ok(EcLevel::Q),
+            "H" => Ok(EcLevel::H),
+            _ => Err(format!("invalid error correction level '{}', expected L, M, Q or H", s)),
+        }
+    }
+}
+
+impl fmt::Display for EcLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EcLevel::L => "L",
+            EcLevel::M => "M",
+            EcLevel::Q => "Q",
+            EcLevel::H => "H",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Shape the icon is cropped to before it is overlaid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IconShape {
+    /// Keep the icon as-is, on a square backing.
+    #[default]
+    Square,
+    /// Crop the icon to its inscribed circle, on a round backing.
+    Circle,
+}
+
+impl FromStr for IconShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(IconShape::Square),
+            "circle" => Ok(IconShape::Circle),
+            _ => Err(format!("invalid icon shape '{}', expected square or circle", s)),
+        }
+    }
+}
+
+/// How an icon is fitted into its square box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IconFit {
+    /// Scale the whole icon to fit, keeping its aspect ratio.
+    #[default]
+    Contain,
+    /// Scale the icon to fill the box, cropping whatever overflows.
+    Cover,
+    /// Scale each axis to the box, ignoring the aspect ratio.
+    Stretch,
+}
+
+impl FromStr for IconFit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "contain" => Ok(IconFit::Contain),
+            "cover" => Ok(IconFit::Cover),
+            "stretch" => Ok(IconFit::Stretch),
+            _ => Err(format!("invalid icon fit '{}', expected contain, cover or stretch", s)),
+        }
+    }
+}
+
+/// Where on the code the icon is placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IconAnchor {
+    /// In the middle of the code.
+    #[default]
+    Center,
+    /// In the top-left corner of the matrix, clear of its finder pattern.
+    TopLeft,
+    /// In the top-right corner of the matrix, clear of its finder pattern.
+    TopRight,
+    /// In the bottom-left corner of the matrix, clear of its finder pattern.
+    BottomLeft,
+    /// In the bottom-right corner of the matrix, which has no finder pattern.
+    BottomRight,
+}
+
+impl FromStr for IconAnchor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "center" => Ok(IconAnchor::Center),
+            "top-left" => Ok(IconAnchor::TopLeft),
+            "top-right" => Ok(IconAnchor::TopRight),
+            "bottom-left" => Ok(IconAnchor::BottomLeft),
+            "bottom-right" => Ok(IconAnchor::BottomRight),
+            _ => Err(format!(
+                "invalid icon anchor '{}', expected center, top-left, top-right, bottom-left or bottom-right",
+                s
+            )),
+        }
+    }
+}
+
+/// Interpolation filter used when resizing the icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IconFilter {
+    /// Nearest neighbor, keeping hard pixel edges for pixel art.
+    Nearest,
+    /// Linear interpolation, the fastest smooth filter.
+    Triangle,
+    /// Cubic interpolation.
+    CatmullRom,
+    /// Gaussian blur while resampling.
+    Gaussian,
+    /// Lanczos with a window of 3, the sharpest smooth filter.
+    #[default]
+    Lanczos3,
+}
+
+impl From<IconFilter> for FilterType {
+    fn from(filter: IconFilter) -> Self {
+        match filter {
+            IconFilter::Nearest => FilterType::Nearest,
+            IconFilter::Triangle => FilterType::Triangle,
+            IconFilter::CatmullRom => FilterType::CatmullRom,
+            IconFilter::Gaussian => FilterType::Gaussian,
+            IconFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FromStr for IconFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(IconFilter::Nearest),
+            "triangle" => Ok(IconFilter::Triangle),
+            "catmull-rom" => Ok(IconFilter::CatmullRom),
+            "gaussian" => Ok(IconFilter::Gaussian),
+            "lanczos3" => Ok(IconFilter::Lanczos3),
+            _ => Err(format!(
+                "invalid icon filter '{}', expected nearest, triangle, catmull-rom, gaussian or lanczos3",
+                s
+            )),
+        }
+    }
+}
+
+/// Shape each dark module is drawn as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModuleStyle {
+    /// Filled squares covering the whole cell.
+    #[default]
+    Square,
+    /// Discs inscribed in each cell.
+    Circle,
+    /// Squares with rounded corners, see [`QrOptions::corner_radius`].
+    Rounded,
+}
+
+impl FromStr for ModuleStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(ModuleStyle::Square),
+            "circle" => Ok(ModuleStyle::Circle),
+            "rounded" => Ok(ModuleStyle::Rounded),
+            _ => Err(format!("invalid module style '{}', expected square, circle or rounded", s)),
+        }
+    }
+}
+
+/// Shape the three finder patterns (eyes) are drawn as, each made of a 7x7
+/// outer ring around a 3x3 center.
+///
+/// The decoder behind [`QrOptions::verify`] only locates eyes with gently
+/// rounded corners, so circular eyes and rounded ones with a `corner_radius`
+/// above about `0.2` may fail verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EyeStyle {
+    /// Square ring and center.
+    #[default]
+    Square,
+    /// Ring and center with rounded corners, see [`QrOptions::corner_radius`].
+    Rounded,
+    /// Circular ring and center.
+    Circle,
+}
+
+impl FromStr for EyeStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(EyeStyle::Square),
+            "rounded" => Ok(EyeStyle::Rounded),
+            "circle" => Ok(EyeStyle::Circle),
+            _ => Err(format!("invalid eye style '{}', expected square, rounded or circle", s)),
+        }
+    }
+}
+
+/// Axis along which a [`Gradient`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GradientDirection {
+    /// Left to right.
+    Horizontal,
+    /// Top to bottom.
+    Vertical,
+    /// Top-left to bottom-right.
+    #[default]
+    Diagonal,
+}
+
+impl FromStr for GradientDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "horizontal" => Ok(GradientDirection::Horizontal),
+            "vertical" => Ok(GradientDirection::Vertical),
+            "diagonal" => Ok(GradientDirection::Diagonal),
+            _ => Err(format!("invalid gradient direction '{}', expected horizontal, vertical or diagonal", s)),
+        }
+    }
+}
+
+/// How a [`Gradient`] spreads over the code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GradientType {
+    /// Along a straight line, in the gradient's direction.
+    #[default]
+    Linear,
+    /// Outward from the center of the canvas, reaching the end color at its
+    /// corners.
+    Radial,
+}
+
+impl FromStr for GradientType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(GradientType::Linear),
+            "radial" => Ok(GradientType::Radial),
+            _ => Err(format!("invalid gradient type '{}', expected linear or radial", s)),
+        }
+    }
+}
+
+/// How the payload's text is stored in the code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadEncoding {
+    /// The most compact mix of numeric, alphanumeric and byte segments, with
+    /// no character set declared. Most readers guess UTF-8 for the bytes.
+    #[default]
+    Auto,
+    /// Byte mode behind an ECI designator declaring UTF-8, so readers decode
+    /// non-ASCII text correctly at the cost of a little capacity.
+    Utf8,
+}
+
+impl FromStr for PayloadEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(PayloadEncoding::Auto),
+            "utf8" | "utf-8" => Ok(PayloadEncoding::Utf8),
+            _ => Err(format!("invalid encoding '{}', expected auto or utf8", s)),
+        }
+    }
+}
+
+/// Clockwise rotation applied to the finished image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    /// The clockwise angle, in degrees.
+    pub fn degrees(self) -> u32 {
+        match self {
+            Rotation::Deg90 => 90,
+            Rotation::Deg180 => 180,
+            Rotation::Deg270 => 270,
+        }
+    }
+
+    /// The rotation that undoes this one.
+    pub fn inverse(self) -> Rotation {
+        match self {
+            Rotation::Deg90 => Rotation::Deg270,
+            Rotation::Deg180 => Rotation::Deg180,
+            Rotation::Deg270 => Rotation::Deg90,
+        }
+    }
+
+    /// Whether the rotation swaps width and height.
+    pub fn is_quarter_turn(self) -> bool {
+        self != Rotation::Deg180
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "90" => Ok(Rotation::Deg90),
+            "180" => Ok(Rotation::Deg180),
+            "270" => Ok(Rotation::Deg270),
+            _ => Err(format!("invalid rotation '{}', expected 90, 180 or 270", s)),
+        }
+    }
+}
+
+/// A gradient used to color the dark modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gradient {
+    #[serde(deserialize_with = "crate::config::color")]
+    pub start: Rgb<u8>,
+    #[serde(deserialize_with = "crate::config::color")]
+    pub end: Rgb<u8>,
+    /// Direction of a linear gradient; radial gradients have none.
+    #[serde(default)]
+    pub direction: GradientDirection,
+    #[serde(default, rename = "type")]
+    pub kind: GradientType,
+}
+
+impl Gradient {
+    /// Interpolates the gradient color at `t` in `0.0..=1.0`.
+    pub fn color_at(&self, t: f64) -> Rgb<u8> {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+        Rgb([
+            mix(self.start[0], self.end[0]),
+            mix(self.start[1], self.end[1]),
+            mix(self.start[2], self.end[2]),
+        ])
+    }
+
+    /// Position along the gradient, in `0.0..=1.0`, of the point `(x, y)` on
+    /// a `size` x `size` canvas.
+    ///
+    /// A radial gradient's position is the distance from the center as a
+    /// fraction of the distance to the corners.
+    pub fn position(&self, x: f64, y: f64, size: f64) -> f64 {
+        if self.kind == GradientType::Radial {
+            let center = size / 2.0;
+            return ((x - center).hypot(y - center) / (center * SQRT_2)).min(1.0);
+        }
+        match self.direction {
+            GradientDirection::Horizontal => x / size,
+            GradientDirection::Vertical => y / size,
+            GradientDirection::Diagonal => (x + y) / (2.0 * size),
+        }
+    }
+}
+
+/// Padding between the icon and the edge of its backing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconPadding {
+    /// A fixed number of pixels.
+    Pixels(u32),
+    /// A percentage of the icon's size.
+    Percent(f64),
+}
+
+impl IconPadding {
+    /// Resolves the padding in pixels for an icon `icon_size` pixels wide.
+    pub fn to_pixels(self, icon_size: u32) -> u32 {
+        match self {
+            IconPadding::Pixels(px) => px,
+            IconPadding::Percent(percent) => (icon_size as f64 * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+impl Default for IconPadding {
+    fn default() -> Self {
+        IconPadding::Pixels(5)
+    }
+}
+
+impl FromStr for IconPadding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid icon padding '{}', expected pixels like 8 or a percentage like 10%", s);
+        match s.strip_suffix('%') {
+            Some(percent) => percent
+                .parse::<f64>()
+                .ok()
+                .filter(|p| *p >= 0.0)
+                .map(IconPadding::Percent)
+                .ok_or_else(invalid),
+            None => s.parse().map(IconPadding::Pixels).map_err(|_| invalid()),
+        }
+    }
+}
+
+/// A soft shadow drawn beneath the icon's backing, offset down and to the
+/// right.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IconShadow {
+    /// Distance the shadow is shifted right and down, in pixels.
+    pub offset: u32,
+    /// Blur radius as the standard deviation of the Gaussian, in pixels.
+    pub blur: f64,
+    /// Opacity of the darkest part of the shadow, from `0.0` to `1.0`.
+    pub opacity: f64,
+}
+
+impl Default for IconShadow {
+    fn default() -> Self {
+        IconShadow { offset: 4, blur: 4.0, opacity: 0.5 }
+    }
+}
+
+/// A ring stroked just inside the edge of the icon's backing.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HaloBorder {
+    #[serde(deserialize_with = "crate::config::color")]
+    pub color: Rgb<u8>,
+    /// Thickness of the ring, in pixels.
+    pub width: u32,
+}
+
+impl Default for HaloBorder {
+    fn default() -> Self {
+        HaloBorder { color: Rgb([0, 0, 0]), width: 3 }
+    }
+}
+
+/// A rounded border around the code that widens into a banner of text below
+/// it, like the classic "SCAN ME" frame.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Frame {
+    /// Color of the border and banner.
+    #[serde(deserialize_with = "crate::config::color")]
+    pub color: Rgb<u8>,
+    /// Text centered in the banner.
+    pub text: String,
+    #[serde(deserialize_with = "crate::config::color")]
+    pub text_color: Rgb<u8>,
+    /// Radius of the outer corners as a fraction of the frame's width, from
+    /// `0.0` (square) to `0.5`.
+    pub corner_radius: f64,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame { color: Rgb([0, 0, 0]), text: "SCAN ME".to_string(), text_color: Rgb([255, 255, 255]), corner_radius: 0.05 }
+    }
+}
+
+/// Highest QR version defined by the standard.
+pub const MAX_VERSION: u8 = 40;
+
+/// Highest Micro QR version, M4.
+pub const MAX_MICRO_VERSION: i16 = 4;
+
+/// Highest mask pattern number defined by the standard.
+pub const MAX_MASK: u8 = 7;
+
+/// Default side length of the generated image, in pixels.
+pub const DEFAULT_SIZE: u32 = 400;
+
+/// Default quiet zone width, in modules, as recommended by the QR spec.
+pub const DEFAULT_MARGIN: u32 = 4;
+
+/// Default limit on the side length of the code's canvas, in pixels.
+pub const DEFAULT_MAX_DIMENSION: u32 = 8192;
+
+/// Default icon size, as a percentage of the QR matrix side.
+pub const DEFAULT_ICON_SCALE: f64 = 18.0;
+
+/// Largest icon size we accept, as a percentage of the QR matrix side.
+pub const MAX_ICON_SCALE: f64 = 40.0;
+
+/// Largest gap between modules we accept, as a fraction of the module size.
+pub const MAX_MODULE_GAP: f64 = 0.3;
+
+/// Fraction of its cell a dark module must cover for
+/// [`QrOptions::module_gap_warning`] not to warn.
+const MIN_MODULE_COVERAGE: f64 = 0.6;
+
+/// Relative luminance a `margin_color` needs for
+/// [`QrOptions::margin_color_warning`] not to warn.
+const MIN_MARGIN_LUMINANCE: f64 = 0.5;
+
+/// Default width of the card around the code when `card_color` is set, in
+/// pixels.
+pub const DEFAULT_CARD_PADDING: u32 = 24;
+
+/// Default caption font size, in pixels per em.
+pub const DEFAULT_CAPTION_SIZE: f64 = 24.0;
+
+/// Default side length of PDF output, in millimetres.
+pub const DEFAULT_PDF_SIZE_MM: f64 = 50.0;
+
+/// Settings controlling how a QR code is generated.
+///
+/// Start from [`QrOptions::default`] and adjust it with the `with_*` setters,
+/// or set fields directly. Options can also be read from TOML with
+/// [`crate::load_config`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QrOptions {
+    /// Error correction level. When unset, `H` is used if an icon is
+    /// overlaid (since it covers the center modules) and `M` otherwise.
+    pub ec_level: Option<EcLevel>,
+    /// QR version from 1 to 40, fixing the number of modules. When unset,
+    /// the smallest version that fits the payload is used.
+    pub version: Option<u8>,
+    /// How the payload is stored in the code.
+    pub encoding: PayloadEncoding,
+    /// Mask pattern from 0 to 7. When unset, the pattern with the lowest
+    /// penalty score is used, which may change with the encoder's version.
+    pub mask: Option<u8>,
+    /// Encode a Micro QR code, M1 to M4, with a single finder pattern,
+    /// instead of a standard one. `version`, `encoding` and `mask` do not
+    /// apply to it, and it cannot carry an icon or be verified.
+    pub micro: bool,
+    /// Side length of the output image, in pixels, including the margin.
+    pub size: u32,
+    /// Width of the quiet zone around the matrix, in modules.
+    pub margin: u32,
+    /// Color of the quiet zone, or `None` to fill it with `background` like
+    /// the light modules.
+    #[serde(deserialize_with = "crate::config::optional_color")]
+    pub margin_color: Option<Rgb<u8>>,
+    /// Crop the pixels left over when `size` does not divide into whole
+    /// modules, so the output ends exactly at the quiet zone and may be
+    /// slightly smaller than `size`.
+    pub trim: bool,
+    /// Scale the quiet zone and matrix up to fill `size` exactly, instead of
+    /// centering whole-pixel modules in the pixels left over, so modules
+    /// differ in size by at most a pixel. Only applied in raster output.
+    pub stretch: bool,
+    /// Largest `size` that is rendered, in pixels, so a mistyped size cannot
+    /// allocate an image of hundreds of megapixels.
+    pub max_dimension: u32,
+    /// Render a `size` over `max_dimension` at `max_dimension` instead of
+    /// failing.
+    pub clamp: bool,
+    /// Color of the dark modules, unless `gradient` is set.
+    #[serde(deserialize_with = "crate::config::color")]
+    pub foreground: Rgb<u8>,
+    /// Color of the light modules and surrounding canvas.
+    #[serde(deserialize_with = "crate::config::color")]
+    pub background: Rgb<u8>,
+    /// Image to overlay in the center, or `None` for a plain QR code. An
+    /// `http://` or `https://` URL is downloaded when `allow_network` is set.
+    pub icon: Option<PathBuf>,
+    /// Allow downloading `icon` when it is a URL, within
+    /// [`DOWNLOAD_TIMEOUT`](crate::DOWNLOAD_TIMEOUT) and
+    /// [`MAX_DOWNLOAD_SIZE`](crate::MAX_DOWNLOAD_SIZE).
+    pub allow_network: bool,
+    /// Shape of the icon and its backing.
+    pub icon_shape: IconShape,
+    /// Icon size as a percentage of the QR matrix side, up to
+    /// [`MAX_ICON_SCALE`].
+    pub icon_scale: f64,
+    /// How the icon is fitted into its `icon_scale` box.
+    pub icon_fit: IconFit,
+    /// Where the icon is placed on the code.
+    pub icon_anchor: IconAnchor,
+    /// Pixels to move the icon right and down from its `icon_anchor`,
+    /// keeping it within the matrix.
+    pub icon_offset: (i32, i32),
+    /// Filter used to resize the icon.
+    pub icon_filter: IconFilter,
+    /// Frame of an animated GIF or WebP icon to overlay, counting from 0.
+    pub icon_frame: usize,
+    /// Padding around the icon's backing; zero draws no backing at all.
+    pub icon_padding: IconPadding,
+    /// Color to recolor the icon in, keeping its shape and light detail,
+    /// see [`tint_icon`](crate::tint_icon).
+    #[serde(deserialize_with = "crate::config::optional_color")]
+    pub icon_tint: Option<Rgb<u8>>,
+    /// Convert the icon to shades of gray, before any `icon_tint`.
+    pub icon_grayscale: bool,
+    /// Shape of the dark modules.
+    pub module_style: ModuleStyle,
+    /// Corner radius for [`ModuleStyle::Rounded`], as a fraction of the
+    /// module size from `0.0` (square) to `0.5` (circle). Also rounds
+    /// [`EyeStyle::Rounded`] eyes, as a fraction of their size.
+    pub corner_radius: f64,
+    /// Whether to anti-alias the edges of non-square modules.
+    pub antialias: bool,
+    /// Gap left around each dark module, as a fraction of the module size
+    /// from `0.0` to [`MAX_MODULE_GAP`]. The finder patterns stay solid.
+    pub module_gap: f64,
+    /// Gradient to color the dark modules with instead of `foreground`.
+    pub gradient: Option<Gradient>,
+    /// Color of the finder patterns (eyes). When unset they are colored like
+    /// the other dark modules.
+    #[serde(deserialize_with = "crate::config::optional_color")]
+    pub eye_color: Option<Rgb<u8>>,
+    /// Shape of each finder pattern as a whole. When unset the eyes are drawn
+    /// module by module in `module_style`.
+    pub eye_style: Option<EyeStyle>,
+    /// Color of the dark modules of the two timing patterns, the dotted
+    /// lines along row and column 6 between the eyes. When unset they are
+    /// colored like the other dark modules.
+    #[serde(deserialize_with = "crate::config::optional_color")]
+    pub timing_color: Option<Rgb<u8>>,
+    /// Image drawn behind the modules instead of `background`, scaled and
+    /// cropped to cover the canvas.
+    pub background_image: Option<PathBuf>,
+    /// Opacity of a layer of `background` laid over `background_image` to
+    /// lighten it behind the modules, from `0.0` (none) to `1.0`.
+    pub scrim_opacity: f64,
+    /// Opacity of `background`, from 0 (none) to 255 (opaque), where the
+    /// output format has an alpha channel.
+    pub background_alpha: u8,
+    /// Leave the background fully transparent instead of `background`,
+    /// whatever `background_alpha` is.
+    pub transparent: bool,
+    /// Opacity of the icon's backing, from `0.0` to `1.0`. The backing is
+    /// drawn in `background`, unless `auto_halo` picks another color.
+    pub halo_opacity: f64,
+    /// Shape of the icon's backing, or `None` to follow `icon_shape`.
+    pub halo_shape: Option<IconShape>,
+    /// Ring around the edge of the icon's backing, drawn only when there is
+    /// a backing.
+    pub halo_border: Option<HaloBorder>,
+    /// Back the icon with `background`, white or a dark neutral, whichever
+    /// stands out most from the icon's mean luminance, so a light logo does
+    /// not wash out on a light backing.
+    pub auto_halo: bool,
+    /// Drop shadow beneath the icon's backing, or beneath the icon itself
+    /// when there is no backing. Only drawn in raster output.
+    pub icon_shadow: Option<IconShadow>,
+    /// Frame drawn around the canvas, outside the quiet zone.
+    pub frame: Option<Frame>,
+    /// Text centered in a band added below the canvas (and frame), outside
+    /// the quiet zone.
+    pub caption: Option<String>,
+    /// Caption font size, in pixels per em. Captions too wide for the canvas
+    /// are set smaller.
+    pub caption_size: f64,
+    /// Color of the caption. When unset it matches `foreground`.
+    #[serde(deserialize_with = "crate::config::optional_color")]
+    pub caption_color: Option<Rgb<u8>>,
+    /// Text alternative set as the `<title>` and `aria-label` of SVG output.
+    /// When unset the payload is used if it is text; an empty `alt` leaves
+    /// the document undescribed.
+    pub alt: Option<String>,
+    /// Clockwise rotation of the finished image, caption and frame included.
+    pub rotate: Option<Rotation>,
+    /// Counter-rotate the icon so it stays upright after `rotate`.
+    pub icon_upright: bool,
+    /// Pixels of background added around the finished image on every side,
+    /// beyond the quiet zone, frame and caption.
+    pub outer_padding: u32,
+    /// Radius in pixels of the finished image's rounded corners, outer
+    /// padding included, past which it is transparent; 0 keeps the corners
+    /// square. With `card_color` set it rounds the card instead.
+    pub card_radius: u32,
+    /// Color of a card drawn behind the finished image, `card_padding` wider
+    /// on each side, inside any outer padding. Past its rounded corners shows
+    /// the outer padding, or transparency without any.
+    #[serde(deserialize_with = "crate::config::optional_color")]
+    pub card_color: Option<Rgb<u8>>,
+    /// Width of the card around the finished image, in pixels.
+    pub card_padding: u32,
+    /// Quality of lossy output (JPEG and lossy WebP), from 1 to 100.
+    pub quality: u8,
+    /// Encode WebP output losslessly instead of at `quality`.
+    pub webp_lossless: bool,
+    /// Bits per pixel channel of PNG output: 1 for a black and white image
+    /// in a grayscale PNG a fraction the size, or 8. An image with any other
+    /// color, such as an icon or antialiased edges, is always written at 8.
+    pub bit_depth: u8,
+    /// Print resolution recorded in PNG output, in dots per inch, so the
+    /// image is placed at its intended physical size.
+    pub dpi: Option<u32>,
+    /// Record this tool and the payload in PNG text chunks and an SVG
+    /// `<metadata>` block. When off, the payload is no longer the default
+    /// `alt` text either, so it appears nowhere but in the code itself.
+    pub metadata: bool,
+    /// Printed side length of the canvas in PDF output, in millimetres. A
+    /// frame, caption, card or outer padding extends the page beyond it.
+    pub pdf_size_mm: f64,
+    /// Decode the finished image and fail unless it reads back as the
+    /// payload.
+    pub verify: bool,
+    /// Refuse to generate a code whose icon hides more of the modules than
+    /// its error correction level can recover, instead of only warning.
+    pub strict: bool,
+    /// Refuse to generate a code whose icon or its backing reaches the quiet
+    /// zone or a finder pattern, with its separator and format information.
+    pub forbid_icon_overlap: bool,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        QrOptions {
+            ec_level: None,
+            version: None,
+            encoding: PayloadEncoding::Auto,
+            mask: None,
+            micro: false,
+            size: DEFAULT_SIZE,
+            margin: DEFAULT_MARGIN,
+            margin_color: None,
+            trim: false,
+            stretch: false,
+            max_dimension: DEFAULT_MAX_DIMENSION,
+            clamp: false,
+            foreground: Rgb([0, 0, 0]),
+            background: Rgb([255, 255, 255]),
+            icon: None,
+            allow_network: false,
+            icon_shape: IconShape::Square,
+            icon_scale: DEFAULT_ICON_SCALE,
+            icon_fit: IconFit::Contain,
+            icon_anchor: IconAnchor::Center,
+            icon_offset: (0, 0),
+            icon_filter: IconFilter::Lanczos3,
+            icon_frame: 0,
+            icon_padding: IconPadding::default(),
+            icon_tint: None,
+            icon_grayscale: false,
+            module_style: ModuleStyle::Square,
+            corner_radius: 0.25,
+            antialias: false,
+            module_gap: 0.0,
+            gradient: None,
+            eye_color: None,
+            timing_color: None,
+            eye_style: None,
+            background_image: None,
+            scrim_opacity: 0.0,
+            background_alpha: 255,
+            transparent: false,
+            halo_opacity: 1.0,
+            halo_shape: None,
+            halo_border: None,
+            auto_halo: false,
+            icon_shadow: None,
+            frame: None,
+            caption: None,
+            caption_size: DEFAULT_CAPTION_SIZE,
+            caption_color: None,
+            alt: None,
+            rotate: None,
+            icon_upright: false,
+            outer_padding: 0,
+            card_radius: 0,
+            card_color: None,
+            card_padding: DEFAULT_CARD_PADDING,
+            quality: 90,
+            webp_lossless: false,
+            bit_depth: 8,
+            dpi: None,
+            metadata: true,
+            pdf_size_mm: DEFAULT_PDF_SIZE_MM,
+            verify: false,
+            strict: false,
+            forbid_icon_overlap: false,
+        }
+    }
+}
+
+impl QrOptions {
+    /// Sets the side length of the output image, in pixels.
+    pub fn with_size(self, size: u32) -> Self {
+        QrOptions { size, ..self }
+    }
+
+    /// Sets the width of the quiet zone, in modules.
+    pub fn with_margin(self, margin: u32) -> Self {
+        QrOptions { margin, ..self }
+    }
+
+    /// Fills the quiet zone with `margin_color` instead of the background.
+    pub fn with_margin_color(self, margin_color: Rgb<u8>) -> Self {
+        QrOptions { margin_color: Some(margin_color), ..self }
+    }
+
+    /// Sets whether to crop the output to the quiet zone.
+    pub fn with_trim(self, trim: bool) -> Self {
+        QrOptions { trim, ..self }
+    }
+
+    /// Sets whether to scale the modules up to fill `size` exactly.
+    pub fn with_stretch(self, stretch: bool) -> Self {
+        QrOptions { stretch, ..self }
+    }
+
+    /// Sets the largest `size` that is rendered, in pixels.
+    pub fn with_max_dimension(self, max_dimension: u32) -> Self {
+        QrOptions { max_dimension, ..self }
+    }
+
+    /// Sets whether a `size` over `max_dimension` is scaled down to it
+    /// rather than rejected.
+    pub fn with_clamp(self, clamp: bool) -> Self {
+        QrOptions { clamp, ..self }
+    }
+
+    /// Adds `padding` pixels of background around the finished image.
+    pub fn with_outer_padding(self, padding: u32) -> Self {
+        QrOptions { outer_padding: padding, ..self }
+    }
+
+    /// Rounds the corners of the finished image by `radius` pixels.
+    pub fn with_card_radius(self, radius: u32) -> Self {
+        QrOptions { card_radius: radius, ..self }
+    }
+
+    /// Draws the finished image on a `color` card `padding` pixels wider on
+    /// each side.
+    pub fn with_card(self, color: Rgb<u8>, padding: u32) -> Self {
+        QrOptions { card_color: Some(color), card_padding: padding, ..self }
+    }
+
+    /// Sets the module and background colors.
+    pub fn with_colors(self, foreground: Rgb<u8>, background: Rgb<u8>) -> Self {
+        QrOptions { foreground, background, ..self }
+    }
+
+    /// Swaps the module and background colors, for light modules on a dark
+    /// background. The quiet zone and icon backing take the background color,
+    /// so they follow.
+    pub fn inverted(self) -> Self {
+        QrOptions { foreground: self.background, background: self.foreground, ..self }
+    }
+
+    /// Fixes the error correction level instead of picking it from whether
+    /// there is an icon.
+    pub fn with_ec_level(self, ec_level: EcLevel) -> Self {
+        QrOptions { ec_level: Some(ec_level), ..self }
+    }
+
+    /// Fixes the QR version instead of using the smallest that fits.
+    pub fn with_version(self, version: u8) -> Self {
+        QrOptions { version: Some(version), ..self }
+    }
+
+    /// Sets how the payload is stored in the code.
+    pub fn with_encoding(self, encoding: PayloadEncoding) -> Self {
+        QrOptions { encoding, ..self }
+    }
+
+    /// Forces mask pattern `mask` instead of the one with the lowest penalty.
+    pub fn with_mask(self, mask: u8) -> Self {
+        QrOptions { mask: Some(mask), ..self }
+    }
+
+    /// Sets whether a Micro QR code is encoded instead of a standard one.
+    pub fn with_micro(self, micro: bool) -> Self {
+        QrOptions { micro, ..self }
+    }
+
+    /// Overlays the image at `path` in the center.
+    pub fn with_icon(self, path: impl Into<PathBuf>) -> Self {
+        QrOptions { icon: Some(path.into()), ..self }
+    }
+
+    /// Sets the icon size as a percentage of the matrix side.
+    pub fn with_icon_scale(self, icon_scale: f64) -> Self {
+        QrOptions { icon_scale, ..self }
+    }
+
+    /// Sets the shape of the icon and its backing.
+    pub fn with_icon_shape(self, icon_shape: IconShape) -> Self {
+        QrOptions { icon_shape, ..self }
+    }
+
+    /// Sets how the icon is fitted into its box.
+    pub fn with_icon_fit(self, icon_fit: IconFit) -> Self {
+        QrOptions { icon_fit, ..self }
+    }
+
+    /// Sets where the icon is placed.
+    pub fn with_icon_anchor(self, icon_anchor: IconAnchor) -> Self {
+        QrOptions { icon_anchor, ..self }
+    }
+
+    /// Sets how many pixels the icon is moved right and down from its anchor.
+    pub fn with_icon_offset(self, x: i32, y: i32) -> Self {
+        QrOptions { icon_offset: (x, y), ..self }
+    }
+
+    /// Sets the filter used to resize the icon.
+    pub fn with_icon_filter(self, icon_filter: IconFilter) -> Self {
+        QrOptions { icon_filter, ..self }
+    }
+
+    /// Allows downloading an icon given as a URL.
+    pub fn with_allow_network(self, allow_network: bool) -> Self {
+        QrOptions { allow_network, ..self }
+    }
+
+    /// Picks frame `icon_frame` of an animated icon, counting from 0.
+    pub fn with_icon_frame(self, icon_frame: usize) -> Self {
+        QrOptions { icon_frame, ..self }
+    }
+
+    /// Sets the padding around the icon's backing.
+    pub fn with_icon_padding(self, icon_padding: IconPadding) -> Self {
+        QrOptions { icon_padding, ..self }
+    }
+
+    /// Recolors the icon in shades of `icon_tint`.
+    pub fn with_icon_tint(self, icon_tint: Rgb<u8>) -> Self {
+        QrOptions { icon_tint: Some(icon_tint), ..self }
+    }
+
+    /// Sets the shape of the icon's backing apart from the icon's own.
+    pub fn with_halo_shape(self, halo_shape: IconShape) -> Self {
+        QrOptions { halo_shape: Some(halo_shape), ..self }
+    }
+
+    /// Strokes `border` around the edge of the icon's backing.
+    pub fn with_halo_border(self, border: HaloBorder) -> Self {
+        QrOptions { halo_border: Some(border), ..self }
+    }
+
+    /// Sets whether the icon's backing color is picked to stand out from it.
+    pub fn with_auto_halo(self, auto_halo: bool) -> Self {
+        QrOptions { auto_halo, ..self }
+    }
+
+    /// Draws `shadow` beneath the icon.
+    pub fn with_icon_shadow(self, shadow: IconShadow) -> Self {
+        QrOptions { icon_shadow: Some(shadow), ..self }
+    }
+
+    /// Sets the shape of the dark modules.
+    pub fn with_module_style(self, module_style: ModuleStyle) -> Self {
+        QrOptions { module_style, ..self }
+    }
+
+    /// Shrinks each dark module by `module_gap` of its size, leaving gaps of
+    /// background between them.
+    pub fn with_module_gap(self, module_gap: f64) -> Self {
+        QrOptions { module_gap, ..self }
+    }
+
+    /// Colors the dark modules with `gradient` instead of the foreground.
+    pub fn with_gradient(self, gradient: Gradient) -> Self {
+        QrOptions { gradient: Some(gradient), ..self }
+    }
+
+    /// Colors the finder patterns (eyes) `eye_color`.
+    pub fn with_eye_color(self, eye_color: Rgb<u8>) -> Self {
+        QrOptions { eye_color: Some(eye_color), ..self }
+    }
+
+    /// Colors the timing patterns `timing_color`.
+    pub fn with_timing_color(self, timing_color: Rgb<u8>) -> Self {
+        QrOptions { timing_color: Some(timing_color), ..self }
+    }
+
+    /// Draws each finder pattern (eye) as a whole in `eye_style`.
+    pub fn with_eye_style(self, eye_style: EyeStyle) -> Self {
+        QrOptions { eye_style: Some(eye_style), ..self }
+    }
+
+    /// Draws the image at `path` behind the modules, lightened by a scrim of
+    /// the background color at `scrim_opacity`.
+    pub fn with_background_image(self, path: impl Into<PathBuf>, scrim_opacity: f64) -> Self {
+        QrOptions { background_image: Some(path.into()), scrim_opacity, ..self }
+    }
+
+    /// Sets the opacity of the background, from 0 to 255.
+    pub fn with_background_alpha(self, background_alpha: u8) -> Self {
+        QrOptions { background_alpha, ..self }
+    }
+
+    /// Sets whether the background is left transparent.
+    pub fn with_transparent(self, transparent: bool) -> Self {
+        QrOptions { transparent, ..self }
+    }
+
+    /// Draws `frame` around the code.
+    pub fn with_frame(self, frame: Frame) -> Self {
+        QrOptions { frame: Some(frame), ..self }
+    }
+
+    /// Adds `caption` below the code.
+    pub fn with_caption(self, caption: impl Into<String>) -> Self {
+        QrOptions { caption: Some(caption.into()), ..self }
+    }
+
+    /// Describes SVG output as `alt` for screen readers.
+    pub fn with_alt(self, alt: impl Into<String>) -> Self {
+        QrOptions { alt: Some(alt.into()), ..self }
+    }
+
+    /// Records or leaves out the payload and generator metadata.
+    pub fn with_metadata(self, metadata: bool) -> Self {
+        QrOptions { metadata, ..self }
+    }
+
+    /// Rotates the finished image clockwise by `rotation`.
+    pub fn with_rotate(self, rotation: Rotation) -> Self {
+        QrOptions { rotate: Some(rotation), ..self }
+    }
+
+    /// Sets whether the finished image must decode back to the payload.
+    pub fn with_verify(self, verify: bool) -> Self {
+        QrOptions { verify, ..self }
+    }
+
+    /// Sets whether an icon that hides too many modules is an error.
+    pub fn with_strict(self, strict: bool) -> Self {
+        QrOptions { strict, ..self }
+    }
+
+    /// Sets whether an icon that reaches the quiet zone or a finder pattern
+    /// is an error.
+    pub fn with_forbid_icon_overlap(self, forbid_icon_overlap: bool) -> Self {
+        QrOptions { forbid_icon_overlap, ..self }
+    }
+
+    /// Returns the pixel the background is filled with: `background` at
+    /// `background_alpha`, or fully transparent when `transparent` is set.
+    pub fn background_pixel(&self) -> Rgba<u8> {
+        let [r, g, b] = self.background.0;
+        Rgba([r, g, b, if self.transparent { 0 } else { self.background_alpha }])
+    }
+
+    /// Returns the shape the icon's backing will actually be drawn in.
+    pub fn effective_halo_shape(&self) -> IconShape {
+        self.halo_shape.unwrap_or(self.icon_shape)
+    }
+
+    /// Returns the error correction level that will actually be used.
+    pub fn effective_ec_level(&self, has_icon: bool) -> EcLevel {
+        self.ec_level.unwrap_or(if has_icon { EcLevel::H } else { EcLevel::M })
+    }
+
+    /// Returns a warning if an icon at `icon_scale` covers enough of the
+    /// matrix to risk exceeding what `ec_level` can recover.
+    ///
+    /// Only half the recoverable fraction is treated as safe, since the icon
+    /// also hides function patterns and damage is rarely spread evenly.
+    pub fn icon_scale_warning(&self, ec_level: EcLevel) -> Option<String> {
+        let covered = (self.icon_scale / 100.0).powi(2);
+        let safe = ec_level.recoverable_fraction() / 2.0;
+        if covered <= safe {
+            return None;
+        }
+        Some(format!(
+            "an icon at {}% covers about {:.1}% of the code, which risks exceeding what error correction level {} \
+             (~{:.0}% recoverable) can repair; consider a smaller --icon-scale or a higher --ec-level",
+            self.icon_scale,
+            covered * 100.0,
+            ec_level,
+            ec_level.recoverable_fraction() * 100.0
+        ))
+    }
+
+    /// Returns a warning if `module_gap` thins the dark modules, as shaped by
+    /// `module_style`, enough that they may be hard to read from a distance
+    /// or in poor light.
+    pub fn module_gap_warning(&self) -> Option<String> {
+        if self.module_gap == 0.0 {
+            return None;
+        }
+        // Area of the module shape within a unit square
+        let shape = match self.module_style {
+            ModuleStyle::Square => 1.0,
+            ModuleStyle::Circle => std::f64::consts::FRAC_PI_4,
+            ModuleStyle::Rounded => 1.0 - (4.0 - std::f64::consts::PI) * self.corner_radius.clamp(0.0, 0.5).powi(2),
+        };
+        let covered = (1.0 - self.module_gap).powi(2) * shape;
+        if covered >= MIN_MODULE_COVERAGE {
+            return None;
+        }
+        Some(format!(
+            "a module gap of {} leaves the dark modules {:.0}% of their cell, which some scanners struggle to read; \
+             consider a smaller --module-gap or square modules, and check the result with --verify",
+            self.module_gap,
+            covered * 100.0
+        ))
+    }
+
+    /// Returns a warning if `size` is over `max_dimension` and `clamp` scales
+    /// the output down to it.
+    pub fn clamp_warning(&self) -> Option<String> {
+        (self.clamp && self.size > self.max_dimension).then(|| {
+            format!(
+                "the size {}px is over the {}px limit, so the code is rendered at {}px; raise --max-dimension to render it larger",
+                self.size, self.max_dimension, self.max_dimension
+            )
+        })
+    }
+
+    /// Returns a warning if `margin_color` is too dark for scanners to tell
+    /// the quiet zone from the dark modules at the edge of the matrix.
+    pub fn margin_color_warning(&self) -> Option<String> {
+        let margin_color = self.margin_color.filter(|&color| relative_luminance(color) < MIN_MARGIN_LUMINANCE)?;
+        Some(format!(
+            "the quiet zone color {} is not light, and scanners look for a light band around the code to find it; \
+             test the code with the scanners you care about",
+            crate::to_hex(margin_color)
+        ))
+    }
+
+    /// Returns a warning when `bit_depth` asks for 1-bit PNG output but the
+    /// code will have colors other than black and white, so it is written at
+    /// 8 bits instead.
+    ///
+    /// This is judged from the options alone, without rendering: an icon,
+    /// background image, gradient, frame, caption, rounded corners or
+    /// translucent background, a color other than black or white, or
+    /// antialiased curves and gaps all leave other colors in the image.
+    pub fn bit_depth_warning(&self) -> Option<String> {
+        if self.bit_depth != 1 {
+            return None;
+        }
+        let black_or_white = |color: Rgb<u8>| color == Rgb([0, 0, 0]) || color == Rgb([255, 255, 255]);
+        let colors = [Some(self.foreground), Some(self.background), self.eye_color, self.timing_color, self.margin_color, self.card_color];
+        let curved = self.module_style != ModuleStyle::Square || self.module_gap > 0.0 || self.eye_style.is_some_and(|style| style != EyeStyle::Square);
+        let black_and_white = self.icon.is_none()
+            && self.background_image.is_none()
+            && self.gradient.is_none()
+            && self.frame.is_none()
+            && crate::caption::caption_text(self).is_none()
+            && self.card_radius == 0
+            && self.background_pixel()[3] == 255
+            && colors.into_iter().flatten().all(black_or_white)
+            && !(self.antialias && curved);
+        (!black_and_white).then(|| {
+            "1-bit PNG output needs a black and white code without an icon, colors or antialiasing, \
+             so it is written in 8-bit color instead"
+                .to_string()
+        })
+    }
+
+    /// Returns a warning if the background, and so the quiet zone, is darker
+    /// than the modules, which many scanners cannot read.
+    pub fn inverted_warning(&self) -> Option<String> {
+        let modules = match self.gradient {
+            Some(gradient) => vec![gradient.start, gradient.end],
+            None => vec![self.foreground],
+        };
+        let background = relative_luminance(self.background);
+        if modules.iter().any(|&color| relative_luminance(color) <= background) {
+            return None;
+        }
+        Some(
+            "the modules are lighter than the background, and many scanners expect dark modules on a light quiet zone; \
+             test the code with the scanners you care about"
+                .to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ec_level() {
+        assert_eq!("L".parse::<EcLevel>(), Ok(EcLevel::L));
+        assert_eq!("q".parse::<EcLevel>(), Ok(EcLevel::Q));
+        assert!("X".parse::<EcLevel>().is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_shape() {
+        assert_eq!("circle".parse::<IconShape>(), Ok(IconShape::Circle));
+        assert_eq!("Square".parse::<IconShape>(), Ok(IconShape::Square));
+        assert!("hexagon".parse::<IconShape>().is_err());
+    }
+
+    #[test]
+    fn test_parse_eye_style() {
+        assert_eq!("rounded".parse::<EyeStyle>(), Ok(EyeStyle::Rounded));
+        assert_eq!("Circle".parse::<EyeStyle>(), Ok(EyeStyle::Circle));
+        assert!("diamond".parse::<EyeStyle>().is_err());
+    }
+
+    #[test]
+    fn test_gradient_interpolation() {
+        let gradient = Gradient {
+            start: Rgb([0, 0, 0]),
+            end: Rgb([200, 100, 50]),
+            direction: GradientDirection::Horizontal,
+            kind: GradientType::Linear,
+        };
+        assert_eq!(gradient.color_at(0.0), Rgb([0, 0, 0]));
+        assert_eq!(gradient.color_at(0.5), Rgb([100, 50, 25]));
+        assert_eq!(gradient.color_at(2.0), Rgb([200, 100, 50]));
+
+        assert_eq!(gradient.position(50.0, 0.0, 100.0), 0.5);
+        let diagonal = Gradient { direction: GradientDirection::Diagonal, ..gradient };
+        assert_eq!(diagonal.position(100.0, 100.0, 100.0), 1.0);
+        assert_eq!(diagonal.position(100.0, 0.0, 100.0), 0.5);
+
+        let radial = Gradient { kind: GradientType::Radial, ..gradient };
+        assert_eq!(radial.position(50.0, 50.0, 100.0), 0.0);
+        assert!((radial.position(100.0, 50.0, 100.0) - 0.5f64.sqrt()).abs() < 1e-9);
+        assert_eq!(radial.position(0.0, 100.0, 100.0), 1.0);
+        assert_eq!("Radial".parse::<GradientType>(), Ok(GradientType::Radial));
+        assert!("conic".parse::<GradientType>().is_err());
+    }
+
+    #[test]
+    fn test_icon_scale_warning() {
+        let opts = QrOptions::default();
+        assert_eq!(opts.icon_scale_warning(EcLevel::H), None);
+
+        let opts = QrOptions { icon_scale: 30.0, ..QrOptions::default() };
+        assert!(opts.icon_scale_warning(EcLevel::L).is_some());
+        assert_eq!(opts.icon_scale_warning(EcLevel::H), None);
+    }
+
+    #[test]
+    fn test_parse_payload_encoding() {
+        assert_eq!("utf8".parse::<PayloadEncoding>(), Ok(PayloadEncoding::Utf8));
+        assert_eq!("UTF-8".parse::<PayloadEncoding>(), Ok(PayloadEncoding::Utf8));
+        assert_eq!("auto".parse::<PayloadEncoding>(), Ok(PayloadEncoding::Auto));
+        assert!("latin1".parse::<PayloadEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_parse_rotation() {
+        assert_eq!("90".parse::<Rotation>(), Ok(Rotation::Deg90));
+        assert_eq!("270".parse::<Rotation>().map(Rotation::inverse), Ok(Rotation::Deg90));
+        assert!("45".parse::<Rotation>().is_err());
+        assert!(!Rotation::Deg180.is_quarter_turn());
+    }
+
+    #[test]
+    fn test_inverted() {
+        let opts = QrOptions::default();
+        assert_eq!(opts.inverted_warning(), None);
+
+        let opts = opts.inverted();
+        assert_eq!((opts.foreground, opts.background), (Rgb([255, 255, 255]), Rgb([0, 0, 0])));
+        assert!(opts.inverted_warning().is_some());
+        assert_eq!(opts.inverted().background, Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_margin_color_warning() {
+        assert_eq!(QrOptions::default().margin_color_warning(), None);
+        assert_eq!(QrOptions::default().with_margin_color(Rgb([0xf0, 0xf0, 0xe0])).margin_color_warning(), None);
+        let warning = QrOptions::default().with_margin_color(Rgb([0x10, 0x60, 0xa0])).margin_color_warning().unwrap();
+        assert!(warning.contains("#1060a0"), "{}", warning);
+    }
+
+    #[test]
+    fn test_module_gap_warning() {
+        assert_eq!(QrOptions::default().module_gap_warning(), None);
+        assert_eq!(QrOptions::default().with_module_gap(0.2).module_gap_warning(), None);
+        let warning = QrOptions::default().with_module_gap(MAX_MODULE_GAP).module_gap_warning().unwrap();
+        assert!(warning.contains("49% of their cell"), "{}", warning);
+
+        // Circles cover less of the cell to begin with
+        let circles = QrOptions::default().with_module_style(ModuleStyle::Circle);
+        assert_eq!(circles.clone().with_module_gap(0.1).module_gap_warning(), None);
+        assert!(circles.with_module_gap(0.15).module_gap_warning().is_some());
+    }
+
+    #[test]
+    fn test_parse_icon_padding() {
+        assert_eq!("8".parse::<IconPadding>(), Ok(IconPadding::Pixels(8)));
+        assert_eq!("10%".parse::<IconPadding>(), Ok(IconPadding::Percent(10.0)));
+        assert!("-3%".parse::<IconPadding>().is_err());
+        assert!("wide".parse::<IconPadding>().is_err());
+
+        assert_eq!(IconPadding::Percent(10.0).to_pixels(80), 8);
+        assert_eq!(IconPadding::Pixels(3).to_pixels(80), 3);
+    }
+
+    #[test]
+    fn test_builder_setters() {
+        let navy = Rgb([0x1a, 0x2b, 0x3c]);
+        let opts = QrOptions::default()
+            .with_size(800)
+            .with_colors(navy, Rgb([255, 255, 255]))
+            .with_ec_level(EcLevel::Q)
+            .with_icon("logo.png")
+            .with_icon_scale(25.0)
+            .with_module_style(ModuleStyle::Circle);
+
+        assert_eq!(opts.size, 800);
+        assert_eq!(opts.foreground, navy);
+        assert_eq!(opts.ec_level, Some(EcLevel::Q));
+        assert_eq!(opts.icon, Some(PathBuf::from("logo.png")));
+        assert_eq!(opts.icon_scale, 25.0);
+        assert_eq!(opts.module_style, ModuleStyle::Circle);
+        assert_eq!(opts.margin, DEFAULT_MARGIN);
+    }
+
+    #[test]
+    fn test_parse_icon_fit() {
+        assert_eq!("contain".parse::<IconFit>(), Ok(IconFit::Contain));
+        assert_eq!("Cover".parse::<IconFit>(), Ok(IconFit::Cover));
+        assert_eq!("stretch".parse::<IconFit>(), Ok(IconFit::Stretch));
+        assert!("fill".parse::<IconFit>().is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_anchor() {
+        assert_eq!("top-left".parse::<IconAnchor>(), Ok(IconAnchor::TopLeft));
+        assert_eq!("Bottom-Right".parse::<IconAnchor>(), Ok(IconAnchor::BottomRight));
+        assert!("middle".parse::<IconAnchor>().is_err());
+    }
+
+    #[test]
+    fn test_parse_icon_filter() {
+        assert_eq!("nearest".parse::<IconFilter>(), Ok(IconFilter::Nearest));
+        assert_eq!("Catmull-Rom".parse::<IconFilter>(), Ok(IconFilter::CatmullRom));
+        assert_eq!("lanczos3".parse::<IconFilter>(), Ok(IconFilter::Lanczos3));
+        assert!("bicubic".parse::<IconFilter>().is_err());
+    }
+
+    #[test]
+    fn test_effective_ec_level_defaults() {
+        let opts = QrOptions::default();
+        assert_eq!(opts.effective_ec_level(true), EcLevel::H);
+        assert_eq!(opts.effective_ec_level(false), EcLevel::M);
+
+        let opts = QrOptions { ec_level: Some(EcLevel::L), ..QrOptions::default() };
+        assert_eq!(opts.effective_ec_level(true), EcLevel::L);
+    }
+}
@@ -0,0 +1,383 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::error::{EncodingError, ImageFormatHint};
+use image::{DynamicImage, ImageError, ImageFormat, RgbImage};
+
+use crate::{QrGenError, QrOptions};
+
+/// PNG text chunk keyword under which the encoded payload is stored.
+pub const PAYLOAD_KEYWORD: &str = "qr-payload";
+
+/// This tool and its version, as recorded in output metadata.
+pub(crate) const GENERATOR: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+/// Lowest JPEG quality that reliably keeps module edges scannable.
+pub const MIN_SAFE_JPEG_QUALITY: u8 = 60;
+
+/// File format to write, detected from the output path's extension or given
+/// by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A raster format handled by the `image` crate.
+    Raster(ImageFormat),
+    /// Scalable vector graphics.
+    Svg,
+    /// A single-page PDF at a fixed physical size.
+    Pdf,
+}
+
+impl OutputFormat {
+    /// Detects the output format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Result<OutputFormat, QrGenError> {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        ext.parse()
+    }
+}
+
+impl OutputFormat {
+    /// The usual file extension for this format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Raster(format) => format.extensions_str().first().copied().unwrap_or_default(),
+            OutputFormat::Svg => "svg",
+            OutputFormat::Pdf => "pdf",
+        }
+    }
+
+    /// MIME type of files in this format.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            OutputFormat::Raster(format) => format.to_mime_type(),
+            OutputFormat::Svg => "image/svg+xml",
+            OutputFormat::Pdf => "application/pdf",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = QrGenError;
+
+    /// Parses a format name, which is any extension `from_path` accepts.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("svg") {
+            return Ok(OutputFormat::Svg);
+        }
+        if s.eq_ignore_ascii_case("pdf") {
+            return Ok(OutputFormat::Pdf);
+        }
+
+        ImageFormat::from_extension(s)
+            .map(OutputFormat::Raster)
+            .ok_or_else(|| QrGenError::UnsupportedOutputFormat(s.to_string()))
+    }
+}
+
+/// Saves `image` to `output_path`, choosing the format from its extension.
+///
+/// JPEG output uses `opts.quality`, with any transparency flattened onto
+/// `opts.background` since JPEG has no alpha channel. WebP output is lossy at
+/// `opts.quality` unless `opts.webp_lossless` is set, and keeps transparency
+/// either way. ICO is refused, since a favicon is rendered from the code at
+/// each of its sizes rather than scaled down from a finished image.
+pub fn save_image(image: &DynamicImage, output_path: &Path, opts: &QrOptions) -> Result<(), QrGenError> {
+    let format = match OutputFormat::from_path(output_path)? {
+        OutputFormat::Raster(ImageFormat::Ico) => return Err(QrGenError::UnsupportedOutputFormat("ico".to_string())),
+        OutputFormat::Raster(format) => format,
+        OutputFormat::Svg => return Err(QrGenError::UnsupportedOutputFormat("svg".to_string())),
+        OutputFormat::Pdf => return Err(QrGenError::UnsupportedOutputFormat("pdf".to_string())),
+    };
+    if format == ImageFormat::Png {
+        return write_png_file(output_path, image, None, opts);
+    }
+    fs::write(output_path, encode_image(image, format, opts)?)?;
+    Ok(())
+}
+
+/// Streams `image` as PNG into a new file at `output_path`, see
+/// [`write_png_image`].
+pub(crate) fn write_png_file(output_path: &Path, image: &DynamicImage, payload: Option<&str>, opts: &QrOptions) -> Result<(), QrGenError> {
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    write_png_image(image, payload, opts, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encodes `image` in `format`, with the same per-format handling as
+/// [`save_image`].
+///
+/// PNG output is tagged with this tool's name and version and with
+/// `opts.dpi`, see [`encode_png`].
+pub fn encode_image(image: &DynamicImage, format: ImageFormat, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    let map_err = |e| match e {
+        ImageError::IoError(e) => QrGenError::Io(e),
+        ImageError::Unsupported(_) => QrGenError::UnsupportedOutputFormat(format!("{:?}", format)),
+        e => QrGenError::ImageEncode(e),
+    };
+
+    let mut bytes = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            let rgb = flatten(image, opts);
+            let quality = opts.quality.clamp(1, 100);
+            JpegEncoder::new_with_quality(&mut bytes, quality)
+                .encode_image(&rgb)
+                .map_err(map_err)?;
+        }
+        ImageFormat::Png => bytes = encode_png(image, None, opts)?,
+        ImageFormat::WebP => bytes = encode_webp(image, opts),
+        _ => image.write_to(&mut Cursor::new(&mut bytes), format).map_err(map_err)?,
+    }
+    Ok(bytes)
+}
+
+/// Encodes `image` as PNG, with a `Software` text chunk naming this tool and
+/// its version and, if given, `payload` in an international text chunk
+/// under [`PAYLOAD_KEYWORD`] so the code can be identified without scanning.
+///
+/// With `opts.metadata` off neither text chunk is written. With `opts.dpi`
+/// set, a `pHYs` chunk records the resolution for printing.
+pub fn encode_png(image: &DynamicImage, payload: Option<&str>, opts: &QrOptions) -> Result<Vec<u8>, QrGenError> {
+    let mut bytes = Vec::new();
+    write_png_image(image, payload, opts, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Encodes `image` as PNG, as [`encode_png`] does, straight into `writer`
+/// rather than collecting the whole file in memory first.
+pub fn write_png_image<W: Write>(image: &DynamicImage, payload: Option<&str>, opts: &QrOptions, writer: W) -> Result<(), QrGenError> {
+    let map_err = |e| match e {
+        png::EncodingError::IoError(e) => QrGenError::Io(e),
+        e => QrGenError::ImageEncode(ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(ImageFormat::Png), e))),
+    };
+
+    let one_bit = if opts.bit_depth == 1 { pack_one_bit(image) } else { None };
+    let (color, depth, data) = match one_bit {
+        Some(data) => (png::ColorType::Grayscale, png::BitDepth::One, data),
+        None if image.color().has_alpha() => (png::ColorType::Rgba, png::BitDepth::Eight, image.to_rgba8().into_raw()),
+        None => (png::ColorType::Rgb, png::BitDepth::Eight, image.to_rgb8().into_raw()),
+    };
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    // Pinned rather than left to the encoder's default, and no tIME chunk
+    // is written, so the same code always encodes to the same bytes
+    encoder.set_compression(png::Compression::Balanced);
+    if let Some(dpi) = opts.dpi {
+        let ppm = pixels_per_meter(dpi);
+        encoder.set_pixel_dims(Some(png::PixelDimensions { xppu: ppm, yppu: ppm, unit: png::Unit::Meter }));
+    }
+    if opts.metadata {
+        encoder.add_text_chunk("Software".to_string(), GENERATOR.to_string()).map_err(map_err)?;
+    }
+    if let Some(payload) = payload.filter(|_| opts.metadata) {
+        // iTXt rather than tEXt, since payloads are UTF-8 rather than Latin-1
+        encoder.add_itxt_chunk(PAYLOAD_KEYWORD.to_string(), payload.to_string()).map_err(map_err)?;
+    }
+    // Finish explicitly, since a write error while dropping the writer would be lost
+    let mut writer = encoder.write_header().map_err(map_err)?;
+    writer.write_image_data(&data).map_err(map_err)?;
+    writer.finish().map_err(map_err)
+}
+
+/// Packs `image` into rows of one bit per pixel, most significant first and
+/// 1 for white, or returns `None` if any pixel is not opaque black or white.
+pub(crate) fn pack_one_bit(image: &DynamicImage) -> Option<Vec<u8>> {
+    let rgba = image.to_rgba8();
+    let row_bytes = (rgba.width() as usize).div_ceil(8);
+    let mut data = vec![0; row_bytes * rgba.height() as usize];
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        match pixel.0 {
+            [0, 0, 0, 255] => {}
+            [255, 255, 255, 255] => data[y as usize * row_bytes + x as usize / 8] |= 0x80 >> (x % 8),
+            _ => return None,
+        }
+    }
+    Some(data)
+}
+
+/// Converts a resolution in dots per inch to the pixels per meter PNG stores.
+fn pixels_per_meter(dpi: u32) -> u32 {
+    (dpi as f64 / 0.0254).round() as u32
+}
+
+/// Encodes `image` as WebP, keeping the alpha channel when it has one.
+fn encode_webp(image: &DynamicImage, opts: &QrOptions) -> Vec<u8> {
+    let (width, height) = (image.width(), image.height());
+    let rgba;
+    let rgb;
+    let encoder = if image.color().has_alpha() {
+        rgba = image.to_rgba8();
+        webp::Encoder::from_rgba(&rgba, width, height)
+    } else {
+        rgb = image.to_rgb8();
+        webp::Encoder::from_rgb(&rgb, width, height)
+    };
+
+    let encoded = if opts.webp_lossless {
+        encoder.encode_lossless()
+    } else {
+        encoder.encode(opts.quality.clamp(1, 100) as f32)
+    };
+    encoded.to_vec()
+}
+
+/// Composites `image` onto an opaque `opts.background`.
+fn flatten(image: &DynamicImage, opts: &QrOptions) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as f64 / 255.0;
+        let mix = |c: u8, bg: u8| (c as f64 * alpha + bg as f64 * (1.0 - alpha)).round() as u8;
+        image::Rgb([
+            mix(pixel[0], opts.background[0]),
+            mix(pixel[1], opts.background[1]),
+            mix(pixel[2], opts.background[2]),
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_jpeg_flattens_transparency() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("out.jpg");
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(32, 32, Rgba([0, 0, 0, 0])));
+        let opts = QrOptions { background: image::Rgb([255, 255, 255]), ..QrOptions::default() };
+        save_image(&image, &output_path, &opts).unwrap();
+
+        let decoded = image::open(&output_path).unwrap();
+        assert_eq!(image::ImageFormat::from_path(&output_path).unwrap(), ImageFormat::Jpeg);
+        assert!(!decoded.color().has_alpha());
+        assert!(decoded.to_rgb8().pixels().all(|p| p[0] > 245));
+    }
+
+    #[test]
+    fn test_jpeg_quality_affects_size() {
+        let temp_dir = tempdir().unwrap();
+        let low = temp_dir.path().join("low.jpeg");
+        let high = temp_dir.path().join("high.jpeg");
+
+        let image = crate::generate("https://example.com", &QrOptions::default()).unwrap();
+        save_image(&image, &low, &QrOptions { quality: 10, ..QrOptions::default() }).unwrap();
+        save_image(&image, &high, &QrOptions { quality: 100, ..QrOptions::default() }).unwrap();
+
+        assert!(std::fs::metadata(&low).unwrap().len() < std::fs::metadata(&high).unwrap().len());
+    }
+
+    #[test]
+    fn test_one_bit_png() {
+        let one_bit = QrOptions { bit_depth: 1, ..QrOptions::default() };
+        let image = crate::generate("https://example.com", &one_bit).unwrap();
+        let small = encode_png(&image, None, &one_bit).unwrap();
+        let full = encode_png(&image, None, &QrOptions::default()).unwrap();
+        assert!(small.len() * 2 < full.len(), "{} vs {} bytes", small.len(), full.len());
+
+        let reader = png::Decoder::new(Cursor::new(&small)).read_info().unwrap();
+        assert_eq!((reader.info().color_type, reader.info().bit_depth), (png::ColorType::Grayscale, png::BitDepth::One));
+        assert_eq!(image::load_from_memory(&small).unwrap().to_rgb8(), image.to_rgb8());
+
+        // Anything but black and white falls back to 8 bits
+        let navy = QrOptions { foreground: image::Rgb([0, 0, 0x80]), ..one_bit.clone() };
+        let image = crate::generate("https://example.com", &navy).unwrap();
+        let reader = png::Decoder::new(Cursor::new(encode_png(&image, None, &navy).unwrap())).read_info().unwrap();
+        assert_eq!((reader.info().color_type, reader.info().bit_depth), (png::ColorType::Rgb, png::BitDepth::Eight));
+        assert!(navy.bit_depth_warning().is_some());
+        assert!(one_bit.bit_depth_warning().is_none());
+    }
+
+    #[test]
+    fn test_bit_depth_warning_matches_encoder() {
+        let one_bit = QrOptions { bit_depth: 1, ..QrOptions::default() };
+        let cases = [
+            one_bit.clone(),
+            QrOptions { foreground: image::Rgb([255, 255, 255]), background: image::Rgb([0, 0, 0]), ..one_bit.clone() },
+            QrOptions { module_style: crate::ModuleStyle::Circle, ..one_bit.clone() },
+            QrOptions { module_style: crate::ModuleStyle::Circle, antialias: true, ..one_bit.clone() },
+            QrOptions { antialias: true, outer_padding: 8, rotate: Some(crate::Rotation::Deg90), ..one_bit.clone() },
+            QrOptions { eye_color: Some(image::Rgb([200, 0, 0])), ..one_bit.clone() },
+            QrOptions { caption: Some("Scan".to_string()), ..one_bit.clone() },
+            QrOptions { card_radius: 20, ..one_bit.clone() },
+            QrOptions { transparent: true, ..one_bit.clone() },
+        ];
+        for opts in cases {
+            let image = crate::generate("https://example.com", &opts).unwrap();
+            assert_eq!(opts.bit_depth_warning().is_none(), pack_one_bit(&image).is_some(), "{:?}", opts);
+        }
+    }
+
+    #[test]
+    fn test_png_metadata() {
+        let image = crate::generate("https://example.com", &QrOptions::default()).unwrap();
+        let bytes = encode_png(&image, Some("https://example.com/ünïcode"), &QrOptions::default()).unwrap();
+
+        let mut reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        let info = reader.info();
+        let software = info.uncompressed_latin1_text.iter().find(|chunk| chunk.keyword == "Software").unwrap();
+        assert_eq!(software.text, format!("qr-generator {}", env!("CARGO_PKG_VERSION")));
+        let payload = info.utf8_text.iter().find(|chunk| chunk.keyword == PAYLOAD_KEYWORD).unwrap();
+        assert_eq!(payload.get_text().unwrap(), "https://example.com/ünïcode");
+
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, image.to_rgb8().into_raw());
+        assert!(info_dims(&bytes).is_none());
+    }
+
+    fn info_dims(bytes: &[u8]) -> Option<png::PixelDimensions> {
+        png::Decoder::new(Cursor::new(bytes)).read_info().unwrap().info().pixel_dims
+    }
+
+    #[test]
+    fn test_png_dpi() {
+        assert_eq!(pixels_per_meter(300), 11811);
+        assert_eq!(pixels_per_meter(72), 2835);
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255])));
+        let opts = QrOptions { dpi: Some(300), ..QrOptions::default() };
+        let bytes = encode_image(&image, ImageFormat::Png, &opts).unwrap();
+        let dims = info_dims(&bytes).unwrap();
+        assert_eq!((dims.xppu, dims.yppu, dims.unit), (11811, 11811, png::Unit::Meter));
+        // Back to the resolution asked for
+        assert_eq!((dims.xppu as f64 * 0.0254).round(), 300.0);
+    }
+
+    #[test]
+    fn test_format_from_name() {
+        assert_eq!("png".parse::<OutputFormat>().unwrap(), OutputFormat::Raster(ImageFormat::Png));
+        assert_eq!("JPEG".parse::<OutputFormat>().unwrap(), OutputFormat::Raster(ImageFormat::Jpeg));
+        assert_eq!("pdf".parse::<OutputFormat>().unwrap(), OutputFormat::Pdf);
+        assert!(matches!("bmpx".parse::<OutputFormat>(), Err(QrGenError::UnsupportedOutputFormat(f)) if f == "bmpx"));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(OutputFormat::from_path(Path::new("out.svg")).unwrap(), OutputFormat::Svg);
+        assert_eq!(OutputFormat::from_path(Path::new("out.SVG")).unwrap(), OutputFormat::Svg);
+        assert_eq!(OutputFormat::from_path(Path::new("out.pdf")).unwrap(), OutputFormat::Pdf);
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.png")).unwrap(),
+            OutputFormat::Raster(ImageFormat::Png)
+        );
+        assert!(matches!(
+            OutputFormat::from_path(Path::new("out")),
+            Err(QrGenError::UnsupportedOutputFormat(_))
+        ));
+    }
+}
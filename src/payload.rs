@@ -0,0 +1,424 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Authentication type advertised by a Wi-Fi network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WifiAuth {
+    /// Open network with no password.
+    Nopass,
+    /// WPA/WPA2/WPA3 personal.
+    #[default]
+    Wpa,
+    /// Legacy WEP.
+    Wep,
+}
+
+impl FromStr for WifiAuth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nopass" | "none" => Ok(WifiAuth::Nopass),
+            "wpa" | "wpa2" => Ok(WifiAuth::Wpa),
+            "wep" => Ok(WifiAuth::Wep),
+            _ => Err(format!("invalid Wi-Fi auth type '{}', expected nopass, WPA or WEP", s)),
+        }
+    }
+}
+
+impl fmt::Display for WifiAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WifiAuth::Nopass => "nopass",
+            WifiAuth::Wpa => "WPA",
+            WifiAuth::Wep => "WEP",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Credentials for joining a Wi-Fi network.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WifiConfig {
+    pub ssid: String,
+    pub password: String,
+    pub auth: WifiAuth,
+    pub hidden: bool,
+}
+
+impl WifiConfig {
+    /// Builds the `WIFI:` payload for this network.
+    pub fn payload(&self) -> String {
+        wifi_payload(&self.ssid, &self.password, self.auth, self.hidden)
+    }
+}
+
+/// Builds a `WIFI:T:<auth>;S:<ssid>;P:<password>;;` payload, escaping the
+/// special characters `\`, `;`, `,`, `:` and `"` in the SSID and password.
+///
+/// The password is omitted for open (`nopass`) networks.
+pub fn wifi_payload(ssid: &str, password: &str, auth: WifiAuth, hidden: bool) -> String {
+    let mut payload = format!("WIFI:T:{};S:{};", auth, escape_wifi(ssid));
+    if auth != WifiAuth::Nopass {
+        payload.push_str(&format!("P:{};", escape_wifi(password)));
+    }
+    if hidden {
+        payload.push_str("H:true;");
+    }
+    payload.push(';');
+    payload
+}
+
+fn escape_wifi(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Longest line allowed in a vCard before it must be folded, in octets.
+const VCARD_LINE_LIMIT: usize = 75;
+
+/// Contact details for a vCard. Empty fields are left out of the payload.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VCard {
+    /// Full name; the last word is taken as the family name.
+    pub name: String,
+    pub org: String,
+    pub title: String,
+    pub phone: String,
+    pub email: String,
+    pub url: String,
+}
+
+impl VCard {
+    /// Builds the vCard 3.0 payload for this contact.
+    pub fn payload(&self) -> String {
+        vcard_payload(&self.name, &self.org, &self.title, &self.phone, &self.email, &self.url)
+    }
+}
+
+/// Builds a vCard 3.0 payload from the given contact fields, skipping any
+/// that are empty.
+///
+/// Text values have `\`, `,`, `;` and newlines escaped, lines end in CRLF and
+/// lines longer than 75 octets are folded onto continuation lines.
+pub fn vcard_payload(name: &str, org: &str, title: &str, phone: &str, email: &str, url: &str) -> String {
+    let (given, family) = match name.trim().rsplit_once(char::is_whitespace) {
+        Some((given, family)) => (given.trim(), family),
+        None => ("", name.trim()),
+    };
+
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        "VERSION:3.0".to_string(),
+        format!("N:{};{};;;", escape_vcard(family), escape_vcard(given)),
+        format!("FN:{}", escape_vcard(name.trim())),
+    ];
+    for (property, value) in [("ORG", org), ("TITLE", title), ("TEL", phone), ("EMAIL", email)] {
+        if !value.is_empty() {
+            lines.push(format!("{}:{}", property, escape_vcard(value)));
+        }
+    }
+    // URIs are not text values, so they are written verbatim
+    if !url.is_empty() {
+        lines.push(format!("URL:{}", url));
+    }
+    lines.push("END:VCARD".to_string());
+
+    let mut payload = String::new();
+    for line in lines {
+        payload.push_str(&fold_vcard_line(&line));
+        payload.push_str("\r\n");
+    }
+    payload
+}
+
+fn escape_vcard(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Splits `line` into CRLF-separated chunks of at most 75 octets, each
+/// continuation starting with a space, without breaking UTF-8 characters.
+fn fold_vcard_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut line_len = 0;
+    for c in line.chars() {
+        if line_len + c.len_utf8() > VCARD_LINE_LIMIT {
+            folded.push_str("\r\n ");
+            line_len = 1;
+        }
+        folded.push(c);
+        line_len += c.len_utf8();
+    }
+    folded
+}
+
+/// Builds a `mailto:` URI for `to`, with `subject` and `body` added as
+/// query parameters when non-empty. All components are percent-encoded.
+pub fn mailto_payload(to: &str, subject: &str, body: &str) -> String {
+    let mut payload = format!("mailto:{}", percent_encode(to.trim(), &['@', '+']));
+    let params: Vec<String> = [("subject", subject), ("body", body)]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| format!("{}={}", name, percent_encode(value, &[])))
+        .collect();
+    if !params.is_empty() {
+        payload.push('?');
+        payload.push_str(&params.join("&"));
+    }
+    payload
+}
+
+/// Builds an `sms:` URI (RFC 5724) for `number`, prefilled with `message`
+/// when non-empty.
+pub fn sms_payload(number: &str, message: &str) -> String {
+    let mut payload = format!("sms:{}", phone_number(number));
+    if !message.is_empty() {
+        payload.push_str("?body=");
+        payload.push_str(&percent_encode(message, &[]));
+    }
+    payload
+}
+
+/// Builds a `tel:` URI (RFC 3966) for `number`.
+pub fn tel_payload(number: &str) -> String {
+    format!("tel:{}", phone_number(number))
+}
+
+/// Builds a `geo:` URI (RFC 5870) for the given coordinates in degrees.
+pub fn geo_payload(latitude: f64, longitude: f64) -> Result<String, String> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(format!("invalid latitude {}, expected -90 to 90", latitude));
+    }
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err(format!("invalid longitude {}, expected -180 to 180", longitude));
+    }
+    Ok(format!("geo:{},{}", latitude, longitude))
+}
+
+/// Keeps the characters of a phone number that are valid in a URI: digits,
+/// a leading `+` and the visual separators `-`, `.`, `(` and `)`.
+fn phone_number(number: &str) -> String {
+    number
+        .trim()
+        .chars()
+        .enumerate()
+        .filter(|&(i, c)| c.is_ascii_digit() || matches!(c, '-' | '.' | '(' | ')') || (c == '+' && i == 0))
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Characters RFC 3986 reserves as URL delimiters, which [`encode_url`]
+/// leaves in place.
+const URL_DELIMITERS: &str = ":/?#[]@!$&'()*+,;=";
+
+/// Percent-encodes the characters of `payload` that cannot appear in a URL
+/// as they are, such as spaces, when it is one: a scheme such as `https`
+/// followed by `://`. Delimiters and `%` escapes already in place are kept,
+/// so a URL that is already encoded comes back unchanged. Any other payload
+/// is returned as it is.
+pub fn encode_url(payload: &str) -> String {
+    let is_url = payload.split_once("://").is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    });
+    if !is_url {
+        return payload.to_string();
+    }
+
+    let bytes = payload.as_bytes();
+    let mut encoded = String::with_capacity(payload.len());
+    for (i, c) in payload.char_indices() {
+        let escape = c == '%' && bytes.get(i + 1..i + 3).is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        if escape || is_unreserved(c) || URL_DELIMITERS.contains(c) {
+            encoded.push(c);
+        } else {
+            push_encoded(&mut encoded, c);
+        }
+    }
+    encoded
+}
+
+/// Percent-encodes every byte of `value` except RFC 3986 unreserved
+/// characters and those in `keep`.
+fn percent_encode(value: &str, keep: &[char]) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        if is_unreserved(c) || keep.contains(&c) {
+            encoded.push(c);
+        } else {
+            push_encoded(&mut encoded, c);
+        }
+    }
+    encoded
+}
+
+/// Whether `c` is an RFC 3986 unreserved character, never percent-encoded.
+fn is_unreserved(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~')
+}
+
+/// Appends each UTF-8 byte of `c` to `encoded` as a `%XX` escape.
+fn push_encoded(encoded: &mut String, c: char) {
+    let mut buf = [0; 4];
+    for byte in c.encode_utf8(&mut buf).bytes() {
+        encoded.push_str(&format!("%{:02X}", byte));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wifi_payload() {
+        assert_eq!(wifi_payload("Home", "secret", WifiAuth::Wpa, false), "WIFI:T:WPA;S:Home;P:secret;;");
+        assert_eq!(wifi_payload("Cafe", "ignored", WifiAuth::Nopass, false), "WIFI:T:nopass;S:Cafe;;");
+        assert_eq!(wifi_payload("Lab", "key", WifiAuth::Wep, true), "WIFI:T:WEP;S:Lab;P:key;H:true;;");
+    }
+
+    #[test]
+    fn test_wifi_payload_escaping() {
+        assert_eq!(
+            wifi_payload("my;net,work", r#"pa:ss\word""#, WifiAuth::Wpa, false),
+            r#"WIFI:T:WPA;S:my\;net\,work;P:pa\:ss\\word\";;"#
+        );
+    }
+
+    /// Unfolds `payload` and returns its properties with values unescaped.
+    fn parse_vcard(payload: &str) -> Vec<(String, String)> {
+        assert!(payload.ends_with("\r\n"));
+        let unfolded = payload.replace("\r\n ", "");
+        unfolded
+            .split_terminator("\r\n")
+            .map(|line| {
+                let (property, raw) = line.split_once(':').unwrap();
+                let mut value = String::new();
+                let mut chars = raw.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => match chars.next().unwrap() {
+                            'n' => value.push('\n'),
+                            escaped => value.push(escaped),
+                        },
+                        _ => value.push(c),
+                    }
+                }
+                (property.to_string(), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_vcard_payload_round_trip() {
+        let card = VCard {
+            name: "Ada King Lovelace".to_string(),
+            org: "Analytical Engines, Ltd; R&D".to_string(),
+            title: "Programmer".to_string(),
+            phone: "+44 20 7946 0000".to_string(),
+            email: "ada@example.com".to_string(),
+            url: "https://example.com/ada?a=1,2".to_string(),
+        };
+        let payload = card.payload();
+        let fields = parse_vcard(&payload);
+
+        let get = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        assert_eq!(fields.first().unwrap(), &("BEGIN".to_string(), "VCARD".to_string()));
+        assert_eq!(get("VERSION"), Some("3.0"));
+        assert_eq!(get("N"), Some("Lovelace;Ada King;;;"));
+        assert_eq!(get("FN"), Some("Ada King Lovelace"));
+        assert_eq!(get("ORG"), Some("Analytical Engines, Ltd; R&D"));
+        assert_eq!(get("TITLE"), Some("Programmer"));
+        assert_eq!(get("TEL"), Some("+44 20 7946 0000"));
+        assert_eq!(get("EMAIL"), Some("ada@example.com"));
+        assert_eq!(get("URL"), Some("https://example.com/ada?a=1,2"));
+        assert_eq!(fields.last().unwrap().0, "END");
+    }
+
+    #[test]
+    fn test_vcard_skips_empty_fields() {
+        let payload = vcard_payload("Cher", "", "", "", "", "");
+        assert_eq!(payload, "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Cher;;;;\r\nFN:Cher\r\nEND:VCARD\r\n");
+    }
+
+    #[test]
+    fn test_vcard_folds_long_lines() {
+        let title = "é".repeat(60);
+        let payload = vcard_payload("A B", "", &title, "", "", "");
+        for line in payload.split("\r\n") {
+            assert!(line.len() <= VCARD_LINE_LIMIT);
+        }
+        let fields = parse_vcard(&payload);
+        assert!(fields.contains(&("TITLE".to_string(), title)));
+    }
+
+    #[test]
+    fn test_mailto_payload() {
+        assert_eq!(mailto_payload("ada@example.com", "", ""), "mailto:ada@example.com");
+        assert_eq!(
+            mailto_payload("ada+qr@example.com", "Hi & bye?", "Line one\nLine 2 — ok"),
+            "mailto:ada+qr@example.com?subject=Hi%20%26%20bye%3F&body=Line%20one%0ALine%202%20%E2%80%94%20ok"
+        );
+        assert_eq!(mailto_payload("a@b.c", "", "x=y"), "mailto:a@b.c?body=x%3Dy");
+    }
+
+    #[test]
+    fn test_encode_url() {
+        assert_eq!(encode_url("https://example.com/search?q=red shoes&page=2"), "https://example.com/search?q=red%20shoes&page=2");
+        assert_eq!(encode_url("https://example.com/café/\"menu\""), "https://example.com/caf%C3%A9/%22menu%22");
+        // Escapes already in place survive, and a stray percent sign is escaped
+        let encoded = "https://example.com/a%20b?q=%E2%9C%93#top";
+        assert_eq!(encode_url(encoded), encoded);
+        assert_eq!(encode_url(&encode_url("https://example.com/?q=a b")), "https://example.com/?q=a%20b");
+        assert_eq!(encode_url("https://example.com/100%"), "https://example.com/100%25");
+        // Anything that is not a URL is left alone
+        assert_eq!(encode_url("Meet at 5 pm"), "Meet at 5 pm");
+        assert_eq!(encode_url("WIFI:T:WPA;S:My Home;P:secret;;"), "WIFI:T:WPA;S:My Home;P:secret;;");
+        assert_eq!(encode_url("not a url: ://x y"), "not a url: ://x y");
+    }
+
+    #[test]
+    fn test_sms_payload() {
+        assert_eq!(sms_payload("+1 (555) 010-0000", ""), "sms:+1(555)010-0000");
+        assert_eq!(sms_payload("5550100", "Call me, maybe?"), "sms:5550100?body=Call%20me%2C%20maybe%3F");
+    }
+
+    #[test]
+    fn test_tel_payload() {
+        assert_eq!(tel_payload(" +44 20 7946 0000 "), "tel:+442079460000");
+        assert_eq!(tel_payload("555+0100"), "tel:5550100");
+    }
+
+    #[test]
+    fn test_geo_payload() {
+        assert_eq!(geo_payload(-33.8568, 151.2153).unwrap(), "geo:-33.8568,151.2153");
+        assert_eq!(geo_payload(0.0, 0.0).unwrap(), "geo:0,0");
+        assert!(geo_payload(91.0, 0.0).is_err());
+        assert!(geo_payload(0.0, -180.5).is_err());
+    }
+
+    #[test]
+    fn test_parse_wifi_auth() {
+        assert_eq!("wpa".parse::<WifiAuth>(), Ok(WifiAuth::Wpa));
+        assert_eq!("WEP".parse::<WifiAuth>(), Ok(WifiAuth::Wep));
+        assert_eq!("nopass".parse::<WifiAuth>(), Ok(WifiAuth::Nopass));
+        assert!("psk".parse::<WifiAuth>().is_err());
+    }
+}
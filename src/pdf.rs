@@ -0,0 +1,650 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::{DynamicImage, Rgb, Rgba, RgbaImage};
+use crate::caption::{self, caption_band_height, CaptionLayout, TextRun};
+use crate::code::Code;
+use crate::eye;
+use crate::frame::FrameLayout;
+use crate::icon::{halo_color, icon_centered, IconArea, IconPlacement};
+use crate::render::{module_color, module_shape, Layout};
+use crate::{IconShape, ModuleStyle, QrOptions, Rotation};
+
+/// Resolution the icon is embedded at in PDF output, in dots per inch.
+pub const PDF_ICON_DPI: f64 = 300.0;
+
+const MM_PER_INCH: f64 = 25.4;
+const POINTS_PER_INCH: f64 = 72.0;
+
+/// Control point distance for approximating a quarter circle with a cubic
+/// Bézier curve, as a fraction of the radius.
+const KAPPA: f64 = 0.552_284_75;
+
+/// Number of icon pixels needed to cover `layout_size` canvas pixels at
+/// [`PDF_ICON_DPI`] when the canvas is printed `opts.pdf_size_mm` wide.
+pub(crate) fn print_pixels(layout_size: u32, layout: &Layout, opts: &QrOptions) -> u32 {
+    let inches = layout_size as f64 / layout.canvas_size as f64 * opts.pdf_size_mm / MM_PER_INCH;
+    ((inches * PDF_ICON_DPI).round() as u32).max(1)
+}
+
+/// Renders `code` as a single-page PDF whose canvas is printed
+/// `opts.pdf_size_mm` square, with `icon` embedded as an image in the center
+/// and `backdrop` behind the modules.
+///
+/// Modules are drawn as vector paths in canvas pixel coordinates and scaled to
+/// the page, so the output stays sharp at any print size. `icon` and
+/// `backdrop` are expected at print resolution (see [`print_pixels`]) and are
+/// drawn at the size they would have on the raster canvas. A frame and caption extend the page, and
+/// their text is embedded as an image of the caption font's glyphs, placed
+/// as in raster output. A quarter turn swaps the page's width and height,
+/// and outer padding and any card add to both. A card radius clips the page
+/// to a rounded rectangle, or with a card color rounds the card drawn behind
+/// the code.
+pub(crate) fn render_pdf(
+    code: &Code,
+    layout: &Layout,
+    opts: &QrOptions,
+    icon: Option<&DynamicImage>,
+    backdrop: Option<&RgbaImage>,
+) -> Vec<u8> {
+    let canvas = layout.canvas_size as f64;
+    let frame = opts.frame.as_ref().map(|frame| (frame, FrameLayout::new(frame, layout)));
+    let (width, body_height) = frame.map_or((canvas, canvas), |(_, placement)| (placement.width as f64, placement.height as f64));
+    let caption = caption::caption_text(opts);
+    let height = body_height + caption.map_or(0.0, |_| caption_band_height(opts.caption_size) as f64);
+    let (turned_width, turned_height) = match opts.rotate {
+        Some(rotation) if rotation.is_quarter_turn() => (height, width),
+        _ => (width, height),
+    };
+    // The card, if any, sits inside the outer padding
+    let card_padding = opts.card_color.map_or(0.0, |_| opts.card_padding as f64);
+    let padding = opts.outer_padding as f64 + card_padding;
+    let (outer_width, outer_height) = (turned_width + 2.0 * padding, turned_height + 2.0 * padding);
+    let page = opts.pdf_size_mm / MM_PER_INCH * POINTS_PER_INCH;
+    let (page_width, page_height) = (page * (outer_width / canvas), page * (outer_height / canvas));
+
+    // Flip the y axis so content can be written top-down in canvas pixels
+    let mut content = String::new();
+    let scale = page / canvas;
+    writeln!(content, "q {} 0 0 {} 0 {} cm", scale, -scale, page_height).unwrap();
+    if opts.card_radius > 0 && opts.card_color.is_none() {
+        let radius = (opts.card_radius as f64).min(outer_width.min(outer_height) / 2.0);
+        write_rounded_rect(&mut content, 0.0, 0.0, outer_width, outer_height, radius);
+        content.push_str("W n\n");
+    }
+
+    match opts.card_color {
+        Some(card_color) => {
+            // Past the card's corners shows the outer padding, or nothing without any
+            if opts.outer_padding > 0 {
+                write_background(&mut content, 0.0, (outer_width, outer_height), opts);
+            }
+            let (card_width, card_height) = (turned_width + 2.0 * card_padding, turned_height + 2.0 * card_padding);
+            let (inset, radius) = (opts.outer_padding as f64, (opts.card_radius as f64).min(card_width.min(card_height) / 2.0));
+            writeln!(content, "{}", fill_color(card_color)).unwrap();
+            write_rounded_rect(&mut content, inset, inset, card_width, card_height, radius);
+            content.push_str("f\n");
+            write_background(&mut content, padding, (turned_width, turned_height), opts);
+        }
+        None => write_background(&mut content, 0.0, (outer_width, outer_height), opts),
+    }
+    let transformed = opts.rotate.is_some() || padding > 0.0;
+    if transformed {
+        // Rotate about the origin, then shift the result back into view
+        // inside the padding
+        let (cos, sin, dx, dy) = match opts.rotate {
+            None => (1, 0, 0.0, 0.0),
+            Some(Rotation::Deg90) => (0, 1, height, 0.0),
+            Some(Rotation::Deg180) => (-1, 0, width, height),
+            Some(Rotation::Deg270) => (0, -1, 0.0, width),
+        };
+        writeln!(content, "q {} {} {} {} {} {} cm", cos, sin, -sin, cos, dx + padding, dy + padding).unwrap();
+    }
+    let mut images = Vec::new();
+    if let Some(backdrop) = backdrop {
+        let inset = frame.map_or(0.0, |(_, placement)| placement.border as f64);
+        write_image(&mut content, "Backdrop", inset, inset, canvas, canvas);
+        // The backdrop is only translucent where the background is
+        let backdrop = match opts.background_pixel()[3] {
+            255 => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(backdrop.clone()).to_rgb8()),
+            _ => DynamicImage::ImageRgba8(backdrop.clone()),
+        };
+        images.push(("Backdrop", backdrop));
+    }
+    if let Some((frame, placement)) = frame {
+        // The border and banner, with a window cut out for the code
+        let border = placement.border as f64;
+        writeln!(content, "{}", fill_color(frame.color)).unwrap();
+        write_rounded_rect(&mut content, 0.0, 0.0, width, body_height, placement.radius);
+        write_rounded_rect(&mut content, border, border, canvas, canvas, placement.inner_radius);
+        content.push_str("f*\n");
+        writeln!(content, "q 1 0 0 1 {0} {0} cm", border).unwrap();
+    }
+    if let Some(margin_color) = opts.margin_color {
+        // The quiet zone, with a window cut out for the matrix
+        let (start, matrix) = (layout.offset, layout.matrix_size());
+        writeln!(content, "{} 0 0 {2} {2} re {1} {1} {3} {3} re f*", fill_color(margin_color), start, canvas, matrix).unwrap();
+    }
+    let eyes_apart = eye::styled(opts);
+    let in_eye = |x: usize, y: usize| eye::is_finder_module(x as u32, y as u32, layout.qr_width);
+    let in_timing = |x: usize, y: usize| opts.timing_color.is_some() && eye::is_timing_module(x as u32, y as u32, layout.qr_width);
+    write_modules(&mut content, code, layout, opts, None, |x, y| !((eyes_apart && in_eye(x, y)) || in_timing(x, y)));
+    if opts.timing_color.is_some() {
+        write_modules(&mut content, code, layout, opts, opts.timing_color, in_timing);
+    }
+    if eyes_apart {
+        write_eyes(&mut content, code, layout, opts, in_eye);
+    }
+
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".as_bytes().to_vec(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".as_bytes().to_vec(),
+    ];
+    let mut graphics_states = Vec::new();
+    let background_alpha = opts.background_pixel()[3];
+    if background_alpha > 0 && background_alpha < 255 {
+        graphics_states.push(format!("/Background << /Type /ExtGState /ca {} >>", background_alpha as f64 / 255.0));
+    }
+
+    if let Some(icon) = icon {
+        // Map the print-resolution icon back onto the canvas
+        let pixels_per_unit = print_pixels(layout.canvas_size, layout, opts) as f64 / canvas;
+        let width = icon.width() as f64 / pixels_per_unit;
+        let height = icon.height() as f64 / pixels_per_unit;
+        let padding = opts.icon_padding.to_pixels(width.max(height).round() as u32);
+        let (x, y) = if icon_centered(opts) {
+            ((canvas - width) / 2.0, (canvas - height) / 2.0)
+        } else {
+            let area = IconArea::matrix(layout);
+            let (placement, _) = IconPlacement::anchored(&area, width.round() as u32, height.round() as u32, padding, opts);
+            (placement.x as f64, placement.y as f64)
+        };
+
+        let padding = padding as f64;
+        if padding > 0.0 {
+            writeln!(content, "q /Halo gs {}", fill_color(halo_color(icon, opts))).unwrap();
+            let (halo_width, halo_height) = (width + 2.0 * padding, height + 2.0 * padding);
+            let shape = opts.effective_halo_shape();
+            let write_halo = |content: &mut String, inset: f64| match shape {
+                IconShape::Square => write_rounded_rect(
+                    content,
+                    x - padding + inset,
+                    y - padding + inset,
+                    halo_width - 2.0 * inset,
+                    halo_height - 2.0 * inset,
+                    0.0,
+                ),
+                IconShape::Circle => {
+                    let diameter = halo_width.min(halo_height) - 2.0 * inset;
+                    let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+                    write_rounded_rect(content, cx - diameter / 2.0, cy - diameter / 2.0, diameter, diameter, diameter / 2.0);
+                }
+            };
+            write_halo(&mut content, 0.0);
+            content.push_str("f Q\n");
+            if let Some(border) = opts.halo_border.filter(|border| border.width > 0) {
+                // The ring is the backing less the backing inset by its width
+                writeln!(content, "q {}", fill_color(border.color)).unwrap();
+                write_halo(&mut content, 0.0);
+                write_halo(&mut content, border.width as f64);
+                content.push_str("f* Q\n");
+            }
+            graphics_states.push(format!("/Halo << /Type /ExtGState /ca {} >>", opts.halo_opacity.clamp(0.0, 1.0)));
+        }
+
+        write_image(&mut content, "Icon", x, y, width, height);
+        images.push(("Icon", icon.clone()));
+    }
+    // Text is rasterized as sharply as the icon
+    let resolution = print_pixels(layout.canvas_size, layout, opts) as f64 / canvas;
+    if let Some((frame, placement)) = frame {
+        content.push_str("Q\n");
+        let run = TextRun::new(&frame.text, placement.text_x, placement.baseline, placement.text_size, resolution);
+        write_text(&mut content, &mut images, "Banner", run, frame.text_color);
+    }
+    if let Some(text) = caption {
+        let placement = CaptionLayout::new(text, width as u32, opts);
+        let run = TextRun::new(text, placement.x, body_height + placement.baseline, placement.size, resolution);
+        write_text(&mut content, &mut images, "Caption", run, opts.caption_color.unwrap_or(opts.foreground));
+    }
+    if transformed {
+        content.push_str("Q\n");
+    }
+    content.push_str("Q\n");
+
+    let mut resources = String::new();
+    if !graphics_states.is_empty() {
+        write!(resources, "/ExtGState << {} >> ", graphics_states.join(" ")).unwrap();
+    }
+    // Object 4 is the content stream, and the images follow it, each with
+    // its soft mask if it has one
+    if !images.is_empty() {
+        let mut image_ref = 5;
+        let entries: Vec<String> = images
+            .iter()
+            .map(|(name, image)| {
+                let entry = format!("/{} {} 0 R", name, image_ref);
+                image_ref += if image.color().has_alpha() { 2 } else { 1 };
+                entry
+            })
+            .collect();
+        write!(resources, "/XObject << {} >> ", entries.join(" ")).unwrap();
+    }
+    objects.push(
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << {}>> /Contents 4 0 R >>",
+            page_width, page_height, resources
+        )
+        .into_bytes(),
+    );
+    objects.push(stream_object("", content.as_bytes()));
+
+    for (_, image) in images {
+        let rgb = image.to_rgb8();
+        let mut dict = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8",
+            image.width(),
+            image.height()
+        );
+        if image.color().has_alpha() {
+            let alpha: Vec<u8> = image.to_rgba8().pixels().map(|p| p[3]).collect();
+            write!(dict, " /SMask {} 0 R", objects.len() + 2).unwrap();
+            objects.push(stream_object(&dict, rgb.as_raw()));
+            let mask = format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 8",
+                image.width(),
+                image.height()
+            );
+            objects.push(stream_object(&mask, &alpha));
+        } else {
+            objects.push(stream_object(&dict, rgb.as_raw()));
+        }
+    }
+
+    write_document(&objects)
+}
+
+/// Fills a `width` x `height` rectangle `inset` pixels from the top left
+/// corner with `opts.background`, at its alpha.
+fn write_background(content: &mut String, inset: f64, (width, height): (f64, f64), opts: &QrOptions) {
+    match opts.background_pixel()[3] {
+        0 => {}
+        255 => writeln!(content, "{} {1} {1} {2} {3} re f", fill_color(opts.background), inset, width, height).unwrap(),
+        _ => writeln!(content, "q /Background gs {} {1} {1} {2} {3} re f Q", fill_color(opts.background), inset, width, height).unwrap(),
+    }
+}
+
+/// Draws the image XObject `name` over the `width` x `height` box at
+/// `(x, y)`.
+fn write_image(content: &mut String, name: &str, x: f64, y: f64, width: f64, height: f64) {
+    writeln!(content, "q {} 0 0 {} {} {} cm /{} Do Q", width, -height, x, y + height, name).unwrap();
+}
+
+/// Draws `run` in `color`, adding the image of its glyphs to `images` as
+/// `name`.
+fn write_text(content: &mut String, images: &mut Vec<(&str, DynamicImage)>, name: &'static str, run: TextRun, color: Rgb<u8>) {
+    let [r, g, b] = color.0;
+    let glyphs = RgbaImage::from_fn(run.mask.width(), run.mask.height(), |x, y| Rgba([r, g, b, run.mask.get_pixel(x, y)[0]]));
+    write_image(content, name, run.x, run.y, run.width, run.height);
+    images.push((name, DynamicImage::ImageRgba8(glyphs)));
+}
+
+/// Writes the dark modules for which `include` returns true, filling each
+/// row's runs with one path for plain square modules and shaping or coloring
+/// modules individually otherwise. Modules are filled with `color` when
+/// given, or colored like any dark module otherwise.
+fn write_modules(
+    content: &mut String,
+    code: &Code,
+    layout: &Layout,
+    opts: &QrOptions,
+    color: Option<Rgb<u8>>,
+    include: impl Fn(usize, usize) -> bool,
+) {
+    let width = code.width();
+    let gradient = color.is_none() && opts.gradient.is_some();
+    let color = color.unwrap_or(opts.foreground);
+
+    if opts.module_style == ModuleStyle::Square && opts.module_gap == 0.0 && !gradient {
+        writeln!(content, "{}", fill_color(color)).unwrap();
+        for y in 0..width {
+            let mut x = 0;
+            while x < width {
+                if code[(x, y)] != qrcode::Color::Dark || !include(x, y) {
+                    x += 1;
+                    continue;
+                }
+                let run_start = x;
+                while x < width && code[(x, y)] == qrcode::Color::Dark && include(x, y) {
+                    x += 1;
+                }
+                writeln!(
+                    content,
+                    "{} {} {} {} re",
+                    layout.offset + run_start as u32 * layout.module_size,
+                    layout.offset + y as u32 * layout.module_size,
+                    (x - run_start) as u32 * layout.module_size,
+                    layout.module_size
+                )
+                .unwrap();
+            }
+        }
+        content.push_str("f\n");
+        return;
+    }
+
+    if !gradient {
+        writeln!(content, "{}", fill_color(color)).unwrap();
+    }
+    for y in 0..width {
+        for x in 0..width {
+            if code[(x, y)] != qrcode::Color::Dark || !include(x, y) {
+                continue;
+            }
+            let start_x = layout.offset + x as u32 * layout.module_size;
+            let start_y = layout.offset + y as u32 * layout.module_size;
+            if gradient {
+                writeln!(content, "{}", fill_color(module_color(opts, layout, start_x, start_y))).unwrap();
+            }
+            let shape = module_shape(x, y, layout, opts);
+            let (left, top) = (start_x as f64 + shape.inset, start_y as f64 + shape.inset);
+            write_rounded_rect(content, left, top, shape.size, shape.size, shape.radius);
+            if gradient {
+                content.push_str("f\n");
+            }
+        }
+    }
+    if !gradient {
+        content.push_str("f\n");
+    }
+}
+
+/// Writes the finder patterns, whose modules are those `in_eye` accepts, in
+/// their own color and, with `opts.eye_style` set, as whole shapes filled
+/// with the even-odd rule so the hole is left empty.
+fn write_eyes(content: &mut String, code: &Code, layout: &Layout, opts: &QrOptions, in_eye: impl Fn(usize, usize) -> bool) {
+    let Some(style) = opts.eye_style else {
+        write_modules(content, code, layout, opts, opts.eye_color, in_eye);
+        return;
+    };
+
+    let rects = eye::eye_rects(style, layout.module_size, opts.corner_radius);
+    for (x, y) in eye::finder_origins(layout.qr_width) {
+        let left = layout.offset + x * layout.module_size;
+        let top = layout.offset + y * layout.module_size;
+        writeln!(content, "{}", fill_color(eye::eye_color(opts, layout, left, top))).unwrap();
+        for rect in rects {
+            let (x, y) = (left as f64 + rect.inset, top as f64 + rect.inset);
+            write_rounded_rect(content, x, y, rect.size, rect.size, rect.radius);
+        }
+        content.push_str("f*\n");
+    }
+}
+
+/// Appends a rectangular subpath at `(x, y)` whose corners are rounded by
+/// `radius`; a square with a radius of half its side produces a circle.
+fn write_rounded_rect(content: &mut String, x: f64, y: f64, width: f64, height: f64, radius: f64) {
+    if radius <= 0.0 {
+        writeln!(content, "{} {} {} {} re", x, y, width, height).unwrap();
+        return;
+    }
+
+    let (left, top, right, bottom) = (x, y, x + width, y + height);
+    let k = radius * KAPPA;
+    writeln!(content, "{} {} m", left + radius, top).unwrap();
+    writeln!(content, "{} {} l", right - radius, top).unwrap();
+    writeln!(content, "{} {} {} {} {} {} c", right - radius + k, top, right, top + radius - k, right, top + radius).unwrap();
+    writeln!(content, "{} {} l", right, bottom - radius).unwrap();
+    writeln!(content, "{} {} {} {} {} {} c", right, bottom - radius + k, right - radius + k, bottom, right - radius, bottom)
+        .unwrap();
+    writeln!(content, "{} {} l", left + radius, bottom).unwrap();
+    writeln!(content, "{} {} {} {} {} {} c", left + radius - k, bottom, left, bottom - radius + k, left, bottom - radius)
+        .unwrap();
+    writeln!(content, "{} {} l", left, top + radius).unwrap();
+    writeln!(content, "{} {} {} {} {} {} c h", left, top + radius - k, left + radius - k, top, left + radius, top).unwrap();
+}
+
+/// Operator setting the nonstroking fill to `color`.
+fn fill_color(color: Rgb<u8>) -> String {
+    let [r, g, b] = color.0.map(|c| c as f64 / 255.0);
+    format!("{:.4} {:.4} {:.4} rg", r, g, b)
+}
+
+/// Builds a Flate-compressed stream object with `dict` as extra dictionary
+/// entries.
+fn stream_object(dict: &str, data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut object = format!("<< {} /Filter /FlateDecode /Length {} >>\nstream\n", dict, compressed.len()).into_bytes();
+    object.extend_from_slice(&compressed);
+    object.extend_from_slice(b"\nendstream");
+    object
+}
+
+/// Serializes `objects`, numbered from 1, into a PDF file with a
+/// cross-reference table.
+fn write_document(objects: &[Vec<u8>]) -> Vec<u8> {
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        writeln!(trailer, "{:010} 00000 n ", offset).unwrap();
+    }
+    write!(trailer, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).unwrap();
+    pdf.extend_from_slice(trailer.as_bytes());
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Frame;
+    use flate2::read::ZlibDecoder;
+    use qrcode::QrCode;
+    use std::io::Read;
+
+    fn render(opts: &QrOptions, icon: Option<&DynamicImage>) -> Vec<u8> {
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, opts).unwrap();
+        render_pdf(&code, &layout, opts, icon, None)
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+
+    /// The decompressed content stream, the first stream in the file.
+    fn content(pdf: &[u8]) -> String {
+        let start = find(pdf, b"stream\n").unwrap() + 7;
+        let end = find(pdf, b"\nendstream").unwrap();
+        let mut content = String::new();
+        ZlibDecoder::new(&pdf[start..end]).read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_pdf_structure() {
+        let opts = QrOptions { pdf_size_mm: 25.4, ..QrOptions::default() };
+        let pdf = render(&opts, None);
+
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(find(&pdf, b"/MediaBox [0 0 72 72]").is_some());
+
+        // Every cross-reference entry points at the start of its object
+        let text = String::from_utf8_lossy(&pdf);
+        let xref = text.rfind("xref\n").unwrap();
+        for (i, line) in text[xref..].lines().skip(3).take_while(|l| l.ends_with(" n ")).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_pdf_modules_are_vector() {
+        let pdf = render(&QrOptions::default(), None);
+        let content = content(&pdf);
+
+        assert!(content.contains(" re\n"));
+        assert!(content.contains("0.0000 0.0000 0.0000 rg"));
+        assert!(find(&pdf, b"/Subtype /Image").is_none());
+    }
+
+    #[test]
+    fn test_pdf_embeds_icon_at_print_resolution() {
+        let opts = QrOptions { pdf_size_mm: 100.0, ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+
+        // A full 100mm canvas at 300 DPI is 1181 pixels
+        assert_eq!(print_pixels(layout.canvas_size, &layout, &opts), 1181);
+
+        let icon = DynamicImage::new_rgba8(120, 120);
+        let pdf = render(&opts, Some(&icon));
+        assert!(find(&pdf, b"/Subtype /Image /Width 120 /Height 120").is_some());
+        assert!(find(&pdf, b"/SMask").is_some());
+        assert!(find(&pdf, b"/Halo").is_some());
+    }
+
+    #[test]
+    fn test_pdf_embeds_backdrop_under_frame() {
+        let opts = QrOptions { frame: Some(Frame::default()), ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let border = FrameLayout::new(opts.frame.as_ref().unwrap(), &layout).border;
+        let backdrop = RgbaImage::from_pixel(600, 600, Rgba([200, 220, 240, 255]));
+        let pdf = render_pdf(&code, &layout, &opts, None, Some(&backdrop));
+
+        // Inset by the border and opaque, so without a soft mask
+        let canvas = layout.canvas_size;
+        let content = content(&pdf);
+        assert!(content.contains(&format!("q {0} 0 0 -{0} {1} {2} cm /Backdrop Do Q", canvas, border, border + canvas)));
+        assert!(content.find("/Backdrop Do").unwrap() < content.find("f*\n").unwrap());
+        assert!(find(&pdf, b"/XObject << /Backdrop 5 0 R /Banner 6 0 R >>").is_some());
+        assert!(find(&pdf, b"/Width 600 /Height 600 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter").is_some());
+    }
+
+    #[test]
+    fn test_pdf_frame_extends_page() {
+        let opts = QrOptions { pdf_size_mm: 25.4, frame: Some(Frame::default()), ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let placement = FrameLayout::new(opts.frame.as_ref().unwrap(), &layout);
+        let pdf = render(&opts, None);
+
+        // The canvas keeps its printed size and the frame is added around it
+        let canvas = layout.canvas_size as f64;
+        let media_box = format!("/MediaBox [0 0 {} {}]", 72.0 * (placement.width as f64 / canvas), 72.0 * (placement.height as f64 / canvas));
+        assert!(find(&pdf, media_box.as_bytes()).is_some());
+
+        let content = content(&pdf);
+        assert!(content.contains("0.0000 0.0000 0.0000 rg\n"));
+        assert!(content.contains("f*\n"));
+        assert!(content.contains(&format!("q 1 0 0 1 {0} {0} cm", placement.border)));
+        assert!(content.contains("/Banner Do"));
+        assert!(find(&pdf, b"/XObject << /Banner 5 0 R >>").is_some());
+        assert!(find(&pdf, b"/SMask 6 0 R").is_some());
+    }
+
+    #[test]
+    fn test_pdf_caption_extends_page() {
+        let opts = QrOptions { pdf_size_mm: 25.4, caption: Some("Scan to order".to_string()), ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let pdf = render(&opts, None);
+
+        let band = caption_band_height(opts.caption_size) as f64 / layout.canvas_size as f64;
+        assert!(find(&pdf, format!("/MediaBox [0 0 72 {}]", 72.0 * (1.0 + band)).as_bytes()).is_some());
+        assert!(content(&pdf).contains("/Caption Do"));
+        assert!(find(&pdf, b"/XObject << /Caption 5 0 R >>").is_some());
+
+        // An empty caption adds nothing
+        let pdf = render(&QrOptions { caption: Some(String::new()), ..opts }, None);
+        assert!(find(&pdf, b"/MediaBox [0 0 72 72]").is_some());
+        assert!(find(&pdf, b"/Subtype /Image").is_none());
+    }
+
+    #[test]
+    fn test_pdf_rotation_turns_page() {
+        let opts = QrOptions { pdf_size_mm: 25.4, caption: Some("Scan".to_string()), ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let canvas = layout.canvas_size as f64;
+        let height = canvas + caption_band_height(opts.caption_size) as f64;
+
+        let pdf = render(&QrOptions { rotate: Some(Rotation::Deg90), ..opts.clone() }, None);
+        assert!(find(&pdf, format!("/MediaBox [0 0 {} 72]", 72.0 * (height / canvas)).as_bytes()).is_some());
+        assert!(content(&pdf).contains(&format!("q 0 1 -1 0 {} 0 cm", height)));
+
+        let pdf = render(&QrOptions { rotate: Some(Rotation::Deg180), ..opts.clone() }, None);
+        assert!(find(&pdf, format!("/MediaBox [0 0 72 {}]", 72.0 * (height / canvas)).as_bytes()).is_some());
+        assert!(content(&pdf).contains(&format!("q -1 0 0 -1 {} {} cm", canvas, height)));
+
+        let pdf = render(&QrOptions { rotate: Some(Rotation::Deg270), ..opts }, None);
+        assert!(content(&pdf).contains(&format!("q 0 -1 1 0 0 {} cm", canvas)));
+    }
+
+    #[test]
+    fn test_pdf_outer_padding_extends_page() {
+        let opts = QrOptions { pdf_size_mm: 25.4, outer_padding: 20, ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let canvas = layout.canvas_size as f64;
+        let pdf = render(&opts, None);
+
+        let side = 72.0 * ((canvas + 40.0) / canvas);
+        assert!(find(&pdf, format!("/MediaBox [0 0 {0} {0}]", side).as_bytes()).is_some());
+        let content = content(&pdf);
+        assert!(content.contains(&format!("0 0 {0} {0} re f", canvas + 40.0)));
+        assert!(content.contains("q 1 0 0 1 20 20 cm"));
+    }
+
+    #[test]
+    fn test_pdf_card_radius_clips_page() {
+        let clipped = content(&render(&QrOptions { card_radius: 30, ..QrOptions::default() }, None));
+        let clip = clipped.find("W n\n").unwrap();
+        assert!(clipped[..clip].contains("30 0 m"));
+        assert!(clip < clipped.find(" re f").unwrap());
+
+        assert!(!content(&render(&QrOptions::default(), None)).contains("W n"));
+    }
+
+    #[test]
+    fn test_pdf_card_behind_code() {
+        let opts = QrOptions {
+            pdf_size_mm: 25.4,
+            card_color: Some(Rgb([255, 0, 0])),
+            card_padding: 10,
+            card_radius: 30,
+            outer_padding: 5,
+            ..QrOptions::default()
+        };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let canvas = layout.canvas_size as f64;
+        let pdf = render(&opts, None);
+
+        let side = 72.0 * ((canvas + 30.0) / canvas);
+        assert!(find(&pdf, format!("/MediaBox [0 0 {0} {0}]", side).as_bytes()).is_some());
+        let content = content(&pdf);
+        // The padding, then the rounded card, then the canvas's background
+        let card = content.find("1.0000 0.0000 0.0000 rg\n35 5 m").unwrap();
+        assert!(content[..card].contains(&format!("0 0 {0} {0} re f", canvas + 30.0)));
+        assert!(content[card..].contains(&format!("15 15 {0} {0} re f", canvas)));
+        assert!(content.contains("q 1 0 0 1 15 15 cm"));
+        // The card is rounded instead of the page
+        assert!(!content.contains("W n"));
+    }
+}
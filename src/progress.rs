@@ -0,0 +1,148 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::LevelFilter;
+
+use crate::Cli;
+
+/// Width of the progress bar, in characters.
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// Shortest time between two redraws of the progress bar.
+const PROGRESS_REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A one-line progress bar with the codes done and the time left, redrawn in
+/// place as the worker threads of a batch or contact sheet finish codes, and
+/// cleared when dropped.
+pub(crate) struct ProgressBar {
+    started: Instant,
+    /// Where the bar is drawn, or `None` when it is hidden.
+    out: Option<Mutex<ProgressOutput>>,
+}
+
+/// The writer a progress bar draws on, with what it last drew.
+struct ProgressOutput {
+    writer: Box<dyn Write + Send>,
+    /// Count last drawn, so a thread that finished earlier but reports later
+    /// does not move the bar back.
+    drawn: usize,
+    drawn_at: Option<Instant>,
+}
+
+impl ProgressBar {
+    /// A bar on stderr, hidden unless [`shown`] says otherwise.
+    pub(crate) fn for_terminal(cli: &Cli) -> ProgressBar {
+        ProgressBar::new(Box::new(io::stderr()), shown(cli))
+    }
+
+    /// A bar drawn on `writer`, or a hidden one that writes nothing unless
+    /// `visible` is set.
+    pub(crate) fn new(writer: Box<dyn Write + Send>, visible: bool) -> ProgressBar {
+        let out = visible.then(|| Mutex::new(ProgressOutput { writer, drawn: 0, drawn_at: None }));
+        ProgressBar { started: Instant::now(), out }
+    }
+
+    /// Redraws the bar at `done` of `total` codes, unless a higher count is
+    /// already drawn or the last redraw is too recent. The last code is always
+    /// drawn.
+    pub(crate) fn update(&self, done: usize, total: usize) {
+        let Some(out) = &self.out else { return };
+        let mut out = out.lock().unwrap();
+        let now = Instant::now();
+        let recent = out.drawn_at.is_some_and(|drawn_at| now - drawn_at < PROGRESS_REDRAW_INTERVAL);
+        if done <= out.drawn || (recent && done < total) {
+            return;
+        }
+        (out.drawn, out.drawn_at) = (done, Some(now));
+        // A bar that cannot be drawn is no reason to stop generating codes
+        let line = progress_line(done, total, now - self.started);
+        let _ = write!(out.writer, "\r{}", line).and_then(|()| out.writer.flush());
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        if let Some(out) = &self.out {
+            let mut out = out.lock().unwrap();
+            if out.drawn > 0 {
+                let _ = write!(out.writer, "\r\x1b[2K").and_then(|()| out.writer.flush());
+            }
+        }
+    }
+}
+
+/// Whether a progress bar is drawn for `cli`: not under `--quiet`, nor when
+/// stdout or stderr is not a terminal, so piped output and captured logs stay
+/// free of it.
+pub(crate) fn shown(cli: &Cli) -> bool {
+    cli.log_level >= LevelFilter::Info && io::stdout().is_terminal() && io::stderr().is_terminal()
+}
+
+/// The progress bar at `done` of `total` codes, `elapsed` after the first
+/// started, with the time left estimated from the pace so far.
+fn progress_line(done: usize, total: usize, elapsed: Duration) -> String {
+    let filled = (done * PROGRESS_BAR_WIDTH).checked_div(total).unwrap_or(PROGRESS_BAR_WIDTH);
+    let left = elapsed.mul_f64(total.saturating_sub(done) as f64 / done.max(1) as f64).as_secs();
+    format!(
+        "[{}{}] {}/{} ETA {}:{:02}",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled),
+        done,
+        total,
+        left / 60,
+        left % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// A writer that keeps what a progress bar draws where the test can read it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        use rayon::prelude::*;
+
+        let buffer = SharedBuffer::default();
+        let bar = ProgressBar::new(Box::new(buffer.clone()), true);
+        (1..=500).into_par_iter().for_each(|done| bar.update(done, 500));
+        drop(bar);
+
+        // Counts only move forward, the last is always drawn, and the bar is cleared at the end
+        let drawn = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let counts: Vec<usize> =
+            drawn.split('\r').filter_map(|line| line.split_once("] ")?.1.split_once('/')?.0.parse().ok()).collect();
+        assert!(counts.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", counts);
+        assert_eq!(counts.last(), Some(&500));
+        assert!(drawn.contains(&format!("[{}] 500/500 ETA 0:00", "#".repeat(PROGRESS_BAR_WIDTH))));
+        assert!(drawn.ends_with("\r\x1b[2K"));
+
+        assert_eq!(progress_line(1, 4, Duration::from_secs(20)), format!("[{}{}] 1/4 ETA 1:00", "#".repeat(7), "-".repeat(23)));
+    }
+
+    #[test]
+    fn test_hidden_progress_bar() {
+        // A hidden bar writes nothing, however many codes report to it
+        let buffer = SharedBuffer::default();
+        let bar = ProgressBar::new(Box::new(buffer.clone()), false);
+        (1..=10).for_each(|done| bar.update(done, 10));
+        drop(bar);
+        assert!(buffer.0.lock().unwrap().is_empty());
+    }
+}
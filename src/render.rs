@@ -0,0 +1,769 @@
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbaImage, Rgba};
+use qrcode::types::{QrError, Version};
+use qrcode::QrCode;
+
+use crate::capacity::{check_fits, payload_bits};
+use crate::code::Code;
+use crate::color::blend_over;
+use crate::eye::{self, FINDER_SIZE};
+use crate::options::{MAX_MASK, MAX_MICRO_VERSION, MAX_MODULE_GAP, MAX_VERSION};
+use crate::{contrast_ratio, EcLevel, EyeStyle, ModuleStyle, PayloadEncoding, QrGenError, QrOptions, MIN_CONTRAST_RATIO};
+
+/// Where the QR matrix sits on the output canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Layout {
+    /// Number of modules along one side of the matrix.
+    pub qr_width: u32,
+    /// Side length of a single module, in pixels.
+    pub module_size: u32,
+    /// Distance from the canvas edge to the first module, in pixels.
+    pub offset: u32,
+    /// Side length of the whole canvas, in pixels.
+    pub canvas_size: u32,
+    /// Side length the modules are drawn at, in pixels, before the canvas is
+    /// scaled up to `canvas_size`; the same unless `opts.stretch` is set.
+    pub drawn_size: u32,
+}
+
+impl Layout {
+    /// Fits the matrix plus `opts.margin` quiet modules on each side into a
+    /// canvas of `opts.size` pixels.
+    ///
+    /// A size over `opts.max_dimension` is refused before anything is
+    /// allocated, or taken down to that limit with `opts.clamp` set.
+    ///
+    /// With `opts.trim` set, the pixels left over from dividing the size into
+    /// whole modules are cropped off, so the canvas ends at the quiet zone.
+    /// With `opts.stretch` set instead, raster output draws the modules
+    /// without them and scales the result up to fill the canvas; the layout
+    /// stays as it would be otherwise, for vector output and the icon.
+    pub fn new(qr_width: u32, opts: &QrOptions) -> Result<Layout, QrGenError> {
+        let qr_size = match opts.size {
+            size if size <= opts.max_dimension => size,
+            _ if opts.clamp => opts.max_dimension,
+            size => return Err(QrGenError::SizeTooLarge { size, max: opts.max_dimension }),
+        };
+        let total_modules = qr_width + 2 * opts.margin;
+        if qr_size < total_modules {
+            return Err(QrGenError::SizeTooSmall { size: qr_size, min: total_modules });
+        }
+        let module_size = qr_size / total_modules; // Calculate module size to fill entire image
+        let actual_qr_size = module_size * total_modules; // Actual size might be slightly smaller
+
+        // Inset the matrix by the quiet zone and center it, splitting any
+        // leftover pixels between both sides
+        let offset = opts.margin * module_size + (qr_size - actual_qr_size) / 2;
+        if opts.trim {
+            let offset = opts.margin * module_size;
+            return Ok(Layout { qr_width, module_size, offset, canvas_size: actual_qr_size, drawn_size: actual_qr_size });
+        }
+        let drawn_size = if opts.stretch { actual_qr_size } else { qr_size };
+        Ok(Layout { qr_width, module_size, offset, canvas_size: qr_size, drawn_size })
+    }
+
+    /// The layout the modules of a stretched canvas are drawn at, with the
+    /// same whole-pixel modules and no pixels left over, or `None` if the
+    /// canvas is not stretched.
+    fn unstretched(&self) -> Option<Layout> {
+        (self.drawn_size != self.canvas_size).then(|| Layout {
+            offset: (self.drawn_size - self.matrix_size()) / 2,
+            canvas_size: self.drawn_size,
+            ..*self
+        })
+    }
+
+    /// Side length covered by modules, in pixels.
+    pub fn matrix_size(&self) -> u32 {
+        self.module_size * self.qr_width
+    }
+}
+
+/// Encodes `url` into a QR matrix after checking the colors are scannable.
+pub(crate) fn encode(url: &[u8], ec_level: EcLevel, opts: &QrOptions) -> Result<Code, QrGenError> {
+    check_contrast(opts)?;
+    encode_matrix(url, ec_level, opts)
+}
+
+/// Checks that every module color stands out from the background.
+pub(crate) fn check_contrast(opts: &QrOptions) -> Result<(), QrGenError> {
+    let mut module_colors = match opts.gradient {
+        Some(gradient) => vec![gradient.start, gradient.end],
+        None => vec![opts.foreground],
+    };
+    module_colors.extend(opts.eye_color);
+    module_colors.extend(opts.timing_color);
+    for color in module_colors {
+        let ratio = contrast_ratio(color, opts.background);
+        if ratio < MIN_CONTRAST_RATIO {
+            return Err(QrGenError::LowContrast { ratio, min: MIN_CONTRAST_RATIO });
+        }
+    }
+    Ok(())
+}
+
+/// Encodes `url` at `opts.version` if one is set, or the smallest version that
+/// fits otherwise. A payload that does not fit is reported with the limit it
+/// exceeds.
+pub(crate) fn encode_matrix(url: &[u8], ec_level: EcLevel, opts: &QrOptions) -> Result<Code, QrGenError> {
+    if opts.micro {
+        return encode_micro(url, ec_level, opts);
+    }
+    if let Some(mask) = opts.mask.filter(|&mask| mask > MAX_MASK) {
+        return Err(QrGenError::InvalidMask(mask));
+    }
+    let encoded = match (opts.encoding, opts.version, opts.mask) {
+        (_, Some(version), _) if !(1..=MAX_VERSION).contains(&version) => return Err(QrGenError::InvalidVersion(version)),
+        (PayloadEncoding::Auto, None, None) => QrCode::with_error_correction_level(url, ec_level.into()).map(Code::from),
+        (PayloadEncoding::Auto, Some(version), None) => {
+            QrCode::with_version(url, Version::Normal(version as i16), ec_level.into()).map(Code::from)
+        }
+        (encoding, version, mask) => {
+            // Take the first version the payload fits, as `QrCode` does
+            let versions = version.map_or(1..=MAX_VERSION, |version| version..=version);
+            versions
+                .map(|version| {
+                    let mut bits = payload_bits(url, version, encoding)?;
+                    bits.push_terminator(ec_level.into())?;
+                    Code::from_data(&bits.into_bytes(), version, ec_level, mask)
+                })
+                .find(|encoded| encoded.is_ok())
+                .unwrap_or(Err(QrError::DataTooLong))
+        }
+    };
+
+    encoded.map_err(|e| match e {
+        QrError::DataTooLong => match check_fits(url, opts.version.unwrap_or(MAX_VERSION), ec_level, opts.encoding) {
+            Err(e) => e,
+            Ok(()) => QrGenError::QrEncode(QrError::DataTooLong),
+        },
+        e => QrGenError::QrEncode(e),
+    })
+}
+
+/// Encodes `url` in the smallest Micro QR code, M1 to M4, that holds it at
+/// `ec_level`.
+pub(crate) fn encode_micro(url: &[u8], ec_level: EcLevel, opts: &QrOptions) -> Result<Code, QrGenError> {
+    if opts.icon.is_some() {
+        return Err(QrGenError::MicroUnsupported("an icon"));
+    }
+    if opts.verify {
+        return Err(QrGenError::MicroUnsupported("--verify, since the decoder only reads standard codes"));
+    }
+    if ec_level == EcLevel::H {
+        return Err(QrGenError::MicroUnsupported("error correction level H"));
+    }
+    // M1 only takes digits and M2 and M3 lack level Q, which `QrCode` rejects
+    (1..=MAX_MICRO_VERSION)
+        .find_map(|version| QrCode::with_version(url, Version::Micro(version), ec_level.into()).ok())
+        .map(Code::from)
+        .ok_or(QrGenError::TooLongForMicro { length: url.len(), ec_level })
+}
+
+/// Rasterizes `code` onto a canvas described by `layout`.
+///
+/// The background is filled with [`QrOptions::background_pixel`], at
+/// whatever opacity that has; dark modules are always opaque. With `opts.eye_style`
+/// set, each finder pattern is drawn as a whole rather than module by module.
+pub(crate) fn render_raster(code: &Code, layout: &Layout, opts: &QrOptions) -> RgbaImage {
+    // Fill with the background color, which also pads out any leftover pixels
+    let background = opts.background_pixel();
+    let canvas = RgbaImage::from_pixel(layout.canvas_size, layout.canvas_size, background);
+    render_raster_onto(canvas, code, layout, opts)
+}
+
+/// Rasterizes `code` over `canvas`, which must be `layout.canvas_size`
+/// square, such as a background image.
+///
+/// A stretched canvas is shrunk to the size the modules are drawn at, then
+/// scaled back up by nearest neighbour once they are, so every module stays
+/// sharp and is within a pixel of the size of the others.
+pub(crate) fn render_raster_onto(canvas: RgbaImage, code: &Code, layout: &Layout, opts: &QrOptions) -> RgbaImage {
+    match layout.unstretched() {
+        Some(drawn) => {
+            let canvas = imageops::resize(&canvas, drawn.canvas_size, drawn.canvas_size, FilterType::Nearest);
+            let qr_image = draw_code(canvas, code, &drawn, opts);
+            imageops::resize(&qr_image, layout.canvas_size, layout.canvas_size, FilterType::Nearest)
+        }
+        None => draw_code(canvas, code, layout, opts),
+    }
+}
+
+/// Draws the quiet zone color and modules of `code` over `canvas`, which must
+/// be `layout.canvas_size` square.
+fn draw_code(canvas: RgbaImage, code: &Code, layout: &Layout, opts: &QrOptions) -> RgbaImage {
+    let module_size = layout.module_size;
+    let mut qr_image = canvas;
+    if let Some(margin_color) = opts.margin_color {
+        fill_margin(&mut qr_image, layout, margin_color);
+    }
+
+    // Draw QR code modules to fill the entire image
+    for y in 0..code.width() {
+        for x in 0..code.width() {
+            if code[(x, y)] == qrcode::Color::Dark {
+                let in_eye = eye::styled(opts) && eye::is_finder_module(x as u32, y as u32, layout.qr_width);
+                if in_eye && opts.eye_style.is_some() {
+                    continue;
+                }
+
+                // Draw a dark module
+                let start_x = layout.offset + (x as u32) * module_size;
+                let start_y = layout.offset + (y as u32) * module_size;
+                let in_timing = || eye::is_timing_module(x as u32, y as u32, layout.qr_width);
+                let color = match (opts.eye_color, opts.timing_color) {
+                    (Some(eye_color), _) if in_eye => eye_color,
+                    (_, Some(timing_color)) if in_timing() => timing_color,
+                    _ => module_color(opts, layout, start_x, start_y),
+                };
+                draw_module(&mut qr_image, start_x, start_y, module_size, module_shape(x, y, layout, opts), color, opts);
+            }
+        }
+    }
+
+    if let Some(style) = opts.eye_style {
+        for (x, y) in eye::finder_origins(layout.qr_width) {
+            let left = layout.offset + x * module_size;
+            let top = layout.offset + y * module_size;
+            let color = eye::eye_color(opts, layout, left, top);
+            draw_eye(&mut qr_image, left, top, module_size, color, style, opts);
+        }
+    }
+
+    qr_image
+}
+
+/// Fills the quiet zone of `canvas`, everything outside the matrix, with
+/// `color`.
+fn fill_margin(canvas: &mut RgbaImage, layout: &Layout, color: Rgb<u8>) {
+    let [r, g, b] = color.0;
+    let color = Rgba([r, g, b, 255]);
+    let (start, end, size) = (layout.offset, layout.offset + layout.matrix_size(), layout.canvas_size);
+    fill_rect(canvas, 0, 0, size, start, color);
+    fill_rect(canvas, 0, end, size, size - end, color);
+    fill_rect(canvas, 0, start, start, end - start, color);
+    fill_rect(canvas, end, start, size - end, end - start, color);
+}
+
+/// Color of the dark module whose cell starts at `(start_x, start_y)`, taken
+/// from the gradient at the module's center when one is set.
+pub(crate) fn module_color(opts: &QrOptions, layout: &Layout, start_x: u32, start_y: u32) -> Rgb<u8> {
+    match opts.gradient {
+        Some(gradient) => {
+            let half = layout.module_size as f64 / 2.0;
+            let t = gradient.position(
+                start_x as f64 + half,
+                start_y as f64 + half,
+                layout.canvas_size as f64,
+            );
+            gradient.color_at(t)
+        }
+        None => opts.foreground,
+    }
+}
+
+/// The dark shape drawn in one module's cell, in pixels from the cell's
+/// top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ModuleShape {
+    /// Distance from the cell's top and left edges.
+    pub inset: f64,
+    pub size: f64,
+    pub radius: f64,
+}
+
+/// The shape of the dark module at column `x`, row `y`: shaped according to
+/// `opts.module_style` and shrunk toward its center by `opts.module_gap`.
+///
+/// Finder pattern modules keep the whole cell, so the patterns stay solid
+/// enough for scanners to find however wide the gaps elsewhere.
+pub(crate) fn module_shape(x: usize, y: usize, layout: &Layout, opts: &QrOptions) -> ModuleShape {
+    let cell = layout.module_size as f64;
+    let gap = if eye::is_finder_module(x as u32, y as u32, layout.qr_width) {
+        0.0
+    } else {
+        opts.module_gap.clamp(0.0, MAX_MODULE_GAP)
+    };
+    let size = cell * (1.0 - gap);
+    let radius = match opts.module_style {
+        ModuleStyle::Square => 0.0,
+        ModuleStyle::Circle => size / 2.0,
+        ModuleStyle::Rounded => opts.corner_radius.clamp(0.0, 0.5) * size,
+    };
+    ModuleShape { inset: (cell - size) / 2.0, size, radius }
+}
+
+/// Draws one dark module whose cell starts at `(start_x, start_y)` in
+/// `color`, as `shape`.
+fn draw_module(
+    img: &mut RgbaImage,
+    start_x: u32,
+    start_y: u32,
+    module_size: u32,
+    shape: ModuleShape,
+    color: Rgb<u8>,
+    opts: &QrOptions,
+) {
+    let (width, height) = img.dimensions();
+
+    if opts.module_style == ModuleStyle::Square && shape.inset == 0.0 {
+        fill_rect(img, start_x, start_y, module_size, module_size, Rgba([color[0], color[1], color[2], 255]));
+        return;
+    }
+
+    for dy in 0..module_size {
+        for dx in 0..module_size {
+            let px = start_x + dx;
+            let py = start_y + dy;
+            if px >= width || py >= height {
+                continue;
+            }
+
+            let ModuleShape { inset, size, radius } = shape;
+            let distance = rounded_rect_distance(dx as f64 + 0.5, dy as f64 + 0.5, inset, inset, size, size, radius);
+            let coverage = coverage(distance, opts.antialias);
+            if coverage > 0.0 {
+                let blended = blend_over(*img.get_pixel(px, py), color, coverage);
+                img.put_pixel(px, py, blended);
+            }
+        }
+    }
+}
+
+/// Draws the finder pattern whose top-left corner is at `(left, top)` in
+/// `color`, shaped as a whole according to `style`.
+fn draw_eye(
+    img: &mut RgbaImage,
+    left: u32,
+    top: u32,
+    module_size: u32,
+    color: Rgb<u8>,
+    style: EyeStyle,
+    opts: &QrOptions,
+) {
+    let (width, height) = img.dimensions();
+    let [outer, hole, center] = eye::eye_rects(style, module_size, opts.corner_radius);
+    let size = FINDER_SIZE * module_size;
+
+    for dy in 0..size {
+        for dx in 0..size {
+            let px = left + dx;
+            let py = top + dy;
+            if px >= width || py >= height {
+                continue;
+            }
+
+            // Inside the outer square but not the hole, or inside the center
+            let (cx, cy) = (dx as f64 + 0.5, dy as f64 + 0.5);
+            let distance_to =
+                |rect: eye::EyeRect| rounded_rect_distance(cx, cy, rect.inset, rect.inset, rect.size, rect.size, rect.radius);
+            let ring = distance_to(outer).max(-distance_to(hole));
+            let coverage = coverage(ring.min(distance_to(center)), opts.antialias);
+            if coverage > 0.0 {
+                let blended = blend_over(*img.get_pixel(px, py), color, coverage);
+                img.put_pixel(px, py, blended);
+            }
+        }
+    }
+}
+
+/// Signed distance from `(x, y)` to the edge of a `width` x `height`
+/// rectangle whose top-left corner is at `(left, top)` and whose corners are
+/// rounded by `radius`; negative inside.
+pub(crate) fn rounded_rect_distance(x: f64, y: f64, left: f64, top: f64, width: f64, height: f64, radius: f64) -> f64 {
+    let (half_width, half_height) = (width / 2.0, height / 2.0);
+    let qx = (x - left - half_width).abs() - (half_width - radius);
+    let qy = (y - top - half_height).abs() - (half_height - radius);
+    let outside = qx.max(0.0).hypot(qy.max(0.0));
+    outside + qx.max(qy).min(0.0) - radius
+}
+
+/// Fraction of a pixel covered by a shape whose edge is `distance` from the
+/// pixel center, hard-edged unless `antialias` is set.
+pub(crate) fn coverage(distance: f64, antialias: bool) -> f64 {
+    if antialias {
+        (0.5 - distance).clamp(0.0, 1.0)
+    } else if distance <= 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Sets every pixel of the `width` x `height` rectangle at `(x, y)` to
+/// `color`, clipped to the image.
+pub(crate) fn fill_rect(img: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    let (img_width, img_height) = img.dimensions();
+    let x_end = x.saturating_add(width).min(img_width);
+    let y_end = y.saturating_add(height).min(img_height);
+    if x >= x_end || y >= y_end {
+        return;
+    }
+
+    // Write whole row slices rather than going through put_pixel per pixel
+    let stride = img_width as usize * 4;
+    let (start, end) = (x as usize * 4, x_end as usize * 4);
+    let buffer: &mut [u8] = img;
+    for row in buffer.chunks_exact_mut(stride).take(y_end as usize).skip(y as usize) {
+        for pixel in row[start..end].chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gradient, GradientDirection, GradientType, DEFAULT_MAX_DIMENSION};
+    use image::{DynamicImage, RgbImage};
+
+    #[test]
+    fn test_layout_reserves_margin() {
+        let opts = QrOptions { size: 330, margin: 4, ..QrOptions::default() };
+        let layout = Layout::new(25, &opts).unwrap();
+
+        assert_eq!(layout.module_size, 10);
+        assert_eq!(layout.offset, 40);
+        assert_eq!(layout.matrix_size(), 250);
+    }
+
+    #[test]
+    fn test_fixed_version() {
+        let opts = QrOptions { version: Some(10), ..QrOptions::default() };
+        let code = encode_matrix(b"https://example.com", EcLevel::M, &opts).unwrap();
+        assert_eq!(code.width(), 57);
+
+        let url = "a".repeat(200);
+        let opts = QrOptions { version: Some(2), ..QrOptions::default() };
+        assert!(matches!(
+            encode_matrix(url.as_bytes(), EcLevel::H, &opts),
+            Err(QrGenError::PayloadTooLong { length: 200, max: 14, version: 2, ec_level: EcLevel::H, .. })
+        ));
+
+        // Without a fixed version the limit is that of the largest one
+        let url = "a".repeat(3000);
+        assert!(matches!(
+            encode_matrix(url.as_bytes(), EcLevel::L, &QrOptions::default()),
+            Err(QrGenError::PayloadTooLong { length: 3000, max: 2953, version: 40, ec_level: EcLevel::L, .. })
+        ));
+
+        let opts = QrOptions { version: Some(41), ..QrOptions::default() };
+        assert!(matches!(encode_matrix(b"x", EcLevel::M, &opts), Err(QrGenError::InvalidVersion(41))));
+    }
+
+    #[test]
+    fn test_utf8_encoding() {
+        let payload = "https://example.jp/店舗/🍣";
+        let opts = QrOptions::default().with_encoding(PayloadEncoding::Utf8);
+        let code = encode_matrix(payload.as_bytes(), EcLevel::M, &opts).unwrap();
+        let plain = encode_matrix(payload.as_bytes(), EcLevel::M, &QrOptions::default()).unwrap();
+        assert_ne!(code.to_colors(), plain.to_colors());
+
+        let image = crate::generate(payload, &opts).unwrap();
+        assert_eq!(crate::verify::decode_payload(&image).as_deref(), Some(payload));
+
+        // A fixed version is honored, and overflowing it is reported
+        let code = encode_matrix(payload.as_bytes(), EcLevel::M, &QrOptions { version: Some(10), ..opts.clone() }).unwrap();
+        assert_eq!(code.version(), 10);
+        assert!(matches!(
+            encode_matrix(payload.as_bytes(), EcLevel::H, &QrOptions { version: Some(1), ..opts }),
+            Err(QrGenError::PayloadTooLong { version: 1, mode: crate::DataMode::Byte, .. })
+        ));
+    }
+
+    #[test]
+    fn test_fixed_mask() {
+        let payload = "https://example.com/menu";
+        let opts = QrOptions::default().with_mask(3);
+        let png = crate::OutputFormat::Raster(image::ImageFormat::Png);
+        let first = crate::generate_bytes(payload, png, &opts).unwrap();
+        assert_eq!(first, crate::generate_bytes(payload, png, &opts).unwrap());
+
+        // Each pattern is applied as asked and still reads back
+        for mask in 0..=MAX_MASK {
+            let image = crate::generate(payload, &opts.clone().with_mask(mask)).unwrap();
+            let mut prepared = rqrr::PreparedImage::prepare(image.to_luma8());
+            let grids = prepared.detect_grids();
+            let (meta, decoded) = grids[0].decode().unwrap();
+            assert_eq!((meta.mask, decoded.as_str()), (mask as u16, payload));
+        }
+
+        let lowest = encode_matrix(payload.as_bytes(), EcLevel::M, &QrOptions::default()).unwrap();
+        assert!((0..=MAX_MASK).any(|mask| encode_matrix(payload.as_bytes(), EcLevel::M, &opts.clone().with_mask(mask)).unwrap() == lowest));
+        assert!(matches!(encode_matrix(payload.as_bytes(), EcLevel::M, &opts.with_mask(8)), Err(QrGenError::InvalidMask(8))));
+    }
+
+    #[test]
+    fn test_layout_size_limit() {
+        let opts = QrOptions { version: Some(40), size: 1_000_000, ..QrOptions::default() };
+        let error = crate::generate("https://example.com", &opts).unwrap_err();
+        assert!(matches!(error, QrGenError::SizeTooLarge { size: 1_000_000, max: DEFAULT_MAX_DIMENSION }));
+        assert!(error.to_string().contains("--max-dimension"), "{}", error);
+
+        let layout = Layout::new(177, &opts.clone().with_clamp(true)).unwrap();
+        assert_eq!((layout.canvas_size, layout.module_size), (DEFAULT_MAX_DIMENSION, 44));
+        assert!(Layout::new(177, &opts.with_max_dimension(1_000_000)).is_ok());
+    }
+
+    #[test]
+    fn test_stretch() {
+        // 33 modules of 12 pixels leave 5 of 401 over, which stretching spreads across the modules
+        let opts = QrOptions { size: 401, ..QrOptions::default() }.with_stretch(true);
+        let image = crate::generate("https://example.com", &opts.clone().with_verify(true)).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (401, 401));
+
+        let row = image.height() / 8;
+        let dark = |x: &u32| image[(*x, row)] == Rgb([0, 0, 0]);
+        let (left, right) = ((0..401).find(dark).unwrap(), (0..401).rev().find(dark).unwrap());
+        assert_eq!((left, 400 - right), (49, 49));
+        let layout = Layout::new(25, &opts).unwrap();
+        assert_eq!((layout.offset, layout.module_size, layout.drawn_size), (50, 12, 396));
+        assert_eq!(layout.unstretched().map(|drawn| (drawn.offset, drawn.canvas_size)), Some((48, 396)));
+
+        // Without leftover pixels there is nothing to stretch
+        let opts = opts.with_size(396);
+        assert_eq!(Layout::new(25, &opts).unwrap().unstretched(), None);
+    }
+
+    #[test]
+    fn test_layout_too_small_for_margin() {
+        let opts = QrOptions { size: 30, margin: 4, ..QrOptions::default() };
+        assert!(matches!(Layout::new(25, &opts), Err(QrGenError::SizeTooSmall { min: 33, .. })));
+    }
+
+    #[test]
+    fn test_fill_rect_matches_put_pixel() {
+        let color = Rgba([10, 20, 30, 255]);
+        for &(x, y, w, h) in &[(0, 0, 5, 5), (3, 4, 10, 2), (15, 15, 10, 10), (25, 0, 3, 3), (2, 2, 0, 4)] {
+            let mut fast = RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+            fill_rect(&mut fast, x, y, w, h, color);
+
+            let mut slow = RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+            for py in y..(y + h).min(20) {
+                for px in x..(x + w).min(20) {
+                    slow.put_pixel(px, py, color);
+                }
+            }
+            assert_eq!(fast, slow, "rect at ({}, {}) sized {}x{}", x, y, w, h);
+        }
+    }
+
+    fn single_module(style: ModuleStyle, antialias: bool) -> RgbImage {
+        gapped_module(style, antialias, 0.0)
+    }
+
+    /// A data module of a 20 pixel cell, drawn by itself.
+    fn gapped_module(style: ModuleStyle, antialias: bool, module_gap: f64) -> RgbImage {
+        let opts = QrOptions { module_style: style, antialias, module_gap, size: 20 * 29, ..QrOptions::default() };
+        let layout = Layout::new(21, &opts).unwrap();
+        let mut img = RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+        draw_module(&mut img, 0, 0, 20, module_shape(10, 10, &layout, &opts), opts.foreground, &opts);
+        DynamicImage::ImageRgba8(img).to_rgb8()
+    }
+
+    #[test]
+    fn test_circle_module() {
+        let img = single_module(ModuleStyle::Circle, false);
+        assert_eq!(*img.get_pixel(10, 10), Rgb([0, 0, 0]));
+        assert_eq!(*img.get_pixel(10, 0), Rgb([0, 0, 0]));
+        assert_eq!(*img.get_pixel(0, 0), Rgb([255, 255, 255]));
+        assert_eq!(*img.get_pixel(19, 19), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_rounded_module() {
+        let img = single_module(ModuleStyle::Rounded, false);
+        // Default radius is a quarter of the module, so the very corner is cut
+        assert_eq!(*img.get_pixel(0, 0), Rgb([255, 255, 255]));
+        assert_eq!(*img.get_pixel(5, 0), Rgb([0, 0, 0]));
+        assert_eq!(*img.get_pixel(0, 10), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_antialias_only_blends_edges() {
+        let img = single_module(ModuleStyle::Circle, true);
+        assert_eq!(*img.get_pixel(10, 10), Rgb([0, 0, 0]));
+        assert_eq!(*img.get_pixel(0, 0), Rgb([255, 255, 255]));
+        assert!(img.pixels().any(|p| p[0] > 0 && p[0] < 255));
+
+        let hard = single_module(ModuleStyle::Circle, false);
+        assert!(hard.pixels().all(|p| p[0] == 0 || p[0] == 255));
+    }
+
+    #[test]
+    fn test_module_gap() {
+        // A gap of 0.2 leaves two pixels of background on each side
+        let img = gapped_module(ModuleStyle::Square, false, 0.2);
+        assert_eq!(*img.get_pixel(1, 10), Rgb([255, 255, 255]));
+        assert_eq!(*img.get_pixel(2, 10), Rgb([0, 0, 0]));
+        assert_eq!(*img.get_pixel(17, 17), Rgb([0, 0, 0]));
+        assert_eq!(*img.get_pixel(18, 18), Rgb([255, 255, 255]));
+        let img = gapped_module(ModuleStyle::Circle, false, 0.2);
+        assert_eq!(*img.get_pixel(2, 10), Rgb([0, 0, 0]));
+        assert_eq!(*img.get_pixel(3, 3), Rgb([255, 255, 255]));
+
+        // The finder patterns stay solid, and the dotted code still reads back
+        let opts = QrOptions { margin: 0, ..QrOptions::default() }.with_module_gap(MAX_MODULE_GAP);
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        assert_eq!(module_shape(0, 0, &layout, &opts).inset, 0.0);
+        assert!(module_shape(8, 8, &layout, &opts).inset > 0.0);
+        let img = DynamicImage::ImageRgba8(render_raster(&code, &layout, &opts));
+        assert_eq!(img.to_rgb8().get_pixel(layout.module_size, 0).0, [0, 0, 0]);
+        assert_eq!(crate::verify::decode_payload(&img).as_deref(), Some("https://example.com"));
+    }
+
+    #[test]
+    fn test_gradient_modules() {
+        let red = Rgb([200, 0, 0]);
+        let blue = Rgb([0, 0, 200]);
+        let opts = QrOptions {
+            gradient: Some(Gradient { start: red, end: blue, direction: GradientDirection::Horizontal, kind: GradientType::Linear }),
+            margin: 0,
+            ..QrOptions::default()
+        };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let img = render_raster(&code, &layout, &opts);
+
+        // The finder patterns sit at the left and right edges
+        let left = *img.get_pixel(0, 0);
+        let right = *img.get_pixel(img.width() - 1, 0);
+        assert!(left[0] > left[2]);
+        assert!(right[2] > right[0]);
+        // Every pixel in a module shares that module's color
+        assert_eq!(*img.get_pixel(layout.module_size - 1, 0), left);
+    }
+
+    #[test]
+    fn test_radial_gradient_modules() {
+        let red = Rgb([200, 0, 0]);
+        let blue = Rgb([0, 0, 200]);
+        let gradient = Gradient { start: red, end: blue, direction: GradientDirection::default(), kind: GradientType::Radial };
+        let opts = QrOptions { gradient: Some(gradient), margin: 0, size: 200, ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let m = layout.module_size;
+        let (x, y) = (code.width() / 2, code.width() / 2);
+        assert_eq!(code[(x, y)], qrcode::Color::Dark, "the test needs a dark center module");
+
+        // The center module takes the start color, the corner modules
+        // nearly the end color, short only by half a module from the corner
+        let img = render_raster(&code, &layout, &opts);
+        assert_eq!(module_color(&opts, &layout, x as u32 * m, y as u32 * m), red);
+        assert_eq!(img.get_pixel(x as u32 * m, y as u32 * m).0, [200, 0, 0, 255]);
+        let corner = module_color(&opts, &layout, 0, 0);
+        assert!(corner[2] > 180 && corner[0] < 20, "{:?}", corner);
+        assert_eq!(img.get_pixel(0, 0).0[..3], corner.0);
+        assert_eq!(gradient.color_at(gradient.position(0.0, 0.0, layout.canvas_size as f64)), blue);
+    }
+
+    #[test]
+    fn test_eye_color_and_style() {
+        let red = Rgb([200, 0, 0]);
+        let opts = QrOptions { margin: 0, size: 250, ..QrOptions::default() }.with_eye_color(red);
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let m = layout.module_size;
+        let pixel = |img: &RgbaImage, x: u32, y: u32| {
+            let p = img.get_pixel(layout.offset + x * m + m / 2, layout.offset + y * m + m / 2);
+            Rgb([p[0], p[1], p[2]])
+        };
+
+        let img = render_raster(&code, &layout, &opts);
+        let last = layout.qr_width - 1;
+        // Outer ring and center of each eye
+        for (x, y) in [(0, 0), (6, 6), (3, 3), (last, 0), (last - 3, 3), (0, last), (3, last - 3)] {
+            assert_eq!(pixel(&img, x, y), red, "eye module ({}, {})", x, y);
+        }
+        // The light ring inside each eye and the separator around it
+        assert_eq!(pixel(&img, 1, 1), Rgb([255, 255, 255]));
+        assert_eq!(pixel(&img, 7, 0), Rgb([255, 255, 255]));
+        // Every other dark module, timing patterns included, keeps the module color
+        for y in 0..layout.qr_width {
+            for x in 0..layout.qr_width {
+                if code[(x as usize, y as usize)] == qrcode::Color::Dark && !eye::is_finder_module(x, y, layout.qr_width) {
+                    assert_eq!(pixel(&img, x, y), opts.foreground, "data module ({}, {})", x, y);
+                }
+            }
+        }
+
+        // Circular eyes keep their colors but round off the outer corner
+        let circles = opts.clone().with_eye_style(EyeStyle::Circle);
+        let img = render_raster(&code, &layout, &circles);
+        assert_eq!(pixel(&img, 3, 3), red);
+        assert_eq!(pixel(&img, 3, 0), red);
+        assert_eq!(pixel(&img, 3, 1), Rgb([255, 255, 255]));
+        let corner = img.get_pixel(layout.offset, layout.offset);
+        assert_eq!(Rgb([corner[0], corner[1], corner[2]]), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_timing_color() {
+        let (red, blue) = (Rgb([200, 0, 0]), Rgb([0, 0, 160]));
+        let opts = QrOptions { margin: 0, size: 250, ..QrOptions::default() }.with_timing_color(blue).with_eye_color(red);
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let m = layout.module_size;
+        let img = render_raster(&code, &layout, &opts);
+        let pixel = |x: u32, y: u32| {
+            let p = img.get_pixel(layout.offset + x * m + m / 2, layout.offset + y * m + m / 2);
+            Rgb([p[0], p[1], p[2]])
+        };
+
+        // The timing lines alternate between the timing color and the
+        // background, starting and ending dark, and stop short of the eyes
+        let last = layout.qr_width - 1;
+        for i in 8..=last - 8 {
+            let expected = if i % 2 == 0 { blue } else { Rgb([255, 255, 255]) };
+            assert_eq!((pixel(i, 6), pixel(6, i)), (expected, expected), "timing module {}", i);
+        }
+        assert_eq!((pixel(6, 6), pixel(last, 6), pixel(6, last)), (red, red, red));
+        assert!(!eye::is_timing_module(7, 6, layout.qr_width) && !eye::is_timing_module(6, last - 7, layout.qr_width));
+        // Other dark modules keep the module color
+        for y in 0..layout.qr_width {
+            for x in 0..layout.qr_width {
+                let other = !eye::is_finder_module(x, y, layout.qr_width) && !eye::is_timing_module(x, y, layout.qr_width);
+                if other && code[(x as usize, y as usize)] == qrcode::Color::Dark {
+                    assert_eq!(pixel(x, y), opts.foreground, "data module ({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        let opts = QrOptions { transparent: true, ..QrOptions::default() };
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let img = render_raster(&code, &layout, &opts);
+
+        // The quiet zone is transparent and the finder pattern opaque
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        assert_eq!(*img.get_pixel(layout.offset, layout.offset), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_margin_color() {
+        let (card, cream) = (Rgb([0x10, 0x60, 0xa0]), Rgb([0xff, 0xf8, 0xe7]));
+        let opts = QrOptions { background: cream, ..QrOptions::default() }.with_margin_color(card);
+        let code = Code::from(QrCode::new("https://example.com").unwrap());
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let img = render_raster(&code, &layout, &opts);
+        let rgb = |x: u32, y: u32| {
+            let p = img.get_pixel(x, y);
+            Rgb([p[0], p[1], p[2]])
+        };
+
+        // Every pixel outside the matrix is the margin color, up to its edge
+        let (start, end) = (layout.offset, layout.offset + layout.matrix_size());
+        for (x, y) in [(0, 0), (start - 1, start), (end, end - 1), (layout.canvas_size - 1, 0), (start, end)] {
+            assert_eq!(rgb(x, y), card, "({}, {})", x, y);
+        }
+        // Light modules inside the matrix keep the background
+        let m = layout.module_size;
+        let (x, y) = (start + 7 * m + m / 2, start + m / 2);
+        assert_eq!(code[(7, 0)], qrcode::Color::Light);
+        assert_eq!(rgb(x, y), cream);
+    }
+}
@@ -0,0 +1,113 @@
+use image::{Rgb, RgbaImage};
+
+use crate::color::blend_over;
+use crate::IconShadow;
+
+/// Coverage of a shape, from `0.0` (outside) to `1.0` (inside), per pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Mask {
+    width: u32,
+    height: u32,
+    /// Row-major coverage values.
+    values: Vec<f32>,
+}
+
+impl Mask {
+    /// Builds a `width` x `height` mask with the coverage `f(x, y)` returns.
+    pub fn from_fn(width: u32, height: u32, mut f: impl FnMut(u32, u32) -> f32) -> Mask {
+        let values = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| f(x, y)).collect();
+        Mask { width, height, values }
+    }
+
+    fn get(&self, x: u32, y: u32) -> f32 {
+        self.values[(y * self.width + x) as usize]
+    }
+
+    /// Blurs the mask with a Gaussian of standard deviation `sigma`, growing
+    /// it by `padding` pixels on every side so nothing is clipped.
+    fn blurred(&self, sigma: f64, padding: u32) -> Mask {
+        let width = self.width + 2 * padding;
+        let height = self.height + 2 * padding;
+        let padded = Mask::from_fn(width, height, |x, y| {
+            let inside = x >= padding && y >= padding && x < self.width + padding && y < self.height + padding;
+            if inside { self.get(x - padding, y - padding) } else { 0.0 }
+        });
+        if sigma <= 0.0 {
+            return padded;
+        }
+
+        // The Gaussian is separable, so blur the rows and then the columns
+        let kernel = gaussian_kernel(sigma);
+        let radius = (kernel.len() / 2) as i64;
+        let sample = |mask: &Mask, x: i64, y: i64| {
+            if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 { 0.0 } else { mask.get(x as u32, y as u32) }
+        };
+        let horizontal = Mask::from_fn(width, height, |x, y| {
+            kernel.iter().enumerate().map(|(i, w)| w * sample(&padded, x as i64 + i as i64 - radius, y as i64)).sum()
+        });
+        Mask::from_fn(width, height, |x, y| {
+            kernel.iter().enumerate().map(|(i, w)| w * sample(&horizontal, x as i64, y as i64 + i as i64 - radius)).sum()
+        })
+    }
+}
+
+/// Normalized weights of a Gaussian with standard deviation `sigma`,
+/// truncated at three deviations on each side.
+fn gaussian_kernel(sigma: f64) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<f64> = (-radius..=radius).map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f64 = weights.iter().sum();
+    weights.iter().map(|w| (w / total) as f32).collect()
+}
+
+/// Darkens `canvas` beneath the shape in `mask`, whose top-left corner sits
+/// at `(x, y)`, shifted and blurred as described by `shadow`.
+pub(crate) fn draw_shadow(canvas: &mut RgbaImage, mask: &Mask, x: u32, y: u32, shadow: IconShadow) {
+    let blur = shadow.blur.max(0.0);
+    let padding = (3.0 * blur).ceil() as u32;
+    let blurred = mask.blurred(blur, padding);
+    let left = (x + shadow.offset) as i64 - padding as i64;
+    let top = (y + shadow.offset) as i64 - padding as i64;
+    let opacity = shadow.opacity.clamp(0.0, 1.0);
+
+    for my in 0..blurred.height {
+        for mx in 0..blurred.width {
+            let (px, py) = (left + mx as i64, top + my as i64);
+            if px < 0 || py < 0 || px >= canvas.width() as i64 || py >= canvas.height() as i64 {
+                continue;
+            }
+            let alpha = blurred.get(mx, my) as f64 * opacity;
+            if alpha > 0.0 {
+                let (px, py) = (px as u32, py as u32);
+                let shaded = blend_over(*canvas.get_pixel(px, py), Rgb([0, 0, 0]), alpha);
+                canvas.put_pixel(px, py, shaded);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gaussian_kernel_is_normalized() {
+        let kernel = gaussian_kernel(2.0);
+        assert_eq!(kernel.len(), 13);
+        assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(kernel[6] > kernel[5] && kernel[5] > kernel[0]);
+    }
+
+    #[test]
+    fn test_blur_spreads_coverage_without_losing_it() {
+        let mask = Mask::from_fn(10, 10, |_, _| 1.0);
+        let blurred = mask.blurred(2.0, 6);
+        assert_eq!((blurred.width, blurred.height), (22, 22));
+
+        let total: f32 = blurred.values.iter().sum();
+        assert!((total - 100.0).abs() < 0.01);
+        assert!(blurred.get(11, 11) > 0.95);
+        assert!(blurred.get(6, 11) > 0.3 && blurred.get(6, 11) < 0.7);
+        assert!(blurred.get(0, 11) < 0.01);
+    }
+}
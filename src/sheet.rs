@@ -0,0 +1,203 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use image::{DynamicImage, RgbaImage};
+use rayon::prelude::*;
+
+use crate::{generate, EcLevel, QrGenError, QrOptions};
+
+/// Error correction levels an EC comparison sheet shows, weakest first.
+const COMPARED_EC_LEVELS: [EcLevel; 4] = [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H];
+
+/// One code on a contact sheet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SheetEntry {
+    pub payload: String,
+    /// Text set below the code, or `None` to fall back to `opts.caption`.
+    pub label: Option<String>,
+}
+
+/// Reads contact sheet entries from `text`, one per line: the payload,
+/// optionally followed by a tab and the label to set below it. Blank lines
+/// are skipped.
+pub fn parse_sheet_entries(text: &str) -> Vec<SheetEntry> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match line.split_once('\t') {
+            Some((payload, label)) => SheetEntry {
+                payload: payload.trim().to_string(),
+                label: Some(label.trim().to_string()).filter(|label| !label.is_empty()),
+            },
+            None => SheetEntry { payload: line.trim().to_string(), label: None },
+        })
+        .collect()
+}
+
+/// Generates a code for each of `entries` with `opts`, as [`generate`] does,
+/// and tiles them left to right and top to bottom in a grid `columns` wide.
+///
+/// Each entry's label becomes its caption. Every cell is as large as the
+/// largest code, with smaller codes centered at its top, and `spacing` pixels
+/// of `opts.background` separate the cells and surround the grid so the
+/// sheet cuts apart evenly. A `columns` of 0 is taken as 1.
+pub fn contact_sheet(entries: &[SheetEntry], columns: u32, spacing: u32, opts: &QrOptions) -> Result<DynamicImage, QrGenError> {
+    contact_sheet_with_progress(entries, columns, spacing, opts, |_, _| {})
+}
+
+/// Builds a contact sheet as [`contact_sheet`] does, calling `progress` with
+/// the number of codes generated so far and the number of entries after
+/// each one is done.
+///
+/// `progress` is called from the worker threads, in whatever order the codes
+/// finish, but the counts it is given are each handed out once.
+pub fn contact_sheet_with_progress(
+    entries: &[SheetEntry],
+    columns: u32,
+    spacing: u32,
+    opts: &QrOptions,
+    progress: impl Fn(usize, usize) + Sync,
+) -> Result<DynamicImage, QrGenError> {
+    Ok(tile(&labelled_codes(entries, opts, &progress)?, columns, spacing, opts))
+}
+
+/// Generates `url` at each error correction level from L to H with `opts`,
+/// icon included, and lays the four codes out in a row as [`contact_sheet`]
+/// does, each captioned with its level.
+///
+/// A stronger level means a denser code that survives more of it being
+/// hidden, so the sheet shows how large an icon each level can carry.
+pub fn ec_comparison_sheet(url: impl AsRef<[u8]>, spacing: u32, opts: &QrOptions) -> Result<DynamicImage, QrGenError> {
+    let url = url.as_ref();
+    let cells = COMPARED_EC_LEVELS
+        .par_iter()
+        .map(|&ec_level| {
+            let opts = opts.clone().with_ec_level(ec_level).with_caption(format!("EC {}", ec_level));
+            generate(url, &opts).map(|image| image.to_rgba8())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(tile(&cells, COMPARED_EC_LEVELS.len() as u32, spacing, opts))
+}
+
+/// Tiles `cells` left to right and top to bottom in a grid `columns` wide,
+/// as laid out by [`contact_sheet`].
+fn tile(cells: &[RgbaImage], columns: u32, spacing: u32, opts: &QrOptions) -> DynamicImage {
+    let columns = columns.max(1);
+    let rows = (cells.len() as u32).div_ceil(columns);
+    let cell_width = cells.iter().map(RgbaImage::width).max().unwrap_or(0);
+    let cell_height = cells.iter().map(RgbaImage::height).max().unwrap_or(0);
+    let background = opts.background_pixel();
+    let mut sheet = RgbaImage::from_pixel(
+        columns * cell_width + (columns + 1) * spacing,
+        rows * cell_height + (rows + 1) * spacing,
+        background,
+    );
+
+    for (i, cell) in cells.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let x = spacing + column * (cell_width + spacing) + (cell_width - cell.width()) / 2;
+        let y = spacing + row * (cell_height + spacing);
+        image::imageops::overlay(&mut sheet, cell, x as i64, y as i64);
+    }
+
+    // Only keep the alpha channel when it carries information
+    let sheet = DynamicImage::ImageRgba8(sheet);
+    if opts.background_pixel()[3] < 255 { sheet } else { DynamicImage::ImageRgb8(sheet.to_rgb8()) }
+}
+
+/// Generates a code for each of `entries` in parallel, with its label as the
+/// caption, reporting each finished code to `progress`.
+pub(crate) fn labelled_codes(
+    entries: &[SheetEntry],
+    opts: &QrOptions,
+    progress: &(dyn Fn(usize, usize) + Sync),
+) -> Result<Vec<RgbaImage>, QrGenError> {
+    let done = AtomicUsize::new(0);
+    entries
+        .par_iter()
+        .map(|entry| {
+            let opts = match &entry.label {
+                Some(label) => opts.clone().with_caption(label.as_str()),
+                None => opts.clone(),
+            };
+            let code = generate(&entry.payload, &opts).map(|image| image.to_rgba8());
+            progress(done.fetch_add(1, Ordering::Relaxed) + 1, entries.len());
+            code
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::DEFAULT_SIZE;
+    use crate::verify::decode_payload;
+
+    #[test]
+    fn test_parse_sheet_entries() {
+        let entries = parse_sheet_entries("https://example.com/a\tTable 1\n\nhttps://example.com/b\n");
+        assert_eq!(
+            entries,
+            [
+                SheetEntry { payload: "https://example.com/a".to_string(), label: Some("Table 1".to_string()) },
+                SheetEntry { payload: "https://example.com/b".to_string(), label: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_contact_sheet_grid() {
+        let entries = parse_sheet_entries("https://example.com/a\nhttps://example.com/b\nhttps://example.com/c\n");
+        let sheet = contact_sheet(&entries, 2, 20, &QrOptions::default()).unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (2 * DEFAULT_SIZE + 3 * 20, 2 * DEFAULT_SIZE + 3 * 20));
+
+        // Each cell holds its own code, and the spare cell is left blank
+        let pitch = DEFAULT_SIZE + 20;
+        let cell = |column: u32, row: u32| sheet.crop_imm(20 + column * pitch, 20 + row * pitch, DEFAULT_SIZE, DEFAULT_SIZE);
+        assert_eq!(decode_payload(&cell(0, 0)).as_deref(), Some("https://example.com/a"));
+        assert_eq!(decode_payload(&cell(1, 0)).as_deref(), Some("https://example.com/b"));
+        assert_eq!(decode_payload(&cell(0, 1)).as_deref(), Some("https://example.com/c"));
+        assert!(cell(1, 1).to_rgb8().pixels().all(|p| p.0 == [255, 255, 255]));
+    }
+
+    #[test]
+    fn test_labels_pad_unlabelled_cells() {
+        let entries = parse_sheet_entries("https://example.com/a\tFirst\nhttps://example.com/b\n");
+        let opts = QrOptions { size: 200, ..QrOptions::default() };
+        let sheet = contact_sheet(&entries, 2, 0, &opts).unwrap();
+        let labelled = generate("https://example.com/a", &opts.clone().with_caption("First")).unwrap();
+        assert!(labelled.height() > 200);
+        assert_eq!((sheet.width(), sheet.height()), (400, labelled.height()));
+
+        // The unlabelled code sits at the top of its cell
+        let unlabelled = sheet.crop_imm(200, 0, 200, 200);
+        assert_eq!(decode_payload(&unlabelled).as_deref(), Some("https://example.com/b"));
+    }
+
+    #[test]
+    fn test_contact_sheet_progress() {
+        let entries = parse_sheet_entries(&(0..12).map(|i| format!("https://example.com/{}\n", i)).collect::<String>());
+        let reported = std::sync::Mutex::new(Vec::new());
+        let opts = QrOptions { size: 100, ..QrOptions::default() };
+        contact_sheet_with_progress(&entries, 4, 0, &opts, |done, total| reported.lock().unwrap().push((done, total))).unwrap();
+
+        // Every count from 1 to 12 is reported once, whichever thread got it
+        let mut reported = reported.into_inner().unwrap();
+        reported.sort();
+        assert_eq!(reported, (1..=12).map(|done| (done, 12)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ec_comparison_sheet() {
+        let opts = QrOptions { size: 200, ..QrOptions::default() };
+        let sheet = ec_comparison_sheet("https://example.com", 10, &opts).unwrap();
+        let cell = generate("https://example.com", &opts.clone().with_caption("EC L")).unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (4 * 200 + 5 * 10, cell.height() + 2 * 10));
+
+        // Four cells, each holding the payload at its own level
+        for (i, ec_level) in COMPARED_EC_LEVELS.into_iter().enumerate() {
+            let code = sheet.crop_imm(10 + i as u32 * 210, 10, 200, 200);
+            assert_eq!(decode_payload(&code).as_deref(), Some("https://example.com"));
+            let expected = generate("https://example.com", &opts.clone().with_ec_level(ec_level)).unwrap();
+            assert_eq!(code.to_rgb8(), expected.to_rgb8(), "{}", ec_level);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use qrcode::bits::Bits;
+use qrcode::types::{QrError, QrResult, Version};
+
+use crate::capacity::payload_bits;
+use crate::code::Code;
+use crate::options::{MAX_MASK, MAX_VERSION};
+use crate::{EcLevel, PayloadEncoding, QrGenError, QrOptions};
+
+/// Most codes a Structured Append sequence can link.
+pub const MAX_SPLIT_PARTS: usize = 16;
+
+/// Bits taken by a Structured Append header: the mode indicator, the part's
+/// position, the part count and the parity byte.
+const HEADER_BITS: usize = 20;
+
+/// Mode indicator of a Structured Append header.
+const STRUCTURED_APPEND_MODE: u32 = 0b0011;
+
+/// Codewords that fill the unused data capacity, alternately.
+const PAD_CODEWORDS: [u8; 2] = [0xec, 0x11];
+
+/// Where one code sits in a Structured Append sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    /// Position from 0.
+    index: usize,
+    /// Number of codes in the sequence.
+    total: usize,
+    /// XOR of every byte of the whole payload, the same in each part.
+    parity: u8,
+}
+
+/// Splits `payload` over the fewest Structured Append codes at `ec_level`
+/// that hold it, up to [`MAX_SPLIT_PARTS`], and encodes each part at
+/// `opts.version`, or the smallest version that fits it otherwise.
+///
+/// Each part carries its own copy of the `opts.encoding` header, so a
+/// scanner that reassembles the sequence reads the same text.
+pub(crate) fn encode_parts(payload: &[u8], ec_level: EcLevel, opts: &QrOptions) -> Result<Vec<Code>, QrGenError> {
+    let max_version = opts.version.unwrap_or(MAX_VERSION);
+    if !(1..=MAX_VERSION).contains(&max_version) {
+        return Err(QrGenError::InvalidVersion(max_version));
+    }
+    if let Some(mask) = opts.mask.filter(|&mask| mask > MAX_MASK) {
+        return Err(QrGenError::InvalidMask(mask));
+    }
+    let parity = payload.iter().fold(0, |parity, b| parity ^ b);
+
+    for total in 2..=MAX_SPLIT_PARTS {
+        let parts = chunks(payload, total);
+        if parts.iter().any(|part| part.is_empty() || !part_fits(part, max_version, ec_level, opts.encoding)) {
+            continue;
+        }
+
+        let versions = opts.version.map_or(1..=MAX_VERSION, |version| version..=version);
+        return parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| {
+                let header = Header { index, total, parity };
+                versions
+                    .clone()
+                    .map(|version| part_code(Some(header), part, version, ec_level, opts))
+                    .find(|code| code.is_ok())
+                    .unwrap_or(Err(QrError::DataTooLong))
+                    .map_err(QrGenError::QrEncode)
+            })
+            .collect();
+    }
+
+    Err(QrGenError::TooLongToSplit { length: payload.len(), parts: MAX_SPLIT_PARTS, version: max_version, ec_level })
+}
+
+/// Cuts `payload` into `count` runs of about the same number of bytes,
+/// without splitting a UTF-8 character.
+fn chunks(payload: &[u8], count: usize) -> Vec<&[u8]> {
+    let mut parts = Vec::with_capacity(count);
+    let mut rest = payload;
+    for remaining in (1..=count).rev() {
+        let mut end = rest.len().div_ceil(remaining);
+        // Step past UTF-8 continuation bytes, 0b10xxxxxx, to the next character
+        while end < rest.len() && rest[end] & 0xc0 == 0x80 {
+            end += 1;
+        }
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
+    }
+    parts
+}
+
+/// Whether `part` fits in `version` at `ec_level` after a Structured Append
+/// header.
+fn part_fits(part: &[u8], version: u8, ec_level: EcLevel, encoding: PayloadEncoding) -> bool {
+    let max_bits = Bits::new(Version::Normal(version as i16)).max_len(ec_level.into());
+    match (payload_bits(part, version, encoding), max_bits) {
+        (Ok(bits), Ok(max_bits)) => HEADER_BITS + bits.len() <= max_bits,
+        _ => false,
+    }
+}
+
+/// Encodes `part` in a code of `version` with `opts.encoding` and
+/// `opts.mask`, after `header` if there is one.
+fn part_code(header: Option<Header>, part: &[u8], version: u8, ec_level: EcLevel, opts: &QrOptions) -> QrResult<Code> {
+    let bits = payload_bits(part, version, opts.encoding)?;
+    let max_bits = Bits::new(Version::Normal(version as i16)).max_len(ec_level.into())?;
+    Code::from_data(&data_codewords(header, bits, max_bits)?, version, ec_level, opts.mask)
+}
+
+/// Assembles the data codewords of a code that holds `max_bits`: `header`,
+/// then `bits`, then the terminator and padding.
+///
+/// `qrcode` has no way to push a Structured Append header, so the whole bit
+/// stream is written here instead.
+fn data_codewords(header: Option<Header>, bits: Bits, max_bits: usize) -> QrResult<Vec<u8>> {
+    let header_bits = header.map_or(0, |_| HEADER_BITS);
+    if header_bits + bits.len() > max_bits {
+        return Err(QrError::DataTooLong);
+    }
+
+    let mut writer = BitWriter::default();
+    if let Some(header) = header {
+        writer.push(STRUCTURED_APPEND_MODE, 4);
+        writer.push(header.index as u32, 4);
+        writer.push(header.total as u32 - 1, 4);
+        writer.push(header.parity as u32, 8);
+    }
+    let len = bits.len();
+    for (i, byte) in bits.into_bytes().into_iter().enumerate() {
+        let bit_count = len.saturating_sub(i * 8).min(8);
+        writer.push(byte as u32 >> (8 - bit_count), bit_count);
+    }
+
+    // Up to four zero bits end the data, then zeros up to a whole codeword
+    writer.push(0, (max_bits - writer.len).min(4));
+    writer.push(0, (8 - writer.len % 8) % 8);
+    for pad in PAD_CODEWORDS.iter().cycle().take((max_bits - writer.len) / 8) {
+        writer.push(*pad as u32, 8);
+    }
+    Ok(writer.bytes)
+}
+
+/// Packs bits most significant first.
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Number of bits written.
+    len: usize,
+}
+
+impl BitWriter {
+    /// Appends the low `count` bits of `value`.
+    fn push(&mut self, value: u32, count: usize) {
+        for shift in (0..count).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> shift) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{render_raster, Layout};
+    use crate::verify::decode_payload;
+    use image::DynamicImage;
+
+    #[test]
+    fn test_chunks_cover_payload() {
+        let payload = "héllo wörld, ünïcode";
+        for count in 2..=5 {
+            let parts = chunks(payload.as_bytes(), count);
+            assert_eq!(parts.len(), count);
+            assert_eq!(parts.concat(), payload.as_bytes());
+        }
+        assert_eq!(chunks(b"abcdefg", 3), [&b"abc"[..], b"de", b"fg"]);
+    }
+
+    #[test]
+    fn test_structured_append_header() {
+        let header = Header { index: 1, total: 3, parity: 0x5a };
+        let bits = payload_bits(b"a", 1, PayloadEncoding::Auto).unwrap();
+        let data = data_codewords(Some(header), bits, 19 * 8).unwrap();
+        // 0011 0001 0010 01011010, then byte mode 0100, a length of 1 and 'a'
+        assert_eq!(&data[..5], &[0x31, 0x25, 0xa4, 0x01, 0x61]);
+        assert_eq!(data.len(), 19);
+        // The terminator rounds up to a whole codeword before the padding
+        assert_eq!(&data[5..8], &[0x00, 0xec, 0x11]);
+    }
+
+    #[test]
+    fn test_assembled_codewords_decode() {
+        // Without a header the hand-assembled stream is a plain code, which
+        // the decoder can read back
+        let opts = QrOptions::default();
+        let code = part_code(None, b"https://example.com/split", 3, EcLevel::M, &opts).unwrap();
+        let layout = Layout::new(code.width() as u32, &opts).unwrap();
+        let image = DynamicImage::ImageRgba8(render_raster(&code, &layout, &opts));
+        assert_eq!(decode_payload(&image).as_deref(), Some("https://example.com/split"));
+    }
+
+    #[test]
+    fn test_encode_parts() {
+        let opts = QrOptions::default();
+        let payload = "x".repeat(3000);
+        let parts = encode_parts(payload.as_bytes(), EcLevel::H, &opts).unwrap();
+        // 1273 bytes fit in a version 40 code at H, less the headers
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| part.version() == parts[0].version()));
+
+        let opts = opts.with_version(10);
+        assert!(matches!(
+            encode_parts(payload.as_bytes(), EcLevel::H, &opts),
+            Err(QrGenError::TooLongToSplit { length: 3000, parts: 16, version: 10, ec_level: EcLevel::H })
+        ));
+    }
+}
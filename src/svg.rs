@@ -0,0 +1,466 @@
+use std::f64::consts::SQRT_2;
+use std::fmt::Write;
+use std::io::Cursor;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use image::{DynamicImage, ImageFormat, Rgb, RgbaImage};
+use crate::caption::{self, caption_band_height, CaptionLayout};
+use crate::code::Code;
+use crate::color::to_hex;
+use crate::eye;
+use crate::frame::FrameLayout;
+use crate::icon::{halo_color, IconArea, IconPlacement};
+use crate::output::GENERATOR;
+use crate::render::{module_shape, Layout};
+use crate::{EyeStyle, GradientDirection, GradientType, IconShape, ModuleStyle, QrGenError, QrOptions, Rotation};
+
+/// Namespace of the elements in the `<metadata>` block of SVG output:
+/// `<qr:generator>` with this tool and its version, and `<qr:payload>` with
+/// the encoded text.
+pub const SVG_METADATA_NAMESPACE: &str = "urn:qr-generator";
+
+/// Renders `code` as an SVG document, with `icon` and `backdrop` embedded as
+/// base64 PNGs. An `alt` text becomes the document's `<title>` and the
+/// `aria-label` of its `role="img"` root, for screen readers. Unless
+/// `opts.metadata` is off, a `<metadata>` block in [`SVG_METADATA_NAMESPACE`]
+/// names this tool and holds `payload`, if given.
+///
+/// With square modules, each row of dark modules is merged into horizontal
+/// runs within a single `<path>` to keep the output small. Other module styles
+/// emit one shape per module. Eyes with their own color or style are drawn
+/// after the data modules. A frame's border and banner are drawn as one path
+/// around the code, and its text and any caption are set as `<text>` in a
+/// sans-serif font, sized and placed as in raster output. A card radius
+/// clips the whole document to a rounded rectangle, or with a card color
+/// rounds the card drawn behind the code.
+pub(crate) fn render_svg(
+    code: &Code,
+    layout: &Layout,
+    opts: &QrOptions,
+    alt: Option<&str>,
+    payload: Option<&str>,
+    icon: Option<&DynamicImage>,
+    backdrop: Option<&RgbaImage>,
+) -> Result<String, QrGenError> {
+    let size = layout.canvas_size;
+    let frame = opts.frame.as_ref().map(|frame| (frame, FrameLayout::new(frame, layout)));
+    let (width, body_height) = frame.map_or((size, size), |(_, placement)| (placement.width, placement.height));
+    let caption = caption::caption_text(opts);
+    let height = body_height + caption.map_or(0, |_| caption_band_height(opts.caption_size));
+    let all_square = opts.module_style == ModuleStyle::Square && matches!(opts.eye_style, None | Some(EyeStyle::Square));
+
+    // The card, if any, sits inside the outer padding
+    let card_padding = opts.card_color.map_or(0, |_| opts.card_padding);
+    let padding = opts.outer_padding + card_padding;
+    let (turned_width, turned_height) = match opts.rotate {
+        Some(rotation) if rotation.is_quarter_turn() => (height, width),
+        _ => (width, height),
+    };
+    let (outer_width, outer_height) = (turned_width + 2 * padding, turned_height + 2 * padding);
+
+    let mut svg = String::new();
+    svg.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" shape-rendering=\"{2}\"",
+        outer_width,
+        outer_height,
+        if all_square || !opts.antialias { "crispEdges" } else { "geometricPrecision" }
+    )
+    .unwrap();
+    match alt.map(escape_xml) {
+        Some(alt) => writeln!(svg, " role=\"img\" aria-label=\"{0}\">\n<title>{0}</title>", alt).unwrap(),
+        None => svg.push_str(">\n"),
+    }
+    if opts.metadata {
+        write!(svg, "<metadata xmlns:qr=\"{}\"><qr:generator>{}</qr:generator>", SVG_METADATA_NAMESPACE, GENERATOR).unwrap();
+        if let Some(payload) = payload {
+            write!(svg, "<qr:payload>{}</qr:payload>", escape_xml(payload)).unwrap();
+        }
+        svg.push_str("</metadata>\n");
+    }
+    let clipped = opts.card_radius > 0 && opts.card_color.is_none();
+    if clipped {
+        let radius = opts.card_radius.min(outer_width.min(outer_height) / 2);
+        writeln!(
+            svg,
+            "<defs><clipPath id=\"card\"><rect width=\"{}\" height=\"{}\" rx=\"{}\"/></clipPath></defs>",
+            outer_width, outer_height, radius
+        )
+        .unwrap();
+        svg.push_str("<g clip-path=\"url(#card)\">\n");
+    }
+    match opts.card_color {
+        Some(card_color) => {
+            // Past the card's corners shows the outer padding, or nothing without any
+            if opts.outer_padding > 0 {
+                write_background(&mut svg, 0, (outer_width, outer_height), opts);
+            }
+            let (card_width, card_height) = (turned_width + 2 * card_padding, turned_height + 2 * card_padding);
+            writeln!(
+                svg,
+                "<rect x=\"{0}\" y=\"{0}\" width=\"{1}\" height=\"{2}\" rx=\"{3}\" fill=\"{4}\"/>",
+                opts.outer_padding,
+                card_width,
+                card_height,
+                opts.card_radius.min(card_width.min(card_height) / 2),
+                to_hex(card_color)
+            )
+            .unwrap();
+            write_background(&mut svg, padding, (turned_width, turned_height), opts);
+        }
+        None => write_background(&mut svg, 0, (outer_width, outer_height), opts),
+    }
+    let transformed = opts.rotate.is_some() || padding > 0;
+    if transformed {
+        // Rotate about the origin, then shift the result back into view
+        // inside the padding
+        let (dx, dy) = match opts.rotate {
+            None => (0, 0),
+            Some(Rotation::Deg90) => (height, 0),
+            Some(Rotation::Deg180) => (width, height),
+            Some(Rotation::Deg270) => (0, width),
+        };
+        write!(svg, "<g transform=\"translate({},{})", dx + padding, dy + padding).unwrap();
+        if let Some(rotation) = opts.rotate {
+            write!(svg, " rotate({})", rotation.degrees()).unwrap();
+        }
+        svg.push_str("\">\n");
+    }
+    if let Some(backdrop) = backdrop {
+        let mut png = Vec::new();
+        backdrop.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(QrGenError::ImageEncode)?;
+        let inset = frame.map_or(0, |(_, placement)| placement.border);
+        writeln!(
+            svg,
+            "<image x=\"{0}\" y=\"{0}\" width=\"{1}\" height=\"{1}\" href=\"data:image/png;base64,{2}\"/>",
+            inset,
+            size,
+            STANDARD.encode(&png)
+        )
+        .unwrap();
+    }
+    if let Some((frame, placement)) = frame {
+        // The border and banner, with a window cut out for the code
+        let (border, window) = (placement.border as f64, size as f64);
+        let mut path = String::new();
+        rounded_rect_path(&mut path, 0.0, 0.0, width as f64, body_height as f64, placement.radius);
+        rounded_rect_path(&mut path, border, border, window, window, placement.inner_radius);
+        writeln!(svg, "<path fill=\"{}\" fill-rule=\"evenodd\" d=\"{}\"/>", to_hex(frame.color), path).unwrap();
+        writeln!(svg, "<g transform=\"translate({0},{0})\">", placement.border).unwrap();
+    }
+    if let Some(margin_color) = opts.margin_color {
+        // The quiet zone, with a window cut out for the matrix
+        let (start, matrix) = (layout.offset as f64, layout.matrix_size() as f64);
+        let mut path = String::new();
+        rounded_rect_path(&mut path, 0.0, 0.0, size as f64, size as f64, 0.0);
+        rounded_rect_path(&mut path, start, start, matrix, matrix, 0.0);
+        writeln!(svg, "<path fill=\"{}\" fill-rule=\"evenodd\" d=\"{}\"/>", to_hex(margin_color), path).unwrap();
+    }
+
+    let module_fill = match opts.gradient {
+        Some(gradient) if gradient.kind == GradientType::Radial => {
+            writeln!(
+                svg,
+                "<defs><radialGradient id=\"modules\" gradientUnits=\"userSpaceOnUse\" cx=\"{0}\" cy=\"{0}\" r=\"{1:.2}\">\
+                 <stop offset=\"0\" stop-color=\"{2}\"/><stop offset=\"1\" stop-color=\"{3}\"/></radialGradient></defs>",
+                size as f64 / 2.0,
+                size as f64 / 2.0 * SQRT_2,
+                to_hex(gradient.start),
+                to_hex(gradient.end)
+            )
+            .unwrap();
+            "url(#modules)".to_string()
+        }
+        Some(gradient) => {
+            let (x2, y2) = match gradient.direction {
+                GradientDirection::Horizontal => (size, 0),
+                GradientDirection::Vertical => (0, size),
+                GradientDirection::Diagonal => (size, size),
+            };
+            writeln!(
+                svg,
+                "<defs><linearGradient id=\"modules\" gradientUnits=\"userSpaceOnUse\" x1=\"0\" y1=\"0\" x2=\"{}\" y2=\"{}\">\
+                 <stop offset=\"0\" stop-color=\"{}\"/><stop offset=\"1\" stop-color=\"{}\"/></linearGradient></defs>",
+                x2,
+                y2,
+                to_hex(gradient.start),
+                to_hex(gradient.end)
+            )
+            .unwrap();
+            "url(#modules)".to_string()
+        }
+        None => to_hex(opts.foreground),
+    };
+
+    let eyes_apart = eye::styled(opts);
+    let in_eye = |x: usize, y: usize| eye::is_finder_module(x as u32, y as u32, layout.qr_width);
+    let in_timing = |x: usize, y: usize| opts.timing_color.is_some() && eye::is_timing_module(x as u32, y as u32, layout.qr_width);
+    write_modules(&mut svg, code, layout, opts, &module_fill, |x, y| !((eyes_apart && in_eye(x, y)) || in_timing(x, y)));
+    if let Some(timing_color) = opts.timing_color {
+        write_modules(&mut svg, code, layout, opts, &to_hex(timing_color), in_timing);
+    }
+    if eyes_apart {
+        let eye_fill = opts.eye_color.map(to_hex).unwrap_or_else(|| module_fill.clone());
+        match opts.eye_style {
+            None => write_modules(&mut svg, code, layout, opts, &eye_fill, in_eye),
+            Some(style) => {
+                let rects = eye::eye_rects(style, layout.module_size, opts.corner_radius);
+                for (x, y) in eye::finder_origins(layout.qr_width) {
+                    let left = (layout.offset + x * layout.module_size) as f64;
+                    let top = (layout.offset + y * layout.module_size) as f64;
+                    let mut path = String::new();
+                    for rect in rects {
+                        let (x, y) = (left + rect.inset, top + rect.inset);
+                        rounded_rect_path(&mut path, x, y, rect.size, rect.size, rect.radius);
+                    }
+                    writeln!(svg, "<path fill=\"{}\" fill-rule=\"evenodd\" d=\"{}\"/>", eye_fill, path).unwrap();
+                }
+            }
+        }
+    }
+
+    if let Some(icon) = icon {
+        let (placement, draw_halo) = IconPlacement::for_options(&IconArea::matrix(layout), icon, opts);
+
+        let mut png = Vec::new();
+        icon.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(QrGenError::ImageEncode)?;
+
+        if draw_halo {
+            write_halo(&mut svg, &placement, halo_color(icon, opts), opts);
+        }
+        writeln!(
+            svg,
+            "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" href=\"data:image/png;base64,{}\"/>",
+            placement.x,
+            placement.y,
+            icon.width(),
+            icon.height(),
+            STANDARD.encode(&png)
+        )
+        .unwrap();
+    }
+
+    if let Some((frame, placement)) = frame {
+        svg.push_str("</g>\n");
+        write_text(&mut svg, &frame.text, width as f64 / 2.0, placement.baseline, placement.text_size, frame.text_color);
+    }
+    if let Some(text) = caption {
+        let placement = CaptionLayout::new(text, width, opts);
+        let color = opts.caption_color.unwrap_or(opts.foreground);
+        write_text(&mut svg, text, width as f64 / 2.0, body_height as f64 + placement.baseline, placement.size, color);
+    }
+
+    if transformed {
+        svg.push_str("</g>\n");
+    }
+    if clipped {
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// Fills a `width` x `height` rectangle `inset` pixels from the top left
+/// corner with `opts.background`, at its alpha.
+fn write_background(svg: &mut String, inset: u32, (width, height): (u32, u32), opts: &QrOptions) {
+    let position = if inset > 0 { format!("x=\"{0}\" y=\"{0}\" ", inset) } else { String::new() };
+    match opts.background_pixel()[3] {
+        0 => {}
+        255 => writeln!(svg, "<rect {}width=\"{}\" height=\"{}\" fill=\"{}\"/>", position, width, height, to_hex(opts.background)).unwrap(),
+        alpha => writeln!(
+            svg,
+            "<rect {}width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{:.3}\"/>",
+            position,
+            width,
+            height,
+            to_hex(opts.background),
+            alpha as f64 / 255.0
+        )
+        .unwrap(),
+    }
+}
+
+/// Writes the dark modules for which `include` returns true, filled with
+/// `fill` and shaped according to `opts.module_style`.
+fn write_modules(
+    svg: &mut String,
+    code: &Code,
+    layout: &Layout,
+    opts: &QrOptions,
+    fill: &str,
+    include: impl Fn(usize, usize) -> bool,
+) {
+    let module_size = layout.module_size;
+    match opts.module_style {
+        ModuleStyle::Square if opts.module_gap == 0.0 => {
+            let path = square_module_path(code, layout, include);
+            writeln!(svg, "<path fill=\"{}\" d=\"{}\"/>", fill, path).unwrap();
+        }
+        style => {
+            writeln!(svg, "<g fill=\"{}\">", fill).unwrap();
+            for y in 0..code.width() {
+                for x in 0..code.width() {
+                    if code[(x, y)] != qrcode::Color::Dark || !include(x, y) {
+                        continue;
+                    }
+                    let shape = module_shape(x, y, layout, opts);
+                    let left = (layout.offset + x as u32 * module_size) as f64 + shape.inset;
+                    let top = (layout.offset + y as u32 * module_size) as f64 + shape.inset;
+                    if style == ModuleStyle::Circle {
+                        let half = shape.size / 2.0;
+                        writeln!(svg, "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"/>", left + half, top + half, half).unwrap();
+                    } else {
+                        writeln!(
+                            svg,
+                            "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" rx=\"{3}\"/>",
+                            left, top, shape.size, shape.radius
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+            svg.push_str("</g>\n");
+        }
+    }
+}
+
+/// Writes the icon's backing shape in `fill`, then its border ring if it
+/// has one.
+fn write_halo(svg: &mut String, placement: &IconPlacement, fill: Rgb<u8>, opts: &QrOptions) {
+    let halo_opacity = opts.halo_opacity.clamp(0.0, 1.0);
+    let halo_fill = to_hex(fill);
+    let (cx, cy) = (
+        placement.halo_x as f64 + placement.halo_width as f64 / 2.0,
+        placement.halo_y as f64 + placement.halo_height as f64 / 2.0,
+    );
+    match opts.effective_halo_shape() {
+        IconShape::Square => writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>",
+            placement.halo_x, placement.halo_y, placement.halo_width, placement.halo_height, halo_fill, halo_opacity
+        ),
+        IconShape::Circle => {
+            writeln!(
+                svg,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>",
+                cx,
+                cy,
+                placement.halo_radius(),
+                halo_fill,
+                halo_opacity
+            )
+        }
+    }
+    .unwrap();
+
+    let Some(border) = opts.halo_border.filter(|border| border.width > 0) else {
+        return;
+    };
+    // Strokes are centered on the outline, so inset it by half the width
+    let inset = border.width as f64 / 2.0;
+    let stroke = format!("fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"", to_hex(border.color), border.width);
+    match opts.effective_halo_shape() {
+        IconShape::Square => writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+            placement.halo_x as f64 + inset,
+            placement.halo_y as f64 + inset,
+            placement.halo_width as f64 - border.width as f64,
+            placement.halo_height as f64 - border.width as f64,
+            stroke
+        ),
+        IconShape::Circle => writeln!(svg, "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" {}/>", cx, cy, placement.halo_radius() - inset, stroke),
+    }
+    .unwrap();
+}
+
+/// Builds path data covering every dark module for which `include` returns
+/// true, merging horizontal runs.
+fn square_module_path(code: &Code, layout: &Layout, include: impl Fn(usize, usize) -> bool) -> String {
+    let module_size = layout.module_size;
+    let mut path = String::new();
+    let width = code.width();
+    for y in 0..width {
+        let mut x = 0;
+        while x < width {
+            if code[(x, y)] != qrcode::Color::Dark || !include(x, y) {
+                x += 1;
+                continue;
+            }
+            let run_start = x;
+            while x < width && code[(x, y)] == qrcode::Color::Dark && include(x, y) {
+                x += 1;
+            }
+            let run = (x - run_start) as u32 * module_size;
+            write!(
+                path,
+                "M{},{}h{}v{}h-{}z",
+                layout.offset + run_start as u32 * module_size,
+                layout.offset + y as u32 * module_size,
+                run,
+                module_size,
+                run
+            )
+            .unwrap();
+        }
+    }
+    path
+}
+
+/// Appends a closed subpath for a `width` x `height` rectangle at `(x, y)`
+/// whose corners are rounded by `radius`; a square with a radius of half its
+/// side produces a circle.
+fn rounded_rect_path(path: &mut String, x: f64, y: f64, width: f64, height: f64, radius: f64) {
+    if radius <= 0.0 {
+        write!(path, "M{},{}h{}v{}h-{}z", x, y, width, height, width).unwrap();
+        return;
+    }
+
+    let (across, down) = (width - 2.0 * radius, height - 2.0 * radius);
+    write!(
+        path,
+        "M{},{}h{2}a{4},{4} 0 0 1 {4},{4}v{3}a{4},{4} 0 0 1 -{4},{4}h-{2}a{4},{4} 0 0 1 -{4},-{4}v-{3}a{4},{4} 0 0 1 {4},-{4}z",
+        x + radius,
+        y,
+        across,
+        down,
+        radius
+    )
+    .unwrap();
+}
+
+/// Writes `text` centered on `x` with its baseline at `y`.
+fn write_text(svg: &mut String, text: &str, x: f64, y: f64, size: f64, color: Rgb<u8>) {
+    writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{}\" font-family=\"DejaVu Sans, Verdana, sans-serif\" font-size=\"{}\" fill=\"{}\" \
+         text-anchor=\"middle\">{}</text>",
+        x,
+        y,
+        size,
+        to_hex(color),
+        escape_xml(text)
+    )
+    .unwrap();
+}
+
+/// Escapes the characters that cannot appear literally in XML text or a
+/// double-quoted attribute.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            // XML has no way to write most control characters
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
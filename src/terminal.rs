@@ -0,0 +1,78 @@
+use crate::render;
+use crate::{QrGenError, QrOptions};
+
+/// Renders a QR code for `url` as text using Unicode half-block characters,
+/// so that two module rows fit in one line of output.
+///
+/// Dark modules are drawn as filled characters, so the preview reads
+/// correctly on terminals with a light background. Icons are never drawn,
+/// but `opts.margin` light modules are left around the matrix.
+pub fn render_terminal(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<String, QrGenError> {
+    let code = render::encode_matrix(url.as_ref(), opts.effective_ec_level(false), opts)?;
+    let width = code.width();
+    let margin = opts.margin as usize;
+    let total = width + 2 * margin;
+
+    let is_dark = |x: usize, y: usize| {
+        x >= margin
+            && y >= margin
+            && x < width + margin
+            && y < width + margin
+            && code[(x - margin, y - margin)] == qrcode::Color::Dark
+    };
+
+    let mut out = String::with_capacity((total + 1) * total.div_ceil(2) * 3);
+    for y in (0..total).step_by(2) {
+        for x in 0..total {
+            let top = is_dark(x, y);
+            let bottom = y + 1 < total && is_dark(x, y + 1);
+            out.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+/// Prints a QR code for `url` to stdout. See [`render_terminal`].
+pub fn print_to_terminal(url: impl AsRef<[u8]>, opts: &QrOptions) -> Result<(), QrGenError> {
+    print!("{}", render_terminal(url, opts)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_MARGIN;
+    use qrcode::QrCode;
+
+    #[test]
+    fn test_render_terminal_dimensions() {
+        let code = QrCode::new("https://example.com").unwrap();
+        let total = code.width() + 2 * DEFAULT_MARGIN as usize;
+
+        let preview = render_terminal("https://example.com", &QrOptions::default()).unwrap();
+        let lines: Vec<&str> = preview.lines().collect();
+
+        assert_eq!(lines.len(), total.div_ceil(2));
+        assert!(lines.iter().all(|line| line.chars().count() == total));
+    }
+
+    #[test]
+    fn test_render_terminal_quiet_zone() {
+        let preview = render_terminal("https://example.com", &QrOptions::default()).unwrap();
+        let lines: Vec<&str> = preview.lines().collect();
+
+        // The first two lines cover the top four quiet rows
+        assert!(lines[0].chars().all(|c| c == ' '));
+        assert!(lines[1].chars().all(|c| c == ' '));
+        // The top-left finder pattern starts right after the quiet zone
+        assert_eq!(lines[2].chars().nth(DEFAULT_MARGIN as usize), Some('█'));
+        assert!(lines[2].chars().take(DEFAULT_MARGIN as usize).all(|c| c == ' '));
+    }
+}
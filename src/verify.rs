@@ -0,0 +1,97 @@
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::QrGenError;
+
+/// White border added around the image before decoding, so codes rendered
+/// without a margin still have a quiet zone for the detector.
+const DECODE_PADDING: u32 = 40;
+
+/// Decodes the QR code in `image`, returning its payload if exactly one code
+/// was found and read and it is valid UTF-8.
+#[cfg(test)]
+pub(crate) fn decode_payload(image: &DynamicImage) -> Option<String> {
+    decode_bytes(image).and_then(|payload| String::from_utf8(payload).ok())
+}
+
+/// Decodes the QR code in `image`, returning its payload bytes if exactly one
+/// code was found and read.
+///
+/// Codes with light modules on a dark background are read by trying again
+/// with the image inverted, as scanners that support them do.
+pub(crate) fn decode_bytes(image: &DynamicImage) -> Option<Vec<u8>> {
+    let luma = image.to_luma8();
+    decode_luma(&luma).or_else(|| {
+        let mut inverted = luma;
+        image::imageops::invert(&mut inverted);
+        decode_luma(&inverted)
+    })
+}
+
+/// Decodes the QR code, drawn dark on light, in `luma`.
+fn decode_luma(luma: &GrayImage) -> Option<Vec<u8>> {
+    let mut padded = GrayImage::from_pixel(
+        luma.width() + 2 * DECODE_PADDING,
+        luma.height() + 2 * DECODE_PADDING,
+        Luma([255]),
+    );
+    image::imageops::overlay(&mut padded, luma, DECODE_PADDING as i64, DECODE_PADDING as i64);
+
+    let mut prepared = rqrr::PreparedImage::prepare(padded);
+    match prepared.detect_grids().as_slice() {
+        [grid] => {
+            let mut payload = Vec::new();
+            grid.decode_to(&mut payload).ok().map(|_| payload)
+        }
+        _ => None,
+    }
+}
+
+/// Reads the QR code in `image`, such as a photo or scan of a printed one,
+/// and returns its payload bytes.
+///
+/// Fails with [`QrGenError::NoCodeFound`] unless exactly one code is found
+/// and read.
+pub fn scan_image(image: &DynamicImage) -> Result<Vec<u8>, QrGenError> {
+    decode_bytes(image).ok_or(QrGenError::NoCodeFound)
+}
+
+/// Checks that `image` decodes back to `expected`.
+///
+/// A payload that is not UTF-8 is reported with its invalid sequences
+/// replaced, as the error carries text.
+pub fn verify_image(image: &DynamicImage, expected: impl AsRef<[u8]>) -> Result<(), QrGenError> {
+    match decode_bytes(image) {
+        Some(payload) if payload == expected.as_ref() => Ok(()),
+        decoded => Err(QrGenError::VerificationFailed(decoded.map(|payload| String::from_utf8_lossy(&payload).into_owned()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QrOptions;
+
+    #[test]
+    fn test_verify_generated_code() {
+        let image = crate::generate("https://example.com", &QrOptions::default()).unwrap();
+        assert!(verify_image(&image, "https://example.com").is_ok());
+        assert!(matches!(
+            verify_image(&image, "https://example.org"),
+            Err(QrGenError::VerificationFailed(Some(decoded))) if decoded == "https://example.com"
+        ));
+
+        let inverted = crate::generate("https://example.com", &QrOptions::default().inverted()).unwrap();
+        assert!(verify_image(&inverted, "https://example.com").is_ok());
+
+        let blank = DynamicImage::new_rgb8(100, 100);
+        assert!(matches!(verify_image(&blank, "x"), Err(QrGenError::VerificationFailed(None))));
+    }
+
+    #[test]
+    fn test_scan_generated_code() {
+        let opts = QrOptions::default().with_size(300);
+        let image = crate::generate("https://example.com/scan", &opts).unwrap();
+        assert_eq!(scan_image(&image).unwrap(), b"https://example.com/scan");
+        assert!(matches!(scan_image(&DynamicImage::new_rgb8(100, 100)), Err(QrGenError::NoCodeFound)));
+    }
+}